@@ -0,0 +1,63 @@
+// Times the tile collision core at several level sizes.
+//
+// Run with `cargo bench --bench collision`. Each case reports the mean time
+// of building the solid tile set (done once per physics step) and of
+// resolving one body against it.
+
+use bevy::prelude::*;
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use last_question::physics::SolidTiles;
+use last_question::tile::ColliderShape;
+
+const TILE_COUNTS: [usize; 3] = [100, 1_000, 10_000];
+const MIN_BENCH_TIME: Duration = Duration::from_millis(500);
+
+// Rows of floor 100 tiles wide, stacked with gaps so every tile has exposed edges
+fn level(tile_count: usize) -> Vec<(IVec2, ColliderShape)> {
+    (0..tile_count)
+        .map(|i| {
+            (
+                IVec2::new((i % 100) as i32 - 50, 3 * (i / 100) as i32),
+                ColliderShape::Aabb,
+            )
+        })
+        .collect()
+}
+
+// Call `f` repeatedly for at least MIN_BENCH_TIME and return the mean duration
+fn bench(mut f: impl FnMut()) -> Duration {
+    // Warm up
+    f();
+    let start = Instant::now();
+    let mut iterations = 0;
+    while start.elapsed() < MIN_BENCH_TIME {
+        f();
+        iterations += 1;
+    }
+    start.elapsed() / iterations
+}
+
+fn main() {
+    for tile_count in TILE_COUNTS {
+        let cells = level(tile_count);
+
+        let build = bench(|| {
+            black_box(SolidTiles::new(black_box(cells.iter().copied())));
+        });
+
+        let solids = SolidTiles::new(cells.iter().copied());
+        let resolve = bench(|| {
+            // A body landing on the bottom floor
+            let mut translation = Vec3::new(0.25, 0.9, 0.);
+            let mut velocity = Vec3::new(10., -5., 0.);
+            black_box(solids.resolve(&mut translation, Vec2::new(1., 2.), &mut velocity));
+        });
+
+        println!(
+            "{:>6} tiles: build {:>12?}  resolve {:>12?}",
+            tile_count, build, resolve
+        );
+    }
+}
@@ -0,0 +1,329 @@
+// Movement abilities, which players start without and unlock by collecting
+// their pickups in a level.
+//
+// Unlocking one sets the matching field of every player's `Mobility`, which
+// the movement systems check before allowing it. `AbilityPlugin` keeps the
+// abilities unlocked in the `Settings`, so they are back whenever the game
+// starts and a level's pickups, which come back whenever it is spawned, can't
+// lock them again. Collecting a pickup for an ability the players already
+// have scores instead; the first time holds the game for a moment while its
+// name is shown.
+
+use bevy::prelude::*;
+use bevy::sprite::Anchor;
+use serde::{Deserialize, Serialize};
+
+use crate::death::LivePlayerQuery;
+use crate::game_state::GameState;
+use crate::level::{LevelEntity, Unspawned};
+use crate::physics::{hitbox, overlaps, Mobility};
+use crate::pixel_perfect::UI_FONT;
+use crate::player::Player;
+use crate::settings::Settings;
+use crate::sfx::{PlaySfx, Sfx};
+
+// Side of a pickup, in tiles. It sits in the middle of its cell.
+const PICKUP_SIZE: f32 = 0.6;
+// Seconds the game is held for while a new ability's name is shown
+const FANFARE_TIME: f32 = 1.5;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum Ability {
+    // One more jump in the air
+    DoubleJump,
+    Dash,
+    WallJump,
+}
+
+impl Ability {
+    pub fn name(self) -> &'static str {
+        match self {
+            Ability::DoubleJump => "Double Jump",
+            Ability::Dash => "Dash",
+            Ability::WallJump => "Wall Jump",
+        }
+    }
+
+    fn color(self) -> Color {
+        match self {
+            Ability::DoubleJump => Color::rgb(0.4, 0.8, 1.),
+            Ability::Dash => Color::rgb(1., 0.5, 0.3),
+            Ability::WallJump => Color::rgb(0.6, 1., 0.5),
+        }
+    }
+
+    pub fn is_unlocked(self, mobility: &Mobility) -> bool {
+        match self {
+            Ability::DoubleJump => mobility.max_air_jumps >= 1,
+            Ability::Dash => mobility.dash_unlocked,
+            Ability::WallJump => mobility.wall_jump_unlocked,
+        }
+    }
+
+    pub fn unlock(self, mobility: &mut Mobility) {
+        match self {
+            Ability::DoubleJump => mobility.max_air_jumps = mobility.max_air_jumps.max(1),
+            Ability::Dash => mobility.dash_unlocked = true,
+            Ability::WallJump => mobility.wall_jump_unlocked = true,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PickupData {
+    // The cell it sits in the middle of
+    pub pos: IVec2,
+    pub ability: Ability,
+}
+
+#[derive(Component)]
+pub struct Pickup(pub Ability);
+
+// A player collected a pickup, centred `at`. `owned` if the players already
+// had its ability.
+pub struct AbilityCollected {
+    pub player: Entity,
+    pub ability: Ability,
+    pub at: Vec2,
+    pub owned: bool,
+}
+
+// Drawn as a square of the ability's color
+pub fn spawn_pickup(commands: &mut Commands, data: &PickupData) -> Entity {
+    let margin = (1. - PICKUP_SIZE) / 2.;
+    commands
+        .spawn_bundle(SpriteBundle {
+            transform: Transform {
+                translation: (data.pos.as_vec2() + Vec2::splat(margin)).extend(0.),
+                scale: Vec3::new(PICKUP_SIZE, PICKUP_SIZE, 1.),
+                ..default()
+            },
+            sprite: Sprite {
+                color: data.ability.color(),
+                custom_size: Some(Vec2::ONE),
+                anchor: Anchor::BottomLeft,
+                ..default()
+            },
+            ..default()
+        })
+        .insert(Pickup(data.ability))
+        .insert(LevelEntity)
+        .id()
+}
+
+// How a pickup was placed, read back from it
+pub fn pickup_data(pickup: &Pickup, transform: &Transform) -> PickupData {
+    let margin = (1. - PICKUP_SIZE) / 2.;
+    PickupData {
+        pos: (transform.translation.truncate() - Vec2::splat(margin))
+            .round()
+            .as_ivec2(),
+        ability: pickup.0,
+    }
+}
+
+// After the collision step. The ability is unlocked for every player, not
+// just the one who collected it.
+pub fn ability_pickup_system(
+    mut commands: Commands,
+    player_query: LivePlayerQuery,
+    mut mobility_query: Query<&mut Mobility, With<Player>>,
+    pickup_query: Query<(Entity, &Pickup, &Transform), Without<Player>>,
+    mut unspawned: ResMut<Unspawned>,
+    mut collected_events: EventWriter<AbilityCollected>,
+) {
+    for (pickup, &Pickup(ability), pickup_transform) in pickup_query.iter() {
+        let (pickup_min, pickup_size) = hitbox(pickup_transform);
+        let collector = player_query
+            .iter()
+            .find(|(_, transform)| overlaps(hitbox(transform), (pickup_min, pickup_size)));
+        let player = match collector {
+            Some((player, _)) => player,
+            None => continue,
+        };
+        commands.entity(pickup).despawn_recursive();
+        unspawned
+            .0
+            .pickups
+            .push(pickup_data(&Pickup(ability), pickup_transform));
+        let owned = mobility_query
+            .get(player)
+            .is_ok_and(|mobility| ability.is_unlocked(mobility));
+        if !owned {
+            for mut mobility in mobility_query.iter_mut() {
+                ability.unlock(&mut mobility);
+            }
+        }
+        collected_events.send(AbilityCollected {
+            player,
+            ability,
+            at: pickup_min + pickup_size / 2.,
+            owned,
+        });
+    }
+}
+
+// The ability being announced, while in `GameState::Fanfare`
+#[derive(Default)]
+struct Fanfare {
+    ability: Option<Ability>,
+    // Seconds it has been shown for
+    elapsed: f32,
+}
+
+#[derive(Component)]
+struct FanfareText;
+
+// Needs the `AbilityCollected` events from `GamePlugin`, the `Settings` from
+// `DisplayPlugin`, the `GameState` from `GameStatePlugin` and `PlaySfx` from
+// `SfxPlugin`
+#[derive(Default)]
+pub struct AbilityPlugin;
+
+impl Plugin for AbilityPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Fanfare>()
+            .add_system(saved_abilities_system)
+            .add_system_set(SystemSet::on_update(GameState::Playing).with_system(unlocked_system))
+            .add_system_set(SystemSet::on_enter(GameState::Fanfare).with_system(spawn_fanfare))
+            .add_system_set(SystemSet::on_update(GameState::Fanfare).with_system(fanfare_system))
+            .add_system_set(SystemSet::on_exit(GameState::Fanfare).with_system(despawn_fanfare));
+    }
+}
+
+// Players start with the abilities unlocked in earlier sessions
+fn saved_abilities_system(settings: Res<Settings>, mut query: Query<&mut Mobility, Added<Player>>) {
+    for mut mobility in query.iter_mut() {
+        for &ability in &settings.abilities {
+            ability.unlock(&mut mobility);
+        }
+    }
+}
+
+// Keep each new ability, and announce it
+fn unlocked_system(
+    mut collected_events: EventReader<AbilityCollected>,
+    mut settings: ResMut<Settings>,
+    mut fanfare: ResMut<Fanfare>,
+    mut state: ResMut<State<GameState>>,
+    mut sfx_events: EventWriter<PlaySfx>,
+) {
+    for collected in collected_events.iter() {
+        if collected.owned || !settings.abilities.insert(collected.ability) {
+            continue;
+        }
+        settings.save();
+        // Fails only if a transition is already queued this frame, in which
+        // case it's saved without the fanfare
+        if state.set(GameState::Fanfare).is_ok() {
+            *fanfare = Fanfare {
+                ability: Some(collected.ability),
+                elapsed: 0.,
+            };
+            // A jump pitched up, until there's a sound of its own
+            sfx_events.send(PlaySfx {
+                speed: 1.5,
+                ..PlaySfx::new(Sfx::Jump)
+            });
+        }
+    }
+}
+
+fn spawn_fanfare(mut commands: Commands, asset_server: Res<AssetServer>, fanfare: Res<Fanfare>) {
+    let name = fanfare.ability.map_or("", Ability::name);
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.), Val::Percent(100.)),
+                position_type: PositionType::Absolute,
+                flex_direction: FlexDirection::ColumnReverse,
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            color: Color::NONE.into(),
+            ..default()
+        })
+        .insert(FanfareText)
+        .with_children(|parent| {
+            parent.spawn_bundle(TextBundle {
+                text: Text::with_section(
+                    format!("{}!", name),
+                    TextStyle {
+                        font: asset_server.load(UI_FONT),
+                        font_size: 48.,
+                        color: Color::WHITE,
+                    },
+                    default(),
+                ),
+                ..default()
+            });
+        });
+}
+
+fn fanfare_system(
+    time: Res<Time>,
+    mut fanfare: ResMut<Fanfare>,
+    mut state: ResMut<State<GameState>>,
+) {
+    fanfare.elapsed += time.delta_seconds();
+    if fanfare.elapsed >= FANFARE_TIME {
+        let _ = state.set(GameState::Playing);
+    }
+}
+
+fn despawn_fanfare(mut commands: Commands, query: Query<Entity, With<FanfareText>>) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::headless::{floor, HeadlessGame};
+    use crate::input::Action;
+    use crate::level::LevelData;
+
+    fn peak_of_two_jumps(game: &mut HeadlessGame) -> f32 {
+        let mut peak: f32 = 1.;
+        // Pressed again near the top of the first jump
+        for (steps, pressed) in [
+            (100, &[Action::Jump][..]),
+            (1, &[][..]),
+            (240, &[Action::Jump][..]),
+        ] {
+            for _ in 0..steps {
+                game.step(pressed);
+                peak = peak.max(game.player_transform().translation.y);
+            }
+        }
+        peak
+    }
+
+    #[test]
+    fn double_jump_is_only_there_once_collected() {
+        let level = |pickups| LevelData {
+            pickups,
+            ..floor(-4..=4)
+        };
+
+        let mut game = HeadlessGame::new();
+        game.spawn_level(&level(Vec::new()));
+        game.place_player(Vec2::new(0., 1.));
+        game.run(10, &[]);
+        let single = peak_of_two_jumps(&mut game);
+
+        let mut game = HeadlessGame::new();
+        game.spawn_level(&level(vec![PickupData {
+            pos: IVec2::new(0, 1),
+            ability: Ability::DoubleJump,
+        }]));
+        game.place_player(Vec2::new(0., 1.));
+        game.run(10, &[]);
+        let mobility = game.app.world.get::<Mobility>(game.player()).unwrap();
+        assert_eq!(mobility.max_air_jumps, 1);
+        let double = peak_of_two_jumps(&mut game);
+        assert!(double > single + 3., "peaked at {} then {}", single, double);
+    }
+}
@@ -0,0 +1,278 @@
+// Sprite-sheet animation. An `Animator` plays one clip of its sheet at a
+// time, picked from how its body moves: standing, running, rising, falling,
+// or landing, which plays once before going back to standing or running.
+//
+// A frame needn't be the size of the body's hitbox. The hitbox stays the
+// body's `Transform` scale, and the frame is drawn at its own size over it,
+// standing on the hitbox's bottom edge and centered across it.
+
+use bevy::math::const_vec2;
+use bevy::prelude::*;
+use bevy::sprite::Anchor;
+use bevy::utils::HashMap;
+
+use crate::physics::{Mobility, Velocity};
+use crate::pixel_perfect::PIXELS_PER_TILE;
+use crate::player::{Facing, Player};
+
+pub const PLAYER_SHEET_PATH: &str = "player.png";
+// Size of a frame of the player's sheet, and how many frames it has in its
+// single row
+const PLAYER_FRAME_PIXELS: Vec2 = const_vec2!([16., 32.]);
+const PLAYER_FRAMES: usize = 10;
+// Slower than this a body on the ground is standing rather than running, in
+// tiles per second
+const RUN_ANIMATION_MIN_SPEED: f32 = 0.5;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum AnimState {
+    #[default]
+    Idle,
+    Run,
+    Rise,
+    Fall,
+    Land,
+}
+
+// A run of consecutive frames in a sheet
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Clip {
+    pub first: usize,
+    pub frames: usize,
+    pub fps: f32,
+    // Clips which don't loop hold their last frame
+    pub looping: bool,
+}
+
+#[derive(Component)]
+pub struct Animator {
+    pub clips: HashMap<AnimState, Clip>,
+    pub current: AnimState,
+    // Frame within the current clip
+    pub frame: usize,
+    // Seconds the current frame has been shown
+    pub timer: f32,
+    // Size frames are drawn at, in tiles
+    pub frame_size: Vec2,
+}
+
+impl Animator {
+    pub fn new(clips: HashMap<AnimState, Clip>, frame_size: Vec2) -> Self {
+        Animator {
+            clips,
+            current: AnimState::default(),
+            frame: 0,
+            timer: 0.,
+            frame_size,
+        }
+    }
+
+    fn clip(&self) -> Option<&Clip> {
+        self.clips.get(&self.current)
+    }
+
+    // Switch to `state`'s clip from its first frame, unless it's already
+    // playing
+    pub fn play(&mut self, state: AnimState) {
+        if self.current != state {
+            self.current = state;
+            self.frame = 0;
+            self.timer = 0.;
+        }
+    }
+
+    // Whether a clip which doesn't loop has shown its last frame for a full
+    // frame's time. Looping clips never finish.
+    pub fn finished(&self) -> bool {
+        self.clip().is_none_or(|clip| {
+            !clip.looping && self.frame + 1 >= clip.frames && self.timer * clip.fps >= 1.
+        })
+    }
+
+    pub fn advance(&mut self, dt: f32) {
+        let clip = match self.clip() {
+            Some(&clip) if clip.frames > 0 && clip.fps > 0. => clip,
+            _ => return,
+        };
+        self.timer += dt;
+        while self.timer * clip.fps >= 1. {
+            if self.frame + 1 < clip.frames {
+                self.frame += 1;
+            } else if clip.looping {
+                self.frame = 0;
+            } else {
+                // Held, with the time kept for `finished`
+                return;
+            }
+            self.timer -= 1. / clip.fps;
+        }
+    }
+
+    // The frame to show, as an index into the sheet
+    pub fn index(&self) -> usize {
+        self.clip().map_or(0, |clip| clip.first + self.frame)
+    }
+}
+
+// The player's clips, by their place in `PLAYER_SHEET_PATH`
+pub fn player_clips() -> HashMap<AnimState, Clip> {
+    let clip = |first, frames, fps, looping| Clip {
+        first,
+        frames,
+        fps,
+        looping,
+    };
+    [
+        (AnimState::Idle, clip(0, 2, 2., true)),
+        (AnimState::Run, clip(2, 4, 10., true)),
+        (AnimState::Rise, clip(6, 1, 1., true)),
+        (AnimState::Fall, clip(7, 1, 1., true)),
+        (AnimState::Land, clip(8, 2, 12., false)),
+    ]
+    .into_iter()
+    .collect()
+}
+
+// What a body should show next, having been showing `current`. Rising and
+// falling go by the sign of the vertical speed.
+pub fn next_state(
+    current: AnimState,
+    finished: bool,
+    mobility: &Mobility,
+    velocity: &Velocity,
+) -> AnimState {
+    if !mobility.on_ground {
+        return if velocity.0.y > 0. {
+            AnimState::Rise
+        } else {
+            AnimState::Fall
+        };
+    }
+    match current {
+        AnimState::Rise | AnimState::Fall => AnimState::Land,
+        AnimState::Land if !finished => AnimState::Land,
+        _ if velocity.0.x.abs() >= RUN_ANIMATION_MIN_SPEED => AnimState::Run,
+        _ => AnimState::Idle,
+    }
+}
+
+struct PlayerSheet(Handle<TextureAtlas>);
+
+impl FromWorld for PlayerSheet {
+    fn from_world(world: &mut World) -> Self {
+        let texture = world.resource::<AssetServer>().load(PLAYER_SHEET_PATH);
+        let atlas = TextureAtlas::from_grid(texture, PLAYER_FRAME_PIXELS, PLAYER_FRAMES, 1);
+        PlayerSheet(world.resource_mut::<Assets<TextureAtlas>>().add(atlas))
+    }
+}
+
+// Needs the `AssetServer` and `Assets<TextureAtlas>` from `DefaultPlugins`
+#[derive(Default)]
+pub struct AnimationPlugin;
+
+impl Plugin for AnimationPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<PlayerSheet>()
+            .add_system(player_sheet_system)
+            .add_system(animation_system.after(player_sheet_system));
+    }
+}
+
+// Draw players from the sheet in place of a plain sprite, tinted their own
+// color
+fn player_sheet_system(
+    mut commands: Commands,
+    sheet: Res<PlayerSheet>,
+    query: Query<(Entity, &Sprite, Option<&Facing>), Added<Player>>,
+) {
+    for (player, sprite, facing) in query.iter() {
+        let frame_size = PLAYER_FRAME_PIXELS / PIXELS_PER_TILE as f32;
+        commands
+            .entity(player)
+            .remove::<Sprite>()
+            .remove::<Handle<Image>>()
+            .insert(TextureAtlasSprite {
+                color: sprite.color,
+                flip_x: facing.is_some_and(|facing| facing.sign() < 0.),
+                ..default()
+            })
+            .insert(sheet.0.clone())
+            .insert(Animator::new(player_clips(), frame_size));
+    }
+}
+
+pub fn animation_system(
+    time: Res<Time>,
+    mut query: Query<(
+        &mut Animator,
+        &mut TextureAtlasSprite,
+        &Transform,
+        &Mobility,
+        &Velocity,
+    )>,
+) {
+    for (mut animator, mut sprite, transform, mobility, velocity) in query.iter_mut() {
+        let state = next_state(animator.current, animator.finished(), mobility, velocity);
+        animator.play(state);
+        animator.advance(time.delta_seconds());
+        sprite.index = animator.index();
+        // Undo the hitbox's scale, so the frame is drawn at its own size
+        let hitbox = transform.scale.truncate();
+        sprite.custom_size = Some(animator.frame_size / hitbox);
+        sprite.anchor = Anchor::Custom(Vec2::new(-hitbox.x / (2. * animator.frame_size.x), -0.5));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mobility(on_ground: bool) -> Mobility {
+        Mobility {
+            on_ground,
+            ..default()
+        }
+    }
+
+    #[test]
+    fn landing_plays_once_then_runs_or_stands() {
+        let mut animator = Animator::new(player_clips(), Vec2::new(1., 2.));
+        let step = |animator: &mut Animator, on_ground, velocity: Vec2| {
+            let velocity = Velocity(velocity.extend(0.));
+            let state = next_state(
+                animator.current,
+                animator.finished(),
+                &mobility(on_ground),
+                &velocity,
+            );
+            animator.play(state);
+            animator.advance(1. / 60.);
+            animator.current
+        };
+        assert_eq!(step(&mut animator, false, Vec2::Y), AnimState::Rise);
+        assert_eq!(step(&mut animator, false, -Vec2::Y), AnimState::Fall);
+        // Running on landing still shows the landing first, two frames at 12
+        // per second
+        for _ in 0..9 {
+            assert_eq!(step(&mut animator, true, Vec2::X), AnimState::Land);
+        }
+        assert_eq!(animator.index(), 9);
+        let after_landing = (0..2)
+            .map(|_| step(&mut animator, true, Vec2::X))
+            .find(|&state| state != AnimState::Land);
+        assert_eq!(after_landing, Some(AnimState::Run));
+        assert_eq!(step(&mut animator, true, Vec2::ZERO), AnimState::Idle);
+    }
+
+    #[test]
+    fn looping_clips_wrap_around() {
+        let mut animator = Animator::new(player_clips(), Vec2::new(1., 2.));
+        animator.play(AnimState::Run);
+        // Four frames at 10 per second
+        animator.advance(0.35);
+        assert_eq!(animator.index(), 2 + 4 - 1);
+        animator.advance(0.1);
+        assert_eq!(animator.index(), 2);
+        assert!(!animator.finished());
+    }
+}
@@ -0,0 +1,249 @@
+// How the world camera follows the players, tuned in one place.
+//
+// `CameraController` turns the point to follow into the camera's position in
+// four stages, always in this order:
+//
+// 1. Deadzone: the focus only moves once the point leaves a box around it,
+//    so small movements don't shift the view.
+// 2. Look-ahead: the view is pushed ahead of the players' horizontal
+//    velocity, showing more of where they are going.
+// 3. Smoothing: the camera eases towards the result rather than jumping.
+// 4. Clamp: the view is kept inside the level's tiles.
+//
+// Each stage sees only what the one before produced, so e.g. the deadzone
+// is measured from the focus rather than the eased camera, and the clamp
+// holds however far the look-ahead pushes.
+
+use bevy::prelude::*;
+
+pub struct CameraController {
+    // Half the size of the box, in tiles, the followed point moves in
+    // before the focus follows it. Zero follows it exactly.
+    pub deadzone: Vec2,
+    // Seconds of horizontal velocity the view is pushed ahead by
+    pub look_ahead_time: f32,
+    // Furthest the look-ahead pushes the view, in tiles
+    pub max_look_ahead: f32,
+    // How quickly the camera closes in on where it's headed, the same as
+    // `CameraZoom::speed`. `f32::INFINITY` turns smoothing off.
+    pub speed: f32,
+    // Keep the view inside the bounds of the level's tiles, centring it on
+    // any axis the level is smaller than the view along
+    pub clamp_to_level: bool,
+    // Where the deadzone is centred. None until the first update, or after
+    // `snap`, when it starts on the followed point.
+    focus: Option<Vec2>,
+}
+
+impl Default for CameraController {
+    fn default() -> Self {
+        CameraController {
+            deadzone: Vec2::new(1., 1.5),
+            look_ahead_time: 0.25,
+            max_look_ahead: 2.,
+            speed: 8.,
+            clamp_to_level: false,
+            focus: None,
+        }
+    }
+}
+
+impl CameraController {
+    // Jump straight to the followed point on the next update, for when the
+    // players are moved rather than travelling there
+    pub fn snap(&mut self) {
+        self.focus = None;
+    }
+
+    // Move along with the followed point jumping by `offset`, such as the
+    // players wrapping round the level, rather than panning after it. The
+    // camera itself should be moved by the same.
+    pub fn shift(&mut self, offset: Vec2) {
+        if let Some(focus) = &mut self.focus {
+            *focus += offset;
+        }
+    }
+
+    // Where the camera moves from `camera` to after `dt` seconds following
+    // `target`, moving at `velocity`. `level` is the smallest and largest
+    // corners of the level in tiles, if there is one, and `half_extent` half
+    // the size of the view, as `CameraZoom::view_half_extent`.
+    pub fn follow(
+        &mut self,
+        camera: Vec2,
+        target: Vec2,
+        velocity: Vec2,
+        level: Option<(Vec2, Vec2)>,
+        half_extent: Vec2,
+        dt: f32,
+    ) -> Vec2 {
+        let snapping = self.focus.is_none();
+
+        let mut focus = self.focus.unwrap_or(target);
+        let offset = target - focus;
+        focus += offset - offset.clamp(-self.deadzone, self.deadzone);
+        self.focus = Some(focus);
+
+        let look_ahead =
+            (velocity.x * self.look_ahead_time).clamp(-self.max_look_ahead, self.max_look_ahead);
+        let ahead = focus + Vec2::new(look_ahead, 0.);
+
+        let smoothed = if snapping {
+            ahead
+        } else {
+            // Framerate independent, unlike a fixed fraction per step
+            camera + (ahead - camera) * (1. - (-self.speed * dt).exp())
+        };
+
+        match level {
+            Some((min, max)) if self.clamp_to_level => clamp_view(smoothed, half_extent, min, max),
+            _ => smoothed,
+        }
+    }
+}
+
+// Keep a view centred at `center`, reaching `half_extent` either side of it,
+// inside `min` to `max`
+fn clamp_view(center: Vec2, half_extent: Vec2, min: Vec2, max: Vec2) -> Vec2 {
+    let axis = |center: f32, min: f32, max: f32, half_extent: f32| {
+        if max - min <= 2. * half_extent {
+            (min + max) / 2.
+        } else {
+            center.clamp(min + half_extent, max - half_extent)
+        }
+    };
+    Vec2::new(
+        axis(center.x, min.x, max.x, half_extent.x),
+        axis(center.y, min.y, max.y, half_extent.y),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pixel_perfect::CameraZoom;
+
+    // Half the view at the default zoom
+    fn view() -> Vec2 {
+        CameraZoom::default().view_half_extent()
+    }
+
+    fn exact() -> CameraController {
+        CameraController {
+            deadzone: Vec2::ZERO,
+            look_ahead_time: 0.,
+            speed: f32::INFINITY,
+            ..default()
+        }
+    }
+
+    #[test]
+    fn first_update_snaps_to_the_target() {
+        let mut controller = CameraController::default();
+        let camera = controller.follow(
+            Vec2::ZERO,
+            Vec2::new(40., 3.),
+            Vec2::ZERO,
+            None,
+            view(),
+            0.01,
+        );
+        assert_eq!(camera, Vec2::new(40., 3.));
+    }
+
+    #[test]
+    fn moving_inside_the_deadzone_holds_the_camera_still() {
+        let mut controller = CameraController {
+            speed: f32::INFINITY,
+            ..default()
+        };
+        let start = controller.follow(Vec2::ZERO, Vec2::ZERO, Vec2::ZERO, None, view(), 0.01);
+        let inside = controller.follow(start, Vec2::new(0.9, -1.4), Vec2::ZERO, None, view(), 0.01);
+        assert_eq!(inside, Vec2::ZERO);
+        // Leaving it drags the focus along by the overshoot
+        let outside = controller.follow(inside, Vec2::new(3., 0.), Vec2::ZERO, None, view(), 0.01);
+        assert_eq!(outside, Vec2::new(2., 0.));
+    }
+
+    #[test]
+    fn look_ahead_is_capped() {
+        let mut controller = CameraController {
+            look_ahead_time: 1.,
+            max_look_ahead: 2.,
+            ..exact()
+        };
+        let camera = controller.follow(
+            Vec2::ZERO,
+            Vec2::ZERO,
+            Vec2::new(-10., 5.),
+            None,
+            view(),
+            0.01,
+        );
+        assert_eq!(camera, Vec2::new(-2., 0.));
+    }
+
+    #[test]
+    fn smoothing_eases_part_of_the_way() {
+        let mut controller = CameraController {
+            speed: 8.,
+            ..exact()
+        };
+        controller.follow(Vec2::ZERO, Vec2::ZERO, Vec2::ZERO, None, view(), 0.01);
+        let camera = controller.follow(
+            Vec2::ZERO,
+            Vec2::new(10., 0.),
+            Vec2::ZERO,
+            None,
+            view(),
+            0.1,
+        );
+        assert!(camera.x > 0. && camera.x < 10.);
+    }
+
+    #[test]
+    fn clamp_holds_after_look_ahead() {
+        let mut controller = CameraController {
+            look_ahead_time: 1.,
+            max_look_ahead: 100.,
+            clamp_to_level: true,
+            ..exact()
+        };
+        let half_extent = view();
+        let level = (Vec2::ZERO, Vec2::new(100., 2. * half_extent.y - 1.));
+        let camera = controller.follow(
+            Vec2::ZERO,
+            Vec2::new(90., 0.),
+            Vec2::new(50., 0.),
+            Some(level),
+            half_extent,
+            0.01,
+        );
+        // Pushed against the right edge, and centred on the short axis
+        assert_eq!(camera.x, 100. - half_extent.x);
+        assert_eq!(camera.y, level.1.y / 2.);
+    }
+
+    #[test]
+    fn zoomed_out_clamp_keeps_the_wider_view_inside() {
+        let mut controller = CameraController {
+            clamp_to_level: true,
+            ..exact()
+        };
+        let mut zoom = CameraZoom::default();
+        zoom.target = 0.5;
+        zoom.snap();
+        let half_extent = zoom.view_half_extent();
+        assert_eq!(half_extent, 2. * view());
+        let level = (Vec2::ZERO, Vec2::splat(100.));
+        let camera = controller.follow(
+            Vec2::ZERO,
+            Vec2::splat(99.),
+            Vec2::ZERO,
+            Some(level),
+            half_extent,
+            0.01,
+        );
+        assert_eq!(camera, level.1 - half_extent);
+    }
+}
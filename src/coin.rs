@@ -0,0 +1,105 @@
+// Coins placed in a level, collected by players touching them. The level's
+// coins come back whenever it is spawned again.
+
+use bevy::prelude::*;
+use bevy::sprite::Anchor;
+
+use crate::death::LivePlayerQuery;
+use crate::level::{LevelEntity, Unspawned};
+use crate::physics::{hitbox, overlaps};
+
+pub const COIN_TEXTURE: &str = "coin.png";
+// Side of a coin, in tiles. It sits in the middle of its cell.
+const COIN_SIZE: f32 = 0.5;
+
+#[derive(Component)]
+pub struct Coin;
+
+// How many coins have been collected
+#[derive(Default)]
+pub struct CoinCount(pub u32);
+
+pub struct CoinCollected {
+    pub player: Entity,
+    pub at: Vec2,
+}
+
+// Drawn with `texture`, usually `COIN_TEXTURE`
+pub fn spawn_coin(commands: &mut Commands, texture: Handle<Image>, cell: IVec2) -> Entity {
+    let margin = (1. - COIN_SIZE) / 2.;
+    commands
+        .spawn_bundle(SpriteBundle {
+            transform: Transform {
+                translation: (cell.as_vec2() + Vec2::splat(margin)).extend(0.),
+                scale: Vec3::new(COIN_SIZE, COIN_SIZE, 1.),
+                ..default()
+            },
+            sprite: Sprite {
+                custom_size: Some(Vec2::ONE),
+                anchor: Anchor::BottomLeft,
+                ..default()
+            },
+            texture,
+            ..default()
+        })
+        .insert(Coin)
+        .insert(LevelEntity)
+        .id()
+}
+
+// The cell a coin was placed in
+pub fn coin_cell(transform: &Transform) -> IVec2 {
+    let margin = (1. - COIN_SIZE) / 2.;
+    (transform.translation.truncate() - Vec2::splat(margin))
+        .round()
+        .as_ivec2()
+}
+
+pub fn coin_pickup_system(
+    mut commands: Commands,
+    player_query: LivePlayerQuery,
+    coin_query: Query<(Entity, &Transform), With<Coin>>,
+    mut coin_count: ResMut<CoinCount>,
+    mut unspawned: ResMut<Unspawned>,
+    mut collected_events: EventWriter<CoinCollected>,
+) {
+    for (coin, coin_transform) in coin_query.iter() {
+        let (coin_min, coin_size) = hitbox(coin_transform);
+        let collector = player_query
+            .iter()
+            .find(|(_, transform)| overlaps(hitbox(transform), (coin_min, coin_size)));
+        if let Some((player, _)) = collector {
+            commands.entity(coin).despawn_recursive();
+            unspawned.0.coins.push(coin_cell(coin_transform));
+            coin_count.0 += 1;
+            collected_events.send(CoinCollected {
+                player,
+                at: coin_min + coin_size / 2.,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::headless::{floor, HeadlessGame};
+    use crate::input::Action;
+    use crate::level::LevelData;
+
+    #[test]
+    fn walking_through_coins_collects_each_once() {
+        let mut game = HeadlessGame::new();
+        let level = LevelData {
+            coins: vec![IVec2::new(2, 1), IVec2::new(4, 1), IVec2::new(4, 5)],
+            ..floor(-2..=8)
+        };
+        game.spawn_level(&level);
+        game.place_player(Vec2::new(0., 1.));
+        game.run(120, &[Action::MoveRight]);
+        assert_eq!(game.app.world.resource::<CoinCount>().0, 2);
+        let mut coins = game.app.world.query_filtered::<&Transform, With<Coin>>();
+        let left: Vec<IVec2> = coins.iter(&game.app.world).map(coin_cell).collect();
+        assert_eq!(left, vec![IVec2::new(4, 5)]);
+    }
+}
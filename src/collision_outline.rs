@@ -0,0 +1,200 @@
+// An outline of the solid tiles as they collide: only the edges bounding solid
+// space, joined into continuous loops, so the silhouette a structure presents
+// to bodies can be seen at a glance. Edges shared between neighbouring tiles
+// don't collide, so aren't drawn.
+//
+// Toggled with `Action::ToggleCollisionOutline`, and rebuilt whenever a solid
+// tile is added, moved, reshaped or removed while shown. The lines are sprites
+// on the world layer, so they are drawn by the `WorldCamera` over the tiles.
+//
+// The hitboxes of the bodies colliding with the tiles are outlined too, over
+// whatever they are drawn with, since a sprite or animation frame needn't
+// match them.
+
+use bevy::prelude::*;
+
+use crate::input::{Action, ActionState};
+use crate::physics::{SolidTiles, TileCollider};
+use crate::pixel_perfect::PIXELS_PER_TILE;
+use crate::tile::{ColliderShape, SolidCollider};
+
+// In front of the tiles, particles and tile flashes, behind the editor's
+// overlays
+const OUTLINE_Z: f32 = 8.5;
+
+pub struct CollisionOutline {
+    pub shown: bool,
+    pub color: Color,
+}
+
+impl Default for CollisionOutline {
+    fn default() -> Self {
+        CollisionOutline {
+            shown: false,
+            color: Color::rgb(1., 0.2, 0.6),
+        }
+    }
+}
+
+// Parent of the outline's lines
+#[derive(Component)]
+struct OutlineRoot;
+
+// One side of the outline around `body`'s hitbox: bottom, right, top or left
+#[derive(Component)]
+struct BodyOutline {
+    body: Entity,
+    side: usize,
+}
+
+// Needs the `ActionState` from `InputMapPlugin`
+#[derive(Default)]
+pub struct CollisionOutlinePlugin;
+
+impl Plugin for CollisionOutlinePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<CollisionOutline>()
+            .add_system(toggle_outline_system)
+            .add_system(outline_system.after(toggle_outline_system))
+            .add_system(body_outline_system.after(toggle_outline_system));
+    }
+}
+
+fn toggle_outline_system(action_state: Res<ActionState>, mut outline: ResMut<CollisionOutline>) {
+    if action_state.just_pressed(Action::ToggleCollisionOutline) {
+        outline.shown = !outline.shown;
+        info!(
+            "Collision outline: {}",
+            if outline.shown { "on" } else { "off" }
+        );
+    }
+}
+
+// Solids just added, moved or reshaped
+type ChangedSolidQuery<'w, 's> = Query<
+    'w,
+    's,
+    (),
+    (
+        With<SolidCollider>,
+        Or<(
+            Added<SolidCollider>,
+            Changed<Transform>,
+            Changed<ColliderShape>,
+        )>,
+    ),
+>;
+
+fn outline_system(
+    mut commands: Commands,
+    outline: Res<CollisionOutline>,
+    solid_query: Query<(&Transform, Option<&ColliderShape>), With<SolidCollider>>,
+    changed_query: ChangedSolidQuery,
+    removed: RemovedComponents<SolidCollider>,
+    root_query: Query<Entity, With<OutlineRoot>>,
+) {
+    let solids_changed = !changed_query.is_empty() || removed.iter().next().is_some();
+    if !(outline.is_changed() || outline.shown && solids_changed) {
+        return;
+    }
+    if let Ok(root) = root_query.get_single() {
+        commands.entity(root).despawn_recursive();
+    }
+    if !outline.shown {
+        return;
+    }
+
+    let solids = SolidTiles::new(solid_query.iter().map(|(transform, shape)| {
+        (
+            transform.translation.truncate().round().as_ivec2(),
+            shape.copied().unwrap_or_default(),
+        )
+    }));
+    let thickness = 1. / PIXELS_PER_TILE as f32;
+    commands
+        .spawn_bundle(TransformBundle::default())
+        .insert(OutlineRoot)
+        .with_children(|parent| {
+            for corners in solids.boundary_loops() {
+                let n = corners.len();
+                for i in 0..n {
+                    let (start, end) = (corners[i].as_vec2(), corners[(i + 1) % n].as_vec2());
+                    let run = end - start;
+                    // Lengthened by the thickness so lines overlap at corners
+                    // rather than leaving notches
+                    parent.spawn_bundle(SpriteBundle {
+                        sprite: Sprite {
+                            color: outline.color,
+                            custom_size: Some(Vec2::new(run.length() + thickness, thickness)),
+                            ..default()
+                        },
+                        transform: Transform {
+                            translation: ((start + end) / 2.).extend(OUTLINE_Z),
+                            rotation: Quat::from_rotation_z(run.y.atan2(run.x)),
+                            ..default()
+                        },
+                        ..default()
+                    });
+                }
+            }
+        });
+}
+
+// Every frame while shown, since bodies move and change size
+fn body_outline_system(
+    mut commands: Commands,
+    outline: Res<CollisionOutline>,
+    body_query: Query<(Entity, &Transform), With<TileCollider>>,
+    mut line_query: Query<
+        (Entity, &BodyOutline, &mut Transform, &mut Sprite),
+        Without<TileCollider>,
+    >,
+) {
+    for (line, &BodyOutline { body, .. }, _, _) in line_query.iter() {
+        if !outline.shown || !body_query.contains(body) {
+            commands.entity(line).despawn();
+        }
+    }
+    if !outline.shown {
+        return;
+    }
+    let thickness = 1. / PIXELS_PER_TILE as f32;
+    for (body, body_transform) in body_query.iter() {
+        if line_query.iter().all(|(_, line, _, _)| line.body != body) {
+            for side in 0..4 {
+                commands
+                    .spawn_bundle(SpriteBundle::default())
+                    .insert(BodyOutline { body, side });
+            }
+        }
+        let min = body_transform.translation.truncate();
+        let size = body_transform.scale.truncate();
+        for (_, line, mut transform, mut sprite) in line_query.iter_mut() {
+            if line.body != body {
+                continue;
+            }
+            // Inside the hitbox, so it shows where it ends
+            let (center, line_size) = match line.side {
+                0 => (
+                    Vec2::new(size.x / 2., thickness / 2.),
+                    Vec2::new(size.x, thickness),
+                ),
+                1 => (
+                    Vec2::new(size.x - thickness / 2., size.y / 2.),
+                    Vec2::new(thickness, size.y),
+                ),
+                2 => (
+                    Vec2::new(size.x / 2., size.y - thickness / 2.),
+                    Vec2::new(size.x, thickness),
+                ),
+                _ => (
+                    Vec2::new(thickness / 2., size.y / 2.),
+                    Vec2::new(thickness, size.y),
+                ),
+            };
+            *transform = Transform::from_translation((min + center).extend(OUTLINE_Z));
+            sprite.color = outline.color;
+            sprite.custom_size = Some(line_size);
+        }
+    }
+}
@@ -0,0 +1,351 @@
+// Mapping between window coordinates and world coordinates.
+//
+// The world render target is drawn as a quad which fills the window's height
+// and is centered horizontally, so the mapping only depends on the window's
+// size and where the world camera is and how far it is zoomed in.
+
+use bevy::input::mouse::MouseButtonInput;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::debug::DebugMode;
+use crate::pixel_perfect::{CameraZoom, WorldCamera, HEIGHT_PIXELS, PIXELS_PER_TILE, WIDTH_PIXELS};
+use crate::settings::Settings;
+use crate::tile::{CellGrid, TILE_GRID};
+
+// Seconds without mouse activity after which the cursor is hidden during play
+pub const CURSOR_HIDE_DELAY: f32 = 2.;
+
+pub struct ScreenToWorld {
+    world_offset: Vec2,
+    screen_dimensions: Vec2,
+    zoom: f32,
+}
+
+impl Default for ScreenToWorld {
+    fn default() -> Self {
+        ScreenToWorld::new()
+    }
+}
+
+impl ScreenToWorld {
+    pub fn new() -> Self {
+        ScreenToWorld {
+            screen_dimensions: Vec2::ONE,
+            world_offset: Vec2::ZERO,
+            zoom: 1.,
+        }
+    }
+
+    // Update the width and height of the screen in logical pixels
+    pub fn set_screen_dimensions(&mut self, dimensions: Vec2) {
+        self.screen_dimensions = dimensions;
+    }
+
+    // Update the center of screen in world coordinates
+    pub fn set_world_offset(&mut self, offset: Vec2) {
+        self.world_offset = offset;
+    }
+
+    // Update how far the world camera is zoomed in, as `CameraZoom::current`
+    pub fn set_zoom(&mut self, zoom: f32) {
+        self.zoom = zoom;
+    }
+
+    // Width of the part of the screen covered by the world
+    fn cropped_width(&self) -> f32 {
+        self.screen_dimensions.y * WIDTH_PIXELS as f32 / HEIGHT_PIXELS as f32
+    }
+
+    // Half the size of the view in world units
+    fn half_extent(&self) -> Vec2 {
+        Vec2::new(WIDTH_PIXELS as f32, HEIGHT_PIXELS as f32)
+            / (2. * PIXELS_PER_TILE as f32 * self.zoom)
+    }
+
+    // Screen point in logical pixels (origin at the bottom-left) to world point
+    pub fn transform(&self, point: Vec2) -> Vec2 {
+        let dim = &self.screen_dimensions;
+        let cropped_width = self.cropped_width();
+        let cropped_x = point.x - (dim.x - cropped_width) / 2.;
+        let half_extent = self.half_extent();
+        Vec2::new(
+            ((2. * cropped_x / cropped_width) - 1.) * half_extent.x + self.world_offset.x,
+            ((2. * point.y / dim.y) - 1.) * half_extent.y + self.world_offset.y,
+        )
+    }
+
+    // World point to screen point in logical pixels, the inverse of `transform`
+    pub fn inverse(&self, world: Vec2) -> Vec2 {
+        let dim = &self.screen_dimensions;
+        let cropped_width = self.cropped_width();
+        let normalized = (world - self.world_offset) / self.half_extent();
+        Vec2::new(
+            (normalized.x + 1.) * cropped_width / 2. + (dim.x - cropped_width) / 2.,
+            (normalized.y + 1.) * dim.y / 2.,
+        )
+    }
+}
+
+// The cursor's position in world coordinates, or None when it is outside the
+// window. Updated at the start of every frame.
+#[derive(Default)]
+pub struct CursorWorldPos(pub Option<Vec2>);
+
+impl CursorWorldPos {
+    // The tile cell under the cursor
+    pub fn cell(&self, rounding: CellRounding) -> Option<IVec2> {
+        self.0.map(|cursor| rounding.cell(&TILE_GRID, cursor))
+    }
+}
+
+// How a point in the world is turned into the cell it picks.
+//
+// Only `Floor` always picks the cell whose tile is drawn under the point,
+// whatever the grid's anchor. `Nearest` agrees with it everywhere except
+// exactly on an edge, where it rounds away from the origin, so edges left of
+// or below the origin pick the other side from those right of or above it.
+// `Round` picks by the tiles' anchor points, so with bottom-left anchors it
+// is half a cell off from the tiles drawn.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CellRounding {
+    // The cell whose tile covers the point
+    #[default]
+    Floor,
+    // The cell whose anchor point is nearest
+    Round,
+    // The cell whose center is nearest
+    Nearest,
+}
+
+impl CellRounding {
+    pub fn cell(self, grid: &CellGrid, point: Vec2) -> IVec2 {
+        let point = point / grid.size;
+        match self {
+            CellRounding::Floor => (point + grid.anchor).floor(),
+            CellRounding::Round => point.round(),
+            CellRounding::Nearest => (point + grid.anchor - 0.5).round(),
+        }
+        .as_ivec2()
+    }
+}
+
+#[derive(Default)]
+pub struct CursorPlugin;
+
+impl Plugin for CursorPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ScreenToWorld>()
+            .init_resource::<CursorWorldPos>()
+            .add_system_to_stage(CoreStage::PreUpdate, update_screen_to_world_system)
+            .add_system_to_stage(
+                CoreStage::PreUpdate,
+                cursor_world_pos_system.after(update_screen_to_world_system),
+            );
+    }
+}
+
+// Seconds since the mouse was last moved or clicked
+#[derive(Default)]
+struct MouseIdle(f32);
+
+// Hides the OS cursor once the mouse has been left alone for a while during
+// play, and shows it again on any mouse activity or while editing in debug
+// mode. With the `confine_cursor` setting it is also kept inside the window
+// while editing. The window losing focus always releases it. Does nothing on
+// wasm, where the browser manages the cursor.
+//
+// Needs the `DebugMode` from `DebugModePlugin` and the `Settings` resource
+#[derive(Default)]
+pub struct CursorGrabPlugin;
+
+impl Plugin for CursorGrabPlugin {
+    fn build(&self, app: &mut App) {
+        if cfg!(target_arch = "wasm32") {
+            return;
+        }
+        app.init_resource::<MouseIdle>()
+            .add_system(cursor_grab_system);
+    }
+}
+
+fn cursor_grab_system(
+    time: Res<Time>,
+    debug_mode: Res<DebugMode>,
+    settings: Res<Settings>,
+    mut idle: ResMut<MouseIdle>,
+    mut cursor_moved_events: EventReader<CursorMoved>,
+    mut mouse_button_events: EventReader<MouseButtonInput>,
+    mut windows: ResMut<Windows>,
+) {
+    // Both readers must be drained every frame
+    let moved = cursor_moved_events.iter().count() > 0;
+    let clicked = mouse_button_events.iter().count() > 0;
+    if moved || clicked {
+        idle.0 = 0.;
+    } else {
+        idle.0 += time.delta_seconds();
+    }
+
+    let window = match windows.get_primary_mut() {
+        Some(window) => window,
+        None => return,
+    };
+    let editing = debug_mode.0;
+    let focused = window.is_focused();
+    let visible = editing || !focused || idle.0 < CURSOR_HIDE_DELAY;
+    let locked = editing && focused && settings.confine_cursor;
+    // Each call queues a window command, so only make the ones that change something
+    if window.cursor_visible() != visible {
+        window.set_cursor_visibility(visible);
+    }
+    if window.cursor_locked() != locked {
+        window.set_cursor_lock_mode(locked);
+    }
+}
+
+fn update_screen_to_world_system(
+    mut screen_to_world: ResMut<ScreenToWorld>,
+    windows: Res<Windows>,
+    zoom: Option<Res<CameraZoom>>,
+    camera_query: Query<&Transform, With<WorldCamera>>,
+) {
+    // The zoom the camera was last drawn at, rather than its target, so the
+    // cursor stays on what it is over while the zoom eases
+    if let Some(zoom) = zoom {
+        screen_to_world.set_zoom(zoom.current());
+    }
+    if let Some(window) = windows.get_primary() {
+        screen_to_world.set_screen_dimensions(Vec2::new(window.width(), window.height()));
+    }
+    if let Ok(transform) = camera_query.get_single() {
+        screen_to_world.set_world_offset(transform.translation.truncate());
+    }
+}
+
+fn cursor_world_pos_system(
+    screen_to_world: Res<ScreenToWorld>,
+    windows: Res<Windows>,
+    mut cursor_world_pos: ResMut<CursorWorldPos>,
+) {
+    let cursor = windows
+        .get_primary()
+        .and_then(|window| window.cursor_position())
+        .map(|cursor| screen_to_world.transform(cursor));
+    // Avoid triggering change detection every frame the cursor is still
+    if cursor_world_pos.0 != cursor {
+        cursor_world_pos.0 = cursor;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn inverse_undoes_transform() {
+        // Wider, exactly matching, and narrower than the world's aspect ratio
+        for dimensions in [
+            Vec2::new(1920., 1080.),
+            Vec2::new(2560., 1080.),
+            Vec2::new(1024., 768.),
+            Vec2::new(300., 900.),
+        ] {
+            let mut screen_to_world = ScreenToWorld::new();
+            screen_to_world.set_screen_dimensions(dimensions);
+            screen_to_world.set_world_offset(Vec2::new(-12.25, 3.5));
+            for point in [
+                Vec2::ZERO,
+                dimensions / 2.,
+                dimensions,
+                Vec2::new(17., dimensions.y - 3.),
+            ] {
+                let round_trip = screen_to_world.inverse(screen_to_world.transform(point));
+                assert!(
+                    round_trip.abs_diff_eq(point, 1e-3),
+                    "{:?} became {:?} at {:?}",
+                    point,
+                    round_trip,
+                    dimensions
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn screen_center_is_world_offset() {
+        let mut screen_to_world = ScreenToWorld::new();
+        screen_to_world.set_screen_dimensions(Vec2::new(2560., 1080.));
+        screen_to_world.set_world_offset(Vec2::new(4., -2.));
+        assert!(screen_to_world
+            .transform(Vec2::new(1280., 540.))
+            .abs_diff_eq(Vec2::new(4., -2.), 1e-5));
+    }
+
+    #[test]
+    fn zooming_in_shrinks_the_view_around_its_center() {
+        let mut screen_to_world = ScreenToWorld::new();
+        screen_to_world.set_screen_dimensions(Vec2::new(1920., 1080.));
+        screen_to_world.set_world_offset(Vec2::new(4., -2.));
+        let corner = screen_to_world.transform(Vec2::ZERO);
+        let mut zoom = CameraZoom::default();
+        zoom.target = 2.;
+        zoom.advance(1. / 60.);
+        assert!(zoom.current() > 1. && zoom.current() < 2.);
+        zoom.snap();
+        screen_to_world.set_zoom(zoom.current());
+        let zoomed_corner = screen_to_world.transform(Vec2::ZERO);
+        assert!(zoomed_corner.abs_diff_eq((corner + Vec2::new(4., -2.)) / 2., 1e-4));
+        assert!(screen_to_world
+            .inverse(zoomed_corner)
+            .abs_diff_eq(Vec2::ZERO, 1e-3));
+    }
+
+    // Check each point picks its cells by Floor, Round and Nearest, in that order
+    fn assert_cells(grid: &CellGrid, cases: &[(Vec2, [(i32, i32); 3])]) {
+        let roundings = [
+            CellRounding::Floor,
+            CellRounding::Round,
+            CellRounding::Nearest,
+        ];
+        for &(point, cells) in cases {
+            for (rounding, cell) in roundings.into_iter().zip(cells) {
+                assert_eq!(
+                    rounding.cell(grid, point),
+                    IVec2::from(cell),
+                    "{:?} at {:?}",
+                    rounding,
+                    point
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn cells_under_points_on_the_tile_grid() {
+        let cases = [
+            (Vec2::new(0.2, 0.2), [(0, 0), (0, 0), (0, 0)]),
+            (Vec2::new(0.7, 0.7), [(0, 0), (1, 1), (0, 0)]),
+            (Vec2::new(-0.3, 2.5), [(-1, 2), (0, 3), (-1, 2)]),
+            // Exactly on edges, right of and left of the origin
+            (Vec2::new(1., 1.), [(1, 1), (1, 1), (1, 1)]),
+            (Vec2::new(-1., -1.), [(-1, -1), (-1, -1), (-2, -2)]),
+        ];
+        assert_cells(&TILE_GRID, &cases);
+    }
+
+    #[test]
+    fn floor_picks_the_covering_cell_of_larger_centered_tiles() {
+        // Each tile covers a cell 2 units wide around its position
+        let grid = CellGrid {
+            size: 2.,
+            anchor: Vec2::splat(0.5),
+        };
+        let cases = [
+            (Vec2::new(0.9, -0.9), [(0, 0), (0, 0), (0, 0)]),
+            (Vec2::new(1.2, 3.5), [(1, 2), (1, 2), (1, 2)]),
+            (Vec2::new(-1., 2.9), [(0, 1), (-1, 1), (-1, 1)]),
+        ];
+        assert_cells(&grid, &cases);
+    }
+}
@@ -0,0 +1,251 @@
+// Players die by falling below the level or running out of health, which
+// hazards take away. A dead player fades out where they died, then fades back
+// in at the spawn, and can't be controlled until they have.
+//
+// The camera holds still through the fade out, and only moves to the spawn
+// once the fade in begins.
+
+use bevy::prelude::*;
+use bevy::utils::HashSet;
+
+use crate::game_state::PhysicsSubsteps;
+use crate::health::{Damage, Health, Invincible};
+use crate::ledge_grab::Hanging;
+use crate::level::PlayerSpawn;
+use crate::physics::{Mobility, Velocity};
+use crate::player::{spawn_point, Player};
+use crate::tile::{Hazard, TileIndex};
+
+// How far below the lowest tile a player has fallen out of the level, in tiles
+pub const KILL_PLANE_DEPTH: f32 = 8.;
+// Seconds spent fading out where the player died, then fading in at the spawn
+pub const DEATH_FADE_OUT_TIME: f32 = 0.5;
+pub const DEATH_FADE_IN_TIME: f32 = 0.3;
+// Health taken by touching a hazard
+pub const HAZARD_DAMAGE: i32 = 1;
+
+// A player has died. Sent at most once per death.
+pub struct PlayerDied {
+    pub player: Entity,
+}
+
+// How many times the players have died
+#[derive(Default)]
+pub struct DeathCount(pub u32);
+
+// A player in the death sequence
+#[derive(Component)]
+pub struct Dying {
+    // Where they died, where they are held until they respawn
+    at: Vec3,
+    // Seconds since they died
+    elapsed: f32,
+}
+
+impl Dying {
+    // Whether they are back at the spawn, fading in
+    pub fn respawned(&self) -> bool {
+        self.elapsed >= DEATH_FADE_OUT_TIME
+    }
+
+    // How visible they are, from 1 down to 0 and back up
+    fn alpha(&self) -> f32 {
+        if self.respawned() {
+            ((self.elapsed - DEATH_FADE_OUT_TIME) / DEATH_FADE_IN_TIME).min(1.)
+        } else {
+            1. - self.elapsed / DEATH_FADE_OUT_TIME
+        }
+    }
+}
+
+// The height below which players have fallen out of the level, if it has any
+// tiles
+pub fn kill_height(tile_index: &TileIndex) -> Option<f32> {
+    tile_index
+        .iter()
+        .map(|(cell, _)| cell.y)
+        .min()
+        .map(|lowest| lowest as f32 - KILL_PLANE_DEPTH)
+}
+
+// A hazard's cell touching the box with its bottom-left corner at `position`,
+// if any
+fn touched_hazard(
+    tile_index: &TileIndex,
+    hazard_query: &Query<(), With<Hazard>>,
+    position: Vec2,
+    size: Vec2,
+) -> Option<IVec2> {
+    // Resting against a hazard counts as touching it
+    const REACH: f32 = 0.01;
+    let min = (position - REACH).floor().as_ivec2();
+    let max = (position + size + REACH).ceil().as_ivec2() - IVec2::ONE;
+    (min.y..=max.y)
+        .flat_map(|y| (min.x..=max.x).map(move |x| IVec2::new(x, y)))
+        .find(|&cell| {
+            tile_index
+                .tile_at(cell)
+                .is_some_and(|tile| hazard_query.get(tile).is_ok())
+        })
+}
+
+// The players still in play, by where they are
+pub type LivePlayerQuery<'w, 's> =
+    Query<'w, 's, (Entity, &'static Transform), (With<Player>, Without<Dying>)>;
+
+// After the collision step, so contacts are those the player ends the step in
+pub fn death_check_system(
+    tile_index: Res<TileIndex>,
+    mut kill_plane: Local<Option<f32>>,
+    hazard_query: Query<(), With<Hazard>>,
+    player_query: LivePlayerQuery,
+    mut died_events: EventWriter<PlayerDied>,
+    mut damage_events: EventWriter<Damage>,
+) {
+    if tile_index.is_changed() {
+        *kill_plane = kill_height(&tile_index);
+    }
+    for (player, transform) in player_query.iter() {
+        let position = transform.translation.truncate();
+        if kill_plane.is_some_and(|kill_plane| position.y < kill_plane) {
+            died_events.send(PlayerDied { player });
+        } else if let Some(cell) = touched_hazard(
+            &tile_index,
+            &hazard_query,
+            position,
+            transform.scale.truncate(),
+        ) {
+            damage_events.send(Damage {
+                target: player,
+                amount: HAZARD_DAMAGE,
+                source: Some(cell.as_vec2() + 0.5),
+            });
+        }
+    }
+}
+
+// Every frame, since the steps which send `PlayerDied` may not run in the
+// frames which would read it
+pub fn start_dying_system(
+    mut commands: Commands,
+    mut died_events: EventReader<PlayerDied>,
+    player_query: Query<&Transform, (With<Player>, Without<Dying>)>,
+) {
+    // Several steps in a frame can each see the same death
+    let mut dying = HashSet::default();
+    for &PlayerDied { player } in died_events.iter() {
+        if let Ok(transform) = player_query.get(player) {
+            if dying.insert(player) {
+                commands
+                    .entity(player)
+                    .insert(Dying {
+                        at: transform.translation,
+                        elapsed: 0.,
+                    })
+                    .remove::<Invincible>()
+                    .remove::<Hanging>();
+            }
+        }
+    }
+}
+
+// The dying players, with everything put back once they respawn
+type DyingPlayerQuery<'w, 's> = Query<
+    'w,
+    's,
+    (
+        Entity,
+        &'static mut Dying,
+        &'static mut Transform,
+        &'static mut Velocity,
+        &'static mut Mobility,
+        Option<&'static mut Health>,
+        Option<&'static mut Sprite>,
+        Option<&'static mut TextureAtlasSprite>,
+    ),
+    With<Player>,
+>;
+
+// Once per input step, in place of the player's controls
+pub fn dying_system(
+    mut commands: Commands,
+    mut death_count: ResMut<DeathCount>,
+    substeps: Res<PhysicsSubsteps>,
+    spawn_query: Query<&Transform, (With<PlayerSpawn>, Without<Player>)>,
+    mut player_query: DyingPlayerQuery,
+) {
+    for (
+        player,
+        mut dying,
+        mut transform,
+        mut velocity,
+        mut mobility,
+        health,
+        sprite,
+        sheet_sprite,
+    ) in player_query.iter_mut()
+    {
+        let was_respawned = dying.respawned();
+        dying.elapsed += substeps.input_time_step();
+        velocity.0 = Vec3::ZERO;
+        if !dying.respawned() {
+            transform.translation = dying.at;
+        } else if !was_respawned {
+            transform.translation = spawn_point(spawn_query.iter().next()).extend(0.);
+            *mobility = mobility.at_rest();
+            if let Some(mut health) = health {
+                health.restore();
+            }
+            death_count.0 += 1;
+        }
+        if let Some(mut sprite) = sprite {
+            sprite.color.set_a(dying.alpha());
+        }
+        if let Some(mut sprite) = sheet_sprite {
+            sprite.color.set_a(dying.alpha());
+        }
+        if dying.elapsed >= DEATH_FADE_OUT_TIME + DEATH_FADE_IN_TIME {
+            commands.entity(player).remove::<Dying>();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::headless::{floor, HeadlessGame};
+    use crate::physics::PHYSICS_TIME_STEP;
+
+    #[test]
+    fn falling_out_of_the_level_respawns_once() {
+        let mut game = HeadlessGame::new();
+        game.spawn_level(&floor(-3..=3));
+        // Beside the floor, with nothing to land on
+        game.place_player(Vec2::new(10., 1.));
+        let mut died_at = None;
+        for _ in 0..1000 {
+            game.step(&[]);
+            if game.app.world.get::<Dying>(game.player()).is_some() {
+                game.step(&[]);
+                died_at = Some(game.player_transform().translation);
+                break;
+            }
+        }
+        let died_at = died_at.expect("the player never died");
+        assert!(died_at.y < -KILL_PLANE_DEPTH);
+
+        // Held where they died through the fade out, without dying again
+        let fade_out_steps = (DEATH_FADE_OUT_TIME / PHYSICS_TIME_STEP) as u32;
+        game.run(fade_out_steps - 3, &[]);
+        assert_eq!(game.player_transform().translation, died_at);
+        let fade_steps = ((DEATH_FADE_OUT_TIME + DEATH_FADE_IN_TIME) / PHYSICS_TIME_STEP) as u32;
+        game.run(fade_steps, &[]);
+        assert!(game.app.world.get::<Dying>(game.player()).is_none());
+        assert_eq!(game.app.world.resource::<DeathCount>().0, 1);
+        // Back at the start, on the floor
+        assert_eq!(
+            game.player_transform().translation.truncate(),
+            Vec2::new(0., 1.)
+        );
+    }
+}
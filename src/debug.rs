@@ -0,0 +1,26 @@
+// A global switch for debug visuals, such as showing invisible tiles.
+
+use bevy::prelude::*;
+
+use crate::input::{Action, ActionState};
+
+#[derive(Default)]
+pub struct DebugMode(pub bool);
+
+// Needs the `ActionState` from `InputMapPlugin`
+#[derive(Default)]
+pub struct DebugModePlugin;
+
+impl Plugin for DebugModePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<DebugMode>()
+            .add_system(toggle_debug_mode_system);
+    }
+}
+
+fn toggle_debug_mode_system(action_state: Res<ActionState>, mut debug_mode: ResMut<DebugMode>) {
+    if action_state.just_pressed(Action::ToggleDebug) {
+        debug_mode.0 = !debug_mode.0;
+        info!("Debug mode: {}", if debug_mode.0 { "on" } else { "off" });
+    }
+}
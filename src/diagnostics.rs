@@ -0,0 +1,166 @@
+// An overlay with performance counters, toggled with F3.
+//
+// It is drawn by bevy_ui at the window's native resolution, so it stays
+// readable at any render scale. In wasm release builds the plugin does
+// nothing at all, so players don't pay for measurements they can't see.
+
+use bevy::diagnostic::{Diagnostics, FrameTimeDiagnosticsPlugin};
+use bevy::prelude::*;
+use std::fmt::Write;
+
+use crate::display::{present_mode_name, DisplaySettings};
+use crate::physics::{PhysicsStats, TileCollider};
+use crate::pixel_perfect::UI_FONT;
+use crate::tile::Tile;
+
+pub const TOGGLE_DIAGNOSTICS_KEY: KeyCode = KeyCode::F3;
+
+const ENABLED: bool = !cfg!(all(target_arch = "wasm32", not(debug_assertions)));
+
+// The overlay's text has a label section followed by a value section for
+// each of these, in this order
+const LABELS: [&str; 7] = [
+    "FPS: ",
+    "\nFrame time: ",
+    "\nTiles: ",
+    "\nBodies: ",
+    "\nPhysics steps: ",
+    "\nCollision time: ",
+    "\nPresent mode: ",
+];
+
+#[derive(Component)]
+struct DiagnosticsOverlay;
+
+#[derive(Default)]
+pub struct DiagnosticsOverlayPlugin;
+
+impl Plugin for DiagnosticsOverlayPlugin {
+    fn build(&self, app: &mut App) {
+        if !ENABLED {
+            return;
+        }
+        app.add_plugin(FrameTimeDiagnosticsPlugin)
+            .init_resource::<PhysicsStats>()
+            .add_startup_system(spawn_overlay)
+            .add_system_to_stage(CoreStage::First, start_physics_stats_frame_system)
+            .add_system(toggle_overlay_system)
+            .add_system(update_overlay_system.after(toggle_overlay_system));
+    }
+}
+
+fn start_physics_stats_frame_system(mut stats: ResMut<PhysicsStats>) {
+    stats.start_frame();
+}
+
+fn spawn_overlay(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let font = asset_server.load(UI_FONT);
+    let label_style = TextStyle {
+        font: font.clone(),
+        font_size: 16.,
+        color: Color::rgb(0.7, 0.7, 0.7),
+    };
+    let value_style = TextStyle {
+        font,
+        font_size: 16.,
+        color: Color::WHITE,
+    };
+    let sections = LABELS
+        .iter()
+        .flat_map(|label| {
+            [
+                TextSection {
+                    value: label.to_string(),
+                    style: label_style.clone(),
+                },
+                TextSection {
+                    value: String::with_capacity(16),
+                    style: value_style.clone(),
+                },
+            ]
+        })
+        .collect();
+    commands
+        .spawn_bundle(TextBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    left: Val::Px(4.),
+                    top: Val::Px(4.),
+                    ..default()
+                },
+                ..default()
+            },
+            text: Text {
+                sections,
+                ..default()
+            },
+            visibility: Visibility { is_visible: false },
+            ..default()
+        })
+        .insert(DiagnosticsOverlay);
+}
+
+fn toggle_overlay_system(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut query: Query<&mut Visibility, With<DiagnosticsOverlay>>,
+) {
+    if keyboard_input.just_pressed(TOGGLE_DIAGNOSTICS_KEY) {
+        for mut visibility in query.iter_mut() {
+            visibility.is_visible = !visibility.is_visible;
+        }
+    }
+}
+
+// Rewrites the value sections in place, reusing their strings
+fn update_overlay_system(
+    diagnostics: Res<Diagnostics>,
+    physics_stats: Res<PhysicsStats>,
+    display_settings: Res<DisplaySettings>,
+    tile_query: Query<(), With<Tile>>,
+    body_query: Query<(), With<TileCollider>>,
+    mut overlay_query: Query<(&mut Text, &Visibility), With<DiagnosticsOverlay>>,
+) {
+    let (mut text, visibility) = match overlay_query.get_single_mut() {
+        Ok(overlay) => overlay,
+        Err(_) => return,
+    };
+    if !visibility.is_visible {
+        return;
+    }
+    let fps = diagnostics
+        .get(FrameTimeDiagnosticsPlugin::FPS)
+        .and_then(|fps| fps.average());
+    let frame_time = diagnostics
+        .get(FrameTimeDiagnosticsPlugin::FRAME_TIME)
+        .and_then(|frame_time| frame_time.average());
+
+    for index in 0..LABELS.len() {
+        let value = &mut text.sections[2 * index + 1].value;
+        value.clear();
+        // Writing to a String can't fail
+        let _ = match index {
+            0 => match fps {
+                Some(fps) => write!(value, "{:.1}", fps),
+                None => write!(value, "-"),
+            },
+            1 => match frame_time {
+                Some(frame_time) => write!(value, "{:.2} ms", frame_time * 1000.),
+                None => write!(value, "-"),
+            },
+            2 => write!(value, "{}", tile_query.iter().count()),
+            3 => write!(value, "{}", body_query.iter().count()),
+            4 => write!(value, "{}", physics_stats.steps_last_frame),
+            5 => write!(
+                value,
+                "{:.3} ms",
+                physics_stats.collision_time_last_frame.as_secs_f64() * 1000.
+            ),
+            _ => write!(
+                value,
+                "{}",
+                present_mode_name(display_settings.present_mode)
+            ),
+        };
+    }
+}
@@ -0,0 +1,143 @@
+// Display options which can change while the game is running.
+//
+// Only the window is touched here; the pixel-perfect render target keeps
+// its size, so cursor mapping is unaffected by any of these settings.
+
+use bevy::prelude::*;
+use bevy::utils::{Duration, Instant};
+use bevy::window::{PresentMode, WindowMode};
+
+use crate::pixel_perfect::{HEIGHT_PIXELS, WIDTH_PIXELS};
+use crate::settings::Settings;
+
+pub const CYCLE_RENDER_SCALE_KEY: KeyCode = KeyCode::F2;
+pub const CYCLE_PRESENT_MODE_KEY: KeyCode = KeyCode::F4;
+pub const MAX_RENDER_SCALE: u32 = 3;
+
+pub struct DisplaySettings {
+    pub present_mode: PresentMode,
+    // Upper bound on rendered frames per second, on top of any vsync limit.
+    //
+    // Gameplay runs in fixed timesteps which catch up on however much time
+    // passed since the last frame, so the cap only changes how many steps
+    // run per frame, not what the steps compute. Input is still read once
+    // per frame though: at low caps several steps share one frame's input.
+    // Ignored on wasm, where the browser paces frames.
+    pub fps_cap: Option<f32>,
+    // Window pixels per world pixel. The window is sized to an exact multiple
+    // of the world render target, so the quad fills it with no bars.
+    // Ignored in fullscreen.
+    pub render_scale: u32,
+}
+
+impl Default for DisplaySettings {
+    fn default() -> Self {
+        DisplaySettings {
+            // Vsync on
+            present_mode: PresentMode::Fifo,
+            fps_cap: None,
+            render_scale: Settings::default().render_scale,
+        }
+    }
+}
+
+pub fn present_mode_name(present_mode: PresentMode) -> &'static str {
+    match present_mode {
+        PresentMode::Fifo => "Fifo (vsync)",
+        PresentMode::Mailbox => "Mailbox",
+        PresentMode::Immediate => "Immediate",
+    }
+}
+
+// Add after `DefaultPlugins`. The window is created with the
+// `WindowDescriptor`'s present mode, so set that from the same
+// `DisplaySettings` to avoid a switch on the first frame.
+#[derive(Default)]
+pub struct DisplayPlugin;
+
+impl Plugin for DisplayPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<DisplaySettings>()
+            .insert_resource(FrameLimiter {
+                last_frame: Instant::now(),
+            })
+            .init_resource::<Settings>()
+            .add_system(cycle_present_mode_system)
+            .add_system(cycle_render_scale_system)
+            .add_system(
+                apply_display_settings_system
+                    .after(cycle_present_mode_system)
+                    .after(cycle_render_scale_system),
+            )
+            .add_system_to_stage(CoreStage::Last, frame_limit_system);
+    }
+}
+
+fn cycle_present_mode_system(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut settings: ResMut<DisplaySettings>,
+) {
+    if keyboard_input.just_pressed(CYCLE_PRESENT_MODE_KEY) {
+        settings.present_mode = match settings.present_mode {
+            PresentMode::Fifo => PresentMode::Mailbox,
+            PresentMode::Mailbox => PresentMode::Immediate,
+            PresentMode::Immediate => PresentMode::Fifo,
+        };
+        info!("Present mode: {}", present_mode_name(settings.present_mode));
+    }
+}
+
+fn cycle_render_scale_system(
+    keyboard_input: Res<Input<KeyCode>>,
+    windows: Res<Windows>,
+    mut display_settings: ResMut<DisplaySettings>,
+    mut settings: ResMut<Settings>,
+) {
+    if !keyboard_input.just_pressed(CYCLE_RENDER_SCALE_KEY) {
+        return;
+    }
+    let windowed = windows
+        .get_primary()
+        .is_some_and(|window| window.mode() == WindowMode::Windowed);
+    if !windowed {
+        return;
+    }
+    display_settings.render_scale = display_settings.render_scale % MAX_RENDER_SCALE + 1;
+    info!("Render scale: {}x", display_settings.render_scale);
+    settings.render_scale = display_settings.render_scale;
+    settings.save();
+}
+
+fn apply_display_settings_system(settings: Res<DisplaySettings>, mut windows: ResMut<Windows>) {
+    if !settings.is_changed() {
+        return;
+    }
+    if let Some(window) = windows.get_primary_mut() {
+        if window.present_mode() != settings.present_mode {
+            window.set_present_mode(settings.present_mode);
+        }
+        if window.mode() == WindowMode::Windowed {
+            // The scale is in physical pixels, the resolution in logical ones
+            let scale = settings.render_scale.max(1) as f32 / window.scale_factor() as f32;
+            window.set_resolution(WIDTH_PIXELS as f32 * scale, HEIGHT_PIXELS as f32 * scale);
+        }
+    }
+}
+
+struct FrameLimiter {
+    last_frame: Instant,
+}
+
+// Sleep out the rest of the frame when running faster than the cap
+fn frame_limit_system(settings: Res<DisplaySettings>, mut limiter: ResMut<FrameLimiter>) {
+    if let Some(fps_cap) = settings.fps_cap {
+        if !cfg!(target_arch = "wasm32") && fps_cap > 0. {
+            let frame_time = Duration::from_secs_f32(1. / fps_cap);
+            let elapsed = limiter.last_frame.elapsed();
+            if elapsed < frame_time {
+                std::thread::sleep(frame_time - elapsed);
+            }
+        }
+    }
+    limiter.last_frame = Instant::now();
+}
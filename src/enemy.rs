@@ -0,0 +1,598 @@
+// Enemies, of two kinds. Walkers patrol a platform, walking back and forth
+// and turning around at walls and, unless told not to, at ledges. Flyers
+// ignore gravity, and either bob along between two points or chase players
+// they can see, going back to where they started once they can't. Both
+// collide with tiles like any other body.
+//
+// Touching an enemy from the side hurts; landing on one from above stomps it
+// and bounces off.
+
+use bevy::utils::HashSet;
+use bevy::{prelude::*, sprite::Anchor};
+use serde::{Deserialize, Serialize};
+use std::f32::consts::TAU;
+
+use crate::death::Dying;
+use crate::health::{Damage, Health};
+use crate::level::{LevelEntity, Unspawned};
+use crate::physics::{
+    hitbox, overlaps, CollisionLayers, Direction, Gravity, GravityScale, Mobility,
+    TerminalVelocity, TileCollider, Velocity, GRAVITY, PHYSICS_TIME_STEP,
+};
+use crate::player::Player;
+use crate::tile::{SolidCollider, TileIndex};
+
+pub const ENEMY_COLOR: Color = Color::rgb(0.9, 0.2, 0.2);
+pub const FLYING_ENEMY_COLOR: Color = Color::rgb(0.7, 0.3, 0.9);
+// Health taken by touching an enemy from the side
+pub const ENEMY_CONTACT_DAMAGE: i32 = 1;
+// The upwards speed a player bounces off a stomped enemy at
+pub const STOMP_BOUNCE_SPEED: f32 = 12.;
+// Shots it takes to defeat an enemy
+pub const ENEMY_HEALTH: i32 = 1;
+// Seconds after leaving the ground in which a walker still turns back from
+// a ledge, so one which stepped a little too far walks back onto it
+const LEDGE_COYOTE_TIME: f32 = 0.1;
+// How far above and below its path a bobbing flyer goes, in tiles, and the
+// seconds it takes to go up and down once
+const BOB_AMPLITUDE: f32 = 0.5;
+const BOB_PERIOD: f32 = 1.5;
+// How near a player has to be for a chasing flyer to see them, in tiles
+const CHASE_RANGE: f32 = 10.;
+// How quickly a chasing flyer changes its velocity, in tiles per second squared
+const CHASE_ACCELERATION: f32 = 20.;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum EnemyKind {
+    // Walks back and forth along a platform
+    #[default]
+    Walker,
+    // Flies back and forth between its cell and `span` cells to its right, or
+    // to its left if negative, bobbing up and down on the way
+    Bobber {
+        span: i32,
+    },
+    // Flies at players it has a clear line to, and back to its cell otherwise
+    Chaser,
+}
+
+// An enemy in a level file
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct EnemyData {
+    // The cell it starts in, with its bottom-left corner at its own
+    pub pos: IVec2,
+    #[serde(default)]
+    pub kind: EnemyKind,
+    // In tiles per second
+    #[serde(default = "default_patrol_speed")]
+    pub speed: f32,
+    // For walkers, whether it turns around rather than walking off the end of
+    // a platform
+    #[serde(default = "default_turns_at_ledges")]
+    pub turns_at_ledges: bool,
+    // For walkers which turn at ledges, where they look for ground
+    #[serde(default)]
+    pub ledge_probe: LedgeProbe,
+}
+
+// Where a walker looks for ground ahead of it: `ahead` tiles past its front
+// edge, down to `below` tiles under its feet. Any deeper is a ledge.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct LedgeProbe {
+    pub ahead: f32,
+    pub below: f32,
+}
+
+impl Default for LedgeProbe {
+    fn default() -> Self {
+        LedgeProbe {
+            ahead: 0.05,
+            below: 1.,
+        }
+    }
+}
+
+fn default_patrol_speed() -> f32 {
+    3.
+}
+
+fn default_turns_at_ledges() -> bool {
+    true
+}
+
+impl EnemyData {
+    pub fn new(pos: IVec2) -> Self {
+        EnemyData {
+            pos,
+            kind: EnemyKind::default(),
+            speed: default_patrol_speed(),
+            turns_at_ledges: default_turns_at_ledges(),
+            ledge_probe: LedgeProbe::default(),
+        }
+    }
+}
+
+#[derive(Component)]
+pub struct Enemy {
+    // How it was placed, which the level is saved with however far it has
+    // walked since
+    pub data: EnemyData,
+}
+
+// A player landed on an enemy, centred `at`, and despawned it
+pub struct EnemyStomped {
+    pub player: Entity,
+    pub at: Vec2,
+}
+
+#[derive(Component)]
+pub struct Patrol {
+    pub speed: f32,
+    pub turns_at_ledges: bool,
+    pub ledge_probe: LedgeProbe,
+    // 1 walking right, -1 walking left
+    pub direction: f32,
+    // Seconds since it was last on the ground
+    airborne_for: f32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum FlightPath {
+    // Between these horizontal positions, of its left edge
+    Bob { min_x: f32, max_x: f32 },
+    Chase,
+}
+
+#[derive(Component)]
+pub struct Flight {
+    path: FlightPath,
+    speed: f32,
+    // Where it started, where a chaser goes back to
+    anchor: Vec2,
+    // 1 flying right, -1 flying left, for bobbers
+    direction: f32,
+    // Seconds since it was spawned, for the bobbing
+    elapsed: f32,
+}
+
+pub fn spawn_enemy(commands: &mut Commands, data: &EnemyData) -> Entity {
+    let color = match data.kind {
+        EnemyKind::Walker => ENEMY_COLOR,
+        _ => FLYING_ENEMY_COLOR,
+    };
+    let mut enemy = commands.spawn_bundle(SpriteBundle {
+        transform: Transform::from_translation(data.pos.as_vec2().extend(0.)),
+        sprite: Sprite {
+            color,
+            anchor: Anchor::BottomLeft,
+            ..default()
+        },
+        ..default()
+    });
+    enemy
+        .insert(Enemy { data: data.clone() })
+        .insert(TileCollider)
+        .insert(CollisionLayers::on(CollisionLayers::ENEMIES))
+        .insert(Gravity(GRAVITY))
+        .insert(TerminalVelocity(40.))
+        .insert(enemy_mobility(data.speed))
+        .insert(Health::new(ENEMY_HEALTH))
+        .insert(LevelEntity);
+    let anchor = data.pos.as_vec2();
+    let flight_path = match data.kind {
+        EnemyKind::Walker => {
+            enemy
+                .insert(Patrol {
+                    speed: data.speed,
+                    turns_at_ledges: data.turns_at_ledges,
+                    ledge_probe: data.ledge_probe,
+                    direction: 1.,
+                    // Not turning while it drops from where it was placed
+                    airborne_for: LEDGE_COYOTE_TIME,
+                })
+                .insert(Velocity(Vec3::new(data.speed, 0., 0.)));
+            return enemy.id();
+        }
+        EnemyKind::Bobber { span } => {
+            let end = anchor.x + span as f32;
+            FlightPath::Bob {
+                min_x: anchor.x.min(end),
+                max_x: anchor.x.max(end),
+            }
+        }
+        EnemyKind::Chaser => FlightPath::Chase,
+    };
+    enemy
+        .insert(Flight {
+            path: flight_path,
+            speed: data.speed,
+            anchor,
+            direction: 1.,
+            elapsed: 0.,
+        })
+        .insert(Velocity(Vec3::ZERO))
+        .insert(GravityScale(0.))
+        .id()
+}
+
+// Only its contacts matter; its velocity is steered directly
+fn enemy_mobility(speed: f32) -> Mobility {
+    Mobility {
+        walk_speed: speed,
+        walk_direction: Direction::Right,
+        ..default()
+    }
+}
+
+// Whether there is ground where `probe` looks ahead of a body walking in
+// `direction`, with its bottom-left corner at `position`. Tiles count as
+// ground where `blocks` says so.
+pub fn ai_edge_check(
+    tile_index: &TileIndex,
+    blocks: impl Fn(Entity) -> bool,
+    position: Vec2,
+    size: Vec2,
+    probe: LedgeProbe,
+    direction: f32,
+) -> bool {
+    let front = if direction > 0. {
+        position.x + size.x
+    } else {
+        position.x
+    };
+    let x = front + direction * probe.ahead;
+    let rows = probe.below.ceil().max(1.) as u32;
+    tile_index
+        .ground_below(x, x, position.y, rows, blocks)
+        .is_some_and(|ground| ground.gap <= probe.below)
+}
+
+// After the collision step, so it turns on the contacts it ends the step with
+pub fn patrol_system(
+    tile_index: Res<TileIndex>,
+    solid_query: Query<(), With<SolidCollider>>,
+    mut query: Query<(&mut Patrol, &Transform, &mut Velocity, &mut Mobility)>,
+) {
+    for (mut patrol, transform, mut velocity, mut mobility) in query.iter_mut() {
+        patrol.airborne_for = if mobility.on_ground {
+            0.
+        } else {
+            patrol.airborne_for + PHYSICS_TIME_STEP
+        };
+        let at_ledge = patrol.turns_at_ledges
+            && patrol.airborne_for < LEDGE_COYOTE_TIME
+            && !ai_edge_check(
+                &tile_index,
+                |tile| solid_query.contains(tile),
+                transform.translation.truncate(),
+                transform.scale.truncate(),
+                patrol.ledge_probe,
+                patrol.direction,
+            );
+        // Only a wall it walked into stops it, not one it's dropping past
+        let blocked = mobility.on_wall && velocity.0.x * patrol.direction <= 0.;
+        if blocked || at_ledge {
+            patrol.direction = -patrol.direction;
+        }
+        // Once turned back, it doesn't turn again until it's on the ground,
+        // where it can look ahead from the ledge's edge
+        if at_ledge && !mobility.on_ground {
+            patrol.airborne_for = LEDGE_COYOTE_TIME;
+        }
+        mobility.walk_direction = if patrol.direction > 0. {
+            Direction::Right
+        } else {
+            Direction::Left
+        };
+        velocity.0.x = patrol.direction * patrol.speed;
+    }
+}
+
+// After the collision step, like the walkers' patrol
+pub fn flight_system(
+    tile_index: Res<TileIndex>,
+    solid_query: Query<(), With<SolidCollider>>,
+    player_query: Query<&Transform, (With<Player>, Without<Dying>)>,
+    mut query: Query<(&mut Flight, &Transform, &mut Velocity, &Mobility)>,
+) {
+    for (mut flight, transform, mut velocity, mobility) in query.iter_mut() {
+        flight.elapsed += PHYSICS_TIME_STEP;
+        let position = transform.translation.truncate();
+        match flight.path {
+            FlightPath::Bob { min_x, max_x } => {
+                if mobility.on_wall
+                    || (position.x >= max_x && flight.direction > 0.)
+                    || (position.x <= min_x && flight.direction < 0.)
+                {
+                    flight.direction = -flight.direction;
+                }
+                // The derivative of the bob's height, so it follows the sine
+                // however it has been pushed about
+                let angular_speed = TAU / BOB_PERIOD;
+                velocity.0.x = flight.direction * flight.speed;
+                velocity.0.y =
+                    BOB_AMPLITUDE * angular_speed * (angular_speed * flight.elapsed).cos();
+            }
+            FlightPath::Chase => {
+                let center = position + transform.scale.truncate() / 2.;
+                let target = player_query
+                    .iter()
+                    .map(|player| player.translation.truncate() + player.scale.truncate() / 2.)
+                    .filter(|&player| {
+                        player.distance(center) <= CHASE_RANGE
+                            && tile_index
+                                .raycast(center, player, |tile| solid_query.contains(tile))
+                                .is_none()
+                    })
+                    .min_by(|a, b| a.distance(center).total_cmp(&b.distance(center)));
+                // Slowing down on the way back, so it settles at the anchor
+                let desired = match target {
+                    Some(player) => (player - center).normalize_or_zero() * flight.speed,
+                    None => ((flight.anchor - position) * 4.).clamp_length_max(flight.speed),
+                };
+                let change = (desired - velocity.0.truncate())
+                    .clamp_length_max(CHASE_ACCELERATION * PHYSICS_TIME_STEP);
+                velocity.0 += change.extend(0.);
+            }
+        }
+    }
+}
+
+// The players still in play, who stomp an enemy by falling onto it
+type StomperQuery<'w, 's> = Query<
+    'w,
+    's,
+    (Entity, &'static Transform, &'static mut Velocity),
+    (With<Player>, Without<Dying>),
+>;
+
+// After the collision step. A player coming down onto the top half of an
+// enemy stomps it; any other touch hurts the player.
+pub fn enemy_contact_system(
+    mut commands: Commands,
+    mut player_query: StomperQuery,
+    enemy_query: Query<(Entity, &Enemy, &Transform), Without<Player>>,
+    mut unspawned: ResMut<Unspawned>,
+    mut damage_events: EventWriter<Damage>,
+    mut stomped_events: EventWriter<EnemyStomped>,
+) {
+    let mut stomped = HashSet::default();
+    for (player, player_transform, mut velocity) in player_query.iter_mut() {
+        let player_box = hitbox(player_transform);
+        let player_min = player_box.0;
+        for (enemy, data, enemy_transform) in enemy_query.iter() {
+            let (enemy_min, enemy_size) = hitbox(enemy_transform);
+            let touching = overlaps(player_box, (enemy_min, enemy_size));
+            if !touching || stomped.contains(&enemy) {
+                continue;
+            }
+            if velocity.0.y <= 0. && player_min.y >= enemy_min.y + enemy_size.y / 2. {
+                stomped.insert(enemy);
+                commands.entity(enemy).despawn_recursive();
+                unspawned.0.enemies.push(data.data.clone());
+                velocity.0.y = STOMP_BOUNCE_SPEED;
+                stomped_events.send(EnemyStomped {
+                    player,
+                    at: enemy_min + enemy_size / 2.,
+                });
+            } else {
+                damage_events.send(Damage {
+                    target: player,
+                    amount: ENEMY_CONTACT_DAMAGE,
+                    source: Some(enemy_min + enemy_size / 2.),
+                });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::headless::{floor, HeadlessGame};
+    use crate::health::Health;
+    use crate::level::{LevelData, TileData};
+
+    fn platform(enemies: Vec<EnemyData>) -> LevelData {
+        // A platform, and a separate one for the player to wait on
+        let mut level = LevelData {
+            enemies,
+            ..floor(-3..=3)
+        };
+        level.tiles.extend(floor(10..=12).tiles);
+        level
+    }
+
+    fn enemy_x(game: &mut HeadlessGame) -> Option<f32> {
+        game.app
+            .world
+            .query_filtered::<&Transform, With<Enemy>>()
+            .iter(&game.app.world)
+            .next()
+            .map(|transform| transform.translation.x)
+    }
+
+    #[test]
+    fn patrol_turns_at_ledges() {
+        let mut game = HeadlessGame::new();
+        game.spawn_level(&platform(vec![EnemyData::new(IVec2::new(0, 1))]));
+        game.place_player(Vec2::new(11., 1.));
+        let (mut min, mut max) = (0f32, 0f32);
+        for _ in 0..1200 {
+            game.step(&[]);
+            let x = enemy_x(&mut game).unwrap();
+            min = min.min(x);
+            max = max.max(x);
+        }
+        // Walked both ends of the platform without stepping off either
+        assert!((-3. ..-2.5).contains(&min), "walked left to {}", min);
+        assert!((2.5..=3.).contains(&max), "walked right to {}", max);
+    }
+
+    #[test]
+    fn deeper_probe_steps_down_a_tile() {
+        // A step down from x = 1 to a floor out to x = 6, and a separate
+        // platform for the player to wait on
+        let tiles: Vec<TileData> = (-3..=0)
+            .map(|x| IVec2::new(x, 1))
+            .chain((-3..=6).chain(10..=12).map(|x| IVec2::new(x, 0)))
+            .map(TileData::solid)
+            .collect();
+        let enemy_y = |probe: LedgeProbe| {
+            let mut game = HeadlessGame::new();
+            let mut walker = EnemyData::new(IVec2::new(-1, 2));
+            walker.ledge_probe = probe;
+            game.spawn_level(&LevelData {
+                tiles: Vec::clone(&tiles),
+                enemies: vec![walker],
+                ..default()
+            });
+            game.place_player(Vec2::new(11., 1.));
+            game.run(240, &[]);
+            game.app
+                .world
+                .query_filtered::<&Transform, With<Enemy>>()
+                .iter(&game.app.world)
+                .next()
+                .unwrap()
+                .translation
+                .y
+        };
+        let high = enemy_y(LedgeProbe::default());
+        assert!((high - 2.).abs() < 0.01, "walker ended at y = {}", high);
+        let low = enemy_y(LedgeProbe {
+            below: 2.,
+            ..default()
+        });
+        assert!((low - 1.).abs() < 0.01, "walker ended at y = {}", low);
+    }
+
+    #[test]
+    fn chaser_closes_in_on_a_player_in_sight() {
+        let mut game = HeadlessGame::new();
+        let mut chaser = EnemyData::new(IVec2::new(4, 5));
+        chaser.kind = EnemyKind::Chaser;
+        game.spawn_level(&platform(vec![chaser]));
+        game.place_player(Vec2::new(11., 1.));
+        let distance = |game: &mut HeadlessGame| {
+            let player = game.player_transform().translation;
+            let enemy = game
+                .app
+                .world
+                .query_filtered::<&Transform, With<Enemy>>()
+                .iter(&game.app.world)
+                .next()
+                .unwrap()
+                .translation;
+            player.distance(enemy)
+        };
+        let start = distance(&mut game);
+        game.run(240, &[]);
+        assert!(distance(&mut game) < start - 2.);
+    }
+
+    #[test]
+    fn landing_on_an_enemy_stomps_it() {
+        let mut game = HeadlessGame::new();
+        let mut enemy = EnemyData::new(IVec2::new(0, 1));
+        enemy.speed = 0.;
+        game.spawn_level(&platform(vec![enemy]));
+        game.place_player(Vec2::new(0., 4.));
+        let health = *game.app.world.get::<Health>(game.player()).unwrap();
+        for _ in 0..120 {
+            game.step(&[]);
+            if enemy_x(&mut game).is_none() {
+                break;
+            }
+        }
+        assert!(enemy_x(&mut game).is_none(), "the enemy wasn't stomped");
+        assert_eq!(
+            *game.app.world.get::<Health>(game.player()).unwrap(),
+            health
+        );
+    }
+
+    // Where the enemy's bottom-left corner was at each of `steps` steps
+    fn enemy_path(game: &mut HeadlessGame, steps: u32) -> Vec<Vec2> {
+        (0..steps)
+            .map(|_| {
+                game.step(&[]);
+                game.app
+                    .world
+                    .query_filtered::<&Transform, With<Enemy>>()
+                    .iter(&game.app.world)
+                    .next()
+                    .unwrap()
+                    .translation
+                    .truncate()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn bobber_flies_its_span_bobbing_up_and_down() {
+        let mut game = HeadlessGame::new();
+        let mut bobber = EnemyData::new(IVec2::new(-2, 3));
+        bobber.kind = EnemyKind::Bobber { span: 4 };
+        game.spawn_level(&platform(vec![bobber]));
+        game.place_player(Vec2::new(11., 1.));
+        let path = enemy_path(&mut game, 1200);
+        let (min, max) = path.iter().fold(
+            (Vec2::splat(f32::MAX), Vec2::splat(f32::MIN)),
+            |(min, max), &position| (min.min(position), max.max(position)),
+        );
+        // Out to either end, give or take a step past it before turning
+        assert!((min.x + 2.).abs() < 0.1, "flew left to {}", min.x);
+        assert!((max.x - 2.).abs() < 0.1, "flew right to {}", max.x);
+        // And up and down by the bob, without falling
+        assert!(
+            (min.y - (3. - BOB_AMPLITUDE)).abs() < 0.05,
+            "down to {}",
+            min.y
+        );
+        assert!(
+            (max.y - (3. + BOB_AMPLITUDE)).abs() < 0.05,
+            "up to {}",
+            max.y
+        );
+    }
+
+    #[test]
+    fn bobber_turns_at_a_wall_in_its_span() {
+        let mut game = HeadlessGame::new();
+        let mut bobber = EnemyData::new(IVec2::new(-2, 3));
+        bobber.kind = EnemyKind::Bobber { span: 8 };
+        let mut level = platform(vec![bobber]);
+        level
+            .tiles
+            .extend((1..6).map(|y| TileData::solid(IVec2::new(2, y))));
+        game.spawn_level(&level);
+        game.place_player(Vec2::new(11., 1.));
+        let width = game
+            .app
+            .world
+            .query_filtered::<&Transform, With<Enemy>>()
+            .iter(&game.app.world)
+            .next()
+            .unwrap()
+            .scale
+            .x;
+        let path = enemy_path(&mut game, 1200);
+        let max_x = path
+            .iter()
+            .map(|position| position.x)
+            .fold(f32::MIN, f32::max);
+        assert!(
+            max_x + width <= 2. + 1e-3,
+            "flew into the wall at {}",
+            max_x
+        );
+        // Back and forth between it and the start of its span
+        let min_x = path
+            .iter()
+            .map(|position| position.x)
+            .fold(f32::MAX, f32::min);
+        assert!((min_x + 2.).abs() < 0.1, "flew left to {}", min_x);
+        assert!((max_x + width - 2.).abs() < 1e-3, "turned at {}", max_x);
+    }
+}
@@ -0,0 +1,265 @@
+// Platforms which hold still until a player stands on one, then shake for a
+// moment and drop. Resting, a platform is a one-tile solid like any other,
+// though it isn't in the `TileIndex`. Once it drops it's a plain body moved
+// by its velocity and gravity, which falls through tiles and holds nobody up.
+//
+// A platform which has fallen out of the level is hidden rather than
+// despawned, so the level is still saved with it, and comes back where it
+// was placed a few seconds later. If a player, enemy or box is in the way it
+// waits for them to move.
+
+use bevy::prelude::*;
+use bevy::sprite::Anchor;
+use serde::{Deserialize, Serialize};
+
+use crate::death::{kill_height, KILL_PLANE_DEPTH};
+use crate::level::LevelEntity;
+use crate::physics::{
+    hitbox, overlaps, Gravity, Mobility, TerminalVelocity, TileCollider, Velocity, GRAVITY,
+    PHYSICS_TIME_STEP,
+};
+use crate::player::Player;
+use crate::tile::{SolidCollider, TileIndex};
+
+pub const PLATFORM_COLOR: Color = Color::rgb(0.65, 0.45, 0.25);
+// Seconds between a player standing on a platform and it dropping
+pub const FALL_DELAY: f32 = 0.5;
+// Seconds between a platform falling out of the level and coming back
+pub const RESPAWN_DELAY: f32 = 3.;
+// How far a platform about to drop shakes to either side, in tiles, and how
+// many times a second
+const SHAKE_DISTANCE: f32 = 0.05;
+const SHAKE_RATE: f32 = 15.;
+
+// A falling platform in a level file
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PlatformData {
+    // The cell it rests in
+    pub pos: IVec2,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PlatformState {
+    Resting,
+    // Stood on, for this many seconds
+    Shaking(f32),
+    Falling,
+    // Out of the level, for this many seconds
+    Gone(f32),
+}
+
+#[derive(Component)]
+pub struct FallingPlatform {
+    pub data: PlatformData,
+    pub state: PlatformState,
+}
+
+pub fn spawn_platform(commands: &mut Commands, data: &PlatformData) -> Entity {
+    commands
+        .spawn_bundle(SpriteBundle {
+            transform: Transform::from_translation(data.pos.as_vec2().extend(0.)),
+            sprite: Sprite {
+                color: PLATFORM_COLOR,
+                custom_size: Some(Vec2::ONE),
+                anchor: Anchor::BottomLeft,
+                ..default()
+            },
+            ..default()
+        })
+        .insert(FallingPlatform {
+            data: data.clone(),
+            state: PlatformState::Resting,
+        })
+        .insert(SolidCollider)
+        .insert(LevelEntity)
+        .id()
+}
+
+// After the collision step, so the players' contacts are those they end the
+// step with
+pub fn falling_platform_system(
+    mut commands: Commands,
+    tile_index: Res<TileIndex>,
+    mut kill_plane: Local<Option<f32>>,
+    player_query: Query<(&Transform, &Mobility), With<Player>>,
+    body_query: Query<&Transform, (With<TileCollider>, Without<FallingPlatform>)>,
+    mut platform_query: Query<
+        (
+            Entity,
+            &mut FallingPlatform,
+            &mut Transform,
+            &mut Visibility,
+        ),
+        Without<Player>,
+    >,
+) {
+    if tile_index.is_changed() {
+        *kill_plane = kill_height(&tile_index);
+    }
+    for (entity, mut platform, mut transform, mut visibility) in platform_query.iter_mut() {
+        let home = platform.data.pos.as_vec2();
+        platform.state = match platform.state {
+            PlatformState::Resting => {
+                let stood_on = player_query.iter().any(|(player, mobility)| {
+                    let position = player.translation.truncate();
+                    let size = player.scale.truncate();
+                    mobility.on_ground
+                        && (position.y - (home.y + 1.)).abs() < 0.01
+                        && position.x < home.x + 1.
+                        && home.x < position.x + size.x
+                });
+                if stood_on {
+                    PlatformState::Shaking(0.)
+                } else {
+                    PlatformState::Resting
+                }
+            }
+            PlatformState::Shaking(elapsed) => {
+                let elapsed = elapsed + PHYSICS_TIME_STEP;
+                if elapsed >= FALL_DELAY {
+                    transform.translation.x = home.x;
+                    commands
+                        .entity(entity)
+                        .remove::<SolidCollider>()
+                        .insert(Velocity(Vec3::ZERO))
+                        .insert(Gravity(GRAVITY))
+                        .insert(TerminalVelocity(40.));
+                    PlatformState::Falling
+                } else {
+                    let phase = elapsed * SHAKE_RATE * std::f32::consts::TAU;
+                    transform.translation.x = home.x + SHAKE_DISTANCE * phase.sin();
+                    PlatformState::Shaking(elapsed)
+                }
+            }
+            PlatformState::Falling => {
+                // A level without tiles has no kill plane, so it's measured
+                // from where the platform rested instead
+                let out_below = kill_plane.unwrap_or(home.y - KILL_PLANE_DEPTH);
+                if transform.translation.y + 1. < out_below {
+                    commands
+                        .entity(entity)
+                        .remove::<Velocity>()
+                        .remove::<Gravity>()
+                        .remove::<TerminalVelocity>();
+                    visibility.is_visible = false;
+                    PlatformState::Gone(0.)
+                } else {
+                    PlatformState::Falling
+                }
+            }
+            PlatformState::Gone(elapsed) => {
+                let elapsed = elapsed + PHYSICS_TIME_STEP;
+                let blocked = body_query
+                    .iter()
+                    .any(|body| overlaps(hitbox(body), (home, Vec2::ONE)));
+                if elapsed >= RESPAWN_DELAY && !blocked {
+                    transform.translation = home.extend(transform.translation.z);
+                    commands.entity(entity).insert(SolidCollider);
+                    visibility.is_visible = true;
+                    PlatformState::Resting
+                } else {
+                    PlatformState::Gone(elapsed)
+                }
+            }
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::headless::{floor, HeadlessGame};
+    use crate::level::LevelData;
+    use crate::push_box::{BoxData, PushBox};
+
+    fn platform(game: &mut HeadlessGame) -> (PlatformState, Vec3, bool) {
+        let (platform, transform, solid) = game
+            .app
+            .world
+            .query::<(&FallingPlatform, &Transform, Option<&SolidCollider>)>()
+            .iter(&game.app.world)
+            .next()
+            .unwrap();
+        (platform.state, transform.translation, solid.is_some())
+    }
+
+    #[test]
+    fn platform_drops_then_waits_for_its_spot_to_clear() {
+        let mut game = HeadlessGame::new();
+        game.spawn_level(&LevelData {
+            platforms: vec![PlatformData {
+                pos: IVec2::new(0, 1),
+            }],
+            ..floor(-6..=6)
+        });
+        game.place_player(Vec2::new(0., 2.));
+
+        // Held up while it shakes
+        game.run(60, &[]);
+        assert!(matches!(platform(&mut game).0, PlatformState::Shaking(_)));
+        assert_eq!(game.player_transform().translation.y, 2.);
+
+        // Then gone from under the player, who lands where it rested
+        game.run(240, &[]);
+        let (state, translation, solid) = platform(&mut game);
+        assert_eq!(state, PlatformState::Falling);
+        assert!(translation.y < 1.);
+        assert!(!solid);
+        assert_eq!(game.player_transform().translation.y, 1.);
+
+        // Out of the level, and kept from coming back while the player is
+        // in the way
+        let steps = |seconds: f32| (seconds / PHYSICS_TIME_STEP) as u32;
+        game.run(steps(1. + RESPAWN_DELAY), &[]);
+        assert!(matches!(platform(&mut game).0, PlatformState::Gone(_)));
+
+        game.place_player(Vec2::new(3., 1.));
+        game.run(2, &[]);
+        let (state, translation, solid) = platform(&mut game);
+        assert_eq!(state, PlatformState::Resting);
+        assert_eq!(translation, Vec3::new(0., 1., 0.));
+        assert!(solid);
+    }
+
+    #[test]
+    fn platform_waits_for_a_box_in_its_spot() {
+        let mut game = HeadlessGame::new();
+        game.spawn_level(&LevelData {
+            platforms: vec![PlatformData {
+                pos: IVec2::new(0, 1),
+            }],
+            boxes: vec![BoxData {
+                pos: IVec2::new(4, 1),
+            }],
+            ..floor(-6..=6)
+        });
+        game.place_player(Vec2::new(0., 2.));
+        let steps = |seconds: f32| (seconds / PHYSICS_TIME_STEP) as u32;
+        game.run(steps(FALL_DELAY + 0.5), &[]);
+        assert_eq!(platform(&mut game).0, PlatformState::Falling);
+
+        // The player leaves and the box is put where it rested while it's
+        // gone
+        game.place_player(Vec2::new(-4., 1.));
+        let pushed = game
+            .app
+            .world
+            .query_filtered::<Entity, With<PushBox>>()
+            .iter(&game.app.world)
+            .next()
+            .unwrap();
+        game.app
+            .world
+            .get_mut::<Transform>(pushed)
+            .unwrap()
+            .translation = Vec3::new(0., 1., 0.);
+        game.run(steps(1. + RESPAWN_DELAY), &[]);
+        let (state, _, solid) = platform(&mut game);
+        assert!(matches!(state, PlatformState::Gone(_)));
+        assert!(!solid);
+
+        game.app.world.despawn(pushed);
+        game.run(2, &[]);
+        assert_eq!(platform(&mut game).0, PlatformState::Resting);
+    }
+}
@@ -0,0 +1,170 @@
+// The simulation itself, apart from any window: the fixed input and physics
+// steps, and the players they move. Everything drawn or edited is added
+// around it by the binary.
+
+use bevy::ecs::schedule::RunCriteriaLabel;
+use bevy::prelude::*;
+
+use crate::ability::{ability_pickup_system, AbilityCollected};
+use crate::camera::CameraController;
+use crate::coin::{coin_pickup_system, CoinCollected, CoinCount};
+use crate::death::{death_check_system, dying_system, start_dying_system, DeathCount, PlayerDied};
+use crate::enemy::{enemy_contact_system, flight_system, patrol_system, EnemyStomped};
+use crate::falling_platform::falling_platform_system;
+use crate::game_state::{every_nth_step, fixed_step, GameState, PhysicsStep, PhysicsSubsteps};
+use crate::health::{damage_system, hit_stop_system, invincibility_system, Damage};
+use crate::input::begin_action_step_system;
+use crate::ledge_grab::{hang_control_system, hang_system, ledge_grab_system};
+use crate::level::{LevelBounds, Unspawned};
+use crate::level_exit::{level_exit_system, ExitReached};
+use crate::path::follow_path_system;
+use crate::physics::{physics_system_set, Landed, PhysicsSystem, PHYSICS_TIME_STEP};
+use crate::pixel_perfect::CameraZoom;
+use crate::player::{
+    facing_sprite_system, facing_system, level_bounds_system, move_to_new_spawn_system,
+    player_control_system, player_dash_system, player_separation_system, respawn_camera_system,
+    update_camera_system, Jumped,
+};
+use crate::pressure_plate::{pressure_plate_system, PlateActivated, PlateDeactivated};
+use crate::projectile::{player_attack_system, projectile_system};
+use crate::push_box::push_box_system;
+use crate::respawn::{
+    respawn_fade_system, respawn_lock_system, start_respawn_system, RespawnFade, RespawnState,
+};
+use crate::saw::saw_system;
+use crate::score::{score_system, Combo, Score, Scored};
+use crate::speedrun::{
+    finish_level_timer_system, level_timer_system, reset_level_timer_system,
+    start_level_timer_system, LevelFinished, LevelTimer,
+};
+
+// Systems which read the action layer once per input step. Physics runs
+// after them.
+#[derive(Clone, Hash, Debug, PartialEq, Eq, SystemLabel)]
+pub struct InputStep;
+
+// Label for the run criteria of the input step, for other sets which read
+// input in the same steps
+#[derive(Clone, Hash, Debug, PartialEq, Eq, RunCriteriaLabel)]
+pub struct InputStepCriteria;
+
+#[derive(Clone, Hash, Debug, PartialEq, Eq, SystemLabel)]
+struct ActionStep;
+
+// A set for systems which add or remove tiles while the game runs, once per
+// input step. Add them with `.exclusive_system().at_start()`, so their
+// commands are applied before that step's physics and a tile painted under a
+// falling player catches them in the same step, rather than the one after.
+pub fn live_edit_system_set() -> SystemSet {
+    SystemSet::new()
+        .with_run_criteria(InputStepCriteria)
+        .label(InputStep)
+}
+
+// Needs the `ActionState` and `InputMap` from `InputMapPlugin` and the
+// `GameState` from `GameStatePlugin`
+#[derive(Default)]
+pub struct GamePlugin;
+
+impl Plugin for GamePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<PhysicsSubsteps>()
+            .init_resource::<DeathCount>()
+            .init_resource::<CameraController>()
+            .init_resource::<CameraZoom>()
+            .init_resource::<LevelBounds>()
+            .init_resource::<Unspawned>()
+            .init_resource::<CoinCount>()
+            .init_resource::<LevelTimer>()
+            .init_resource::<Score>()
+            .init_resource::<Combo>()
+            .init_resource::<RespawnFade>()
+            .init_resource::<RespawnState>()
+            .add_event::<CoinCollected>()
+            .add_event::<PlayerDied>()
+            .add_event::<Damage>()
+            .add_event::<Landed>()
+            .add_event::<Jumped>()
+            .add_event::<ExitReached>()
+            .add_event::<LevelFinished>()
+            .add_event::<EnemyStomped>()
+            .add_event::<Scored>()
+            .add_event::<AbilityCollected>()
+            .add_event::<PlateActivated>()
+            .add_event::<PlateDeactivated>()
+            .add_system_set(
+                physics_system_set()
+                    .with_run_criteria(fixed_step(PHYSICS_TIME_STEP).label(PhysicsStep))
+                    .after(InputStep)
+                    .with_system(death_check_system.after(PhysicsSystem::Collision))
+                    .with_system(patrol_system.after(PhysicsSystem::Collision))
+                    .with_system(flight_system.after(PhysicsSystem::Collision))
+                    .with_system(enemy_contact_system.after(PhysicsSystem::Collision))
+                    .with_system(coin_pickup_system.after(PhysicsSystem::Collision))
+                    .with_system(ability_pickup_system.after(PhysicsSystem::Collision))
+                    .with_system(push_box_system.after(PhysicsSystem::Collision))
+                    .with_system(falling_platform_system.after(push_box_system))
+                    .with_system(pressure_plate_system.after(push_box_system))
+                    .with_system(level_timer_system.before(PhysicsSystem::Gravity))
+                    .with_system(level_exit_system.after(PhysicsSystem::Collision))
+                    .with_system(finish_level_timer_system.after(level_exit_system))
+                    .with_system(
+                        score_system
+                            .after(coin_pickup_system)
+                            .after(ability_pickup_system)
+                            .after(enemy_contact_system)
+                            .after(finish_level_timer_system)
+                            .after(damage_system),
+                    )
+                    .with_system(ledge_grab_system.after(PhysicsSystem::Collision))
+                    .with_system(hang_system.after(ledge_grab_system))
+                    .with_system(projectile_system.after(PhysicsSystem::Velocity))
+                    .with_system(follow_path_system.after(PhysicsSystem::Velocity))
+                    .with_system(saw_system.after(follow_path_system))
+                    .with_system(
+                        damage_system
+                            .after(death_check_system)
+                            .after(enemy_contact_system)
+                            .after(projectile_system)
+                            .after(saw_system),
+                    )
+                    .with_system(invincibility_system.before(damage_system))
+                    .with_system(level_bounds_system.after(PhysicsSystem::Collision))
+                    .with_system(player_separation_system.after(level_bounds_system))
+                    .with_system(
+                        update_camera_system
+                            .label(PhysicsSystem::Camera)
+                            .after(player_separation_system),
+                    ),
+            )
+            // Piped from the physics step, so it runs at the start of every
+            // `PhysicsSubsteps`th one
+            .add_system_set(
+                SystemSet::new()
+                    .with_run_criteria(
+                        RunCriteria::pipe(PhysicsStep, every_nth_step).label(InputStepCriteria),
+                    )
+                    .label(InputStep)
+                    .with_system(begin_action_step_system.label(ActionStep))
+                    .with_system(player_control_system.after(ActionStep))
+                    .with_system(respawn_lock_system.after(ActionStep))
+                    .with_system(start_respawn_system.after(player_control_system))
+                    .with_system(hang_control_system.after(ActionStep))
+                    .with_system(start_level_timer_system.after(ActionStep))
+                    .with_system(player_dash_system.after(player_control_system))
+                    .with_system(facing_system.after(player_control_system))
+                    .with_system(player_attack_system.after(facing_system))
+                    .with_system(dying_system.after(ActionStep)),
+            )
+            .add_system_set(
+                SystemSet::on_update(GameState::Respawning)
+                    .with_system(respawn_fade_system)
+                    .with_system(respawn_camera_system.after(respawn_fade_system)),
+            )
+            .add_system_to_stage(CoreStage::PreUpdate, hit_stop_system)
+            .add_system(move_to_new_spawn_system)
+            .add_system(reset_level_timer_system)
+            .add_system(facing_sprite_system.after(InputStep))
+            .add_system(start_dying_system.after(damage_system));
+    }
+}
@@ -0,0 +1,250 @@
+// Lives, and the game over screen once they run out.
+//
+// Each death takes a life. At none left the game moves to
+// `GameState::GameOver`, which stops the fixed steps like pausing does, and
+// shows an overlay until Enter is pressed. Retrying reloads the current level
+// and gives back the lives, and takes back the coins collected and the score
+// made since the level was last loaded.
+
+use bevy::prelude::*;
+use bevy::utils::HashSet;
+
+use crate::coin::CoinCount;
+use crate::death::{start_dying_system, Dying, PlayerDied};
+use crate::game_state::GameState;
+use crate::input::{MenuAction, MenuActionState};
+use crate::level::{CurrentLevel, LevelCommand};
+use crate::pixel_perfect::UI_FONT;
+use crate::player::Player;
+use crate::score::Score;
+
+pub const STARTING_LIVES: u32 = 3;
+
+// How many more deaths the players can take before the game is over, shared
+// between them in co-op
+pub struct Lives(pub u32);
+
+impl Default for Lives {
+    fn default() -> Self {
+        Lives(STARTING_LIVES)
+    }
+}
+
+// The `CoinCount` and `Score` when the current attempt at the level began,
+// which a retry puts them back to
+#[derive(Default)]
+struct AttemptStart {
+    coins: u32,
+    score: u64,
+}
+
+#[derive(Component)]
+struct GameOverOverlay;
+
+// Needs the `MenuActionState` from `InputMapPlugin`, the `CurrentLevel` and
+// `LevelCommand` from `LevelPlugin`, the `GameState` from `GameStatePlugin`
+// and the `PlayerDied` events, `CoinCount` and `Score` from `GamePlugin`
+#[derive(Default)]
+pub struct GameOverPlugin;
+
+impl Plugin for GameOverPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Lives>()
+            .init_resource::<AttemptStart>()
+            .add_system(lose_life_system.after(start_dying_system))
+            .add_system(attempt_start_system)
+            .add_system_set(
+                SystemSet::on_update(GameState::Playing)
+                    .with_system(game_over_check_system.after(lose_life_system)),
+            )
+            .add_system_set(
+                SystemSet::on_enter(GameState::GameOver).with_system(spawn_game_over_overlay),
+            )
+            .add_system_set(SystemSet::on_update(GameState::GameOver).with_system(retry_system))
+            .add_system_set(
+                SystemSet::on_exit(GameState::GameOver).with_system(despawn_game_over_overlay),
+            );
+    }
+}
+
+// Each death takes a life. Several steps in a frame can each see the same
+// death before the player is `Dying`, so it's counted once per player, as
+// `start_dying_system` does. Run alongside it, so the player isn't `Dying`
+// yet when their death is read.
+fn lose_life_system(
+    mut lives: ResMut<Lives>,
+    mut died_events: EventReader<PlayerDied>,
+    player_query: Query<(), (With<Player>, Without<Dying>)>,
+) {
+    let mut dying = HashSet::default();
+    for &PlayerDied { player } in died_events.iter() {
+        if player_query.contains(player) && dying.insert(player) {
+            lives.0 = lives.0.saturating_sub(1);
+        }
+    }
+}
+
+// Loading any level, retries included, starts a new attempt at it
+fn attempt_start_system(
+    mut level_commands: EventReader<LevelCommand>,
+    coin_count: Res<CoinCount>,
+    score: Res<Score>,
+    mut attempt: ResMut<AttemptStart>,
+) {
+    if level_commands.iter().count() > 0 {
+        attempt.coins = coin_count.0;
+        attempt.score = score.0;
+    }
+}
+
+// Checked every frame rather than on the last death, so a transition already
+// queued that frame only puts it off
+fn game_over_check_system(lives: Res<Lives>, mut state: ResMut<State<GameState>>) {
+    if lives.0 == 0 {
+        let _ = state.set(GameState::GameOver);
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn retry_system(
+    menu_actions: Res<MenuActionState>,
+    current_level: Res<CurrentLevel>,
+    attempt: Res<AttemptStart>,
+    mut lives: ResMut<Lives>,
+    mut coin_count: ResMut<CoinCount>,
+    mut score: ResMut<Score>,
+    mut level_commands: EventWriter<LevelCommand>,
+    mut state: ResMut<State<GameState>>,
+) {
+    if !menu_actions.just_pressed(MenuAction::Confirm) {
+        return;
+    }
+    if state.set(GameState::Playing).is_err() {
+        return;
+    }
+    *lives = Lives::default();
+    coin_count.0 = attempt.coins;
+    score.0 = attempt.score;
+    // Reloading needs a file, and a level without one is the default level
+    level_commands.send(match current_level.path {
+        Some(_) => LevelCommand::Reload,
+        None => LevelCommand::ResetToDefault,
+    });
+}
+
+fn spawn_game_over_overlay(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.), Val::Percent(100.)),
+                position_type: PositionType::Absolute,
+                // The UI is y-up, so this lists the children top to bottom
+                flex_direction: FlexDirection::ColumnReverse,
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            color: Color::BLACK.into(),
+            ..default()
+        })
+        .insert(GameOverOverlay)
+        .with_children(|parent| {
+            parent.spawn_bundle(TextBundle {
+                text: Text::with_section(
+                    "Game Over",
+                    TextStyle {
+                        font: asset_server.load(UI_FONT),
+                        font_size: 48.,
+                        color: Color::WHITE,
+                    },
+                    default(),
+                ),
+                ..default()
+            });
+            parent.spawn_bundle(TextBundle {
+                text: Text::with_section(
+                    "Press Enter to retry",
+                    TextStyle {
+                        font: asset_server.load(UI_FONT),
+                        font_size: 16.,
+                        color: Color::rgb(0.7, 0.7, 0.7),
+                    },
+                    default(),
+                ),
+                ..default()
+            });
+        });
+}
+
+fn despawn_game_over_overlay(mut commands: Commands, query: Query<Entity, With<GameOverOverlay>>) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::headless::{floor, HeadlessGame};
+    use bevy::asset::FileAssetIo;
+    use bevy::tasks::TaskPoolBuilder;
+
+    // Standing on a floor, with lives and the game over screen
+    fn game() -> HeadlessGame {
+        let mut game = HeadlessGame::new();
+        game.app
+            .insert_resource(AssetServer::new(
+                FileAssetIo::new("assets", false),
+                TaskPoolBuilder::new().build(),
+            ))
+            .init_resource::<CurrentLevel>()
+            .add_event::<LevelCommand>()
+            .add_plugin(GameOverPlugin);
+        game.spawn_level(&floor(-3..=3));
+        game.place_player(Vec2::new(0., 1.));
+        game
+    }
+
+    // Out of the level, through the whole death sequence
+    fn fall_out(game: &mut HeadlessGame) {
+        game.place_player(Vec2::new(0., -20.));
+        game.run(240, &[]);
+    }
+
+    fn lives(game: &HeadlessGame) -> u32 {
+        game.app.world.resource::<Lives>().0
+    }
+
+    fn state(game: &HeadlessGame) -> GameState {
+        *game.app.world.resource::<State<GameState>>().current()
+    }
+
+    #[test]
+    fn each_death_takes_one_life() {
+        let mut game = game();
+        fall_out(&mut game);
+        assert_eq!(lives(&game), STARTING_LIVES - 1);
+        assert_eq!(state(&game), GameState::Playing);
+        fall_out(&mut game);
+        assert_eq!(lives(&game), STARTING_LIVES - 2);
+    }
+
+    #[test]
+    fn last_life_lost_is_game_over_and_retrying_gives_them_back() {
+        let mut game = game();
+        for _ in 0..STARTING_LIVES {
+            fall_out(&mut game);
+        }
+        assert_eq!(lives(&game), 0);
+        assert_eq!(state(&game), GameState::GameOver);
+
+        let confirm = [MenuAction::Confirm].into_iter().collect();
+        game.app
+            .world
+            .resource_mut::<MenuActionState>()
+            .update(confirm, 0.);
+        game.step(&[]);
+        assert_eq!(state(&game), GameState::Playing);
+        assert_eq!(lives(&game), STARTING_LIVES);
+    }
+}
@@ -0,0 +1,246 @@
+// Whether the game is running or paused, and the fixed timesteps which stop
+// while it is paused. The menu shown while paused is `PauseMenuPlugin`'s.
+
+use bevy::ecs::schedule::{RunCriteriaLabel, ShouldRun};
+use bevy::prelude::*;
+
+use crate::input::{Action, ActionState, MenuActionState, SecondPlayerInput};
+use crate::physics::PHYSICS_TIME_STEP;
+use crate::replay::ReplayDelta;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum GameState {
+    // In the menu from `MainMenuPlugin`
+    MainMenu,
+    Playing,
+    Paused,
+    // Choosing a level to play, in the menu from `LevelSelectPlugin`
+    LevelSelect,
+    // In the Escape menu from `SystemMenuPlugin`
+    SystemMenu,
+    // Out of lives, until retrying from `GameOverPlugin`
+    GameOver,
+    // Reading what someone says, in the dialog box from `NpcPlugin`
+    Dialog,
+    // Held while a newly unlocked ability is announced by `AbilityPlugin`
+    Fanfare,
+    // Held while the screen fades for a player's reset, by `GamePlugin`
+    Respawning,
+}
+
+// Drops the time the fixed steps have yet to catch up on, e.g. after the
+// world has been put back to an earlier state
+pub struct FixedStepReset;
+
+#[derive(Default)]
+pub struct FixedStepState {
+    accumulator: f64,
+    looping: bool,
+}
+
+// Run criteria for a set which advances in steps of `step` seconds, running
+// as many times as needed to catch up with the frame. Like bevy's
+// `FixedTimestep`, except that no time accumulates while the game is paused,
+// so resuming doesn't cause a burst of catch-up steps. A replay supplies the
+// frame time through `ReplayDelta` so it steps exactly as the recording did.
+#[allow(clippy::type_complexity)]
+pub fn fixed_step(
+    step: f32,
+) -> impl FnMut(
+    Res<Time>,
+    Option<Res<ReplayDelta>>,
+    Res<State<GameState>>,
+    EventReader<FixedStepReset>,
+    Local<FixedStepState>,
+) -> ShouldRun {
+    let step = step as f64;
+    move |time: Res<Time>,
+          replay_delta: Option<Res<ReplayDelta>>,
+          state: Res<State<GameState>>,
+          mut resets: EventReader<FixedStepReset>,
+          mut fixed: Local<FixedStepState>| {
+        if *state.current() != GameState::Playing || resets.iter().count() > 0 {
+            fixed.accumulator = 0.;
+            fixed.looping = false;
+            return ShouldRun::No;
+        }
+        if !fixed.looping {
+            fixed.accumulator += match replay_delta {
+                Some(delta) => delta.0,
+                None => time.delta_seconds_f64(),
+            };
+        }
+        if fixed.accumulator >= step {
+            fixed.accumulator -= step;
+            fixed.looping = true;
+            ShouldRun::YesAndCheckAgain
+        } else {
+            fixed.looping = false;
+            ShouldRun::No
+        }
+    }
+}
+
+// Label for the run criteria of the physics `fixed_step`, which the input
+// step is piped from with `every_nth_step`
+#[derive(Clone, Hash, Debug, PartialEq, Eq, RunCriteriaLabel)]
+pub struct PhysicsStep;
+
+// Physics steps per input step. Input is read once, then physics runs this
+// many times on it, so the two never drift against each other. Input used to
+// be read at 300 Hz, which no whole number of 240 Hz physics steps makes, so
+// it defaults to the physics rate. The timers input advances are all kept in
+// seconds, so they last as long at any count, only counted off in coarser or
+// finer steps.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PhysicsSubsteps(pub u32);
+
+impl Default for PhysicsSubsteps {
+    fn default() -> Self {
+        PhysicsSubsteps(1)
+    }
+}
+
+impl PhysicsSubsteps {
+    // How long each input step is, in seconds
+    pub fn input_time_step(&self) -> f32 {
+        self.0.max(1) as f32 * PHYSICS_TIME_STEP
+    }
+}
+
+// Run criteria to pipe from a `fixed_step`, passing on only every
+// `PhysicsSubsteps`th of its steps. Sets using it run in the same step as the
+// other set, just less often, so they always step in whole multiples of its
+// time step.
+pub fn every_nth_step(
+    In(should_run): In<ShouldRun>,
+    mut count: Local<u32>,
+    substeps: Res<PhysicsSubsteps>,
+) -> ShouldRun {
+    match should_run {
+        ShouldRun::Yes | ShouldRun::YesAndCheckAgain => {
+            let due = count.is_multiple_of(substeps.0.max(1));
+            *count = count.wrapping_add(1);
+            match (due, should_run) {
+                (true, _) => should_run,
+                (false, ShouldRun::Yes) => ShouldRun::No,
+                // Keep being asked, or the rest of this frame's steps are missed
+                (false, _) => ShouldRun::NoAndCheckAgain,
+            }
+        }
+        other => other,
+    }
+}
+
+// Needs the `ActionState` and `MenuActionState` from `InputMapPlugin`
+pub struct GameStatePlugin {
+    // The state the game starts in
+    pub initial_state: GameState,
+}
+
+impl Default for GameStatePlugin {
+    fn default() -> Self {
+        GameStatePlugin {
+            initial_state: GameState::Playing,
+        }
+    }
+}
+
+impl Plugin for GameStatePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_state(self.initial_state)
+            .add_event::<FixedStepReset>()
+            .add_system(toggle_pause_system);
+        for state in [
+            GameState::MainMenu,
+            GameState::Playing,
+            GameState::Paused,
+            GameState::LevelSelect,
+            GameState::SystemMenu,
+            GameState::GameOver,
+            GameState::Dialog,
+            GameState::Fanfare,
+            GameState::Respawning,
+        ] {
+            app.add_system_set(SystemSet::on_enter(state).with_system(swallow_input_system));
+        }
+    }
+}
+
+// The press which changed the state was meant for the state being left, so
+// nothing in the new one sees it. Presses made while paused or in a menu
+// aren't meant for gameplay either, so those no step has seen go too.
+fn swallow_input_system(
+    mut action_state: ResMut<ActionState>,
+    mut menu_actions: ResMut<MenuActionState>,
+    second_player: Option<ResMut<SecondPlayerInput>>,
+) {
+    action_state.swallow();
+    menu_actions.swallow();
+    if let Some(mut second_player) = second_player {
+        second_player.action_state.swallow();
+    }
+}
+
+fn toggle_pause_system(action_state: Res<ActionState>, mut state: ResMut<State<GameState>>) {
+    if action_state.just_pressed(Action::Pause) {
+        let next = match state.current() {
+            GameState::Playing => GameState::Paused,
+            GameState::Paused => GameState::Playing,
+            GameState::MainMenu
+            | GameState::LevelSelect
+            | GameState::SystemMenu
+            | GameState::GameOver
+            | GameState::Dialog
+            | GameState::Fanfare
+            | GameState::Respawning => return,
+        };
+        // Fails only if a transition is already queued this frame
+        let _ = state.set(next);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Default)]
+    struct Runs {
+        steps: u32,
+        nth_steps: u32,
+    }
+
+    #[test]
+    fn nth_step_runs_within_the_piped_steps() {
+        let mut world = World::new();
+        world.init_resource::<Runs>();
+        world.insert_resource(PhysicsSubsteps(4));
+        // Six steps a frame
+        let six_steps = |mut count: Local<u32>| {
+            *count += 1;
+            if count.is_multiple_of(7) {
+                ShouldRun::No
+            } else {
+                ShouldRun::YesAndCheckAgain
+            }
+        };
+        let mut stage = SystemStage::single_threaded()
+            .with_system_set(
+                SystemSet::new()
+                    .with_run_criteria(six_steps.label(PhysicsStep))
+                    .with_system(|mut runs: ResMut<Runs>| runs.steps += 1),
+            )
+            .with_system_set(
+                SystemSet::new()
+                    .with_run_criteria(RunCriteria::pipe(PhysicsStep, every_nth_step))
+                    .with_system(|mut runs: ResMut<Runs>| runs.nth_steps += 1),
+            );
+        stage.run(&mut world);
+        let runs = world.resource::<Runs>();
+        assert_eq!((runs.steps, runs.nth_steps), (6, 2));
+        // The count carries over between frames
+        stage.run(&mut world);
+        let runs = world.resource::<Runs>();
+        assert_eq!((runs.steps, runs.nth_steps), (12, 3));
+    }
+}
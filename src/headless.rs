@@ -0,0 +1,261 @@
+// The game without a window or assets, for tests of the physics and
+// controls. A `HeadlessGame` advances exactly one physics step per update,
+// with the actions it is given held, so a test can script its input frame by
+// frame and check where the player ends up:
+//
+//     let mut game = HeadlessGame::new();
+//     game.spawn_level(&level);
+//     game.place_player(Vec2::new(0., 1.));
+//     game.run(24, &[Action::Jump]);
+//     game.run(240, &[]);
+//     let landed_at = game.player_transform().translation;
+
+use bevy::ecs::system::CommandQueue;
+use bevy::prelude::*;
+use bevy::utils::HashSet;
+use std::ops::RangeInclusive;
+
+use crate::ability::spawn_pickup;
+use crate::coin::spawn_coin;
+use crate::enemy::spawn_enemy;
+use crate::falling_platform::spawn_platform;
+use crate::game::GamePlugin;
+use crate::game_state::GameStatePlugin;
+use crate::input::{Action, ActionState, InputMap, MenuActionState, PlayerId};
+use crate::level::{LevelBounds, LevelData, TileAppearanceData, TileData};
+use crate::level_exit::spawn_level_exit;
+use crate::npc::spawn_npc;
+use crate::physics::{Velocity, PHYSICS_TIME_STEP};
+use crate::player::{spawn_player, PlayerSpec};
+use crate::pressure_plate::spawn_plate;
+use crate::push_box::spawn_box;
+use crate::replay::ReplayDelta;
+use crate::saw::spawn_saw;
+use crate::tile::{TileAppearance, TileIndex};
+
+pub struct HeadlessGame {
+    pub app: App,
+    player: Entity,
+}
+
+impl Default for HeadlessGame {
+    fn default() -> Self {
+        HeadlessGame::new()
+    }
+}
+
+impl HeadlessGame {
+    // A game with one player at the start, no level and the default bindings
+    pub fn new() -> Self {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .insert_resource(InputMap::default())
+            .init_resource::<ActionState>()
+            .init_resource::<MenuActionState>()
+            .init_resource::<TileIndex>()
+            // No time passes until the first step
+            .insert_resource(ReplayDelta(0.))
+            .add_plugin(GameStatePlugin::default())
+            .add_plugin(GamePlugin);
+        // Enter the first state now, since entering `Playing` discards the
+        // presses made before it
+        app.update();
+        // The same fixed frame time a replay uses, one physics step long
+        app.insert_resource(ReplayDelta(PHYSICS_TIME_STEP as f64));
+        let player = apply_commands(&mut app.world, |commands, _| {
+            spawn_player(commands, &PlayerSpec::new(PlayerId(0)))
+        });
+        HeadlessGame { app, player }
+    }
+
+    pub fn player(&self) -> Entity {
+        self.player
+    }
+
+    // Spawn the level's tiles, enemies, coins, exit, NPCs, pickups, saw
+    // blades, falling platforms, boxes and pressure plates, with the tiles
+    // drawn in flat colors in place of textures. Its stamps and background
+    // layers are left out.
+    pub fn spawn_level(&mut self, level: &LevelData) {
+        apply_commands(&mut self.app.world, |commands, tile_index| {
+            for tile in &level.tiles {
+                let appearance = match &tile.appearance {
+                    TileAppearanceData::Color(color) => TileAppearance::Color(*color),
+                    TileAppearanceData::Texture(_) => TileAppearance::Color(Color::WHITE),
+                    TileAppearanceData::None => TileAppearance::None,
+                };
+                tile.spawn_with_appearance(commands, tile_index, IVec2::ZERO, appearance);
+            }
+            for enemy in &level.enemies {
+                spawn_enemy(commands, enemy);
+            }
+            for &coin in &level.coins {
+                spawn_coin(commands, Handle::default(), coin);
+            }
+            if let Some(exit) = level.exit {
+                spawn_level_exit(commands, exit);
+            }
+            for npc in &level.npcs {
+                spawn_npc(commands, npc);
+            }
+            for pickup in &level.pickups {
+                spawn_pickup(commands, pickup);
+            }
+            for saw in &level.saws {
+                spawn_saw(commands, saw);
+            }
+            for platform in &level.platforms {
+                spawn_platform(commands, platform);
+            }
+            for pushed in &level.boxes {
+                spawn_box(commands, pushed);
+            }
+            for plate in &level.plates {
+                spawn_plate(commands, plate);
+            }
+            commands.insert_resource(LevelBounds { edges: level.edges });
+        });
+    }
+
+    // Put the player's bottom-left corner at `position`, at rest
+    pub fn place_player(&mut self, position: Vec2) {
+        let mut player = self.app.world.entity_mut(self.player);
+        player.get_mut::<Transform>().unwrap().translation = position.extend(0.);
+        player.get_mut::<Velocity>().unwrap().0 = Vec3::ZERO;
+    }
+
+    pub fn player_transform(&self) -> Transform {
+        *self.app.world.get::<Transform>(self.player).unwrap()
+    }
+
+    // Run one physics step with exactly `pressed` held
+    pub fn step(&mut self, pressed: &[Action]) {
+        let pressed: HashSet<Action> = pressed.iter().copied().collect();
+        self.app.world.resource_mut::<ActionState>().update(pressed);
+        self.app.update();
+    }
+
+    pub fn run(&mut self, steps: u32, pressed: &[Action]) {
+        for _ in 0..steps {
+            self.step(pressed);
+        }
+    }
+}
+
+// A level of nothing but a row of solid tiles at y = 0, one in each of the
+// columns `xs`
+pub fn floor(xs: RangeInclusive<i32>) -> LevelData {
+    LevelData {
+        tiles: xs.map(|x| TileData::solid(IVec2::new(x, 0))).collect(),
+        ..default()
+    }
+}
+
+// Run `spawn` with commands for the world, then apply them
+fn apply_commands<T>(
+    world: &mut World,
+    spawn: impl FnOnce(&mut Commands, &mut TileIndex) -> T,
+) -> T {
+    let mut queue = CommandQueue::default();
+    let result = world.resource_scope(|world, mut tile_index: Mut<TileIndex>| {
+        let mut commands = Commands::new(&mut queue, world);
+        spawn(&mut commands, &mut tile_index)
+    });
+    queue.apply(world);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::live_edit_system_set;
+    use crate::physics::Mobility;
+    use crate::player::Player;
+    use crate::tile::TileSpec;
+
+    #[test]
+    fn full_jump_lands_where_it_started() {
+        let mut game = HeadlessGame::new();
+        game.spawn_level(&floor(-10..=10));
+        game.place_player(Vec2::new(0., 1.));
+        game.run(10, &[]);
+        let on_ground = |game: &HeadlessGame| {
+            game.app
+                .world
+                .get::<Mobility>(game.player())
+                .unwrap()
+                .on_ground
+        };
+        assert!(on_ground(&game));
+
+        // Held for the whole arc, so it isn't cut short
+        let mut peak: f32 = 1.;
+        for _ in 0..360 {
+            game.step(&[Action::Jump]);
+            peak = peak.max(game.player_transform().translation.y);
+        }
+        game.run(10, &[]);
+
+        // The player's jump reaches 5.8 tiles
+        assert!((peak - 6.8).abs() < 0.1, "peaked at {}", peak);
+        assert_eq!(
+            game.player_transform().translation.truncate(),
+            Vec2::new(0., 1.)
+        );
+        assert!(on_ground(&game));
+    }
+
+    // Paint a floor along y = 0 in the step the player would fall past it
+    fn paint_floor_system(
+        mut commands: Commands,
+        mut tile_index: ResMut<TileIndex>,
+        query: Query<(&Transform, &Velocity), With<Player>>,
+    ) {
+        let (transform, velocity) = query.single();
+        let next_y = transform.translation.y + velocity.0.y * PHYSICS_TIME_STEP;
+        if tile_index.tile_at(IVec2::ZERO).is_none() && next_y < 1. {
+            for x in -2..=2 {
+                tile_index.spawn(
+                    &mut commands,
+                    TileSpec {
+                        pos: IVec2::new(x, 0),
+                        appearance: TileAppearance::Color(Color::WHITE),
+                        shape: default(),
+                    },
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn floor_painted_under_a_falling_player_catches_them() {
+        let mut game = HeadlessGame::new();
+        game.app.add_system_set(
+            live_edit_system_set().with_system(paint_floor_system.exclusive_system().at_start()),
+        );
+        game.place_player(Vec2::new(0., 10.));
+        let mut steps = 0;
+        while game
+            .app
+            .world
+            .resource::<TileIndex>()
+            .iter()
+            .next()
+            .is_none()
+        {
+            assert!(steps < 1000, "the floor was never painted");
+            game.step(&[]);
+            steps += 1;
+        }
+
+        // Landed in the step the floor was painted
+        assert_eq!(game.player_transform().translation.y, 1.);
+        assert!(
+            game.app
+                .world
+                .get::<Mobility>(game.player())
+                .unwrap()
+                .on_ground
+        );
+    }
+}
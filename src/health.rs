@@ -0,0 +1,291 @@
+// Health, and the damage which takes it away. A damaged player is knocked
+// away from what hurt them and can't be hurt again for a moment, flickering
+// until they can. The game also stops dead for a few frames as they're hit,
+// to sell the impact. At zero health they die.
+//
+// Anything which hurts a player, such as a hazard or an enemy, sends `Damage`
+// rather than changing their `Health` itself. Anything which heals them, such
+// as a pickup, can change it directly.
+
+use bevy::prelude::*;
+use bevy::utils::HashSet;
+
+use crate::death::{Dying, PlayerDied};
+use crate::enemy::Enemy;
+use crate::game_state::FixedStepReset;
+use crate::level::Unspawned;
+use crate::physics::{Velocity, PHYSICS_TIME_STEP};
+use crate::player::Player;
+
+// Seconds a damaged player can't be damaged again
+pub const INVINCIBILITY_TIME: f32 = 1.;
+// Seconds of that in which they are knocked back, out of their control
+pub const KNOCKBACK_TIME: f32 = 0.2;
+// The speed they are knocked back at, away from the source and upwards
+const KNOCKBACK_VELOCITY: Vec2 = bevy::math::const_vec2!([8., 10.]);
+// Seconds the sprite spends shown, then hidden, while flickering, which
+// makes it flicker at 15Hz
+const FLASH_PERIOD: f32 = 1. / 30.;
+// Frames the game is held for when a player is damaged
+pub const HIT_STOP_FRAMES: u32 = 3;
+
+#[derive(Component, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Health {
+    pub current: i32,
+    pub max: i32,
+}
+
+impl Health {
+    pub fn new(max: i32) -> Self {
+        Health { current: max, max }
+    }
+
+    pub fn heal(&mut self, amount: i32) {
+        self.current = (self.current + amount).min(self.max);
+    }
+
+    pub fn restore(&mut self) {
+        self.current = self.max;
+    }
+}
+
+// Hurt `target` by `amount`, knocking them away from `source` if given
+pub struct Damage {
+    pub target: Entity,
+    pub amount: i32,
+    pub source: Option<Vec2>,
+}
+
+// Recently damaged, so further damage is ignored. The flicker and the
+// hit-stop both run off it.
+#[derive(Component, Default)]
+pub struct Invincible {
+    // Seconds since they were damaged
+    elapsed: f32,
+    // Frames left of the hit-stop
+    hit_stop: u32,
+}
+
+impl Invincible {
+    // Whether they are still being knocked back, and shouldn't be steered
+    pub fn knocked_back(&self) -> bool {
+        self.elapsed < KNOCKBACK_TIME
+    }
+}
+
+// What can be hurt right now, with what's needed to knock it back or
+// remove it
+type DamageableQuery<'w, 's> = Query<
+    'w,
+    's,
+    (
+        &'static mut Health,
+        &'static Transform,
+        &'static mut Velocity,
+        Option<&'static Player>,
+        Option<&'static Enemy>,
+    ),
+    (Without<Invincible>, Without<Dying>),
+>;
+
+// In the physics step, after anything which sends `Damage`
+pub fn damage_system(
+    mut commands: Commands,
+    mut damage_events: EventReader<Damage>,
+    mut query: DamageableQuery,
+    mut unspawned: ResMut<Unspawned>,
+    mut died_events: EventWriter<PlayerDied>,
+) {
+    // Only the first damage in a step counts, since the others would land
+    // during the invincibility it grants
+    let mut damaged = HashSet::default();
+    for damage in damage_events.iter() {
+        if !damaged.insert(damage.target) {
+            continue;
+        }
+        let (mut health, transform, mut velocity, player, enemy) =
+            match query.get_mut(damage.target) {
+                Ok(target) => target,
+                Err(_) => continue,
+            };
+        health.current = (health.current - damage.amount).max(0);
+        if health.current == 0 {
+            // Players go through the death sequence, anything else is simply gone
+            if player.is_some() {
+                died_events.send(PlayerDied {
+                    player: damage.target,
+                });
+            } else {
+                commands.entity(damage.target).despawn_recursive();
+                if let Some(enemy) = enemy {
+                    unspawned.0.enemies.push(enemy.data.clone());
+                }
+            }
+            continue;
+        }
+        if let Some(source) = damage.source {
+            let center = transform.translation.truncate() + transform.scale.truncate() / 2.;
+            let away = if center.x < source.x { -1. } else { 1. };
+            velocity.0 = (KNOCKBACK_VELOCITY * Vec2::new(away, 1.)).extend(0.);
+        }
+        commands.entity(damage.target).insert(Invincible {
+            hit_stop: if player.is_some() { HIT_STOP_FRAMES } else { 0 },
+            ..default()
+        });
+    }
+}
+
+// What's invincible, with the sprite which flickers while it is
+type InvincibleQuery<'w, 's> = Query<
+    'w,
+    's,
+    (
+        Entity,
+        &'static mut Invincible,
+        Option<&'static mut Sprite>,
+        Option<&'static mut TextureAtlasSprite>,
+    ),
+    Without<Dying>,
+>;
+
+// Once per physics step
+pub fn invincibility_system(mut commands: Commands, mut query: InvincibleQuery) {
+    for (entity, mut invincible, sprite, sheet_sprite) in query.iter_mut() {
+        invincible.elapsed += PHYSICS_TIME_STEP;
+        let done = invincible.elapsed >= INVINCIBILITY_TIME;
+        if done {
+            commands.entity(entity).remove::<Invincible>();
+        }
+        // Always shown once it's over, whichever half of the flicker it ends in
+        let shown = done || (invincible.elapsed / FLASH_PERIOD) as u32 % 2 == 1;
+        let alpha = if shown { 1. } else { 0. };
+        if let Some(mut sprite) = sprite {
+            sprite.color.set_a(alpha);
+        }
+        if let Some(mut sprite) = sheet_sprite {
+            sprite.color.set_a(alpha);
+        }
+    }
+}
+
+// Every frame, before the fixed steps. A frame of hit-stop skips that
+// frame's steps and drops the time they would have caught up on, so the game
+// carries on afterwards at its usual pace instead of rushing to make it up.
+pub fn hit_stop_system(
+    mut query: Query<&mut Invincible>,
+    mut reset_events: EventWriter<FixedStepReset>,
+) {
+    let mut stopped = false;
+    for mut invincible in query.iter_mut() {
+        if invincible.hit_stop > 0 {
+            invincible.hit_stop -= 1;
+            stopped = true;
+        }
+    }
+    if stopped {
+        reset_events.send(FixedStepReset);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::headless::HeadlessGame;
+    use crate::level::{LevelData, TileData};
+
+    #[test]
+    fn heal_stops_at_max() {
+        let mut health = Health { current: 1, max: 3 };
+        health.heal(1);
+        assert_eq!(health.current, 2);
+        health.heal(5);
+        assert_eq!(health.current, 3);
+    }
+
+    #[test]
+    fn hazard_hurts_once_then_knocks_back() {
+        let mut game = HeadlessGame::new();
+        let floor = LevelData {
+            tiles: (-3..=3)
+                .map(|x| TileData {
+                    hazard: x == 3,
+                    ..TileData::solid(IVec2::new(x, 0))
+                })
+                .collect(),
+            ..default()
+        };
+        game.spawn_level(&floor);
+        // Standing at the edge of the hazard, to its left
+        game.place_player(Vec2::new(2.5, 1.));
+        let max = game.app.world.get::<Health>(game.player()).unwrap().max;
+        game.step(&[]);
+        let player = game.app.world.entity(game.player());
+        assert_eq!(player.get::<Health>().unwrap().current, max - 1);
+        assert!(player.contains::<Invincible>());
+        assert!(player.get::<Velocity>().unwrap().0.x < 0.);
+
+        // Not hurt again while invincible
+        game.run(5, &[]);
+        assert_eq!(
+            game.app.world.get::<Health>(game.player()).unwrap().current,
+            max - 1
+        );
+        game.run((INVINCIBILITY_TIME / PHYSICS_TIME_STEP) as u32, &[]);
+        assert!(!game
+            .app
+            .world
+            .entity(game.player())
+            .contains::<Invincible>());
+    }
+
+    #[test]
+    fn hit_stop_holds_the_game_then_flicker_ends_shown() {
+        let mut game = HeadlessGame::new();
+        game.spawn_level(&LevelData {
+            tiles: (-10..=10)
+                .map(|x| TileData {
+                    hazard: x == 0,
+                    ..TileData::solid(IVec2::new(x, 0))
+                })
+                .collect(),
+            ..default()
+        });
+        game.place_player(Vec2::new(0., 1.));
+        game.step(&[]);
+        assert!(game
+            .app
+            .world
+            .entity(game.player())
+            .contains::<Invincible>());
+
+        // Knocked back, but not moving until the hit-stop is over
+        let hit_at = game.player_transform().translation;
+        game.run(HIT_STOP_FRAMES, &[]);
+        assert_eq!(game.player_transform().translation, hit_at);
+        game.step(&[]);
+        assert_ne!(game.player_transform().translation, hit_at);
+
+        // Hidden at some point while flickering, and shown at the end
+        let alpha = |game: &HeadlessGame| {
+            game.app
+                .world
+                .get::<Sprite>(game.player())
+                .unwrap()
+                .color
+                .a()
+        };
+        let mut hidden = false;
+        // A step over, since the steps' times add up to a hair under it
+        for _ in 0..=(INVINCIBILITY_TIME / PHYSICS_TIME_STEP) as u32 {
+            game.step(&[]);
+            hidden |= alpha(&game) == 0.;
+        }
+        assert!(hidden);
+        assert!(!game
+            .app
+            .world
+            .entity(game.player())
+            .contains::<Invincible>());
+        assert_eq!(alpha(&game), 1.);
+    }
+}
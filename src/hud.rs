@@ -0,0 +1,519 @@
+// The heads-up display: the first player's health as hearts, the coins
+// collected and the score with its combo, in the top right, the level's name
+// in the top left, fading out a few seconds after the level is spawned, and
+// the `LevelTimer` in the bottom left. Finishing a level shows its time and
+// the score in the middle. It is hidden while debug mode is on, which is when
+// the editor is in use.
+//
+// It is drawn by bevy_ui at the window's native resolution. Icons are drawn at
+// a whole number of window pixels per icon pixel, picked from the window's
+// height, so they stay crisp at any window size. Nodes are updated as what
+// they show changes, rather than every frame.
+
+use bevy::prelude::*;
+use bevy::window::WindowResized;
+
+use crate::coin::{CoinCount, COIN_TEXTURE};
+use crate::debug::DebugMode;
+use crate::health::Health;
+use crate::level::{CurrentLevel, LevelName};
+use crate::pixel_perfect::{HEIGHT_PIXELS, UI_FONT};
+use crate::player::Player;
+use crate::score::{Combo, Score};
+use crate::speedrun::{format_time, Comparison, LevelTimer};
+
+const HEART_TEXTURE: &str = "ui/heart.png";
+const EMPTY_HEART_TEXTURE: &str = "ui/heart_empty.png";
+// Size of the icons' art, and of the text beside them, in their own pixels
+const ICON_PIXELS: f32 = 8.;
+const TEXT_PIXELS: f32 = 8.;
+// Window pixels between the HUD and the window's edges, per icon pixel
+const MARGIN_PIXELS: f32 = 4.;
+// Seconds the level's name is shown for, then spends fading out
+const LEVEL_NAME_TIME: f32 = 3.;
+const LEVEL_NAME_FADE_TIME: f32 = 1.;
+// Of a finished run's comparison with the best time
+const FASTER_COLOR: Color = Color::rgb(0.4, 1., 0.4);
+const SLOWER_COLOR: Color = Color::rgb(1., 0.4, 0.4);
+const COMBO_COLOR: Color = Color::rgb(1., 0.85, 0.3);
+
+// Every node of the HUD, so it can be hidden
+#[derive(Component)]
+struct HudNode;
+
+// Sized with the window
+#[derive(Component)]
+struct HudIcon;
+
+#[derive(Component)]
+struct HeartRow;
+
+// Full while the player's health is more than its index
+#[derive(Component)]
+struct Heart(i32);
+
+#[derive(Component)]
+struct CoinText;
+
+// The score, then the combo while there is one
+#[derive(Component)]
+struct ScoreText;
+
+// Empty until the level is finished
+#[derive(Component)]
+struct LevelCompleteText;
+
+// The time, then how a finished run compares with the best
+#[derive(Component)]
+struct TimerText;
+
+#[derive(Component)]
+struct LevelNameText {
+    // Seconds since the level was spawned
+    shown_for: f32,
+}
+
+struct HudImages {
+    heart: Handle<Image>,
+    empty_heart: Handle<Image>,
+    coin: Handle<Image>,
+}
+
+impl FromWorld for HudImages {
+    fn from_world(world: &mut World) -> Self {
+        let asset_server = world.resource::<AssetServer>();
+        HudImages {
+            heart: asset_server.load(HEART_TEXTURE),
+            empty_heart: asset_server.load(EMPTY_HEART_TEXTURE),
+            coin: asset_server.load(COIN_TEXTURE),
+        }
+    }
+}
+
+// Window pixels per icon pixel, for a window `height` pixels high
+fn hud_scale(height: f32) -> f32 {
+    (height / HEIGHT_PIXELS as f32).floor().max(1.)
+}
+
+// Needs the `CoinCount`, `Score`, `Combo` and `LevelTimer` from `GamePlugin`
+// and the `LevelName` from `LevelPlugin`. Stays hidden while there is a
+// `DebugMode` which is on.
+#[derive(Default)]
+pub struct HudPlugin;
+
+impl Plugin for HudPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<HudImages>()
+            .add_startup_system(spawn_hud)
+            .add_system(heart_system)
+            .add_system(coin_text_system)
+            .add_system(level_name_system)
+            .add_system(timer_text_system)
+            .add_system(score_text_system)
+            .add_system(level_complete_system)
+            .add_system(
+                hud_scale_system
+                    .after(heart_system)
+                    .after(coin_text_system)
+                    .after(level_name_system)
+                    .after(timer_text_system)
+                    .after(score_text_system)
+                    .after(level_complete_system),
+            )
+            .add_system(hud_visibility_system.after(heart_system));
+    }
+}
+
+fn spawn_hud(mut commands: Commands, asset_server: Res<AssetServer>, images: Res<HudImages>) {
+    let text_style = TextStyle {
+        font: asset_server.load(UI_FONT),
+        font_size: TEXT_PIXELS,
+        color: Color::WHITE,
+    };
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    top: Val::Px(MARGIN_PIXELS),
+                    right: Val::Px(MARGIN_PIXELS),
+                    ..default()
+                },
+                // The UI is y-up, so this lists the children top to bottom
+                flex_direction: FlexDirection::ColumnReverse,
+                align_items: AlignItems::FlexEnd,
+                ..default()
+            },
+            color: Color::NONE.into(),
+            ..default()
+        })
+        .insert(HudNode)
+        .with_children(|parent| {
+            parent
+                .spawn_bundle(NodeBundle {
+                    color: Color::NONE.into(),
+                    ..default()
+                })
+                .insert(HeartRow)
+                .insert(HudNode);
+            parent
+                .spawn_bundle(NodeBundle {
+                    style: Style {
+                        align_items: AlignItems::Center,
+                        ..default()
+                    },
+                    color: Color::NONE.into(),
+                    ..default()
+                })
+                .insert(HudNode)
+                .with_children(|parent| {
+                    parent
+                        .spawn_bundle(ImageBundle {
+                            image: images.coin.clone().into(),
+                            ..default()
+                        })
+                        .insert(HudIcon)
+                        .insert(HudNode);
+                    parent
+                        .spawn_bundle(TextBundle {
+                            text: Text::with_section("0", text_style.clone(), default()),
+                            ..default()
+                        })
+                        .insert(CoinText)
+                        .insert(HudNode);
+                });
+            parent
+                .spawn_bundle(TextBundle {
+                    text: Text {
+                        sections: vec![
+                            TextSection {
+                                value: "0".to_string(),
+                                style: text_style.clone(),
+                            },
+                            TextSection {
+                                value: String::new(),
+                                style: TextStyle {
+                                    color: COMBO_COLOR,
+                                    ..text_style.clone()
+                                },
+                            },
+                        ],
+                        ..default()
+                    },
+                    ..default()
+                })
+                .insert(ScoreText)
+                .insert(HudNode);
+        });
+
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.), Val::Percent(100.)),
+                position_type: PositionType::Absolute,
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            color: Color::NONE.into(),
+            ..default()
+        })
+        .insert(HudNode)
+        .with_children(|parent| {
+            parent
+                .spawn_bundle(TextBundle {
+                    text: Text::with_section(
+                        "",
+                        text_style.clone(),
+                        TextAlignment {
+                            horizontal: HorizontalAlign::Center,
+                            ..default()
+                        },
+                    ),
+                    ..default()
+                })
+                .insert(LevelCompleteText)
+                .insert(HudNode);
+        });
+
+    commands
+        .spawn_bundle(TextBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    top: Val::Px(MARGIN_PIXELS),
+                    left: Val::Px(MARGIN_PIXELS),
+                    ..default()
+                },
+                ..default()
+            },
+            text: Text::with_section("", text_style.clone(), default()),
+            ..default()
+        })
+        .insert(LevelNameText {
+            shown_for: LEVEL_NAME_TIME + LEVEL_NAME_FADE_TIME,
+        })
+        .insert(HudNode);
+
+    commands
+        .spawn_bundle(TextBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    bottom: Val::Px(MARGIN_PIXELS),
+                    left: Val::Px(MARGIN_PIXELS),
+                    ..default()
+                },
+                ..default()
+            },
+            text: Text {
+                sections: vec![
+                    TextSection {
+                        value: format_time(0.),
+                        style: text_style.clone(),
+                    },
+                    TextSection {
+                        value: String::new(),
+                        style: text_style,
+                    },
+                ],
+                ..default()
+            },
+            ..default()
+        })
+        .insert(TimerText)
+        .insert(HudNode);
+}
+
+// A heart per point of the first player's maximum health, respawned only if
+// that changes
+fn heart_system(
+    mut commands: Commands,
+    images: Res<HudImages>,
+    player_query: Query<(Entity, &Health), With<Player>>,
+    changed_query: Query<(), (With<Player>, Changed<Health>)>,
+    row_query: Query<(Entity, Option<&Children>), With<HeartRow>>,
+    mut heart_query: Query<(&Heart, &mut UiImage)>,
+) {
+    if changed_query.is_empty() {
+        return;
+    }
+    let health = match player_query.iter().min_by_key(|(entity, _)| entity.id()) {
+        Some((_, health)) => *health,
+        None => return,
+    };
+    let image = |index| {
+        if health.current > index {
+            images.heart.clone()
+        } else {
+            images.empty_heart.clone()
+        }
+    };
+    let (row, children) = match row_query.get_single() {
+        Ok(row) => row,
+        Err(_) => return,
+    };
+    if children.map_or(0, |children| children.len()) != health.max.max(0) as usize {
+        commands.entity(row).despawn_descendants();
+        commands.entity(row).with_children(|parent| {
+            for index in 0..health.max {
+                parent
+                    .spawn_bundle(ImageBundle {
+                        image: image(index).into(),
+                        ..default()
+                    })
+                    .insert(Heart(index))
+                    .insert(HudIcon)
+                    .insert(HudNode);
+            }
+        });
+        return;
+    }
+    for (heart, mut ui_image) in heart_query.iter_mut() {
+        let new_image = image(heart.0);
+        if ui_image.0 != new_image {
+            ui_image.0 = new_image;
+        }
+    }
+}
+
+fn coin_text_system(coin_count: Res<CoinCount>, mut query: Query<&mut Text, With<CoinText>>) {
+    if !coin_count.is_changed() {
+        return;
+    }
+    for mut text in query.iter_mut() {
+        text.sections[0].value = format!("{}", coin_count.0);
+    }
+}
+
+fn timer_text_system(timer: Res<LevelTimer>, mut query: Query<&mut Text, With<TimerText>>) {
+    if !timer.is_changed() {
+        return;
+    }
+    let time = timer.seconds();
+    let (comparison, color) = match timer.comparison {
+        Some(Comparison::FirstFinish) => ("  New best".to_string(), FASTER_COLOR),
+        Some(Comparison::Best(best)) => {
+            let sign = if time < best { '-' } else { '+' };
+            let color = if time < best {
+                FASTER_COLOR
+            } else {
+                SLOWER_COLOR
+            };
+            let difference = format_time((time - best).abs());
+            (
+                format!("  {}{} (best {})", sign, difference, format_time(best)),
+                color,
+            )
+        }
+        None => (String::new(), Color::WHITE),
+    };
+    for mut text in query.iter_mut() {
+        text.sections[0].value = format_time(time);
+        text.sections[1].value = comparison.clone();
+        text.sections[1].style.color = color;
+    }
+}
+
+fn score_text_system(
+    score: Res<Score>,
+    combo: Res<Combo>,
+    mut query: Query<&mut Text, With<ScoreText>>,
+) {
+    if !score.is_changed() && !combo.is_changed() {
+        return;
+    }
+    for mut text in query.iter_mut() {
+        text.sections[0].value = format!("{}", score.0);
+        text.sections[1].value = if combo.multiplier > 1 {
+            format!(" x{}", combo.multiplier)
+        } else {
+            String::new()
+        };
+    }
+}
+
+fn level_complete_system(
+    timer: Res<LevelTimer>,
+    score: Res<Score>,
+    mut query: Query<&mut Text, With<LevelCompleteText>>,
+) {
+    if !timer.is_changed() && !score.is_changed() {
+        return;
+    }
+    let value = if timer.is_finished() {
+        format!(
+            "Level Complete\nTime {}\nScore {}",
+            format_time(timer.seconds()),
+            score.0
+        )
+    } else {
+        String::new()
+    };
+    for mut text in query.iter_mut() {
+        if text.sections[0].value != value {
+            text.sections[0].value = value.clone();
+        }
+    }
+}
+
+// Named by the level, or after its file if it has no name
+fn level_name_system(
+    time: Res<Time>,
+    level_name: Res<LevelName>,
+    current_level: Res<CurrentLevel>,
+    mut query: Query<(&mut LevelNameText, &mut Text)>,
+) {
+    for (mut name_text, mut text) in query.iter_mut() {
+        if level_name.is_changed() {
+            let file_name = || {
+                current_level.path.as_ref().and_then(|path| {
+                    let stem = std::path::Path::new(path).file_stem()?;
+                    Some(stem.to_string_lossy().into_owned())
+                })
+            };
+            text.sections[0].value = level_name.0.clone().or_else(file_name).unwrap_or_default();
+            name_text.shown_for = 0.;
+        } else if name_text.shown_for >= LEVEL_NAME_TIME + LEVEL_NAME_FADE_TIME {
+            continue;
+        } else {
+            name_text.shown_for += time.delta_seconds();
+        }
+        let fade = (name_text.shown_for - LEVEL_NAME_TIME) / LEVEL_NAME_FADE_TIME;
+        text.sections[0].style.color.set_a(1. - fade.clamp(0., 1.));
+    }
+}
+
+// Sizes the icons, text and margins for the window, when it's resized or
+// they are spawned
+fn hud_scale_system(
+    windows: Res<Windows>,
+    mut resized_events: EventReader<WindowResized>,
+    added_query: Query<(), Added<HudNode>>,
+    mut icon_query: Query<&mut Style, With<HudIcon>>,
+    mut text_query: Query<&mut Text, With<HudNode>>,
+    mut positioned_query: Query<&mut Style, (With<HudNode>, Without<HudIcon>)>,
+) {
+    if resized_events.iter().count() == 0 && added_query.is_empty() {
+        return;
+    }
+    let scale = match windows.get_primary() {
+        Some(window) => hud_scale(window.height()),
+        None => return,
+    };
+    for mut style in icon_query.iter_mut() {
+        let size = Val::Px(ICON_PIXELS * scale);
+        style.size = Size::new(size, size);
+        style.margin = Rect::all(Val::Px(scale));
+    }
+    for mut text in text_query.iter_mut() {
+        for section in &mut text.sections {
+            section.style.font_size = TEXT_PIXELS * scale;
+        }
+    }
+    // Only the nodes placed against the window's edges
+    let margin = Val::Px(MARGIN_PIXELS * scale);
+    for mut style in positioned_query.iter_mut() {
+        let position = &mut style.position;
+        for side in [
+            &mut position.top,
+            &mut position.right,
+            &mut position.bottom,
+            &mut position.left,
+        ] {
+            if *side != Val::Undefined {
+                *side = margin;
+            }
+        }
+    }
+}
+
+fn hud_visibility_system(
+    debug_mode: Option<Res<DebugMode>>,
+    added_query: Query<(), Added<HudNode>>,
+    mut query: Query<&mut Visibility, With<HudNode>>,
+) {
+    let changed = debug_mode
+        .as_ref()
+        .is_some_and(|debug_mode| debug_mode.is_changed());
+    if !changed && added_query.is_empty() {
+        return;
+    }
+    let shown = !debug_mode.is_some_and(|debug_mode| debug_mode.0);
+    for mut visibility in query.iter_mut() {
+        visibility.is_visible = shown;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hud_scales_by_whole_numbers() {
+        assert_eq!(hud_scale(HEIGHT_PIXELS as f32), 1.);
+        assert_eq!(hud_scale(1080.), 3.);
+        assert_eq!(hud_scale(1439.), 4.);
+        assert_eq!(hud_scale(100.), 1.);
+    }
+}
@@ -0,0 +1,1327 @@
+// Logical actions and the keys and gamepad buttons bound to them.
+//
+// Bindings are read from `assets/config/input.ron`, which maps actions to
+// lists of key names, gamepad button names and mouse wheel directions, e.g.
+// `keys: { MoveLeft: ["Q"] }`. Names are the variants of bevy's `KeyCode` and
+// `GamepadButtonType`, and of `WheelInput`. A key can also be bound by its
+// physical position, as `Scan(<scan code>)`, so it stays in the same place
+// whatever the keyboard layout; movement and jumping are bound that way by
+// default.
+// Actions missing from the file, or listing a name that isn't recognised,
+// keep their default bindings.
+//
+// Gameplay reads the combined result from `ActionState`, so it doesn't know
+// which device an action came from.
+//
+// In co-op the second player has its own bindings and actions in
+// `SecondPlayerInput`, and the second gamepad is theirs alone.
+//
+// Menus read `MenuActionState` instead, with fixed bindings on the arrows,
+// WASD and every gamepad's d-pad, so rebinding gameplay can't leave a menu
+// unusable.
+
+use bevy::input::keyboard::KeyboardInput;
+use bevy::input::mouse::MouseWheel;
+use bevy::input::{ElementState, InputSystem};
+use bevy::prelude::*;
+use bevy::utils::{HashMap, HashSet};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::respawn::RespawnState;
+
+pub const INPUT_MAP_PATH: &str = "assets/config/input.ron";
+// The gamepad which controls the second player in co-op
+pub const SECOND_PLAYER_GAMEPAD: Gamepad = Gamepad(1);
+// Seconds a menu direction is held before it starts repeating, and between
+// repeats after that
+pub const MENU_REPEAT_DELAY: f32 = 0.4;
+pub const MENU_REPEAT_INTERVAL: f32 = 0.1;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Action {
+    MoveLeft,
+    MoveRight,
+    Jump,
+    // Fast-falls in the air and crouches on the ground
+    FastFall,
+    Run,
+    Dash,
+    // Fires a shot the way the player is facing
+    Attack,
+    // Talks to someone the player is next to
+    Interact,
+    Reset,
+    // Opens the system menu, or quits with the `escape_quits` setting
+    Quit,
+    // Editor actions
+    LevelSelect,
+    ReloadLevel,
+    DefaultLevel,
+    // Moves the level so its tiles start at the origin
+    RecenterLevel,
+    // Mirrors the level left to right
+    FlipLevel,
+    ToggleDebug,
+    ToggleCollisionOutline,
+    CyclePrefab,
+    // Switches the palette the paintbrush's tiles are chosen from
+    CyclePalette,
+    ToggleStampOverwrite,
+    ToggleMirrorX,
+    ToggleMirrorY,
+    ToggleSolid,
+    CycleTileShape,
+    NextTool,
+    PrevTool,
+    NextBrush,
+    PrevBrush,
+    // Use the left click tool, or erase, at the cursor from the keyboard
+    PlaceTile,
+    EraseTile,
+    Pause,
+    ToggleMute,
+    QuickSave,
+    QuickLoad,
+}
+
+// A key's physical position, as reported by the platform
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct ScanCode(pub u32);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum KeyBinding {
+    // The key with this label in the current layout
+    Key(KeyCode),
+    // The key in this position, whatever its label
+    Scan(ScanCode),
+}
+
+impl KeyBinding {
+    pub fn pressed(self, keys: &Input<KeyCode>, scans: &Input<ScanCode>) -> bool {
+        match self {
+            KeyBinding::Key(key) => keys.pressed(key),
+            KeyBinding::Scan(scan) => scans.pressed(scan),
+        }
+    }
+
+    pub fn just_pressed(self, keys: &Input<KeyCode>, scans: &Input<ScanCode>) -> bool {
+        match self {
+            KeyBinding::Key(key) => keys.just_pressed(key),
+            KeyBinding::Scan(scan) => scans.just_pressed(scan),
+        }
+    }
+
+    pub fn just_released(self, keys: &Input<KeyCode>, scans: &Input<ScanCode>) -> bool {
+        match self {
+            KeyBinding::Key(key) => keys.just_released(key),
+            KeyBinding::Scan(scan) => scans.just_released(scan),
+        }
+    }
+}
+
+// Scan codes of QWERTY keys, which winit reports as positions on every
+// desktop platform, although macOS numbers them differently
+#[cfg(not(target_os = "macos"))]
+mod qwerty {
+    pub const A: u32 = 0x1e;
+    pub const S: u32 = 0x1f;
+    pub const D: u32 = 0x20;
+    pub const SPACE: u32 = 0x39;
+    pub const LSHIFT: u32 = 0x2a;
+}
+
+#[cfg(target_os = "macos")]
+mod qwerty {
+    pub const A: u32 = 0x00;
+    pub const S: u32 = 0x01;
+    pub const D: u32 = 0x02;
+    pub const SPACE: u32 = 0x31;
+    pub const LSHIFT: u32 = 0x38;
+}
+
+// Bound by position where scan codes are positions. On the web they are
+// layout dependent, so the key with the QWERTY label is used instead.
+fn positional(key: KeyCode, scan: u32) -> KeyBinding {
+    if cfg!(target_arch = "wasm32") {
+        KeyBinding::Key(key)
+    } else {
+        KeyBinding::Scan(ScanCode(scan))
+    }
+}
+
+// The label each scan code had in the last key event for it, so bindings by
+// position can be shown as the keys the player sees
+#[derive(Default)]
+pub struct KeyLabels {
+    labels: HashMap<ScanCode, KeyCode>,
+}
+
+impl KeyLabels {
+    pub fn label(&self, binding: KeyBinding) -> String {
+        match binding {
+            KeyBinding::Key(key) => format!("{:?}", key),
+            KeyBinding::Scan(scan) => match self.labels.get(&scan) {
+                Some(key) => format!("{:?}", key),
+                None => format!("Scan({})", scan.0),
+            },
+        }
+    }
+}
+
+// A notch of the mouse wheel. The Shift variants are turns made while Shift is
+// held, which don't also count as the plain direction.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum WheelInput {
+    Up,
+    Down,
+    ShiftUp,
+    ShiftDown,
+}
+
+impl Action {
+    pub const ALL: [Action; 34] = [
+        Action::MoveLeft,
+        Action::MoveRight,
+        Action::Jump,
+        Action::FastFall,
+        Action::Run,
+        Action::Dash,
+        Action::Attack,
+        Action::Interact,
+        Action::Reset,
+        Action::Quit,
+        Action::LevelSelect,
+        Action::ReloadLevel,
+        Action::DefaultLevel,
+        Action::RecenterLevel,
+        Action::FlipLevel,
+        Action::ToggleDebug,
+        Action::ToggleCollisionOutline,
+        Action::CyclePrefab,
+        Action::CyclePalette,
+        Action::ToggleStampOverwrite,
+        Action::ToggleMirrorX,
+        Action::ToggleMirrorY,
+        Action::ToggleSolid,
+        Action::CycleTileShape,
+        Action::NextTool,
+        Action::PrevTool,
+        Action::NextBrush,
+        Action::PrevBrush,
+        Action::PlaceTile,
+        Action::EraseTile,
+        Action::Pause,
+        Action::ToggleMute,
+        Action::QuickSave,
+        Action::QuickLoad,
+    ];
+
+    pub fn default_keys(self) -> Vec<KeyBinding> {
+        use KeyBinding::Key;
+        vec![match self {
+            Action::MoveLeft => positional(KeyCode::A, qwerty::A),
+            Action::MoveRight => positional(KeyCode::D, qwerty::D),
+            Action::Jump => positional(KeyCode::Space, qwerty::SPACE),
+            Action::FastFall => positional(KeyCode::S, qwerty::S),
+            Action::Run => positional(KeyCode::LShift, qwerty::LSHIFT),
+            Action::Dash => Key(KeyCode::LControl),
+            Action::Attack => Key(KeyCode::F),
+            // By label, so the prompt to talk names the key pressed
+            Action::Interact => Key(KeyCode::W),
+            Action::Reset => Key(KeyCode::R),
+            Action::Quit => Key(KeyCode::Escape),
+            Action::LevelSelect => Key(KeyCode::L),
+            Action::ReloadLevel => Key(KeyCode::F10),
+            Action::DefaultLevel => Key(KeyCode::F6),
+            Action::RecenterLevel => Key(KeyCode::Home),
+            // H for horizontal, since F7 is held to tune the background
+            Action::FlipLevel => Key(KeyCode::H),
+            Action::ToggleDebug => Key(KeyCode::F1),
+            // F2 cycles the render scale
+            Action::ToggleCollisionOutline => Key(KeyCode::C),
+            Action::CyclePrefab => Key(KeyCode::Tab),
+            // Beside the brackets, which pick within it
+            Action::CyclePalette => Key(KeyCode::Backslash),
+            Action::ToggleStampOverwrite => Key(KeyCode::O),
+            Action::ToggleMirrorX => Key(KeyCode::M),
+            Action::ToggleMirrorY => Key(KeyCode::N),
+            Action::ToggleSolid => Key(KeyCode::K),
+            Action::CycleTileShape => Key(KeyCode::J),
+            Action::NextTool => Key(KeyCode::Period),
+            Action::PrevTool => Key(KeyCode::Comma),
+            Action::NextBrush => Key(KeyCode::RBracket),
+            Action::PrevBrush => Key(KeyCode::LBracket),
+            Action::PlaceTile => Key(KeyCode::Insert),
+            Action::EraseTile => Key(KeyCode::Delete),
+            Action::Pause => Key(KeyCode::P),
+            // V for volume, since M mirrors the editor's strokes and F8
+            // shows the input overlay
+            Action::ToggleMute => Key(KeyCode::V),
+            Action::QuickSave => Key(KeyCode::F5),
+            Action::QuickLoad => Key(KeyCode::F9),
+        }]
+    }
+
+    pub fn default_gamepad_buttons(self) -> &'static [GamepadButtonType] {
+        match self {
+            Action::MoveLeft => &[GamepadButtonType::DPadLeft],
+            Action::MoveRight => &[GamepadButtonType::DPadRight],
+            Action::Jump => &[GamepadButtonType::South],
+            Action::FastFall => &[GamepadButtonType::DPadDown],
+            Action::Run => &[GamepadButtonType::RightTrigger2],
+            Action::Dash => &[GamepadButtonType::West],
+            Action::Attack => &[GamepadButtonType::North],
+            Action::Interact => &[GamepadButtonType::DPadUp],
+            Action::Pause => &[GamepadButtonType::Start],
+            _ => &[],
+        }
+    }
+
+    pub fn default_wheel(self) -> &'static [WheelInput] {
+        match self {
+            Action::NextTool => &[WheelInput::Down],
+            Action::PrevTool => &[WheelInput::Up],
+            Action::NextBrush => &[WheelInput::ShiftDown],
+            Action::PrevBrush => &[WheelInput::ShiftUp],
+            _ => &[],
+        }
+    }
+}
+
+// Keys and buttons may be bound to several actions, in which case they all
+// fire. Besides the bound buttons, the left stick and the d-pad axes move left
+// and right.
+pub struct InputMap {
+    bindings: HashMap<Action, Vec<KeyBinding>>,
+    gamepad_bindings: HashMap<Action, Vec<GamepadButtonType>>,
+    wheel_bindings: HashMap<Action, Vec<WheelInput>>,
+    // How far the left stick has to be pushed to count as a direction, from 0 to 1
+    pub stick_deadzone: f32,
+    // Seconds the reset has to be held before it happens
+    pub reset_hold_time: f32,
+    // Whether tapping a direction twice dashes, as well as the dash action
+    pub double_tap_dash: bool,
+    // Seconds within which the second tap has to follow the first
+    pub double_tap_window: f32,
+    // Whether holding an editor action which places or erases repeats it,
+    // first after `editor_repeat_delay` seconds, then every
+    // `editor_repeat_interval` seconds
+    pub editor_repeat: bool,
+    pub editor_repeat_delay: f32,
+    pub editor_repeat_interval: f32,
+}
+
+impl Default for InputMap {
+    fn default() -> Self {
+        InputMap {
+            bindings: Action::ALL
+                .iter()
+                .map(|&action| (action, action.default_keys()))
+                .collect(),
+            gamepad_bindings: Action::ALL
+                .iter()
+                .map(|&action| (action, action.default_gamepad_buttons().to_vec()))
+                .collect(),
+            wheel_bindings: Action::ALL
+                .iter()
+                .map(|&action| (action, action.default_wheel().to_vec()))
+                .collect(),
+            stick_deadzone: 0.3,
+            reset_hold_time: 0.4,
+            double_tap_dash: true,
+            double_tap_window: 0.25,
+            editor_repeat: true,
+            editor_repeat_delay: 0.3,
+            editor_repeat_interval: 0.05,
+        }
+    }
+}
+
+// The layout of the input config file, with bindings still as names
+#[derive(Default, Deserialize)]
+#[serde(default)]
+struct InputMapFile {
+    keys: HashMap<Action, Vec<String>>,
+    gamepad_buttons: HashMap<Action, Vec<String>>,
+    mouse_wheel: HashMap<Action, Vec<String>>,
+    stick_deadzone: Option<f32>,
+    reset_hold_time: Option<f32>,
+    double_tap_dash: Option<bool>,
+    double_tap_window: Option<f32>,
+    editor_repeat: Option<bool>,
+    editor_repeat_delay: Option<f32>,
+    editor_repeat_interval: Option<f32>,
+}
+
+impl InputMap {
+    // Arrow keys to move, fast-fall and talk, right Ctrl to jump, right Shift
+    // to run, right Alt to dash, slash to attack and Enter to reset, for a
+    // second player sharing the keyboard. Only the player's own actions are
+    // bound.
+    pub fn second_player() -> Self {
+        use KeyBinding::Key;
+        let mut map = InputMap::default();
+        for action in Action::ALL {
+            let keys = match action {
+                Action::MoveLeft => vec![Key(KeyCode::Left)],
+                Action::MoveRight => vec![Key(KeyCode::Right)],
+                Action::Jump => vec![Key(KeyCode::RControl)],
+                Action::FastFall => vec![Key(KeyCode::Down)],
+                Action::Run => vec![Key(KeyCode::RShift)],
+                Action::Dash => vec![Key(KeyCode::RAlt)],
+                Action::Attack => vec![Key(KeyCode::Slash)],
+                Action::Interact => vec![Key(KeyCode::Up)],
+                Action::Reset => vec![Key(KeyCode::Return)],
+                _ => Vec::new(),
+            };
+            if !matches!(
+                action,
+                Action::MoveLeft
+                    | Action::MoveRight
+                    | Action::Jump
+                    | Action::FastFall
+                    | Action::Run
+                    | Action::Dash
+                    | Action::Attack
+                    | Action::Interact
+            ) {
+                map.bind_gamepad(action, Vec::new());
+            }
+            map.bind(action, keys);
+            map.bind_wheel(action, Vec::new());
+        }
+        map
+    }
+
+    // Read the bindings at `path`, falling back to the defaults where needed
+    pub fn load(path: &str) -> Self {
+        let mut map = InputMap::default();
+        let text = match std::fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(err) => {
+                // There is no filesystem on wasm, so only the defaults exist there
+                if !cfg!(target_arch = "wasm32") {
+                    warn!("{}: {}, using default key bindings", path, err);
+                }
+                return map;
+            }
+        };
+        let file: InputMapFile = match ron::from_str(&text) {
+            Ok(file) => file,
+            Err(err) => {
+                warn!("{}: {}, using default key bindings", path, err);
+                return map;
+            }
+        };
+        for (action, names) in file.keys {
+            match parse_names(path, action, &names, parse_key_binding) {
+                Some(keys) => map.bind(action, keys),
+                None => continue,
+            }
+        }
+        for (action, names) in file.gamepad_buttons {
+            match parse_names(path, action, &names, parse_name) {
+                Some(buttons) => map.bind_gamepad(action, buttons),
+                None => continue,
+            }
+        }
+        for (action, names) in file.mouse_wheel {
+            match parse_names(path, action, &names, parse_name) {
+                Some(wheel) => map.bind_wheel(action, wheel),
+                None => continue,
+            }
+        }
+        if let Some(deadzone) = file.stick_deadzone {
+            map.stick_deadzone = deadzone.clamp(0., 1.);
+        }
+        if let Some(hold_time) = file.reset_hold_time {
+            map.reset_hold_time = hold_time.max(0.);
+        }
+        if let Some(double_tap_dash) = file.double_tap_dash {
+            map.double_tap_dash = double_tap_dash;
+        }
+        if let Some(window) = file.double_tap_window {
+            map.double_tap_window = window.max(0.);
+        }
+        if let Some(editor_repeat) = file.editor_repeat {
+            map.editor_repeat = editor_repeat;
+        }
+        if let Some(delay) = file.editor_repeat_delay {
+            map.editor_repeat_delay = delay.max(0.);
+        }
+        if let Some(interval) = file.editor_repeat_interval {
+            map.editor_repeat_interval = interval.max(0.01);
+        }
+        map
+    }
+
+    pub fn bind(&mut self, action: Action, keys: Vec<KeyBinding>) {
+        self.bindings.insert(action, keys);
+    }
+
+    pub fn bind_gamepad(&mut self, action: Action, buttons: Vec<GamepadButtonType>) {
+        self.gamepad_bindings.insert(action, buttons);
+    }
+
+    pub fn bind_wheel(&mut self, action: Action, wheel: Vec<WheelInput>) {
+        self.wheel_bindings.insert(action, wheel);
+    }
+
+    pub fn keys(&self, action: Action) -> &[KeyBinding] {
+        self.bindings.get(&action).map_or(&[], Vec::as_slice)
+    }
+
+    pub fn gamepad_buttons(&self, action: Action) -> &[GamepadButtonType] {
+        self.gamepad_bindings
+            .get(&action)
+            .map_or(&[], Vec::as_slice)
+    }
+
+    pub fn wheel(&self, action: Action) -> &[WheelInput] {
+        self.wheel_bindings.get(&action).map_or(&[], Vec::as_slice)
+    }
+
+    pub fn pressed(&self, action: Action, keys: &Input<KeyCode>, scans: &Input<ScanCode>) -> bool {
+        self.keys(action)
+            .iter()
+            .any(|binding| binding.pressed(keys, scans))
+    }
+
+    pub fn just_pressed(
+        &self,
+        action: Action,
+        keys: &Input<KeyCode>,
+        scans: &Input<ScanCode>,
+    ) -> bool {
+        self.keys(action)
+            .iter()
+            .any(|binding| binding.just_pressed(keys, scans))
+    }
+
+    // Only once no bound key is held any more
+    pub fn just_released(
+        &self,
+        action: Action,
+        keys: &Input<KeyCode>,
+        scans: &Input<ScanCode>,
+    ) -> bool {
+        self.keys(action)
+            .iter()
+            .any(|binding| binding.just_released(keys, scans))
+            && !self.pressed(action, keys, scans)
+    }
+}
+
+// `KeyCode`, `GamepadButtonType` and `WheelInput` have unit variants, which RON
+// writes as bare names
+fn parse_name<T: DeserializeOwned>(name: &str) -> Option<T> {
+    ron::from_str(name).ok()
+}
+
+// A `KeyBinding`, or a bare `KeyCode` name as shorthand for `Key(<name>)`
+fn parse_key_binding(name: &str) -> Option<KeyBinding> {
+    parse_name(name).or_else(|| parse_name(name).map(KeyBinding::Key))
+}
+
+// Parse every name bound to `action`, warning and returning None if one is unknown
+fn parse_names<T>(
+    path: &str,
+    action: Action,
+    names: &[String],
+    parse: fn(&str) -> Option<T>,
+) -> Option<Vec<T>> {
+    let parsed: Result<Vec<T>, &String> =
+        names.iter().map(|name| parse(name).ok_or(name)).collect();
+    match parsed {
+        Ok(parsed) => Some(parsed),
+        Err(name) => {
+            warn!(
+                "{}: unknown input {:?} for {:?}, using its default bindings",
+                path, name, action
+            );
+            None
+        }
+    }
+}
+
+// Which actions are held, combined over the keyboard and every connected
+// gamepad. Updated at the start of every frame.
+//
+// `just_pressed` and `just_released` describe the frame, so they suit systems
+// which run once per frame. Fixed timestep systems may run several times in a
+// frame or not at all, so they use the `step_` variants instead: every edge is
+// seen by exactly one step, the first to begin after it happened.
+#[derive(Default)]
+pub struct ActionState {
+    pressed: HashSet<Action>,
+    just_pressed: HashSet<Action>,
+    just_released: HashSet<Action>,
+    // Edges since the last step began
+    unstepped_presses: HashSet<Action>,
+    unstepped_releases: HashSet<Action>,
+    step_just_pressed: HashSet<Action>,
+    step_just_released: HashSet<Action>,
+}
+
+impl ActionState {
+    pub fn pressed(&self, action: Action) -> bool {
+        self.pressed.contains(&action)
+    }
+
+    pub fn just_pressed(&self, action: Action) -> bool {
+        self.just_pressed.contains(&action)
+    }
+
+    pub fn just_released(&self, action: Action) -> bool {
+        self.just_released.contains(&action)
+    }
+
+    // Whether the action was pressed since the previous step. A press and
+    // release between two steps shows up as both.
+    pub fn step_just_pressed(&self, action: Action) -> bool {
+        self.step_just_pressed.contains(&action)
+    }
+
+    pub fn step_just_released(&self, action: Action) -> bool {
+        self.step_just_released.contains(&action)
+    }
+
+    // Replace the held actions, deriving the edges from the previous update
+    pub fn update(&mut self, pressed: HashSet<Action>) {
+        self.just_pressed = pressed.difference(&self.pressed).copied().collect();
+        self.just_released = self.pressed.difference(&pressed).copied().collect();
+        self.unstepped_presses
+            .extend(self.just_pressed.iter().copied());
+        self.unstepped_releases
+            .extend(self.just_released.iter().copied());
+        self.pressed = pressed;
+    }
+
+    // Forget the edges no step has seen yet, e.g. those from while the steps
+    // were stopped, so they don't fire once the steps start again
+    pub fn discard_unstepped(&mut self) {
+        self.unstepped_presses.clear();
+        self.unstepped_releases.clear();
+    }
+
+    // Forget this frame's edges as well as the unstepped ones, so none of
+    // them reach anything else this frame
+    pub fn swallow(&mut self) {
+        self.just_pressed.clear();
+        self.just_released.clear();
+        self.discard_unstepped();
+    }
+
+    // Hand the edges gathered since the previous step to the step beginning now
+    pub fn begin_step(&mut self) {
+        self.step_just_pressed = std::mem::take(&mut self.unstepped_presses);
+        self.step_just_released = std::mem::take(&mut self.unstepped_releases);
+    }
+}
+
+// Run first in the fixed timestep set whose systems read the `step_` edges.
+// While a respawn has the input locked, the steps get none.
+pub fn begin_action_step_system(
+    respawn: Res<RespawnState>,
+    mut action_state: ResMut<ActionState>,
+    second_player: Option<ResMut<SecondPlayerInput>>,
+) {
+    let locked = respawn.input_locked();
+    if locked {
+        action_state.discard_unstepped();
+    }
+    action_state.begin_step();
+    if let Some(mut second_player) = second_player {
+        if locked {
+            second_player.action_state.discard_unstepped();
+        }
+        second_player.action_state.begin_step();
+    }
+}
+
+// Recognizes a direction tapped twice in quick succession, for dashing
+// without the dash button. Tapping the other direction in between starts
+// over, so quickly turning around doesn't count.
+#[derive(Component, Default)]
+pub struct DoubleTap {
+    // The direction last tapped, and seconds since
+    last: Option<(Action, f32)>,
+}
+
+impl DoubleTap {
+    // Advance by a step `delta` seconds long, returning the direction tapped
+    // a second time in it, within `window` seconds of the first tap
+    pub fn step(&mut self, action_state: &ActionState, delta: f32, window: f32) -> Option<Action> {
+        if let Some((_, since)) = &mut self.last {
+            *since += delta;
+        }
+        let mut tapped_twice = None;
+        for action in [Action::MoveLeft, Action::MoveRight] {
+            if !action_state.step_just_pressed(action) {
+                continue;
+            }
+            match self.last {
+                Some((last, since)) if last == action && since <= window => {
+                    // A third tap starts a new pair
+                    self.last = None;
+                    tapped_twice = Some(action);
+                }
+                _ => self.last = Some((action, 0.)),
+            }
+        }
+        tapped_twice
+    }
+}
+
+// Repeats a held editor action, like a key held in a text field: it fires
+// when pressed, then again at intervals once held long enough. The
+// `ActionState` is left as it is, so gameplay actions only ever see the one
+// press.
+#[derive(Default)]
+pub struct KeyRepeat {
+    // Seconds the action has been held
+    held: Option<f32>,
+}
+
+impl KeyRepeat {
+    // Advance by a step `delta` seconds long, returning whether the action
+    // fires in it
+    pub fn step(
+        &mut self,
+        action_state: &ActionState,
+        action: Action,
+        input_map: &InputMap,
+        delta: f32,
+    ) -> bool {
+        if action_state.step_just_pressed(action) {
+            self.held = Some(0.);
+            return true;
+        }
+        let held = match &mut self.held {
+            Some(held) if action_state.pressed(action) => held,
+            _ => {
+                self.held = None;
+                return false;
+            }
+        };
+        // How many times an action held this long has repeated
+        let repeats = |held: f32| {
+            if held < input_map.editor_repeat_delay {
+                0
+            } else {
+                ((held - input_map.editor_repeat_delay) / input_map.editor_repeat_interval) as u32
+                    + 1
+            }
+        };
+        let before = repeats(*held);
+        *held += delta;
+        input_map.editor_repeat && repeats(*held) > before
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum MenuAction {
+    Up,
+    Down,
+    Confirm,
+    Back,
+}
+
+impl MenuAction {
+    pub const ALL: [MenuAction; 4] = [
+        MenuAction::Up,
+        MenuAction::Down,
+        MenuAction::Confirm,
+        MenuAction::Back,
+    ];
+
+    pub fn keys(self) -> &'static [KeyCode] {
+        match self {
+            MenuAction::Up => &[KeyCode::Up, KeyCode::W],
+            MenuAction::Down => &[KeyCode::Down, KeyCode::S],
+            MenuAction::Confirm => &[KeyCode::Return, KeyCode::Space],
+            MenuAction::Back => &[KeyCode::Escape, KeyCode::Back],
+        }
+    }
+
+    pub fn gamepad_buttons(self) -> &'static [GamepadButtonType] {
+        match self {
+            MenuAction::Up => &[GamepadButtonType::DPadUp],
+            MenuAction::Down => &[GamepadButtonType::DPadDown],
+            MenuAction::Confirm => &[GamepadButtonType::South],
+            MenuAction::Back => &[GamepadButtonType::East],
+        }
+    }
+
+    // Whether holding it keeps pressing it again, to scroll through a list
+    fn repeats(self) -> bool {
+        matches!(self, MenuAction::Up | MenuAction::Down)
+    }
+}
+
+// Which menu actions are held, updated at the start of every frame like
+// `ActionState`
+#[derive(Default)]
+pub struct MenuActionState {
+    // Seconds each held action has been held
+    held: HashMap<MenuAction, f32>,
+    just_pressed: HashSet<MenuAction>,
+}
+
+impl MenuActionState {
+    pub fn pressed(&self, action: MenuAction) -> bool {
+        self.held.contains_key(&action)
+    }
+
+    // Pressed this frame, or repeated this frame by being held
+    pub fn just_pressed(&self, action: MenuAction) -> bool {
+        self.just_pressed.contains(&action)
+    }
+
+    // Replace the held actions, `delta` seconds after the previous update
+    pub fn update(&mut self, pressed: HashSet<MenuAction>, delta: f32) {
+        // How many times an action held this long has repeated
+        let repeats = |held: f32| {
+            if held < MENU_REPEAT_DELAY {
+                0
+            } else {
+                ((held - MENU_REPEAT_DELAY) / MENU_REPEAT_INTERVAL) as u32 + 1
+            }
+        };
+        self.just_pressed.clear();
+        self.held.retain(|action, _| pressed.contains(action));
+        for action in pressed {
+            match self.held.get_mut(&action) {
+                Some(held) => {
+                    let before = repeats(*held);
+                    *held += delta;
+                    if action.repeats() && repeats(*held) > before {
+                        self.just_pressed.insert(action);
+                    }
+                }
+                None => {
+                    self.held.insert(action, 0.);
+                    self.just_pressed.insert(action);
+                }
+            }
+        }
+    }
+
+    // Forget this frame's presses, e.g. when the menu they were meant for
+    // has just closed
+    pub fn swallow(&mut self) {
+        self.just_pressed.clear();
+    }
+}
+
+// Which player controls an entity, 0 being the first
+#[derive(Component, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct PlayerId(pub usize);
+
+// The second player's bindings and the actions they hold, present only in
+// co-op. The first player keeps `InputMap` and `ActionState`, which also
+// carry the editor actions.
+pub struct SecondPlayerInput {
+    pub input_map: InputMap,
+    pub action_state: ActionState,
+}
+
+impl Default for SecondPlayerInput {
+    fn default() -> Self {
+        SecondPlayerInput {
+            input_map: InputMap::second_player(),
+            action_state: ActionState::default(),
+        }
+    }
+}
+
+impl SecondPlayerInput {
+    // The actions of the player with this id, if they have any
+    pub fn actions<'a>(
+        id: PlayerId,
+        first: &'a ActionState,
+        second: Option<&'a SecondPlayerInput>,
+    ) -> Option<&'a ActionState> {
+        match id.0 {
+            0 => Some(first),
+            1 => second.map(|second| &second.action_state),
+            _ => None,
+        }
+    }
+}
+
+// Systems which update `ActionState`. A system which replaces the devices as
+// the source of actions should share the label.
+#[derive(Clone, Hash, Debug, PartialEq, Eq, SystemLabel)]
+pub struct ActionStateUpdate;
+
+// While present, the keyboard and gamepads are not read
+pub struct IgnoreDevices;
+
+// Actions held through on-screen controls, combined with the first player's
+// devices each frame
+#[derive(Default)]
+pub struct VirtualInput {
+    pub pressed: HashSet<Action>,
+    // Whether the mouse is over an on-screen control, so clicks on it aren't
+    // also taken as clicks on the world
+    pub pointer_over: bool,
+}
+
+#[derive(Default)]
+pub struct InputMapPlugin;
+
+impl Plugin for InputMapPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(InputMap::load(INPUT_MAP_PATH))
+            .init_resource::<ActionState>()
+            .init_resource::<Input<ScanCode>>()
+            .init_resource::<KeyLabels>()
+            .init_resource::<VirtualInput>()
+            .init_resource::<MenuActionState>()
+            .add_system_to_stage(
+                CoreStage::PreUpdate,
+                scan_code_input_system.after(InputSystem),
+            )
+            .add_system_to_stage(
+                CoreStage::PreUpdate,
+                update_action_state_system
+                    .label(ActionStateUpdate)
+                    .after(scan_code_input_system),
+            )
+            .add_system_to_stage(
+                CoreStage::PreUpdate,
+                update_second_player_system
+                    .label(ActionStateUpdate)
+                    .after(scan_code_input_system),
+            )
+            .add_system_to_stage(
+                CoreStage::PreUpdate,
+                update_menu_action_system.after(InputSystem),
+            );
+    }
+}
+
+// Bevy only tracks keys by `KeyCode`, so do the same by scan code
+fn scan_code_input_system(
+    mut scans: ResMut<Input<ScanCode>>,
+    mut labels: ResMut<KeyLabels>,
+    mut events: EventReader<KeyboardInput>,
+) {
+    scans.clear();
+    for event in events.iter() {
+        let scan = ScanCode(event.scan_code);
+        match event.state {
+            ElementState::Pressed => scans.press(scan),
+            ElementState::Released => scans.release(scan),
+        }
+        if let Some(key) = event.key_code {
+            labels.labels.insert(scan, key);
+        }
+    }
+}
+
+// The wheel directions turned this frame. A turn holds its actions for a
+// single frame, however many notches it was.
+fn wheel_inputs<'a>(
+    events: impl Iterator<Item = &'a MouseWheel>,
+    keyboard_input: &Input<KeyCode>,
+) -> HashSet<WheelInput> {
+    let shift = keyboard_input.any_pressed([KeyCode::LShift, KeyCode::RShift]);
+    events
+        .filter(|event| event.y != 0.)
+        .map(|event| match (event.y > 0., shift) {
+            (true, false) => WheelInput::Up,
+            (false, false) => WheelInput::Down,
+            (true, true) => WheelInput::ShiftUp,
+            (false, true) => WheelInput::ShiftDown,
+        })
+        .collect()
+}
+
+// The devices actions are read from
+struct Devices<'a> {
+    keyboard: &'a Input<KeyCode>,
+    scans: &'a Input<ScanCode>,
+    gamepad_buttons: &'a Input<GamepadButton>,
+    gamepad_axes: &'a Axis<GamepadAxis>,
+}
+
+impl Devices<'_> {
+    // The actions `input_map` binds to anything held on the keyboard, on
+    // `gamepads` or among the `wheel` turns
+    fn pressed_actions(
+        &self,
+        input_map: &InputMap,
+        gamepads: &[Gamepad],
+        wheel: &HashSet<WheelInput>,
+    ) -> HashSet<Action> {
+        let stick_x = |gamepad: Gamepad| {
+            let stick = self
+                .gamepad_axes
+                .get(GamepadAxis(gamepad, GamepadAxisType::LeftStickX))
+                .unwrap_or(0.);
+            let dpad = self
+                .gamepad_axes
+                .get(GamepadAxis(gamepad, GamepadAxisType::DPadX))
+                .unwrap_or(0.);
+            if stick.abs() > input_map.stick_deadzone {
+                stick
+            } else {
+                dpad
+            }
+        };
+        Action::ALL
+            .iter()
+            .copied()
+            .filter(|&action| {
+                input_map.pressed(action, self.keyboard, self.scans)
+                    || input_map
+                        .wheel(action)
+                        .iter()
+                        .any(|input| wheel.contains(input))
+                    || gamepads.iter().any(|&gamepad| {
+                        let x = stick_x(gamepad);
+                        input_map.gamepad_buttons(action).iter().any(|&button| {
+                            self.gamepad_buttons.pressed(GamepadButton(gamepad, button))
+                        }) || (action == Action::MoveLeft && x < -input_map.stick_deadzone)
+                            || (action == Action::MoveRight && x > input_map.stick_deadzone)
+                    })
+            })
+            .collect()
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn update_action_state_system(
+    input_map: Res<InputMap>,
+    keyboard_input: Res<Input<KeyCode>>,
+    scan_input: Res<Input<ScanCode>>,
+    gamepads: Res<Gamepads>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
+    gamepad_axes: Res<Axis<GamepadAxis>>,
+    mut mouse_wheel: EventReader<MouseWheel>,
+    ignore_devices: Option<Res<IgnoreDevices>>,
+    second_player: Option<Res<SecondPlayerInput>>,
+    virtual_input: Res<VirtualInput>,
+    mut action_state: ResMut<ActionState>,
+) {
+    // Read even when ignored, so old turns don't fire once the devices are back
+    let wheel = wheel_inputs(mouse_wheel.iter(), &keyboard_input);
+    if ignore_devices.is_some() {
+        return;
+    }
+    let devices = Devices {
+        keyboard: &keyboard_input,
+        scans: &scan_input,
+        gamepad_buttons: &gamepad_buttons,
+        gamepad_axes: &gamepad_axes,
+    };
+    // Only connected gamepads are read, so a disconnected one releases its actions
+    let gamepads: Vec<Gamepad> = gamepads
+        .iter()
+        .copied()
+        .filter(|&gamepad| second_player.is_none() || gamepad != SECOND_PLAYER_GAMEPAD)
+        .collect();
+    let mut pressed = devices.pressed_actions(&input_map, &gamepads, &wheel);
+    pressed.extend(virtual_input.pressed.iter().copied());
+    action_state.update(pressed);
+}
+
+fn update_second_player_system(
+    keyboard_input: Res<Input<KeyCode>>,
+    scan_input: Res<Input<ScanCode>>,
+    gamepads: Res<Gamepads>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
+    gamepad_axes: Res<Axis<GamepadAxis>>,
+    ignore_devices: Option<Res<IgnoreDevices>>,
+    second_player: Option<ResMut<SecondPlayerInput>>,
+) {
+    let mut second_player = match second_player {
+        Some(second_player) if ignore_devices.is_none() => second_player,
+        _ => return,
+    };
+    let devices = Devices {
+        keyboard: &keyboard_input,
+        scans: &scan_input,
+        gamepad_buttons: &gamepad_buttons,
+        gamepad_axes: &gamepad_axes,
+    };
+    let gamepads: Vec<Gamepad> = gamepads
+        .iter()
+        .copied()
+        .filter(|&gamepad| gamepad == SECOND_PLAYER_GAMEPAD)
+        .collect();
+    let pressed = devices.pressed_actions(&second_player.input_map, &gamepads, &HashSet::default());
+    second_player.action_state.update(pressed);
+}
+
+// Every gamepad steers menus, the second player's included
+fn update_menu_action_system(
+    time: Res<Time>,
+    keyboard_input: Res<Input<KeyCode>>,
+    gamepads: Res<Gamepads>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
+    mut menu_actions: ResMut<MenuActionState>,
+) {
+    let pressed = MenuAction::ALL
+        .into_iter()
+        .filter(|action| {
+            action.keys().iter().any(|&key| keyboard_input.pressed(key))
+                || gamepads.iter().any(|&gamepad| {
+                    action
+                        .gamepad_buttons()
+                        .iter()
+                        .any(|&button| gamepad_buttons.pressed(GamepadButton(gamepad, button)))
+                })
+        })
+        .collect();
+    menu_actions.update(pressed, time.delta_seconds());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diagnostics::TOGGLE_DIAGNOSTICS_KEY;
+    use crate::display::{CYCLE_PRESENT_MODE_KEY, CYCLE_RENDER_SCALE_KEY};
+    use crate::input_overlay::TOGGLE_INPUT_OVERLAY_KEY;
+
+    #[test]
+    fn parses_key_names() {
+        assert_eq!(parse_name("Q"), Some(KeyCode::Q));
+        assert_eq!(parse_name("Space"), Some(KeyCode::Space));
+        assert_eq!(parse_name::<KeyCode>("Spacebar"), None);
+        assert_eq!(parse_name("South"), Some(GamepadButtonType::South));
+        assert_eq!(parse_name("ShiftUp"), Some(WheelInput::ShiftUp));
+    }
+
+    #[test]
+    fn parses_key_bindings() {
+        assert_eq!(parse_key_binding("Q"), Some(KeyBinding::Key(KeyCode::Q)));
+        assert_eq!(
+            parse_key_binding("Key(Q)"),
+            Some(KeyBinding::Key(KeyCode::Q))
+        );
+        assert_eq!(
+            parse_key_binding("Scan(30)"),
+            Some(KeyBinding::Scan(ScanCode(30)))
+        );
+        assert_eq!(parse_key_binding("Scan(Q)"), None);
+    }
+
+    #[test]
+    fn shared_key_fires_both_actions() {
+        let mut map = InputMap::default();
+        map.bind(Action::Reset, vec![KeyBinding::Key(KeyCode::Space)]);
+        map.bind(Action::Jump, vec![KeyBinding::Key(KeyCode::Space)]);
+        let mut keys = Input::<KeyCode>::default();
+        keys.press(KeyCode::Space);
+        let scans = Input::<ScanCode>::default();
+        assert!(map.just_pressed(Action::Jump, &keys, &scans));
+        assert!(map.just_pressed(Action::Reset, &keys, &scans));
+    }
+
+    #[test]
+    fn default_bindings_share_no_keys() {
+        // Keys read directly rather than through an action, including the F7
+        // the binary holds to tune the background
+        let fixed = [
+            TOGGLE_INPUT_OVERLAY_KEY,
+            CYCLE_RENDER_SCALE_KEY,
+            CYCLE_PRESENT_MODE_KEY,
+            TOGGLE_DIAGNOSTICS_KEY,
+            KeyCode::F7,
+        ];
+        for map in [InputMap::default(), InputMap::load(INPUT_MAP_PATH)] {
+            let mut bound: HashMap<KeyBinding, Option<Action>> = fixed
+                .into_iter()
+                .map(|key| (KeyBinding::Key(key), None))
+                .collect();
+            for action in Action::ALL {
+                for &binding in &map.bindings[&action] {
+                    if let Some(other) = bound.insert(binding, Some(action)) {
+                        let other =
+                            other.map_or("a fixed key".to_string(), |other| format!("{:?}", other));
+                        panic!("{:?} is bound to {:?} and {}", binding, action, other);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn scan_binding_ignores_the_layout() {
+        let mut map = InputMap::default();
+        map.bind(Action::MoveLeft, vec![KeyBinding::Scan(ScanCode(0x1e))]);
+        // The key in QWERTY's A position, labelled Q on AZERTY
+        let mut keys = Input::<KeyCode>::default();
+        keys.press(KeyCode::Q);
+        let mut scans = Input::<ScanCode>::default();
+        scans.press(ScanCode(0x1e));
+        assert!(map.pressed(Action::MoveLeft, &keys, &scans));
+        scans.release(ScanCode(0x1e));
+        keys.press(KeyCode::A);
+        assert!(!map.pressed(Action::MoveLeft, &keys, &scans));
+    }
+
+    // Run one frame of input followed by `steps` fixed steps, returning how
+    // many of the steps saw `action` pressed
+    fn frame(
+        action_state: &mut ActionState,
+        held: &[Action],
+        steps: usize,
+        action: Action,
+    ) -> usize {
+        action_state.update(held.iter().copied().collect());
+        (0..steps)
+            .filter(|_| {
+                action_state.begin_step();
+                action_state.step_just_pressed(action)
+            })
+            .count()
+    }
+
+    #[test]
+    fn press_during_frame_without_steps_fires_on_next_step() {
+        let mut action_state = ActionState::default();
+        assert_eq!(
+            frame(&mut action_state, &[Action::Jump], 0, Action::Jump),
+            0
+        );
+        assert_eq!(
+            frame(&mut action_state, &[Action::Jump], 3, Action::Jump),
+            1
+        );
+        assert_eq!(
+            frame(&mut action_state, &[Action::Jump], 3, Action::Jump),
+            0
+        );
+    }
+
+    #[test]
+    fn press_fires_once_across_three_steps() {
+        let mut action_state = ActionState::default();
+        assert_eq!(frame(&mut action_state, &[], 3, Action::Jump), 0);
+        assert_eq!(
+            frame(&mut action_state, &[Action::Jump], 3, Action::Jump),
+            1
+        );
+        assert_eq!(frame(&mut action_state, &[], 3, Action::Jump), 0);
+        assert_eq!(
+            frame(&mut action_state, &[Action::Jump], 3, Action::Jump),
+            1
+        );
+    }
+
+    #[test]
+    fn tap_between_steps_is_pressed_and_released() {
+        let mut action_state = ActionState::default();
+        frame(&mut action_state, &[Action::Jump], 0, Action::Jump);
+        frame(&mut action_state, &[], 0, Action::Jump);
+        action_state.begin_step();
+        assert!(action_state.step_just_pressed(Action::Jump));
+        assert!(action_state.step_just_released(Action::Jump));
+        assert!(!action_state.pressed(Action::Jump));
+    }
+
+    #[test]
+    fn held_menu_direction_repeats_after_a_delay() {
+        let mut menu_actions = MenuActionState::default();
+        let held = || {
+            [MenuAction::Down, MenuAction::Confirm]
+                .into_iter()
+                .collect()
+        };
+        menu_actions.update(held(), 0.);
+        assert!(menu_actions.just_pressed(MenuAction::Down));
+        assert!(menu_actions.just_pressed(MenuAction::Confirm));
+
+        // Frames of 50ms, to halfway between the second and third repeats
+        let mut repeats = 0;
+        for _ in 0..11 {
+            menu_actions.update(held(), 0.05);
+            repeats += menu_actions.just_pressed(MenuAction::Down) as u32;
+            assert!(!menu_actions.just_pressed(MenuAction::Confirm));
+        }
+        assert_eq!(repeats, 2);
+
+        menu_actions.update(HashSet::default(), 0.05);
+        assert!(!menu_actions.pressed(MenuAction::Down));
+        menu_actions.update(held(), 0.05);
+        assert!(menu_actions.just_pressed(MenuAction::Down));
+    }
+
+    #[test]
+    fn held_editor_actions_repeat_after_the_delay() {
+        let input_map = InputMap::default();
+        let mut action_state = ActionState::default();
+        let mut repeat = KeyRepeat::default();
+        let mut step = |action_state: &mut ActionState, pressed: bool| {
+            let pressed = pressed.then_some(Action::PlaceTile);
+            action_state.update(pressed.into_iter().collect());
+            action_state.begin_step();
+            repeat.step(action_state, Action::PlaceTile, &input_map, 0.01)
+        };
+        assert!(step(&mut action_state, true));
+        // Nothing more until the delay has passed, then once an interval
+        let fired: Vec<usize> = (1..=42).filter(|_| step(&mut action_state, true)).collect();
+        assert_eq!(fired.len(), 3, "fired at {:?}", fired);
+        assert!(!step(&mut action_state, false));
+        assert!(step(&mut action_state, true));
+
+        // Off, only the press fires
+        let input_map = InputMap {
+            editor_repeat: false,
+            ..InputMap::default()
+        };
+        let mut action_state = ActionState::default();
+        let mut repeat = KeyRepeat::default();
+        let fired = (0..100)
+            .filter(|_| {
+                action_state.update([Action::PlaceTile].into_iter().collect());
+                action_state.begin_step();
+                repeat.step(&action_state, Action::PlaceTile, &input_map, 0.01)
+            })
+            .count();
+        assert_eq!(fired, 1);
+    }
+
+    #[test]
+    fn double_tap_needs_the_same_direction_twice_in_the_window() {
+        let step = 0.05;
+        // Tap each of `taps` in turn, a step apart with a step of release
+        // in between, returning the taps recognized as doubles
+        let tap = |double_tap: &mut DoubleTap, taps: &[Option<Action>]| {
+            let mut action_state = ActionState::default();
+            let mut doubles = Vec::new();
+            for &pressed in taps {
+                action_state.update(pressed.into_iter().collect());
+                action_state.begin_step();
+                doubles.extend(double_tap.step(&action_state, step, 0.25));
+            }
+            doubles
+        };
+        let (left, right) = (Some(Action::MoveLeft), Some(Action::MoveRight));
+
+        let mut double_tap = DoubleTap::default();
+        assert_eq!(
+            tap(&mut double_tap, &[left, None, left]),
+            [Action::MoveLeft]
+        );
+        // Turning around quickly isn't a double tap
+        let mut double_tap = DoubleTap::default();
+        assert_eq!(tap(&mut double_tap, &[left, None, right]), []);
+        assert_eq!(tap(&mut double_tap, &[None, left]), []);
+        // Too slow
+        let mut double_tap = DoubleTap::default();
+        assert_eq!(
+            tap(
+                &mut double_tap,
+                &[right, None, None, None, None, None, right]
+            ),
+            []
+        );
+    }
+}
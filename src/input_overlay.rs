@@ -0,0 +1,140 @@
+// An overlay showing what the game thinks is being pressed, toggled with F8.
+//
+// It reads the same `ActionState` and `Mobility` as gameplay, so anything it
+// shows which doesn't match what was felt is a real bug. Like the diagnostics
+// overlay it is drawn by bevy_ui at the window's native resolution.
+//
+// The only input timer so far is the wall jump's coyote time, shown as a bar
+// which empties as it runs out.
+
+use bevy::prelude::*;
+use std::fmt::Write;
+
+use crate::input::{Action, ActionState, PlayerId};
+use crate::physics::Mobility;
+use crate::pixel_perfect::UI_FONT;
+
+pub const TOGGLE_INPUT_OVERLAY_KEY: KeyCode = KeyCode::F8;
+
+// Actions the player's movement reads; the editor's are left out
+const SHOWN_ACTIONS: [Action; 8] = [
+    Action::MoveLeft,
+    Action::MoveRight,
+    Action::Jump,
+    Action::FastFall,
+    Action::Run,
+    Action::Dash,
+    Action::Attack,
+    Action::Reset,
+];
+// Characters in a full timer bar
+const BAR_WIDTH: usize = 20;
+
+#[derive(Component)]
+struct InputOverlay;
+
+// Needs the `ActionState` from `InputMapPlugin`
+#[derive(Default)]
+pub struct InputOverlayPlugin;
+
+impl Plugin for InputOverlayPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_startup_system(spawn_overlay)
+            .add_system(toggle_overlay_system)
+            .add_system(update_overlay_system.after(toggle_overlay_system));
+    }
+}
+
+fn spawn_overlay(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands
+        .spawn_bundle(TextBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    left: Val::Px(4.),
+                    bottom: Val::Px(4.),
+                    ..default()
+                },
+                ..default()
+            },
+            text: Text::with_section(
+                String::new(),
+                TextStyle {
+                    font: asset_server.load(UI_FONT),
+                    font_size: 16.,
+                    color: Color::WHITE,
+                },
+                default(),
+            ),
+            visibility: Visibility { is_visible: false },
+            ..default()
+        })
+        .insert(InputOverlay);
+}
+
+fn toggle_overlay_system(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut query: Query<&mut Visibility, With<InputOverlay>>,
+) {
+    if keyboard_input.just_pressed(TOGGLE_INPUT_OVERLAY_KEY) {
+        for mut visibility in query.iter_mut() {
+            visibility.is_visible = !visibility.is_visible;
+        }
+    }
+}
+
+// `fraction` of a bar, from 0 to 1
+fn bar(fraction: f32) -> String {
+    let filled = (fraction.clamp(0., 1.) * BAR_WIDTH as f32).round() as usize;
+    format!("[{}{}]", "#".repeat(filled), " ".repeat(BAR_WIDTH - filled))
+}
+
+fn update_overlay_system(
+    action_state: Res<ActionState>,
+    mobility_query: Query<(&PlayerId, &Mobility)>,
+    mut overlay_query: Query<(&mut Text, &Visibility), With<InputOverlay>>,
+) {
+    let (mut text, visibility) = match overlay_query.get_single_mut() {
+        Ok(overlay) => overlay,
+        Err(_) => return,
+    };
+    if !visibility.is_visible {
+        return;
+    }
+    let value = &mut text.sections[0].value;
+    value.clear();
+    // Writing to a String can't fail. Just pressed and released are for this
+    // frame, while the steps see the edges gathered since the last step.
+    let _ = writeln!(value, "Action        held  press  release");
+    for action in SHOWN_ACTIONS {
+        let mark = |on: bool| if on { "*" } else { "." };
+        let _ = writeln!(
+            value,
+            "{:<12}  {:<4}  {:<5}  {}",
+            format!("{:?}", action),
+            mark(action_state.pressed(action)),
+            mark(action_state.just_pressed(action)),
+            mark(action_state.just_released(action)),
+        );
+    }
+
+    // The first player, who the action state above belongs to
+    let mobility = mobility_query
+        .iter()
+        .find(|(id, _)| id.0 == 0)
+        .map(|(_, mobility)| mobility);
+    if let Some(mobility) = mobility {
+        let _ = writeln!(value, "Walk direction: {:?}", mobility.walk_direction);
+        let _ = writeln!(
+            value,
+            "On ground: {}  On wall: {}  Crouching: {}",
+            mobility.on_ground, mobility.on_wall, mobility.crouching
+        );
+        let wall_coyote = if mobility.wall_coyote_time > 0. {
+            mobility.wall_coyote_timer / mobility.wall_coyote_time
+        } else {
+            0.
+        };
+        let _ = write!(value, "Wall coyote {}", bar(wall_coyote));
+    }
+}
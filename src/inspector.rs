@@ -0,0 +1,165 @@
+// A panel describing the tile under the cursor, shown in debug mode.
+//
+// The hovered tile's shape can be changed in place with CycleTileShape,
+// without respawning it.
+
+use bevy::prelude::*;
+use std::fmt::Write;
+
+use crate::cursor::CursorWorldPos;
+use crate::debug::DebugMode;
+use crate::game_state::GameState;
+use crate::input::{Action, ActionState};
+use crate::level::CurrentLevel;
+use crate::physics::SurfaceMaterial;
+use crate::pixel_perfect::UI_FONT;
+use crate::settings::Settings;
+use crate::sign::Sign;
+use crate::tile::{ColliderShape, Hazard, HiddenTile, SolidCollider, TileIndex};
+
+#[derive(Component)]
+struct TileInspector;
+
+// Needs the `ActionState` from `InputMapPlugin`, the `TileIndex` from
+// `TilePlugin`, the `CurrentLevel` from `LevelPlugin`, the `GameState` from
+// `GameStatePlugin` and the `Settings` resource
+#[derive(Default)]
+pub struct TileInspectorPlugin;
+
+impl Plugin for TileInspectorPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_startup_system(spawn_inspector)
+            .add_system(update_inspector_system)
+            .add_system_set(
+                SystemSet::on_update(GameState::Playing)
+                    .with_system(cycle_tile_shape_system.before(update_inspector_system)),
+            );
+    }
+}
+
+fn spawn_inspector(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands
+        .spawn_bundle(TextBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    right: Val::Px(4.),
+                    top: Val::Px(4.),
+                    ..default()
+                },
+                ..default()
+            },
+            text: Text::with_section(
+                String::new(),
+                TextStyle {
+                    font: asset_server.load(UI_FONT),
+                    font_size: 16.,
+                    color: Color::WHITE,
+                },
+                default(),
+            ),
+            visibility: Visibility { is_visible: false },
+            ..default()
+        })
+        .insert(TileInspector);
+}
+
+// Everything the inspector shows about a tile
+type InspectedTileQuery<'w, 's> = Query<
+    'w,
+    's,
+    (
+        Option<&'static ColliderShape>,
+        Option<&'static SolidCollider>,
+        Option<&'static HiddenTile>,
+        Option<&'static SurfaceMaterial>,
+        Option<&'static Hazard>,
+        Option<&'static Sign>,
+        &'static Sprite,
+        &'static Handle<Image>,
+    ),
+>;
+
+#[allow(clippy::too_many_arguments)]
+fn update_inspector_system(
+    debug_mode: Res<DebugMode>,
+    cursor_world_pos: Res<CursorWorldPos>,
+    settings: Res<Settings>,
+    tile_index: Res<TileIndex>,
+    current_level: Res<CurrentLevel>,
+    asset_server: Res<AssetServer>,
+    tile_query: InspectedTileQuery,
+    mut inspector_query: Query<(&mut Text, &mut Visibility), With<TileInspector>>,
+) {
+    let (mut text, mut visibility) = match inspector_query.get_single_mut() {
+        Ok(inspector) => inspector,
+        Err(_) => return,
+    };
+    let cell = cursor_world_pos.cell(settings.cell_rounding);
+    visibility.is_visible = debug_mode.0 && cell.is_some();
+    let cell = match cell {
+        Some(cell) if visibility.is_visible => cell,
+        _ => return,
+    };
+
+    let value = &mut text.sections[0].value;
+    value.clear();
+    // Writing to a String can't fail
+    let _ = writeln!(value, "Cell: {}, {}", cell.x, cell.y);
+    if current_level.offset != IVec2::ZERO {
+        let level_cell = cell - current_level.offset;
+        let _ = writeln!(value, "Level cell: {}, {}", level_cell.x, level_cell.y);
+    }
+    let tile = tile_index
+        .tile_at(cell)
+        .and_then(|entity| tile_query.get(entity).ok().map(|tile| (entity, tile)));
+    let (entity, (shape, solid, hidden, material, hazard, sign, sprite, texture)) = match tile {
+        Some(tile) => tile,
+        None => {
+            let _ = write!(value, "Empty cell");
+            return;
+        }
+    };
+    let _ = writeln!(value, "Entity: {:?}", entity);
+    let _ = writeln!(value, "Shape: {:?}", shape.copied().unwrap_or_default());
+    let _ = writeln!(value, "Solid: {}", solid.is_some());
+    let _ = writeln!(value, "Hidden: {}", hidden.is_some_and(|hidden| hidden.0));
+    let _ = writeln!(value, "Hazard: {}", hazard.is_some());
+    if let Some(sign) = sign {
+        let _ = writeln!(value, "Sign: {:?}", sign.text);
+    }
+    let material = material.copied().unwrap_or_default();
+    let _ = writeln!(
+        value,
+        "Friction: {}, restitution: {}",
+        material.friction, material.restitution
+    );
+    let _ = match asset_server.get_handle_path(texture) {
+        Some(path) => write!(value, "Texture: {}", path.path().display()),
+        None => write!(value, "Color: {:?}", sprite.color),
+    };
+}
+
+#[allow(clippy::too_many_arguments)]
+fn cycle_tile_shape_system(
+    mut commands: Commands,
+    action_state: Res<ActionState>,
+    debug_mode: Res<DebugMode>,
+    cursor_world_pos: Res<CursorWorldPos>,
+    settings: Res<Settings>,
+    tile_index: Res<TileIndex>,
+    mut current_level: ResMut<CurrentLevel>,
+    shape_query: Query<&ColliderShape>,
+) {
+    if !debug_mode.0 || !action_state.just_pressed(Action::CycleTileShape) {
+        return;
+    }
+    let tile = cursor_world_pos
+        .cell(settings.cell_rounding)
+        .and_then(|cell| tile_index.tile_at(cell));
+    if let Some(tile) = tile {
+        let shape = shape_query.get(tile).copied().unwrap_or_default();
+        commands.entity(tile).insert(shape.next());
+        current_level.unsaved = true;
+    }
+}
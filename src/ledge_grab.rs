@@ -0,0 +1,242 @@
+// Grabbing ledges: a player falling beside the top of a wall they're facing
+// catches hold of it and hangs there. Jump mantles onto the top of the wall,
+// and Down lets go.
+//
+// A ledge is a solid cell with room above it for the body to stand in. It is
+// caught while the body's top is within `LedgeGrab::margin` of the ledge's
+// top, and the wall within the margin of the body's front, so a fast fall
+// still catches it in one of its steps.
+
+use bevy::prelude::*;
+
+use crate::death::Dying;
+use crate::input::{Action, ActionState, PlayerId, SecondPlayerInput};
+use crate::physics::{Mobility, Velocity, PHYSICS_TIME_STEP};
+use crate::player::Facing;
+use crate::tile::{SolidCollider, TileIndex};
+
+// Lets a body grab ledges
+#[derive(Component, Clone, Copy, Debug)]
+pub struct LedgeGrab {
+    // How far off the body may be from a ledge and still catch it, in tiles
+    pub margin: f32,
+    // Seconds after letting go before another ledge can be caught, so the
+    // one let go of isn't caught again at once
+    pub regrab_delay: f32,
+    regrab_timer: f32,
+}
+
+impl Default for LedgeGrab {
+    fn default() -> Self {
+        LedgeGrab {
+            margin: 0.25,
+            regrab_delay: 0.3,
+            regrab_timer: 0.,
+        }
+    }
+}
+
+// A body hanging from a ledge, held still until it mantles or lets go
+#[derive(Component, Clone, Copy, Debug)]
+pub struct Hanging {
+    // The top cell of the wall
+    pub ledge: IVec2,
+    // Which side of the body the wall is on, -1 or 1
+    pub side: f32,
+    // Where the body's bottom-left corner is held
+    at: Vec2,
+}
+
+// The ledge a body with its bottom-left corner at `position` can hang from,
+// with the wall on its `side` (-1 or 1), if there is one within `margin`
+pub fn find_ledge(
+    tile_index: &TileIndex,
+    blocks: impl Fn(Entity) -> bool,
+    position: Vec2,
+    size: Vec2,
+    side: f32,
+    margin: f32,
+) -> Option<IVec2> {
+    let front = if side > 0. {
+        position.x + size.x
+    } else {
+        position.x
+    };
+    let probe = front + side * margin;
+    let top = position.y + size.y;
+    let wall = tile_index.ground_below(probe, probe, top + margin, 2, &blocks)?;
+    if (wall.top - top).abs() > margin {
+        return None;
+    }
+    // Room to stand on top of it
+    let room = (1..=size.y.ceil() as i32).all(|dy| {
+        !tile_index
+            .tile_at(wall.cell + IVec2::new(0, dy))
+            .is_some_and(&blocks)
+    });
+    room.then_some(wall.cell)
+}
+
+// Where a body of `size` hanging from `ledge` has its bottom-left corner
+fn hang_position(ledge: IVec2, side: f32, size: Vec2) -> Vec2 {
+    let x = if side > 0. {
+        ledge.x as f32 - size.x
+    } else {
+        ledge.x as f32 + 1.
+    };
+    Vec2::new(x, (ledge.y + 1) as f32 - size.y)
+}
+
+// Where a body of `size` mantling onto `ledge` ends up, standing on it
+fn mantle_position(ledge: IVec2, side: f32, size: Vec2) -> Vec2 {
+    let x = if side > 0. {
+        ledge.x as f32
+    } else {
+        ledge.x as f32 + 1. - size.x
+    };
+    Vec2::new(x, (ledge.y + 1) as f32)
+}
+
+// The bodies which can grab a ledge, while they aren't already hanging
+// from one
+type GrabberQuery<'w, 's> = Query<
+    'w,
+    's,
+    (
+        Entity,
+        &'static mut LedgeGrab,
+        &'static mut Transform,
+        &'static mut Velocity,
+        &'static mut Mobility,
+        &'static Facing,
+    ),
+    (Without<Hanging>, Without<Dying>),
+>;
+
+// After the collision step, so it catches ledges from where the body ends it
+pub fn ledge_grab_system(
+    mut commands: Commands,
+    tile_index: Res<TileIndex>,
+    solid_query: Query<(), With<SolidCollider>>,
+    mut query: GrabberQuery,
+) {
+    for (entity, mut grab, mut transform, mut velocity, mut mobility, facing) in query.iter_mut() {
+        grab.regrab_timer = (grab.regrab_timer - PHYSICS_TIME_STEP).max(0.);
+        if mobility.on_ground || velocity.0.y > 0. || grab.regrab_timer > 0. {
+            continue;
+        }
+        let size = transform.scale.truncate();
+        let side = facing.sign();
+        let ledge = match find_ledge(
+            &tile_index,
+            |tile| solid_query.contains(tile),
+            transform.translation.truncate(),
+            size,
+            side,
+            grab.margin,
+        ) {
+            Some(ledge) => ledge,
+            None => continue,
+        };
+        let at = hang_position(ledge, side, size);
+        transform.translation = at.extend(transform.translation.z);
+        velocity.0 = Vec3::ZERO;
+        mobility.fast_falling = false;
+        commands.entity(entity).insert(Hanging { ledge, side, at });
+    }
+}
+
+// After `ledge_grab_system`. Holds hanging bodies in place against gravity,
+// and lets go if the ledge is removed or the body is moved away, such as by
+// respawning.
+pub fn hang_system(
+    mut commands: Commands,
+    tile_index: Res<TileIndex>,
+    mut query: Query<(Entity, &Hanging, &LedgeGrab, &mut Transform, &mut Velocity)>,
+) {
+    for (entity, hanging, grab, mut transform, mut velocity) in query.iter_mut() {
+        let moved = transform.translation.truncate().distance(hanging.at) > grab.margin;
+        if moved || tile_index.tile_at(hanging.ledge).is_none() {
+            commands.entity(entity).remove::<Hanging>();
+            continue;
+        }
+        transform.translation = hanging.at.extend(transform.translation.z);
+        velocity.0 = Vec3::ZERO;
+    }
+}
+
+// Once per input step, in place of the controls of the players hanging
+pub fn hang_control_system(
+    mut commands: Commands,
+    action_state: Res<ActionState>,
+    second_player: Option<Res<SecondPlayerInput>>,
+    mut query: Query<(Entity, &PlayerId, &Hanging, &mut LedgeGrab, &mut Transform)>,
+) {
+    for (entity, &id, hanging, mut grab, mut transform) in query.iter_mut() {
+        let actions = match SecondPlayerInput::actions(id, &action_state, second_player.as_deref())
+        {
+            Some(actions) => actions,
+            None => continue,
+        };
+        if actions.step_just_pressed(Action::Jump) {
+            let size = transform.scale.truncate();
+            let at = mantle_position(hanging.ledge, hanging.side, size);
+            transform.translation = at.extend(transform.translation.z);
+            commands.entity(entity).remove::<Hanging>();
+        } else if actions.step_just_pressed(Action::FastFall) {
+            grab.regrab_timer = grab.regrab_delay;
+            commands.entity(entity).remove::<Hanging>();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::headless::HeadlessGame;
+    use crate::level::{LevelData, TileData};
+
+    // A wall three tiles high with its top at y = 3, right of the player
+    fn wall() -> LevelData {
+        LevelData {
+            tiles: (0..3).map(|y| TileData::solid(IVec2::new(2, y))).collect(),
+            ..default()
+        }
+    }
+
+    #[test]
+    fn falling_beside_a_ledge_grabs_it_then_mantles_onto_it() {
+        let mut game = HeadlessGame::new();
+        game.spawn_level(&wall());
+        // Facing the wall, a little off it, with the hands above its top
+        game.place_player(Vec2::new(0.9, 3.));
+        game.run(120, &[]);
+        let hanging = game.app.world.get::<Hanging>(game.player()).copied();
+        assert_eq!(hanging.map(|hanging| hanging.ledge), Some(IVec2::new(2, 2)));
+        assert_eq!(
+            game.player_transform().translation.truncate(),
+            Vec2::new(1., 1.)
+        );
+
+        game.step(&[Action::Jump]);
+        game.run(10, &[]);
+        assert!(game.app.world.get::<Hanging>(game.player()).is_none());
+        assert_eq!(
+            game.player_transform().translation.truncate(),
+            Vec2::new(2., 3.)
+        );
+    }
+
+    #[test]
+    fn down_lets_go() {
+        let mut game = HeadlessGame::new();
+        game.spawn_level(&wall());
+        game.place_player(Vec2::new(0.9, 3.));
+        game.run(120, &[]);
+        assert!(game.app.world.get::<Hanging>(game.player()).is_some());
+        game.step(&[Action::FastFall]);
+        game.run(10, &[]);
+        assert!(game.app.world.get::<Hanging>(game.player()).is_none());
+        assert!(game.player_transform().translation.y < 1.);
+    }
+}
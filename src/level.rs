@@ -0,0 +1,1688 @@
+// Levels are described by RON files under `assets/levels`. This module
+// holds the serializable description and the code that turns it into
+// entities.
+
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use bevy::sprite::Anchor;
+use bevy::utils::HashMap;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use crate::ability::{pickup_data, spawn_pickup, Pickup, PickupData};
+use crate::coin::{coin_cell, spawn_coin, Coin, COIN_TEXTURE};
+use crate::debug::DebugMode;
+use crate::enemy::{spawn_enemy, Enemy, EnemyData, EnemyKind};
+use crate::falling_platform::{spawn_platform, FallingPlatform, PlatformData};
+use crate::input::{Action, ActionState};
+use crate::level_exit::{level_exit_cell, spawn_level_exit, LevelExit};
+use crate::music::{LevelMusic, MusicTrack};
+use crate::npc::{npc_data, spawn_npc, Npc, NpcData};
+use crate::parallax::ParallaxLayerBundle;
+use crate::path::WaypointPath;
+use crate::physics::SurfaceMaterial;
+use crate::pixel_perfect::WorldClearColor;
+use crate::player::Player;
+use crate::prefab::{stamp_prefab, PrefabLibrary, PREFAB_LIBRARY_PATH};
+use crate::pressure_plate::{plate_data, spawn_plate, PlateData, PressurePlate};
+use crate::push_box::{spawn_box, BoxData, PushBox};
+use crate::saw::{spawn_saw, SawBlade, SawData};
+use crate::sign::Sign;
+use crate::tile::{
+    ColliderShape, Hazard, HiddenTile, SolidCollider, Tile, TileAppearance, TileIndex, TileSpec,
+};
+
+pub const STARTUP_LEVEL_PATH: &str = "assets/levels/startup.ron";
+// The startup level, built into the binary for where its file can't be read,
+// such as on the web. Its textures are still loaded through the `AssetServer`.
+pub const EMBEDDED_STARTUP_LEVEL: &str = include_str!("../assets/levels/startup.ron");
+// Where level files are listed from for the level select menu
+pub const LEVELS_DIR: &str = "assets/levels";
+// Where a level which wasn't loaded from a file is saved
+pub const UNSAVED_LEVEL_PATH: &str = "assets/levels/untitled.ron";
+
+// Where the player's bottom-left corner starts in a level with no spawn and
+// no solid tiles to stand on
+pub const PLAYER_START: Vec2 = bevy::math::const_vec2!([0., 1.]);
+// How the spawn marker is drawn while `DebugMode` is on
+pub const PLAYER_SPAWN_DEBUG_COLOR: Color = Color::rgba(0., 1., 0., 0.4);
+
+// Thumbnail pixel colors, one pixel per tile
+const THUMBNAIL_SOLID: [u8; 4] = [220, 220, 220, 255];
+const THUMBNAIL_SLOPE: [u8; 4] = [150, 150, 150, 255];
+const THUMBNAIL_HIDDEN: [u8; 4] = [255, 0, 255, 255];
+const THUMBNAIL_SPAWN: [u8; 4] = [0, 255, 0, 255];
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct LevelData {
+    // Clear color of the world render target, visible wherever nothing is drawn
+    #[serde(default = "default_background_color")]
+    pub background_color: Color,
+    // Background layers, listed from farthest to nearest
+    #[serde(default)]
+    pub parallax: Vec<ParallaxLayerData>,
+    // The cell the players start in, with their bottom-left corner at its own
+    #[serde(default)]
+    pub player_spawn: Option<IVec2>,
+    #[serde(default)]
+    pub tiles: Vec<TileData>,
+    // Prefabs from the library, placed after `tiles`
+    #[serde(default)]
+    pub stamps: Vec<StampData>,
+    #[serde(default)]
+    pub enemies: Vec<EnemyData>,
+    // Looped while the level is played, crossfading from the last level's
+    // track if it's a different one. A level without one is played in silence,
+    // with the last level's track faded out.
+    #[serde(default)]
+    pub music: Option<MusicTrack>,
+    // Shown as the level starts. Levels without one go by their file's name.
+    #[serde(default)]
+    pub name: Option<String>,
+    // Cells with a coin in the middle
+    #[serde(default)]
+    pub coins: Vec<IVec2>,
+    // What the players meet at the left and right edges of the tiles
+    #[serde(default)]
+    pub edges: LevelEdges,
+    // The cell which finishes the level, with the exit's bottom-left corner
+    // at its own
+    #[serde(default)]
+    pub exit: Option<IVec2>,
+    #[serde(default)]
+    pub npcs: Vec<NpcData>,
+    // Unlocking movement abilities
+    #[serde(default)]
+    pub pickups: Vec<PickupData>,
+    // Travelling along paths, through the tiles
+    #[serde(default)]
+    pub saws: Vec<SawData>,
+    // Dropping away once stood on
+    #[serde(default)]
+    pub platforms: Vec<PlatformData>,
+    // Pushed around by the players
+    #[serde(default)]
+    pub boxes: Vec<BoxData>,
+    // Held down by whatever rests on them
+    #[serde(default)]
+    pub plates: Vec<PlateData>,
+}
+
+impl Default for LevelData {
+    fn default() -> Self {
+        LevelData {
+            background_color: default_background_color(),
+            parallax: Vec::new(),
+            player_spawn: None,
+            tiles: Vec::new(),
+            stamps: Vec::new(),
+            enemies: Vec::new(),
+            music: None,
+            name: None,
+            coins: Vec::new(),
+            edges: LevelEdges::Open,
+            exit: None,
+            npcs: Vec::new(),
+            pickups: Vec::new(),
+            saws: Vec::new(),
+            platforms: Vec::new(),
+            boxes: Vec::new(),
+            plates: Vec::new(),
+        }
+    }
+}
+
+fn default_background_color() -> Color {
+    Color::BLACK
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ParallaxLayerData {
+    // Path relative to the assets directory
+    pub texture: String,
+    // Fraction of the camera's translation the layer follows.
+    // (0, 0) is fixed in the world, (1, 1) is fixed to the camera.
+    pub factor: Vec2,
+    // Position of the layer's bottom-left corner when the camera is at the origin
+    #[serde(default)]
+    pub offset: Vec2,
+    #[serde(default)]
+    pub repeat: bool,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TileData {
+    pub pos: IVec2,
+    #[serde(default)]
+    pub shape: ColliderShape,
+    #[serde(default)]
+    pub appearance: TileAppearanceData,
+    // Decorative tiles are drawn but don't collide
+    #[serde(default = "default_solid")]
+    pub solid: bool,
+    // Ice, trampolines and the like
+    #[serde(default)]
+    pub material: SurfaceMaterial,
+    // Kills players who touch it, like spikes
+    #[serde(default)]
+    pub hazard: bool,
+    // Makes it a sign, showing this text while a player stands in front of it
+    #[serde(default)]
+    pub sign: Option<String>,
+}
+
+fn default_solid() -> bool {
+    true
+}
+
+impl TileData {
+    // A plain white square which collides, as floors and walls are built
+    // from in tests
+    pub fn solid(pos: IVec2) -> Self {
+        TileData {
+            pos,
+            shape: default(),
+            appearance: TileAppearanceData::Color(Color::WHITE),
+            solid: true,
+            material: default(),
+            hazard: false,
+            sign: None,
+        }
+    }
+
+    // Spawn the tile with its position offset by `origin`
+    pub fn spawn(
+        &self,
+        commands: &mut Commands,
+        asset_server: &AssetServer,
+        tile_index: &mut TileIndex,
+        origin: IVec2,
+    ) -> Entity {
+        let appearance = self.appearance.load(asset_server);
+        self.spawn_with_appearance(commands, tile_index, origin, appearance)
+    }
+
+    // Like `spawn`, but drawn with `appearance` in place of the tile's own
+    pub fn spawn_with_appearance(
+        &self,
+        commands: &mut Commands,
+        tile_index: &mut TileIndex,
+        origin: IVec2,
+        appearance: TileAppearance,
+    ) -> Entity {
+        let entity = tile_index.spawn(
+            commands,
+            TileSpec {
+                pos: origin + self.pos,
+                appearance,
+                shape: self.shape,
+            },
+        );
+        commands.entity(entity).insert(LevelEntity);
+        if !self.solid {
+            commands.entity(entity).remove::<SolidCollider>();
+        }
+        if self.material != SurfaceMaterial::default() {
+            commands.entity(entity).insert(self.material);
+        }
+        if self.hazard {
+            commands.entity(entity).insert(Hazard);
+        }
+        if let Some(text) = &self.sign {
+            commands.entity(entity).insert(Sign { text: text.clone() });
+        }
+        entity
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct StampData {
+    // Name in the prefab library
+    pub prefab: String,
+    pub origin: IVec2,
+    // Whether to replace tiles already in the prefab's cells
+    #[serde(default)]
+    pub overwrite: bool,
+    // Whether the prefab is mirrored left to right about `origin`
+    #[serde(default)]
+    pub flip_x: bool,
+}
+
+impl StampData {
+    // The cell the prefab's tile at `pos` is placed in
+    pub fn cell(&self, pos: IVec2) -> IVec2 {
+        let x = if self.flip_x { -pos.x } else { pos.x };
+        self.origin + IVec2::new(x, pos.y)
+    }
+}
+
+// `TileAppearance` with textures named by path relative to the assets directory
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum TileAppearanceData {
+    Color(Color),
+    Texture(String),
+    None,
+}
+
+impl Default for TileAppearanceData {
+    fn default() -> Self {
+        TileAppearanceData::Texture("tile.png".to_string())
+    }
+}
+
+impl TileAppearanceData {
+    pub fn load(&self, asset_server: &AssetServer) -> TileAppearance {
+        match self {
+            TileAppearanceData::Color(color) => TileAppearance::Color(*color),
+            TileAppearanceData::Texture(path) => {
+                TileAppearance::Texture(asset_server.load(path.as_str()))
+            }
+            TileAppearanceData::None => TileAppearance::None,
+        }
+    }
+}
+
+// Marks every entity spawned for a level, so it can be despawned with the level
+#[derive(Component)]
+pub struct LevelEntity;
+
+// Marks where the players start in the level, and where they go back to on
+// reset. A level has exactly one, which the editor's spawn tool moves.
+#[derive(Component)]
+pub struct PlayerSpawn;
+
+// Spawn the marker for the players' spawn in `cell`, drawn only in debug mode
+pub fn spawn_player_spawn(commands: &mut Commands, cell: IVec2) -> Entity {
+    commands
+        .spawn_bundle(SpriteBundle {
+            transform: Transform::from_translation(cell.as_vec2().extend(-1.)),
+            sprite: Sprite {
+                color: PLAYER_SPAWN_DEBUG_COLOR,
+                // The size of a standing player
+                custom_size: Some(Vec2::new(1., 2.)),
+                anchor: Anchor::BottomLeft,
+                ..default()
+            },
+            visibility: Visibility { is_visible: false },
+            ..default()
+        })
+        .insert(PlayerSpawn)
+        .insert(LevelEntity)
+        .id()
+}
+
+#[derive(Debug)]
+pub enum LevelError {
+    Io(std::io::Error),
+    Parse(ron::Error),
+    Thumbnail(image::ImageError),
+}
+
+impl fmt::Display for LevelError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LevelError::Io(err) => write!(f, "failed to read level: {}", err),
+            LevelError::Parse(err) => write!(f, "invalid level: {}", err),
+            LevelError::Thumbnail(err) => write!(f, "failed to write thumbnail: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for LevelError {}
+
+// Parse a level from its RON text, such as one built in with `include_str!`
+pub fn load_level_from_str(text: &str) -> Result<LevelData, LevelError> {
+    ron::from_str(text).map_err(LevelError::Parse)
+}
+
+impl LevelData {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, LevelError> {
+        let text = std::fs::read_to_string(path).map_err(LevelError::Io)?;
+        load_level_from_str(&text)
+    }
+
+    // Also writes the level's thumbnail, to `thumbnail_path(path)`
+    pub fn save(&self, path: impl AsRef<Path>, prefabs: &PrefabLibrary) -> Result<(), LevelError> {
+        let text = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+            .map_err(LevelError::Parse)?;
+        std::fs::write(&path, text).map_err(LevelError::Io)?;
+        self.thumbnail(prefabs)
+            .save(thumbnail_path(path))
+            .map_err(LevelError::Thumbnail)
+    }
+
+    // The cell the players start in. Without a spawn, it's the one above the
+    // first solid tile.
+    pub fn player_spawn_cell(&self) -> IVec2 {
+        if let Some(cell) = self.player_spawn {
+            return cell;
+        }
+        match self.tiles.iter().find(|tile| tile.solid) {
+            Some(tile) => {
+                warn!(
+                    "The level has no player spawn, using the top of the tile at {}",
+                    tile.pos
+                );
+                tile.pos + IVec2::Y
+            }
+            None => {
+                warn!("The level has no player spawn or solid tiles");
+                PLAYER_START.floor().as_ivec2()
+            }
+        }
+    }
+
+    // The level moved by `offset` cells: its tiles, stamps, enemies, coins,
+    // NPCs, pickups, saw blades' paths, falling platforms, boxes, pressure
+    // plates, spawn and exit. Its background layers stay put, since they
+    // follow the camera.
+    pub fn shifted(mut self, offset: IVec2) -> Self {
+        for tile in &mut self.tiles {
+            tile.pos += offset;
+        }
+        for stamp in &mut self.stamps {
+            stamp.origin += offset;
+        }
+        for enemy in &mut self.enemies {
+            enemy.pos += offset;
+        }
+        for coin in &mut self.coins {
+            *coin += offset;
+        }
+        if let Some(spawn) = &mut self.player_spawn {
+            *spawn += offset;
+        }
+        if let Some(exit) = &mut self.exit {
+            *exit += offset;
+        }
+        for npc in &mut self.npcs {
+            npc.pos += offset;
+        }
+        for pickup in &mut self.pickups {
+            pickup.pos += offset;
+        }
+        for saw in &mut self.saws {
+            for cell in &mut saw.path {
+                *cell += offset;
+            }
+        }
+        for platform in &mut self.platforms {
+            platform.pos += offset;
+        }
+        for pushed in &mut self.boxes {
+            pushed.pos += offset;
+        }
+        for plate in &mut self.plates {
+            plate.pos += offset;
+        }
+        self
+    }
+
+    // The level mirrored left to right, so that columns `left` and `right`
+    // swap places: its tiles and their slopes, stamps, enemies, coins, NPCs,
+    // pickups, saw blades' paths, falling platforms, boxes, pressure plates,
+    // spawn and exit. Flipping it the same way again gives back the level as
+    // it was.
+    pub fn flipped_x(mut self, left: i32, right: i32) -> Self {
+        let flip = |cell: &mut IVec2| cell.x = left + right - cell.x;
+        for tile in &mut self.tiles {
+            flip(&mut tile.pos);
+            tile.shape = tile.shape.flipped_x();
+        }
+        for stamp in &mut self.stamps {
+            flip(&mut stamp.origin);
+            stamp.flip_x = !stamp.flip_x;
+        }
+        for enemy in &mut self.enemies {
+            flip(&mut enemy.pos);
+            if let EnemyKind::Bobber { span } = &mut enemy.kind {
+                *span = -*span;
+            }
+        }
+        for coin in &mut self.coins {
+            flip(coin);
+        }
+        if let Some(spawn) = &mut self.player_spawn {
+            flip(spawn);
+        }
+        if let Some(exit) = &mut self.exit {
+            flip(exit);
+        }
+        for npc in &mut self.npcs {
+            flip(&mut npc.pos);
+        }
+        for pickup in &mut self.pickups {
+            flip(&mut pickup.pos);
+        }
+        for saw in &mut self.saws {
+            saw.path.iter_mut().for_each(flip);
+        }
+        for platform in &mut self.platforms {
+            flip(&mut platform.pos);
+        }
+        for pushed in &mut self.boxes {
+            flip(&mut pushed.pos);
+        }
+        // A plate's rightmost cell becomes its leftmost
+        for plate in &mut self.plates {
+            flip(&mut plate.pos);
+            plate.pos.x -= plate.width - 1;
+        }
+        self
+    }
+
+    // Add `other`'s enemies, coins, NPCs, pickups, saw blades, falling
+    // platforms, boxes and pressure plates to the level's
+    pub fn add_entities(&mut self, other: &LevelData) {
+        self.enemies.extend_from_slice(&other.enemies);
+        self.coins.extend_from_slice(&other.coins);
+        self.npcs.extend_from_slice(&other.npcs);
+        self.pickups.extend_from_slice(&other.pickups);
+        self.saws.extend_from_slice(&other.saws);
+        self.platforms.extend_from_slice(&other.platforms);
+        self.boxes.extend_from_slice(&other.boxes);
+        self.plates.extend_from_slice(&other.plates);
+    }
+
+    // Sort everything placed by row, so a level saved again after small
+    // edits only differs by them
+    fn sort_by_row(&mut self) {
+        let row = |pos: IVec2| (pos.y, pos.x);
+        self.tiles.sort_by_key(|tile| row(tile.pos));
+        self.enemies.sort_by_key(|enemy| row(enemy.pos));
+        self.coins.sort_by_key(|&coin| row(coin));
+        self.npcs.sort_by_key(|npc| row(npc.pos));
+        self.pickups.sort_by_key(|pickup| row(pickup.pos));
+        self.saws
+            .sort_by_key(|saw| saw.path.first().copied().map(row));
+        self.platforms.sort_by_key(|platform| row(platform.pos));
+        self.boxes.sort_by_key(|pushed| row(pushed.pos));
+        self.plates.sort_by_key(|plate| row(plate.pos));
+    }
+
+    // Every tile the level places, stamps included, by cell
+    fn tiles_by_cell<'a>(&'a self, prefabs: &'a PrefabLibrary) -> HashMap<IVec2, &'a TileData> {
+        let mut cells: HashMap<IVec2, &TileData> =
+            self.tiles.iter().map(|tile| (tile.pos, tile)).collect();
+        for stamp in &self.stamps {
+            let prefab = match prefabs.get(&stamp.prefab) {
+                Some(prefab) => prefab,
+                None => continue,
+            };
+            // Only where a flipped stamp's tiles go matters here, not which
+            // way its slopes face
+            for tile in &prefab.tiles {
+                let cell = stamp.cell(tile.pos);
+                if stamp.overwrite || !cells.contains_key(&cell) {
+                    cells.insert(cell, tile);
+                }
+            }
+        }
+        cells
+    }
+
+    // A picture of the whole level at one pixel per tile, on the background
+    // color, with the player's start marked
+    pub fn thumbnail(&self, prefabs: &PrefabLibrary) -> image::RgbaImage {
+        let cells = self.tiles_by_cell(prefabs);
+        let spawn = self.player_spawn_cell();
+        let (min, max) = cells.keys().fold((spawn, spawn), |(min, max), &cell| {
+            (min.min(cell), max.max(cell))
+        });
+        let size = (max - min + IVec2::ONE).as_uvec2();
+        let background = self.background_color.as_rgba_u32().to_le_bytes();
+        let mut thumbnail = image::RgbaImage::from_pixel(size.x, size.y, image::Rgba(background));
+        let mut plot = |cell: IVec2, color: [u8; 4]| {
+            // Image rows run downwards
+            let pixel = IVec2::new(cell.x - min.x, max.y - cell.y).as_uvec2();
+            thumbnail.put_pixel(pixel.x, pixel.y, image::Rgba(color));
+        };
+        for (&cell, tile) in &cells {
+            let color = match (&tile.appearance, tile.shape) {
+                (TileAppearanceData::None, _) => THUMBNAIL_HIDDEN,
+                (TileAppearanceData::Color(color), _) => color.as_rgba_u32().to_le_bytes(),
+                (_, ColliderShape::Aabb) => THUMBNAIL_SOLID,
+                _ => THUMBNAIL_SLOPE,
+            };
+            plot(cell, color);
+        }
+        plot(spawn, THUMBNAIL_SPAWN);
+        thumbnail
+    }
+
+    // Spawn the level's entities. Despawn any previous level first with `despawn_level`.
+    pub fn spawn(
+        &self,
+        commands: &mut Commands,
+        asset_server: &AssetServer,
+        tile_index: &mut TileIndex,
+        prefabs: &PrefabLibrary,
+    ) {
+        commands.insert_resource(WorldClearColor(self.background_color));
+        commands.insert_resource(LevelMusic(self.music.clone()));
+        commands.insert_resource(LevelName(self.name.clone()));
+        commands.insert_resource(LevelBounds { edges: self.edges });
+        spawn_player_spawn(commands, self.player_spawn_cell());
+        for (depth, layer) in self.parallax.iter().enumerate() {
+            commands
+                .spawn_bundle(ParallaxLayerBundle::new(
+                    layer.factor,
+                    layer.offset,
+                    asset_server.load(layer.texture.as_str()),
+                    layer.repeat,
+                    depth,
+                ))
+                .insert(LevelEntity);
+        }
+        for tile in &self.tiles {
+            tile.spawn(commands, asset_server, tile_index, IVec2::ZERO);
+        }
+        for stamp in &self.stamps {
+            match prefabs.get(&stamp.prefab) {
+                Some(prefab) if stamp.flip_x => {
+                    stamp_prefab(
+                        commands,
+                        tile_index,
+                        asset_server,
+                        stamp.origin,
+                        &prefab.flipped_x(),
+                        stamp.overwrite,
+                    );
+                }
+                Some(prefab) => {
+                    stamp_prefab(
+                        commands,
+                        tile_index,
+                        asset_server,
+                        stamp.origin,
+                        prefab,
+                        stamp.overwrite,
+                    );
+                }
+                None => warn!("Unknown prefab {:?}", stamp.prefab),
+            }
+        }
+        for enemy in &self.enemies {
+            spawn_enemy(commands, enemy);
+        }
+        for &coin in &self.coins {
+            spawn_coin(commands, asset_server.load(COIN_TEXTURE), coin);
+        }
+        if let Some(exit) = self.exit {
+            spawn_level_exit(commands, exit);
+        }
+        for npc in &self.npcs {
+            spawn_npc(commands, npc);
+        }
+        for pickup in &self.pickups {
+            spawn_pickup(commands, pickup);
+        }
+        self.check_saw_paths(prefabs);
+        let unspawned_saws = self
+            .saws
+            .iter()
+            .filter(|saw| spawn_saw(commands, saw).is_none())
+            .cloned()
+            .collect();
+        commands.insert_resource(Unspawned(LevelData {
+            saws: unspawned_saws,
+            ..default()
+        }));
+        for platform in &self.platforms {
+            spawn_platform(commands, platform);
+        }
+        for pushed in &self.boxes {
+            spawn_box(commands, pushed);
+        }
+        for plate in &self.plates {
+            spawn_plate(commands, plate);
+        }
+    }
+
+    // Blades pass through tiles, so a waypoint inside a solid one is most
+    // likely a mistake
+    fn check_saw_paths(&self, prefabs: &PrefabLibrary) {
+        let cells = self.tiles_by_cell(prefabs);
+        for cell in self.saws.iter().flat_map(|saw| &saw.path) {
+            if cells.get(cell).is_some_and(|tile| tile.solid) {
+                warn!("A saw blade's waypoint at {} is inside a solid tile", cell);
+            }
+        }
+    }
+}
+
+// The level file's path with a `.png` extension, e.g.
+// `assets/levels/startup.png` for `assets/levels/startup.ron`
+pub fn thumbnail_path(level_path: impl AsRef<Path>) -> PathBuf {
+    level_path.as_ref().with_extension("png")
+}
+
+// The paths of the level files in `dir`, sorted by name
+pub fn list_levels(dir: impl AsRef<Path>) -> Vec<PathBuf> {
+    let mut levels: Vec<PathBuf> = match std::fs::read_dir(dir) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension().is_some_and(|extension| extension == "ron"))
+            .collect(),
+        Err(_) => Vec::new(),
+    };
+    levels.sort();
+    levels
+}
+
+// The built-in level, available even when no level file can be read
+pub fn default_level() -> LevelData {
+    let tiles = [
+        (-5, 0),
+        (-4, 0),
+        (-3, 0),
+        (-2, 0),
+        (-1, 0),
+        (0, 0),
+        (1, 0),
+        (2, 0),
+        (3, 0),
+        (4, 0),
+        (5, 0),
+        (5, 1),
+        (5, 2),
+        (5, 3),
+        (5, 4),
+        (5, 5),
+        (5, 6),
+        (5, 7),
+        (5, 8),
+        (5, 9),
+        (5, 10),
+        (5, 11),
+        (-5, 1),
+        (-5, 2),
+        (-5, 3),
+        (-5, 4),
+        (-5, 5),
+        (-5, 6),
+        (-5, 7),
+        (-5, 8),
+        (-5, 9),
+        (-5, 10),
+        (2, 5),
+        (3, 5),
+        (-4, 3),
+        (-3, 3),
+    ];
+    LevelData {
+        player_spawn: Some(IVec2::new(0, 1)),
+        tiles: tiles
+            .into_iter()
+            .map(|(x, y)| TileData {
+                pos: IVec2::new(x, y),
+                shape: ColliderShape::Aabb,
+                appearance: TileAppearanceData::default(),
+                solid: true,
+                material: SurfaceMaterial::default(),
+                hazard: false,
+                sign: None,
+            })
+            .collect(),
+        ..default()
+    }
+}
+
+// Despawn every tile, including those placed since the level was spawned, and
+// `level_entities`, which should be the level's other entities
+pub fn despawn_level(
+    commands: &mut Commands,
+    tile_index: &mut TileIndex,
+    level_entities: impl IntoIterator<Item = Entity>,
+) {
+    tile_index.clear(commands);
+    for entity in level_entities {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+// The level file the current level was loaded from, if any
+#[derive(Default)]
+pub struct CurrentLevel {
+    pub path: Option<String>,
+    // Whether tiles have been edited since the level was loaded or saved
+    pub unsaved: bool,
+    // Where the level's cell (0, 0) is placed in the world, so the same level
+    // can be placed in different rooms. Levels are loaded shifted by it and
+    // saved shifted back, while the editor works in world cells throughout.
+    // It's set by `LevelCommand::LoadAt`, and any other load puts it back at
+    // zero, except a reload.
+    pub offset: IVec2,
+}
+
+impl CurrentLevel {
+    // Load the level at `path`, or the default level if that fails
+    pub fn load_or_default(&mut self, path: &str) -> LevelData {
+        match LevelData::load(path) {
+            Ok(level) => {
+                self.path = Some(path.to_string());
+                level
+            }
+            Err(err) => {
+                warn!("{}: {}, using the default level", path, err);
+                self.path = None;
+                default_level()
+            }
+        }
+    }
+
+    // Load the level at `path`, or `embedded`, a copy of it built into the
+    // binary, if there's no such file. On the web there are no files, so it's
+    // always `embedded`. The level keeps `path` either way, so it is reloaded
+    // and saved there. A file which is there but can't be loaded is left
+    // alone, for the default level without a path.
+    pub fn load_or_embedded(&mut self, path: &str, embedded: &str) -> LevelData {
+        let level = if cfg!(target_arch = "wasm32") {
+            load_level_from_str(embedded)
+        } else {
+            match LevelData::load(path) {
+                Err(LevelError::Io(err)) if err.kind() == std::io::ErrorKind::NotFound => {
+                    load_level_from_str(embedded)
+                }
+                level => level,
+            }
+        };
+        match level {
+            Ok(level) => {
+                self.path = Some(path.to_string());
+                level
+            }
+            Err(err) => {
+                warn!("{}: {}, using the default level", path, err);
+                self.path = None;
+                default_level()
+            }
+        }
+    }
+}
+
+// The name of the level last spawned, if it has one
+#[derive(Default)]
+pub struct LevelName(pub Option<String>);
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LevelEdges {
+    // Players walk off into the void
+    #[default]
+    Open,
+    // Players are stopped at the edges
+    Walls,
+    // Players leaving past one edge come back in at the other, keeping their
+    // velocity
+    Wrap,
+}
+
+// How the level last spawned holds the players in. Its edges are the outer
+// edges of the leftmost and rightmost tiles, however the tiles are edited
+// since.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct LevelBounds {
+    pub edges: LevelEdges,
+}
+
+impl LevelBounds {
+    // The left and right edges, if the level holds the players in and has
+    // any tiles
+    pub fn extent(&self, tile_index: &TileIndex) -> Option<(f32, f32)> {
+        if self.edges == LevelEdges::Open {
+            return None;
+        }
+        let (min, max) = tile_index.bounds()?;
+        Some((min.x as f32, (max.x + 1) as f32))
+    }
+}
+
+// What the level last spawned has which isn't in the world, such as saw
+// blades only spawned for testing, or enemies, coins and pickups gone in
+// play, so the level is still saved with it. It's moved and flipped along
+// with the level.
+#[derive(Default)]
+pub struct Unspawned(pub LevelData);
+
+// Replace the current level
+pub enum LevelCommand {
+    // Load the current level's file again, keeping the current level if that fails
+    Reload,
+    // Switch to `default_level()`
+    ResetToDefault,
+    // Switch to the level file at the path, keeping the current level if that fails
+    Load(String),
+    // As `Load`, but with the level's cell (0, 0) placed at the cell, which
+    // it's saved shifted back from
+    LoadAt(String, IVec2),
+}
+
+// Move the tiles, spawn and enemies, and the players with them, so the
+// bottom-left of the tiles' bounds is at the cell. Everything keeps its
+// place relative to everything else.
+pub struct RecenterLevel(pub IVec2);
+
+// Mirror the level left to right in place, edits included, along with the
+// players in it
+pub struct FlipLevel;
+
+// Write the level as it currently is, edits included, to the current level's
+// file, or to `UNSAVED_LEVEL_PATH` if it has none
+pub struct SaveLevel;
+
+// Needs the `ActionState` from `InputMapPlugin`. Shows the spawn marker while
+// there is a `DebugMode` which is on.
+#[derive(Default)]
+pub struct LevelPlugin;
+
+impl Plugin for LevelPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(PrefabLibrary::load(PREFAB_LIBRARY_PATH))
+            .init_resource::<CurrentLevel>()
+            .init_resource::<LevelName>()
+            .add_event::<LevelCommand>()
+            .add_event::<SaveLevel>()
+            .add_event::<RecenterLevel>()
+            .add_event::<FlipLevel>()
+            .add_system(level_keys_system)
+            .add_system(level_command_system.after(level_keys_system))
+            .add_system(recenter_level_system.after(level_keys_system))
+            .add_system(flip_level_system.after(level_keys_system))
+            .add_system(save_level_system)
+            .add_system(player_spawn_visibility_system);
+    }
+}
+
+fn player_spawn_visibility_system(
+    debug_mode: Option<Res<DebugMode>>,
+    mut query: Query<&mut Visibility, With<PlayerSpawn>>,
+) {
+    let debug = debug_mode.is_some_and(|debug_mode| debug_mode.0);
+    for mut visibility in query.iter_mut() {
+        if visibility.is_visible != debug {
+            visibility.is_visible = debug;
+        }
+    }
+}
+
+fn level_keys_system(
+    action_state: Res<ActionState>,
+    mut level_commands: EventWriter<LevelCommand>,
+    mut recenter_events: EventWriter<RecenterLevel>,
+    mut flip_events: EventWriter<FlipLevel>,
+) {
+    if action_state.just_pressed(Action::RecenterLevel) {
+        recenter_events.send(RecenterLevel(IVec2::ZERO));
+    }
+    if action_state.just_pressed(Action::FlipLevel) {
+        flip_events.send(FlipLevel);
+    }
+    if action_state.just_pressed(Action::ReloadLevel) {
+        level_commands.send(LevelCommand::Reload);
+    }
+    if action_state.just_pressed(Action::DefaultLevel) {
+        level_commands.send(LevelCommand::ResetToDefault);
+    }
+}
+
+fn level_command_system(
+    mut commands: Commands,
+    mut level_commands: EventReader<LevelCommand>,
+    mut current_level: ResMut<CurrentLevel>,
+    mut tile_index: ResMut<TileIndex>,
+    asset_server: Res<AssetServer>,
+    prefabs: Res<PrefabLibrary>,
+    level_query: Query<Entity, (With<LevelEntity>, Without<Tile>)>,
+) {
+    for command in level_commands.iter() {
+        let level = match command {
+            LevelCommand::Reload => match &current_level.path {
+                Some(path) => match LevelData::load(path) {
+                    Ok(level) => {
+                        info!("Reloaded {}", path);
+                        level
+                    }
+                    Err(err) => {
+                        warn!("{}: {}", path, err);
+                        continue;
+                    }
+                },
+                None => {
+                    info!("The current level has no file to reload");
+                    continue;
+                }
+            },
+            LevelCommand::ResetToDefault => {
+                info!("Reset to the default level");
+                current_level.path = None;
+                current_level.offset = IVec2::ZERO;
+                default_level()
+            }
+            LevelCommand::Load(path) | LevelCommand::LoadAt(path, _) => {
+                match LevelData::load(path) {
+                    Ok(level) => {
+                        info!("Loaded {}", path);
+                        current_level.path = Some(path.clone());
+                        current_level.offset = match command {
+                            LevelCommand::LoadAt(_, offset) => *offset,
+                            _ => IVec2::ZERO,
+                        };
+                        level
+                    }
+                    Err(err) => {
+                        warn!("{}: {}", path, err);
+                        continue;
+                    }
+                }
+            }
+        };
+        current_level.unsaved = false;
+        despawn_level(&mut commands, &mut tile_index, level_query.iter());
+        level.shifted(current_level.offset).spawn(
+            &mut commands,
+            &asset_server,
+            &mut tile_index,
+            &prefabs,
+        );
+    }
+}
+
+// Everything moved along with the level's tiles
+type LevelTransformQuery<'w, 's> = Query<
+    'w,
+    's,
+    &'static mut Transform,
+    Or<(
+        With<Tile>,
+        With<PlayerSpawn>,
+        With<Enemy>,
+        With<Coin>,
+        With<LevelExit>,
+        With<Npc>,
+        With<Pickup>,
+        With<SawBlade>,
+        With<FallingPlatform>,
+        With<PushBox>,
+        With<PressurePlate>,
+        With<Player>,
+    )>,
+>;
+
+#[allow(clippy::too_many_arguments)]
+fn recenter_level_system(
+    mut recenter_events: EventReader<RecenterLevel>,
+    mut current_level: ResMut<CurrentLevel>,
+    mut tile_index: ResMut<TileIndex>,
+    mut transform_query: LevelTransformQuery,
+    mut enemy_query: Query<&mut Enemy>,
+    mut platform_query: Query<&mut FallingPlatform>,
+    mut box_query: Query<&mut PushBox>,
+    mut saw_query: Query<(&mut SawBlade, &mut WaypointPath)>,
+    mut unspawned: ResMut<Unspawned>,
+) {
+    for &RecenterLevel(origin) in recenter_events.iter() {
+        let offset = match tile_index.bounds() {
+            Some((min, _)) if min != origin => origin - min,
+            _ => continue,
+        };
+        tile_index.shift(offset);
+        unspawned.0 = std::mem::take(&mut unspawned.0).shifted(offset);
+        for mut transform in transform_query.iter_mut() {
+            transform.translation += offset.as_vec2().extend(0.);
+        }
+        for mut enemy in enemy_query.iter_mut() {
+            enemy.data.pos += offset;
+        }
+        for mut platform in platform_query.iter_mut() {
+            platform.data.pos += offset;
+        }
+        for mut pushed in box_query.iter_mut() {
+            pushed.data.pos += offset;
+        }
+        for (mut saw, mut path) in saw_query.iter_mut() {
+            for cell in &mut saw.data.path {
+                *cell += offset;
+            }
+            for waypoint in &mut path.waypoints {
+                *waypoint += offset.as_vec2();
+            }
+        }
+        current_level.unsaved = true;
+        info!("Moved the level by {}", offset);
+    }
+}
+
+// The players, kept apart from the placed entities whose transforms are
+// read alongside
+type FlippedPlayerQuery<'w, 's> = Query<
+    'w,
+    's,
+    &'static mut Transform,
+    (
+        With<Player>,
+        Without<PlayerSpawn>,
+        Without<Coin>,
+        Without<LevelExit>,
+        Without<Npc>,
+        Without<Pickup>,
+        Without<SawBlade>,
+        Without<PressurePlate>,
+    ),
+>;
+
+// The tiles, enemies, coins, NPCs, pickups, saw blades, falling platforms,
+// boxes, pressure plates, spawn and exit are read back as they are, flipped
+// across the middle of the tiles' bounds, and spawned again, which rebuilds
+// the `TileIndex`. The rest of the level is left alone. Boxes go back to
+// where they were placed.
+#[allow(clippy::too_many_arguments)]
+fn flip_level_system(
+    mut commands: Commands,
+    mut flip_events: EventReader<FlipLevel>,
+    mut current_level: ResMut<CurrentLevel>,
+    mut tile_index: ResMut<TileIndex>,
+    asset_server: Res<AssetServer>,
+    mut unspawned: ResMut<Unspawned>,
+    tile_query: TileDataQuery,
+    placed: PlacedEntities,
+    mut player_query: FlippedPlayerQuery,
+) {
+    if flip_events.iter().count() == 0 {
+        return;
+    }
+    let (left, right) = match tile_index.bounds() {
+        Some((min, max)) => (min.x, max.x),
+        None => return,
+    };
+    let level =
+        live_level_data(&tile_index, &asset_server, &tile_query, &placed).flipped_x(left, right);
+    unspawned.0 = std::mem::take(&mut unspawned.0).flipped_x(left, right);
+
+    despawn_level(&mut commands, &mut tile_index, placed.entities());
+    for tile in &level.tiles {
+        tile.spawn(&mut commands, &asset_server, &mut tile_index, IVec2::ZERO);
+    }
+    for enemy in &level.enemies {
+        spawn_enemy(&mut commands, enemy);
+    }
+    for &coin in &level.coins {
+        spawn_coin(&mut commands, asset_server.load(COIN_TEXTURE), coin);
+    }
+    if let Some(exit) = level.exit {
+        spawn_level_exit(&mut commands, exit);
+    }
+    for npc in &level.npcs {
+        spawn_npc(&mut commands, npc);
+    }
+    for pickup in &level.pickups {
+        spawn_pickup(&mut commands, pickup);
+    }
+    for saw in &level.saws {
+        spawn_saw(&mut commands, saw);
+    }
+    for platform in &level.platforms {
+        spawn_platform(&mut commands, platform);
+    }
+    for pushed in &level.boxes {
+        spawn_box(&mut commands, pushed);
+    }
+    for plate in &level.plates {
+        spawn_plate(&mut commands, plate);
+    }
+    spawn_player_spawn(&mut commands, level.player_spawn_cell());
+    // Across the same line as the tiles, which covers cells `left` to `right`
+    for mut transform in player_query.iter_mut() {
+        transform.translation.x =
+            (left + right + 1) as f32 - transform.translation.x - transform.scale.x;
+    }
+    current_level.unsaved = true;
+    info!("Flipped the level");
+}
+
+// The level's entities besides its tiles, each placed in the editor or from
+// the level's file, which are read back with `data`
+#[derive(SystemParam)]
+pub struct PlacedEntities<'w, 's> {
+    spawns: Query<'w, 's, (Entity, &'static Transform), With<PlayerSpawn>>,
+    enemies: Query<'w, 's, (Entity, &'static Enemy)>,
+    coins: Query<'w, 's, (Entity, &'static Transform), With<Coin>>,
+    exits: Query<'w, 's, (Entity, &'static Transform), With<LevelExit>>,
+    npcs: Query<'w, 's, (Entity, &'static Npc, &'static Transform)>,
+    pickups: Query<'w, 's, (Entity, &'static Pickup, &'static Transform)>,
+    saws: Query<'w, 's, (Entity, &'static SawBlade)>,
+    platforms: Query<'w, 's, (Entity, &'static FallingPlatform)>,
+    boxes: Query<'w, 's, (Entity, &'static PushBox)>,
+    plates: Query<'w, 's, (Entity, &'static PressurePlate, &'static Transform)>,
+}
+
+impl<'w, 's> PlacedEntities<'w, 's> {
+    // The entities as a level without tiles or any of the level's settings
+    pub fn data(&self) -> LevelData {
+        LevelData {
+            player_spawn: self
+                .spawns
+                .iter()
+                .next()
+                .map(|(_, transform)| transform.translation.truncate().round().as_ivec2()),
+            enemies: self
+                .enemies
+                .iter()
+                .map(|(_, enemy)| enemy.data.clone())
+                .collect(),
+            coins: self
+                .coins
+                .iter()
+                .map(|(_, transform)| coin_cell(transform))
+                .collect(),
+            exit: self
+                .exits
+                .iter()
+                .next()
+                .map(|(_, transform)| level_exit_cell(transform)),
+            npcs: self
+                .npcs
+                .iter()
+                .map(|(_, npc, transform)| npc_data(npc, transform))
+                .collect(),
+            pickups: self
+                .pickups
+                .iter()
+                .map(|(_, pickup, transform)| pickup_data(pickup, transform))
+                .collect(),
+            saws: self.saws.iter().map(|(_, saw)| saw.data.clone()).collect(),
+            platforms: self
+                .platforms
+                .iter()
+                .map(|(_, platform)| platform.data.clone())
+                .collect(),
+            boxes: self
+                .boxes
+                .iter()
+                .map(|(_, pushed)| pushed.data.clone())
+                .collect(),
+            plates: self
+                .plates
+                .iter()
+                .map(|(_, plate, transform)| plate_data(plate, transform))
+                .collect(),
+            ..default()
+        }
+    }
+
+    pub fn entities(&self) -> impl Iterator<Item = Entity> + '_ {
+        self.spawns
+            .iter()
+            .map(|(entity, _)| entity)
+            .chain(self.enemies.iter().map(|(entity, _)| entity))
+            .chain(self.coins.iter().map(|(entity, _)| entity))
+            .chain(self.exits.iter().map(|(entity, _)| entity))
+            .chain(self.npcs.iter().map(|(entity, _, _)| entity))
+            .chain(self.pickups.iter().map(|(entity, _, _)| entity))
+            .chain(self.saws.iter().map(|(entity, _)| entity))
+            .chain(self.platforms.iter().map(|(entity, _)| entity))
+            .chain(self.boxes.iter().map(|(entity, _)| entity))
+            .chain(self.plates.iter().map(|(entity, _, _)| entity))
+    }
+}
+
+// The components a tile's `TileData` is read back from
+pub type TileDataQuery<'w, 's> = Query<
+    'w,
+    's,
+    (
+        Option<&'static ColliderShape>,
+        Option<&'static SolidCollider>,
+        Option<&'static HiddenTile>,
+        Option<&'static SurfaceMaterial>,
+        Option<&'static Hazard>,
+        Option<&'static Sign>,
+        &'static Sprite,
+        &'static Handle<Image>,
+    ),
+>;
+
+// Every tile as it is now, edits included, sorted by row
+pub fn current_tiles(
+    tile_index: &TileIndex,
+    asset_server: &AssetServer,
+    tile_query: &TileDataQuery,
+) -> Vec<TileData> {
+    let mut tiles: Vec<TileData> = tile_index
+        .iter()
+        .filter_map(|(pos, entity)| {
+            let (shape, solid, hidden, material, hazard, sign, sprite, texture) =
+                tile_query.get(entity).ok()?;
+            let appearance = if hidden.is_some_and(|hidden| hidden.0) {
+                TileAppearanceData::None
+            } else {
+                match asset_server.get_handle_path(texture) {
+                    Some(path) => {
+                        TileAppearanceData::Texture(path.path().to_string_lossy().into_owned())
+                    }
+                    None => TileAppearanceData::Color(sprite.color),
+                }
+            };
+            Some(TileData {
+                pos,
+                shape: shape.copied().unwrap_or_default(),
+                appearance,
+                solid: solid.is_some(),
+                material: material.copied().unwrap_or_default(),
+                hazard: hazard.is_some(),
+                sign: sign.map(|sign| sign.text.clone()),
+            })
+        })
+        .collect();
+    tiles.sort_by_key(|tile| (tile.pos.y, tile.pos.x));
+    tiles
+}
+
+// The level as it is now, edits included, read back from the world, with
+// stamps flattened into its tiles. Its settings, such as its music, are left
+// at their defaults.
+pub fn live_level_data(
+    tile_index: &TileIndex,
+    asset_server: &AssetServer,
+    tile_query: &TileDataQuery,
+    placed: &PlacedEntities,
+) -> LevelData {
+    LevelData {
+        tiles: current_tiles(tile_index, asset_server, tile_query),
+        ..placed.data()
+    }
+}
+
+// Everything placed in the level is saved as it is now, along with what of
+// it isn't in the world. The level's settings are kept from its file, except
+// the background color, which may have been tuned.
+#[allow(clippy::too_many_arguments)]
+fn save_level_system(
+    mut save_events: EventReader<SaveLevel>,
+    mut current_level: ResMut<CurrentLevel>,
+    tile_index: Res<TileIndex>,
+    clear_color: Res<WorldClearColor>,
+    asset_server: Res<AssetServer>,
+    prefabs: Res<PrefabLibrary>,
+    unspawned: Res<Unspawned>,
+    tile_query: TileDataQuery,
+    placed: PlacedEntities,
+) {
+    if save_events.iter().count() == 0 {
+        return;
+    }
+    let path = current_level
+        .path
+        .clone()
+        .unwrap_or_else(|| UNSAVED_LEVEL_PATH.to_string());
+    let file = LevelData::load(&path).unwrap_or_else(|_| default_level());
+    let mut level = LevelData {
+        background_color: clear_color.0,
+        parallax: file.parallax,
+        music: file.music,
+        name: file.name,
+        edges: file.edges,
+        ..live_level_data(&tile_index, &asset_server, &tile_query, &placed)
+    };
+    level.add_entities(&unspawned.0);
+    level.sort_by_row();
+    let level = level.shifted(-current_level.offset);
+    match level.save(&path, &prefabs) {
+        Ok(()) => {
+            info!("Saved {}", path);
+            current_level.path = Some(path);
+            current_level.unsaved = false;
+        }
+        Err(err) => error!("Failed to save {}: {}", path, err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ability::Ability;
+    use crate::headless::{floor, HeadlessGame};
+    use crate::input::Action;
+    use bevy::asset::FileAssetIo;
+    use bevy::ecs::event::Events;
+    use bevy::ecs::schedule::IntoSystemDescriptor;
+    use bevy::ecs::system::{CommandQueue, SystemState};
+    use bevy::tasks::TaskPoolBuilder;
+
+    #[test]
+    fn default_level_round_trips_through_ron() {
+        let level = default_level();
+        let text = ron::ser::to_string_pretty(&level, ron::ser::PrettyConfig::default()).unwrap();
+        let parsed: LevelData = ron::from_str(&text).unwrap();
+        assert_eq!(parsed.tiles, level.tiles);
+        assert_eq!(parsed.background_color, level.background_color);
+    }
+
+    #[test]
+    fn startup_level_has_the_default_tiles() {
+        let startup = LevelData::load(STARTUP_LEVEL_PATH).unwrap();
+        assert_eq!(startup.tiles, default_level().tiles);
+    }
+
+    #[test]
+    fn embedded_startup_level_matches_its_file() {
+        let embedded = load_level_from_str(EMBEDDED_STARTUP_LEVEL).unwrap();
+        let startup = LevelData::load(STARTUP_LEVEL_PATH).unwrap();
+        assert_eq!(embedded.tiles, startup.tiles);
+        assert_eq!(embedded.player_spawn, startup.player_spawn);
+        assert!(matches!(
+            load_level_from_str("(tiles: oops)"),
+            Err(LevelError::Parse(_))
+        ));
+    }
+
+    #[test]
+    fn only_a_missing_file_falls_back_to_the_embedded_level() {
+        let mut current_level = CurrentLevel::default();
+        let path = std::env::temp_dir().join("last-question-embedded-test.ron");
+        let path = path.to_string_lossy().into_owned();
+        let level = current_level.load_or_embedded(&path, EMBEDDED_STARTUP_LEVEL);
+        assert_eq!(level.tiles, default_level().tiles);
+        assert_eq!(current_level.path, Some(path.clone()));
+
+        // A broken file is neither played as the embedded level nor saved
+        // over
+        std::fs::write(&path, "(tiles: oops)").unwrap();
+        let embedded = LevelData {
+            background_color: Color::RED,
+            ..default_level()
+        };
+        let embedded = ron::to_string(&embedded).unwrap();
+        let level = current_level.load_or_embedded(&path, &embedded);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(level, default_level());
+        assert_eq!(current_level.path, None);
+    }
+
+    #[test]
+    fn thumbnail_covers_the_level_and_marks_the_start() {
+        let thumbnail = default_level().thumbnail(&PrefabLibrary::default());
+        // Tiles span x from -5 to 5 and y from 0 to 11
+        assert_eq!(thumbnail.dimensions(), (11, 12));
+        // The bottom-left tile, then the start just above the floor at x = 0
+        assert_eq!(thumbnail.get_pixel(0, 11).0, THUMBNAIL_SOLID);
+        assert_eq!(thumbnail.get_pixel(5, 10).0, THUMBNAIL_SPAWN);
+    }
+
+    #[test]
+    fn missing_spawn_is_above_the_first_solid_tile() {
+        let mut level = default_level();
+        assert_eq!(level.player_spawn_cell(), IVec2::new(0, 1));
+        level.player_spawn = None;
+        level.tiles[0].solid = false;
+        // The second tile is the first solid one
+        assert_eq!(level.player_spawn_cell(), IVec2::new(-4, 1));
+    }
+
+    #[test]
+    fn recentering_keeps_relative_positions() {
+        let mut world = World::new();
+        world.init_resource::<TileIndex>();
+        world.init_resource::<CurrentLevel>();
+        world.init_resource::<Events<RecenterLevel>>();
+        world.init_resource::<Unspawned>();
+        let level = default_level().shifted(IVec2::new(1000, -70));
+        let mut queue = CommandQueue::default();
+        world.resource_scope(|world, mut tile_index: Mut<TileIndex>| {
+            let mut commands = Commands::new(&mut queue, world);
+            for tile in &level.tiles {
+                tile.spawn_with_appearance(
+                    &mut commands,
+                    &mut tile_index,
+                    IVec2::ZERO,
+                    TileAppearance::None,
+                );
+            }
+            spawn_player_spawn(&mut commands, level.player_spawn_cell());
+        });
+        queue.apply(&mut world);
+
+        world
+            .resource_mut::<Events<RecenterLevel>>()
+            .send(RecenterLevel(IVec2::ZERO));
+        let mut stage = SystemStage::single_threaded().with_system(recenter_level_system);
+        stage.run(&mut world);
+
+        // As the default level, whose bottom-left tile is at (-5, 0)
+        let moved = default_level().shifted(IVec2::new(5, 0));
+        let tile_index = world.resource::<TileIndex>();
+        assert_eq!(tile_index.bounds().unwrap().0, IVec2::ZERO);
+        for tile in &moved.tiles {
+            let entity = tile_index.tile_at(tile.pos).unwrap();
+            let transform = world.get::<Transform>(entity).unwrap();
+            assert_eq!(transform.translation.truncate(), tile.pos.as_vec2());
+        }
+        let spawn = world
+            .query_filtered::<&Transform, With<PlayerSpawn>>()
+            .iter(&world)
+            .next()
+            .unwrap();
+        assert_eq!(
+            spawn.translation.truncate(),
+            moved.player_spawn_cell().as_vec2()
+        );
+        assert!(world.resource::<CurrentLevel>().unsaved);
+    }
+
+    #[test]
+    fn flipping_twice_gives_back_the_level() {
+        let mut level = default_level();
+        level.tiles[0].shape = ColliderShape::SlopeNE;
+        level.tiles[1].shape = ColliderShape::SlopeSW;
+        level.stamps.push(StampData {
+            prefab: "ledge".to_string(),
+            origin: IVec2::new(1, 2),
+            overwrite: false,
+            flip_x: false,
+        });
+        let mut bobber = EnemyData::new(IVec2::new(-2, 3));
+        bobber.kind = EnemyKind::Bobber { span: 4 };
+        level.enemies.push(bobber);
+        level.player_spawn = Some(IVec2::new(-3, 1));
+
+        let flipped = level.clone().flipped_x(-5, 5);
+        // The first tile, at the far left, is now at the far right
+        assert_eq!(flipped.tiles[0].pos, IVec2::new(5, 0));
+        assert_eq!(flipped.tiles[0].shape, ColliderShape::SlopeNW);
+        assert_eq!(flipped.tiles[1].shape, ColliderShape::SlopeSE);
+        assert_eq!(flipped.enemies[0].pos, IVec2::new(2, 3));
+        assert_eq!(flipped.enemies[0].kind, EnemyKind::Bobber { span: -4 });
+        assert_eq!(flipped.player_spawn, Some(IVec2::new(3, 1)));
+        let stamp = &flipped.stamps[0];
+        assert!(stamp.flip_x);
+        assert_eq!(stamp.cell(IVec2::new(2, 1)), IVec2::new(-3, 3));
+
+        assert_eq!(flipped.flipped_x(-5, 5), level);
+    }
+
+    // A world which flips and saves levels, saving them to `path`
+    fn editor_world(path: &Path) -> World {
+        let mut world = World::new();
+        world.init_resource::<TileIndex>();
+        world.insert_resource(CurrentLevel {
+            path: Some(path.to_string_lossy().into_owned()),
+            ..default()
+        });
+        world.init_resource::<WorldClearColor>();
+        world.init_resource::<PrefabLibrary>();
+        world.init_resource::<Unspawned>();
+        world.insert_resource(AssetServer::new(
+            FileAssetIo::new("assets", false),
+            TaskPoolBuilder::new().build(),
+        ));
+        world.init_resource::<Events<FlipLevel>>();
+        world.init_resource::<Events<SaveLevel>>();
+        world
+    }
+
+    fn spawn_level(world: &mut World, level: &LevelData) {
+        let asset_server = world.resource::<AssetServer>().clone();
+        let mut queue = CommandQueue::default();
+        world.resource_scope(|world, mut tile_index: Mut<TileIndex>| {
+            let mut commands = Commands::new(&mut queue, world);
+            level.spawn(
+                &mut commands,
+                &asset_server,
+                &mut tile_index,
+                &PrefabLibrary::default(),
+            );
+        });
+        queue.apply(world);
+    }
+
+    fn run_system<Params>(world: &mut World, system: impl IntoSystemDescriptor<Params>) {
+        SystemStage::single_threaded()
+            .with_system(system)
+            .run(world);
+    }
+
+    #[test]
+    fn saving_a_flipped_level_writes_everything_flipped() {
+        let path = std::env::temp_dir().join("last-question-flip-save-test.ron");
+        let mut world = editor_world(&path);
+        let level = LevelData {
+            player_spawn: Some(IVec2::new(-2, 1)),
+            enemies: vec![EnemyData::new(IVec2::new(-1, 1))],
+            coins: vec![IVec2::new(0, 2), IVec2::new(1, 2)],
+            exit: Some(IVec2::new(3, 1)),
+            npcs: vec![NpcData {
+                pos: IVec2::new(1, 1),
+                dialog: vec!["Hello".to_string()],
+            }],
+            pickups: vec![PickupData {
+                pos: IVec2::new(-3, 3),
+                ability: Ability::Dash,
+            }],
+            saws: vec![
+                SawData {
+                    path: vec![IVec2::new(-3, 4), IVec2::new(0, 4)],
+                    mode: default(),
+                    speed: 2.,
+                    test_only: false,
+                },
+                // Not spawned, but still saved
+                SawData {
+                    path: vec![IVec2::new(2, 5)],
+                    mode: default(),
+                    speed: 2.,
+                    test_only: true,
+                },
+            ],
+            platforms: vec![PlatformData {
+                pos: IVec2::new(-1, 3),
+            }],
+            boxes: vec![BoxData {
+                pos: IVec2::new(2, 1),
+            }],
+            plates: vec![PlateData {
+                pos: IVec2::new(-3, 1),
+                width: 2,
+                id: 4,
+            }],
+            ..floor(-3..=3)
+        };
+        spawn_level(&mut world, &level);
+
+        world.resource_mut::<Events<FlipLevel>>().send(FlipLevel);
+        run_system(&mut world, flip_level_system);
+        world.resource_mut::<Events<SaveLevel>>().send(SaveLevel);
+        run_system(&mut world, save_level_system);
+
+        let saved = LevelData::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        std::fs::remove_file(thumbnail_path(&path)).unwrap();
+        let mut flipped = level.flipped_x(-3, 3);
+        flipped.sort_by_row();
+        assert_eq!(saved, flipped);
+    }
+
+    #[test]
+    fn level_loaded_at_an_offset_is_saved_back_where_it_was() {
+        let path = std::env::temp_dir().join("last-question-offset-save-test.ron");
+        let level = LevelData {
+            player_spawn: Some(IVec2::new(-2, 1)),
+            coins: vec![IVec2::new(1, 2)],
+            exit: Some(IVec2::new(3, 1)),
+            ..floor(-3..=3)
+        };
+        level.save(&path, &PrefabLibrary::default()).unwrap();
+        let mut world = editor_world(&path);
+        world.init_resource::<Events<LevelCommand>>();
+
+        let offset = IVec2::new(40, -7);
+        let command = LevelCommand::LoadAt(path.to_string_lossy().into_owned(), offset);
+        world.resource_mut::<Events<LevelCommand>>().send(command);
+        run_system(&mut world, level_command_system);
+        assert_eq!(
+            world.resource::<TileIndex>().bounds(),
+            Some((IVec2::new(-3, 0) + offset, IVec2::new(3, 0) + offset))
+        );
+        world.resource_mut::<Events<SaveLevel>>().send(SaveLevel);
+        run_system(&mut world, save_level_system);
+
+        let saved = LevelData::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        std::fs::remove_file(thumbnail_path(&path)).unwrap();
+        assert_eq!(saved, level);
+    }
+
+    #[test]
+    fn whats_gone_in_play_is_still_saved() {
+        let mut game = HeadlessGame::new();
+        let mut enemy = EnemyData::new(IVec2::new(0, 1));
+        enemy.speed = 0.;
+        let level = LevelData {
+            enemies: vec![enemy],
+            coins: vec![IVec2::new(3, 1), IVec2::new(3, 6)],
+            pickups: vec![PickupData {
+                pos: IVec2::new(5, 1),
+                ability: Ability::Dash,
+            }],
+            ..floor(-2..=8)
+        };
+        game.spawn_level(&level);
+        // Stomping the enemy, then walking through the coin and the pickup
+        game.place_player(Vec2::new(0., 4.));
+        game.run(480, &[]);
+        game.run(240, &[Action::MoveRight]);
+
+        let world = &mut game.app.world;
+        let mut state = SystemState::<(PlacedEntities, Res<Unspawned>)>::new(world);
+        let (placed, unspawned) = state.get_mut(world);
+        assert_eq!(placed.data().coins, vec![IVec2::new(3, 6)]);
+        assert!(placed.data().enemies.is_empty());
+        assert!(placed.data().pickups.is_empty());
+        let mut saved = placed.data();
+        saved.add_entities(&unspawned.0);
+        saved.sort_by_row();
+        assert_eq!(saved.enemies, level.enemies);
+        assert_eq!(saved.coins, level.coins);
+        assert_eq!(saved.pickups, level.pickups);
+    }
+
+    #[test]
+    fn shifting_moves_tiles_stamps_and_spawn() {
+        let mut level = default_level();
+        level.stamps.push(StampData {
+            prefab: "ledge".to_string(),
+            origin: IVec2::new(1, 2),
+            overwrite: false,
+            flip_x: false,
+        });
+        let offset = IVec2::new(20, -3);
+        let shifted = level.clone().shifted(offset);
+        assert_eq!(shifted.tiles[0].pos, level.tiles[0].pos + offset);
+        assert_eq!(shifted.stamps[0].origin, IVec2::new(21, -1));
+        assert_eq!(shifted.player_spawn_cell(), IVec2::new(20, -2));
+
+        let back = shifted.shifted(-offset);
+        assert_eq!(back.tiles, level.tiles);
+        assert_eq!(back.stamps, level.stamps);
+        assert_eq!(back.player_spawn, level.player_spawn);
+    }
+}
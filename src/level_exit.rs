@@ -0,0 +1,59 @@
+// The exit placed in a level, which finishes it for a player touching it.
+// Nothing moves them on yet; finishing stops the `LevelTimer`.
+
+use bevy::prelude::*;
+use bevy::sprite::Anchor;
+
+use crate::death::LivePlayerQuery;
+use crate::level::LevelEntity;
+use crate::physics::{hitbox, overlaps};
+use crate::player::Player;
+
+pub const LEVEL_EXIT_COLOR: Color = Color::rgba(1., 0.85, 0.3, 0.6);
+
+// Fills its cell, from the bottom-left corner
+#[derive(Component)]
+pub struct LevelExit;
+
+pub struct ExitReached {
+    pub player: Entity,
+}
+
+// Drawn as a door of `LEVEL_EXIT_COLOR` the height of a standing player
+pub fn spawn_level_exit(commands: &mut Commands, cell: IVec2) -> Entity {
+    commands
+        .spawn_bundle(SpriteBundle {
+            transform: Transform::from_translation(cell.as_vec2().extend(-1.)),
+            sprite: Sprite {
+                color: LEVEL_EXIT_COLOR,
+                custom_size: Some(Vec2::new(1., 2.)),
+                anchor: Anchor::BottomLeft,
+                ..default()
+            },
+            ..default()
+        })
+        .insert(LevelExit)
+        .insert(LevelEntity)
+        .id()
+}
+
+// The cell an exit was placed in
+pub fn level_exit_cell(transform: &Transform) -> IVec2 {
+    transform.translation.truncate().round().as_ivec2()
+}
+
+// After the collision step, every step a player overlaps the exit
+pub fn level_exit_system(
+    player_query: LivePlayerQuery,
+    exit_query: Query<&Transform, (With<LevelExit>, Without<Player>)>,
+    mut reached_events: EventWriter<ExitReached>,
+) {
+    for exit_transform in exit_query.iter() {
+        let exit_box = (exit_transform.translation.truncate(), Vec2::new(1., 2.));
+        for (player, transform) in player_query.iter() {
+            if overlaps(hitbox(transform), exit_box) {
+                reached_events.send(ExitReached { player });
+            }
+        }
+    }
+}
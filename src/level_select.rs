@@ -0,0 +1,309 @@
+// A menu listing the level files in `assets/levels`, with their thumbnails.
+//
+// It is opened from play with the LevelSelect action, and then driven by
+// `MenuActionState`: Up and Down move the highlight, Confirm loads the
+// highlighted level and returns to play, and Back returns to where it was
+// opened from without changing level.
+
+use bevy::prelude::*;
+use std::path::{Path, PathBuf};
+
+use crate::game_state::GameState;
+use crate::input::{Action, ActionState, MenuAction, MenuActionState};
+use crate::level::{list_levels, thumbnail_path, LevelCommand, LEVELS_DIR};
+use crate::pixel_perfect::UI_FONT;
+
+// Thumbnails have a pixel per tile, so they are drawn larger
+const THUMBNAIL_SCALE: f32 = 3.;
+const SELECTED_COLOR: Color = Color::rgb(1., 0.85, 0.3);
+const UNSELECTED_COLOR: Color = Color::rgb(0.7, 0.7, 0.7);
+
+#[derive(Default)]
+struct LevelList {
+    levels: Vec<PathBuf>,
+    selected: usize,
+}
+
+// The state Back returns to, which whatever opens the menu sets
+pub struct LevelSelectReturn(pub GameState);
+
+impl Default for LevelSelectReturn {
+    fn default() -> Self {
+        LevelSelectReturn(GameState::Playing)
+    }
+}
+
+#[derive(Component)]
+struct LevelSelectMenu;
+
+// The name of the level at this index in the `LevelList`
+#[derive(Component)]
+struct LevelEntryText(usize);
+
+// Needs the `ActionState` and `MenuActionState` from `InputMapPlugin`, and
+// the `GameState` from `GameStatePlugin`
+#[derive(Default)]
+pub struct LevelSelectPlugin;
+
+impl Plugin for LevelSelectPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<LevelList>()
+            .init_resource::<LevelSelectReturn>()
+            .add_system_set(SystemSet::on_update(GameState::Playing).with_system(open_menu_system))
+            .add_system_set(
+                SystemSet::on_enter(GameState::LevelSelect).with_system(spawn_menu_system),
+            )
+            .add_system_set(
+                SystemSet::on_update(GameState::LevelSelect)
+                    .with_system(menu_input_system)
+                    .with_system(highlight_system.after(menu_input_system)),
+            )
+            .add_system_set(
+                SystemSet::on_exit(GameState::LevelSelect).with_system(despawn_menu_system),
+            );
+    }
+}
+
+fn open_menu_system(
+    action_state: Res<ActionState>,
+    mut level_select_return: ResMut<LevelSelectReturn>,
+    mut state: ResMut<State<GameState>>,
+) {
+    // Fails only if a transition is already queued this frame
+    if action_state.just_pressed(Action::LevelSelect) && state.set(GameState::LevelSelect).is_ok() {
+        level_select_return.0 = GameState::Playing;
+    }
+}
+
+fn level_name(path: &Path) -> String {
+    path.file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+fn spawn_menu_system(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut level_list: ResMut<LevelList>,
+) {
+    level_list.levels = list_levels(LEVELS_DIR);
+    level_list.selected = level_list
+        .selected
+        .min(level_list.levels.len().saturating_sub(1));
+
+    let font = asset_server.load(UI_FONT);
+    let text_style = |font_size, color| TextStyle {
+        font: font.clone(),
+        font_size,
+        color,
+    };
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.), Val::Percent(100.)),
+                position_type: PositionType::Absolute,
+                // The UI is y-up, so this lists the children top to bottom
+                flex_direction: FlexDirection::ColumnReverse,
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            color: Color::rgba(0., 0., 0., 0.75).into(),
+            ..default()
+        })
+        .insert(LevelSelectMenu)
+        .with_children(|parent| {
+            parent.spawn_bundle(TextBundle {
+                text: Text::with_section(
+                    "Select a level",
+                    text_style(32., Color::WHITE),
+                    default(),
+                ),
+                style: Style {
+                    margin: Rect {
+                        bottom: Val::Px(16.),
+                        ..default()
+                    },
+                    ..default()
+                },
+                ..default()
+            });
+            if level_list.levels.is_empty() {
+                parent.spawn_bundle(TextBundle {
+                    text: Text::with_section(
+                        format!("No levels found in {}", LEVELS_DIR),
+                        text_style(20., UNSELECTED_COLOR),
+                        default(),
+                    ),
+                    ..default()
+                });
+            }
+            for (index, path) in level_list.levels.iter().enumerate() {
+                parent
+                    .spawn_bundle(NodeBundle {
+                        style: Style {
+                            align_items: AlignItems::Center,
+                            margin: Rect::all(Val::Px(4.)),
+                            ..default()
+                        },
+                        color: Color::NONE.into(),
+                        ..default()
+                    })
+                    .with_children(|row| {
+                        let thumbnail = thumbnail_path(path);
+                        // Sized from the file, as the image asset loads too
+                        // late for layout
+                        if let Ok((width, height)) = image::image_dimensions(&thumbnail) {
+                            let asset_path = thumbnail.strip_prefix("assets").unwrap_or(&thumbnail);
+                            row.spawn_bundle(ImageBundle {
+                                image: asset_server.load(asset_path).into(),
+                                style: Style {
+                                    size: Size::new(
+                                        Val::Px(width as f32 * THUMBNAIL_SCALE),
+                                        Val::Px(height as f32 * THUMBNAIL_SCALE),
+                                    ),
+                                    margin: Rect {
+                                        right: Val::Px(12.),
+                                        ..default()
+                                    },
+                                    ..default()
+                                },
+                                ..default()
+                            });
+                        }
+                        row.spawn_bundle(TextBundle {
+                            text: Text::with_section(
+                                level_name(path),
+                                text_style(20., UNSELECTED_COLOR),
+                                default(),
+                            ),
+                            ..default()
+                        })
+                        .insert(LevelEntryText(index));
+                    });
+            }
+        });
+}
+
+fn menu_input_system(
+    menu_actions: Res<MenuActionState>,
+    level_select_return: Res<LevelSelectReturn>,
+    mut level_list: ResMut<LevelList>,
+    mut state: ResMut<State<GameState>>,
+    mut level_commands: EventWriter<LevelCommand>,
+) {
+    let count = level_list.levels.len();
+    if count > 0 {
+        if menu_actions.just_pressed(MenuAction::Down) {
+            level_list.selected = (level_list.selected + 1) % count;
+        }
+        if menu_actions.just_pressed(MenuAction::Up) {
+            level_list.selected = (level_list.selected + count - 1) % count;
+        }
+    }
+    if menu_actions.just_pressed(MenuAction::Confirm) {
+        let path = match level_list.levels.get(level_list.selected) {
+            Some(path) => path,
+            None => return,
+        };
+        level_commands.send(LevelCommand::Load(path.to_string_lossy().into_owned()));
+        let _ = state.set(GameState::Playing);
+    } else if menu_actions.just_pressed(MenuAction::Back) {
+        let _ = state.set(level_select_return.0);
+    }
+}
+
+fn highlight_system(level_list: Res<LevelList>, mut query: Query<(&LevelEntryText, &mut Text)>) {
+    for (entry, mut text) in query.iter_mut() {
+        let color = if entry.0 == level_list.selected {
+            SELECTED_COLOR
+        } else {
+            UNSELECTED_COLOR
+        };
+        for section in text.sections.iter_mut() {
+            section.style.color = color;
+        }
+    }
+}
+
+fn despawn_menu_system(mut commands: Commands, query: Query<Entity, With<LevelSelectMenu>>) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game_state::GameStatePlugin;
+    use bevy::asset::FileAssetIo;
+    use bevy::ecs::event::Events;
+    use bevy::tasks::TaskPoolBuilder;
+
+    // Opened from the main menu
+    fn menu_app() -> App {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .insert_resource(AssetServer::new(
+                FileAssetIo::new("assets", false),
+                TaskPoolBuilder::new().build(),
+            ))
+            .init_resource::<ActionState>()
+            .init_resource::<MenuActionState>()
+            .add_event::<LevelCommand>()
+            .add_plugin(GameStatePlugin {
+                initial_state: GameState::LevelSelect,
+            })
+            .add_plugin(LevelSelectPlugin)
+            .insert_resource(LevelSelectReturn(GameState::MainMenu));
+        app.update();
+        app
+    }
+
+    // Pressed for a frame, then released for one
+    fn press(app: &mut App, action: MenuAction) {
+        let mut menu_actions = app.world.resource_mut::<MenuActionState>();
+        menu_actions.update([action].into_iter().collect(), 0.);
+        app.update();
+        let mut menu_actions = app.world.resource_mut::<MenuActionState>();
+        menu_actions.update(default(), 0.);
+        app.update();
+    }
+
+    fn state(app: &App) -> GameState {
+        *app.world.resource::<State<GameState>>().current()
+    }
+
+    fn loaded(app: &App) -> Vec<String> {
+        let events = app.world.resource::<Events<LevelCommand>>();
+        events
+            .get_reader()
+            .iter(events)
+            .filter_map(|command| match command {
+                LevelCommand::Load(path) => Some(path.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn back_returns_to_where_it_was_opened_from() {
+        let mut app = menu_app();
+        press(&mut app, MenuAction::Back);
+        assert_eq!(state(&app), GameState::MainMenu);
+        assert!(loaded(&app).is_empty());
+    }
+
+    #[test]
+    fn confirm_loads_the_highlighted_level() {
+        let mut app = menu_app();
+        let levels = app.world.resource::<LevelList>().levels.clone();
+        assert!(!levels.is_empty());
+        // Round from the top to the bottom of the list
+        press(&mut app, MenuAction::Up);
+        press(&mut app, MenuAction::Confirm);
+        assert_eq!(state(&app), GameState::Playing);
+        let last = levels.last().unwrap().to_string_lossy().into_owned();
+        assert_eq!(loaded(&app), vec![last]);
+    }
+}
@@ -1,2 +1,54 @@
+pub mod ability;
+pub mod animation;
+pub mod camera;
+pub mod coin;
+pub mod collision_outline;
+pub mod cursor;
+pub mod death;
+pub mod debug;
+pub mod diagnostics;
+pub mod display;
+pub mod enemy;
+pub mod falling_platform;
+pub mod game;
+pub mod game_over;
+pub mod game_state;
+pub mod headless;
+pub mod health;
+pub mod hud;
+pub mod input;
+pub mod input_overlay;
+pub mod inspector;
+pub mod ledge_grab;
+pub mod level;
+pub mod level_exit;
+pub mod level_select;
+pub mod main_menu;
+pub mod music;
+pub mod npc;
+pub mod palette;
+pub mod parallax;
+pub mod particles;
+pub mod path;
+pub mod pause_menu;
+pub mod physics;
 pub mod pixel_perfect;
+pub mod player;
+pub mod prefab;
+pub mod pressure_plate;
+pub mod projectile;
+pub mod push_box;
+pub mod quicksave;
+pub mod replay;
+pub mod respawn;
+pub mod saw;
+pub mod score;
+pub mod settings;
+pub mod sfx;
+pub mod sign;
+pub mod speedrun;
+pub mod system_menu;
 pub mod tile;
+pub mod tile_feedback;
+pub mod virtual_buttons;
+pub mod wrap_ghost;
@@ -1,503 +1,1176 @@
-use bevy::{
-    app::AppExit,
-    core::FixedTimestep,
-    prelude::*,
-    sprite::collide_aabb::{collide, Collision},
-    sprite::Anchor,
-    window::WindowMode,
-};
+use bevy::{prelude::*, sprite::Anchor, window::WindowMode};
 
 use std::collections::HashSet;
 
+use last_question::ability::AbilityPlugin;
+use last_question::animation::AnimationPlugin;
+use last_question::collision_outline::{CollisionOutline, CollisionOutlinePlugin};
+use last_question::cursor::{CursorGrabPlugin, CursorPlugin, CursorWorldPos};
+use last_question::debug::DebugModePlugin;
+use last_question::diagnostics::DiagnosticsOverlayPlugin;
+use last_question::display::{DisplayPlugin, DisplaySettings};
+use last_question::enemy::{spawn_enemy, Enemy, EnemyData, ENEMY_COLOR};
+use last_question::falling_platform::{
+    spawn_platform, FallingPlatform, PlatformData, PLATFORM_COLOR,
+};
+use last_question::game::{live_edit_system_set, GamePlugin};
+use last_question::game_over::GameOverPlugin;
+use last_question::game_state::{GameState, GameStatePlugin, PhysicsSubsteps};
+use last_question::hud::HudPlugin;
+use last_question::input::{
+    Action, ActionState, InputMap, InputMapPlugin, KeyRepeat, PlayerId, SecondPlayerInput,
+    VirtualInput,
+};
+use last_question::input_overlay::InputOverlayPlugin;
+use last_question::inspector::TileInspectorPlugin;
+use last_question::level::{
+    default_level, spawn_player_spawn, CurrentLevel, LevelPlugin, PlayerSpawn, TileAppearanceData,
+    EMBEDDED_STARTUP_LEVEL, PLAYER_SPAWN_DEBUG_COLOR, STARTUP_LEVEL_PATH,
+};
+use last_question::level_select::LevelSelectPlugin;
+use last_question::main_menu::MainMenuPlugin;
+use last_question::music::MusicPlugin;
+use last_question::npc::NpcPlugin;
+use last_question::palette::{TilePalettes, TILE_PALETTES_PATH};
+use last_question::parallax::ParallaxPlugin;
+use last_question::particles::ParticlePlugin;
+use last_question::pause_menu::PauseMenuPlugin;
+use last_question::physics::PhysicsSystem;
 use last_question::pixel_perfect::{
-    PixelPerfectPlugin, WorldCamera, HEIGHT_PIXELS, PIXELS_PER_TILE, WIDTH_PIXELS,
+    PixelPerfectPlugin, WorldCamera, WorldClearColor, HEIGHT_PIXELS, PIXELS_PER_TILE, WIDTH_PIXELS,
 };
-use last_question::tile;
-
-const INPUT_TIME_STEP: f32 = 1.0 / 300.0;
-const PHYSICS_TIME_STEP: f32 = 1.0 / 240.0;
-const GRAVITY: f32 = 30.;
-
-#[derive(Component)]
-struct Label(String);
-
-#[derive(Component)]
-struct Velocity(Vec3);
+use last_question::player::{self, PlayerSpec, ResetHold};
+use last_question::prefab::{stamp_prefab, PrefabLibrary, PREFAB_LIBRARY_PATH};
+use last_question::quicksave::QuickSavePlugin;
+use last_question::replay::{ReplayMode, ReplayPlugin};
+use last_question::respawn::ScreenFadePlugin;
+use last_question::score::ScorePopupPlugin;
+use last_question::settings::Settings;
+use last_question::sfx::{AudioSettings, SfxPlugin};
+use last_question::sign::SignPlugin;
+use last_question::speedrun::SpeedrunPlugin;
+use last_question::system_menu::SystemMenuPlugin;
+use last_question::tile::{self, SolidCollider, TileIndex, TilePlaced, TilePlugin, TileRemoved};
+use last_question::tile_feedback::TileFeedbackPlugin;
+use last_question::virtual_buttons::VirtualButtonsPlugin;
+use last_question::wrap_ghost::WrapGhostPlugin;
 
-#[derive(Component)]
-struct Gravity(f32);
-
-#[derive(Component)]
-struct Player;
-
-enum Direction {
-    Left,
-    Right,
-    Neutral,
-}
-
-#[derive(Component)]
-struct Mobility {
-    on_ground: bool,
-    jump_speed: f32,
-    walk_speed: f32,
-    walk_direction: Direction,
-}
+// In front of the tiles and the player
+const MIRROR_AXIS_Z: f32 = 10.;
+const SELECTION_Z: f32 = 9.;
+// The largest eraser radius, in cells
+const MAX_ERASER_RADIUS: i32 = 8;
+const ERASER_COLOR: Color = Color::rgba(1., 0.2, 0.2, 0.4);
 
 #[derive(Clone, Hash, Debug, PartialEq, Eq, SystemLabel)]
-enum PhysicsSystem {
-    Gravity,
-    Velocity,
-    Collision,
-    Camera,
-}
-
-fn physics_system(mut query: Query<(&mut Transform, &Velocity)>) {
-    for (mut transform, velocity) in query.iter_mut() {
-        transform.translation += velocity.0 * PHYSICS_TIME_STEP;
-    }
-}
+struct MouseInput;
 
-fn gravity_system(mut query: Query<(&mut Velocity, &Gravity)>) {
-    for (mut velocity, gravity) in query.iter_mut() {
-        velocity.0.y -= gravity.0 * PHYSICS_TIME_STEP;
-    }
-}
+#[derive(Clone, Hash, Debug, PartialEq, Eq, SystemLabel)]
+struct KeyboardInput;
 
-fn keyboard_input_system(
+// Hold F7 and use the arrow keys to tune the level's background color:
+// left/right shift the hue, up/down the lightness
+fn background_color_tuning_system(
     keyboard_input: Res<Input<KeyCode>>,
-    mut query: Query<(&mut Transform, &mut Velocity, &mut Mobility), With<Player>>,
-    mut app_exit_events: EventWriter<AppExit>,
+    mut clear_color: ResMut<WorldClearColor>,
 ) {
-    let (mut transform, mut velocity, mut mobility) = query.single_mut();
-
-    if keyboard_input.just_pressed(KeyCode::R) {
-        transform.translation = Vec3::new(0., 1., 0.);
-        velocity.0 = Vec3::new(0., 0., 0.);
-    }
-
-    if keyboard_input.just_pressed(KeyCode::A) {
-        mobility.walk_direction = Direction::Left;
-    }
-    if keyboard_input.just_released(KeyCode::A)
-        && matches!(mobility.walk_direction, Direction::Left)
-    {
-        mobility.walk_direction = if keyboard_input.pressed(KeyCode::D) {
-            Direction::Right
-        } else {
-            Direction::Neutral
-        };
-    }
-
-    if keyboard_input.just_pressed(KeyCode::D) {
-        mobility.walk_direction = Direction::Right;
+    if !keyboard_input.pressed(KeyCode::F7) {
+        return;
     }
-    if keyboard_input.just_released(KeyCode::D)
-        && matches!(mobility.walk_direction, Direction::Right)
+    let (hue_step, lightness_step) = match keyboard_input.get_just_pressed().next() {
+        Some(KeyCode::Left) => (-10., 0.),
+        Some(KeyCode::Right) => (10., 0.),
+        Some(KeyCode::Down) => (0., -0.05),
+        Some(KeyCode::Up) => (0., 0.05),
+        _ => return,
+    };
+    if let Color::Hsla {
+        hue,
+        saturation,
+        lightness,
+        alpha,
+    } = clear_color.0.as_hsla()
     {
-        mobility.walk_direction = if keyboard_input.pressed(KeyCode::A) {
-            Direction::Left
-        } else {
-            Direction::Neutral
-        };
-    }
-
-    velocity.0.x = mobility.walk_speed
-        * match mobility.walk_direction {
-            Direction::Left => -1.0,
-            Direction::Right => 1.0,
-            Direction::Neutral => 0.0,
-        };
-
-    if keyboard_input.just_pressed(KeyCode::Space) {
-        if mobility.on_ground {
-            mobility.on_ground = false;
-            velocity.0.y = mobility.jump_speed;
-        }
-    }
-    if keyboard_input.just_released(KeyCode::Space) {
-        if velocity.0.y > 0.0 {
-            velocity.0.y = 0.0;
-        }
-    }
-
-    if !cfg!(target_arch = "wasm32") {
-        if keyboard_input.pressed(KeyCode::Escape) {
-            app_exit_events.send(AppExit);
-        }
+        clear_color.0 = Color::hsla(
+            (hue + hue_step).rem_euclid(360.),
+            // Black and white have no saturation to shift the hue of
+            if saturation == 0. { 0.5 } else { saturation },
+            (lightness + lightness_step).clamp(0., 1.),
+            alpha,
+        );
+        let [red, green, blue, alpha] = clear_color.0.as_rgba_f32();
+        info!(
+            "background_color: Rgba(red: {:.3}, green: {:.3}, blue: {:.3}, alpha: {:.3})",
+            red, green, blue, alpha
+        );
     }
 }
 
+// Left click uses the selected tool, right click always erases and middle
+// click always selects. A stroke lasts until its button is released.
 fn mouse_input_system(
     mouse_button_input: Res<Input<MouseButton>>,
+    virtual_input: Res<VirtualInput>,
     mut tile_edit: ResMut<TileEdit>,
 ) {
-    if mouse_button_input.just_released(MouseButton::Left) {
-        if let TileEditTool::Paintbrush = tile_edit.tool {
+    if let Some(button) = tile_edit.button {
+        if mouse_button_input.just_released(button) {
             tile_edit.deactivate();
         }
     }
 
+    // Clicks on the on-screen buttons aren't edits
+    if tile_edit.active || virtual_input.pointer_over {
+        return;
+    }
     if mouse_button_input.just_pressed(MouseButton::Left) {
-        if !tile_edit.active {
-            tile_edit.activate_paintbrush();
-        }
+        let tool = tile_edit.left_click_tool();
+        tile_edit.activate(tool, MouseButton::Left);
+    } else if mouse_button_input.just_pressed(MouseButton::Right) {
+        tile_edit.activate(TileEditTool::Eraser, MouseButton::Right);
+    } else if mouse_button_input.just_pressed(MouseButton::Middle) {
+        tile_edit.activate(TileEditTool::Select, MouseButton::Middle);
     }
+}
 
-    if mouse_button_input.just_released(MouseButton::Right) {
-        if let TileEditTool::Eraser = tile_edit.tool {
-            tile_edit.deactivate();
+// Place with the left click tool, or erase, at the cursor from the keyboard.
+// A held key repeats, each time a cell further in the direction the movement
+// keys are held, so holding right and place lays a row of tiles. The stroke
+// lasts until the key is released.
+fn keyboard_edit_system(
+    action_state: Res<ActionState>,
+    input_map: Res<InputMap>,
+    cursor_world_pos: Res<CursorWorldPos>,
+    settings: Res<Settings>,
+    substeps: Res<PhysicsSubsteps>,
+    mut repeats: Local<[KeyRepeat; 2]>,
+    mut tile_edit: ResMut<TileEdit>,
+) {
+    let dt = substeps.input_time_step();
+    for (action, repeat) in [Action::PlaceTile, Action::EraseTile]
+        .into_iter()
+        .zip(repeats.iter_mut())
+    {
+        let fired = repeat.step(&action_state, action, &input_map, dt);
+        let stroke_cell = match tile_edit.key {
+            Some((key, cell)) if key == action => Some(cell),
+            _ => None,
+        };
+        if let Some(cell) = stroke_cell {
+            if !action_state.pressed(action) {
+                tile_edit.deactivate();
+            } else if fired {
+                let held = |action| action_state.pressed(action) as i32;
+                let direction = IVec2::new(
+                    held(Action::MoveRight) - held(Action::MoveLeft),
+                    held(Action::Jump) - held(Action::FastFall),
+                );
+                tile_edit.key = Some((action, cell + direction));
+            }
+        } else if fired && !tile_edit.active {
+            if let Some(cell) = cursor_world_pos.cell(settings.cell_rounding) {
+                let tool = match action {
+                    Action::PlaceTile => tile_edit.left_click_tool(),
+                    _ => TileEditTool::Eraser,
+                };
+                tile_edit.active = true;
+                tile_edit.tool = tool;
+                tile_edit.key = Some((action, cell));
+            }
         }
     }
+}
 
-    if mouse_button_input.just_pressed(MouseButton::Right) {
-        if !tile_edit.active {
-            tile_edit.activate_eraser();
-        }
+// Cycle the left click tool, the palette and the paintbrush's tile in it, or
+// with the eraser selected, change its radius instead. Ignored mid-stroke, so
+// a stroke never mixes tools or tiles.
+fn tool_select_system(
+    action_state: Res<ActionState>,
+    palettes: Res<TilePalettes>,
+    mut tile_edit: ResMut<TileEdit>,
+) {
+    if tile_edit.active {
+        return;
+    }
+    let tool_step = action_state.just_pressed(Action::NextTool) as isize
+        - action_state.just_pressed(Action::PrevTool) as isize;
+    if tool_step != 0 {
+        let tools = &TileEditTool::SELECTABLE;
+        let index = tools
+            .iter()
+            .position(|&tool| tool == tile_edit.selected_tool)
+            .unwrap_or(0) as isize;
+        tile_edit.selected_tool =
+            tools[(index + tool_step).rem_euclid(tools.len() as isize) as usize];
+        info!("Tool: {:?}", tile_edit.selected_tool);
+    }
+    if action_state.just_pressed(Action::CyclePalette) {
+        tile_edit.palette = (tile_edit.palette + 1) % palettes.count();
+        // Starting from the first tile, rather than the same place in a list
+        // of other tiles
+        tile_edit.brush = 0;
+        info!("Palette: {}", palettes.palette(tile_edit.palette).name);
+    }
+    let brush_step = action_state.just_pressed(Action::NextBrush) as isize
+        - action_state.just_pressed(Action::PrevBrush) as isize;
+    if brush_step != 0 && tile_edit.selected_tool == TileEditTool::Eraser {
+        tile_edit.eraser_radius =
+            (tile_edit.eraser_radius + brush_step as i32).clamp(0, MAX_ERASER_RADIUS);
+        info!("Eraser radius: {}", tile_edit.eraser_radius);
+    } else if brush_step != 0 {
+        let entries = palettes.palette(tile_edit.palette).entries.len();
+        tile_edit.brush =
+            (tile_edit.brush as isize + brush_step).rem_euclid(entries as isize) as usize;
+        info!(
+            "Brush: {:?}",
+            palettes.entry(tile_edit.palette, tile_edit.brush)
+        );
     }
 }
 
-fn update_screen_to_world_system(
-    mut screen_to_world: ResMut<ScreenToWorld>,
-    windows: Res<Windows>,
-    camera_query: Query<&Transform, With<WorldCamera>>,
+// Cycle the prefab left click stamps, then back to painting single tiles
+fn prefab_select_system(
+    action_state: Res<ActionState>,
+    prefabs: Res<PrefabLibrary>,
+    mut tile_edit: ResMut<TileEdit>,
 ) {
-    let window = windows.primary();
-    screen_to_world.set_screen_dimensions(Vec2::new(window.width(), window.height()));
-    let transform = camera_query.single();
-    screen_to_world.set_world_offset(transform.translation.truncate());
+    if action_state.just_pressed(Action::CyclePrefab) && !tile_edit.active {
+        tile_edit.prefab = match tile_edit.prefab {
+            None if !prefabs.is_empty() => Some(0),
+            Some(index) if index + 1 < prefabs.len() => Some(index + 1),
+            _ => None,
+        };
+        match tile_edit.prefab.and_then(|index| prefabs.iter().nth(index)) {
+            Some((name, _)) => info!("Stamping prefab {:?}", name),
+            None => info!("Painting single tiles"),
+        }
+    }
+    if action_state.just_pressed(Action::ToggleStampOverwrite) {
+        tile_edit.stamp_overwrite = !tile_edit.stamp_overwrite;
+        info!(
+            "Stamps {} existing tiles",
+            if tile_edit.stamp_overwrite {
+                "overwrite"
+            } else {
+                "keep"
+            }
+        );
+    }
 }
 
+// The enemies the eraser can remove
+type PlacedEnemyQuery<'w, 's> =
+    Query<'w, 's, (Entity, &'static Transform), (With<Enemy>, Without<PlayerSpawn>)>;
+
+#[allow(clippy::too_many_arguments)]
 fn tile_edit_system(
     mut commands: Commands,
-    screen_to_world: Res<ScreenToWorld>,
-    window: Res<Windows>,
+    cursor_world_pos: Res<CursorWorldPos>,
+    settings: Res<Settings>,
     mut tile_edit: ResMut<TileEdit>,
-    tile_query: Query<(Entity, &Transform), With<tile::Tile>>,
+    mut tile_index: ResMut<TileIndex>,
+    mut current_level: ResMut<CurrentLevel>,
     asset_server: Res<AssetServer>,
+    prefabs: Res<PrefabLibrary>,
+    palettes: Res<TilePalettes>,
+    mut spawn_query: Query<&mut Transform, With<PlayerSpawn>>,
+    enemy_query: PlacedEnemyQuery,
+    platform_query: Query<(Entity, &FallingPlatform)>,
+    mut placed_events: EventWriter<TilePlaced>,
+    mut removed_events: EventWriter<TileRemoved>,
 ) {
     if !tile_edit.active {
         return;
     }
 
-    if let Some(cursor) = window.primary().cursor_position() {
-        let cursor = (screen_to_world.transform(cursor) - 0.5).round().as_ivec2();
-        if !tile_edit.interacted.contains(&cursor.to_array()) {
+    let cursor = match tile_edit.key {
+        Some((_, cell)) => Some(cell),
+        None => cursor_world_pos.cell(settings.cell_rounding),
+    };
+    if let Some(cursor) = cursor {
+        // A wide eraser has usually reached the cell under the cursor already,
+        // so it checks each cell it reaches instead
+        if tile_edit.tool == TileEditTool::Eraser
+            || !tile_edit.interacted.contains(&cursor.to_array())
+        {
             match tile_edit.tool {
                 TileEditTool::Paintbrush => {
-                    tile_edit.interacted.insert(cursor.to_array());
-                    let mut exists = false;
-                    for (_, tile_transform) in tile_query.iter() {
-                        if tile_transform.translation.truncate().round().as_ivec2() == cursor {
-                            exists = true;
+                    for cell in tile_edit.mirror.reflections(cursor) {
+                        tile_edit.interacted.insert(cell.to_array());
+                        if tile_index.tile_at(cell).is_none() {
+                            let brush = palettes.entry(tile_edit.palette, tile_edit.brush);
+                            tile_index.spawn(&mut commands, brush.spec(cell, &asset_server));
+                            placed_events.send(TilePlaced(cell));
+                            current_level.unsaved = true;
                         }
                     }
-                    if !exists {
-                        commands.spawn_bundle(tile::SolidTile::from_spec(tile::TileSpec {
-                            pos: cursor,
-                            appearance: tile::TileAppearance::Texture(
-                                asset_server.load("tile.png"),
-                            ),
-                        }));
-                    }
                 }
+                // Looks up each cell within the radius, rather than every tile
                 TileEditTool::Eraser => {
-                    tile_edit.interacted.insert(cursor.to_array());
-                    for (entity, tile_transform) in tile_query.iter() {
-                        if tile_transform.translation.truncate().round().as_ivec2() == cursor {
-                            commands.entity(entity).despawn_recursive();
+                    for offset in disk(tile_edit.eraser_radius) {
+                        for cell in tile_edit.mirror.reflections(cursor + offset) {
+                            if !tile_edit.interacted.insert(cell.to_array()) {
+                                continue;
+                            }
+                            if tile_index.despawn(&mut commands, cell).is_some() {
+                                removed_events.send(TileRemoved(cell));
+                                current_level.unsaved = true;
+                            }
+                            for (enemy, transform) in enemy_query.iter() {
+                                if transform.translation.truncate().floor().as_ivec2() == cell {
+                                    commands.entity(enemy).despawn_recursive();
+                                    current_level.unsaved = true;
+                                }
+                            }
+                            // Wherever it has fallen to, it's erased from where it rests
+                            for (entity, platform) in platform_query.iter() {
+                                if platform.data.pos == cell {
+                                    commands.entity(entity).despawn_recursive();
+                                    current_level.unsaved = true;
+                                }
+                            }
                         }
                     }
                 }
-            }
-        }
-    }
-}
-
-fn player_tile_collision_system(
-    mut player_query: Query<(&mut Velocity, &mut Transform, &mut Mobility), With<Player>>,
-    collider_query: Query<&Transform, (With<tile::SolidCollider>, Without<Player>)>,
-) {
-    // First pass: detect internal segments to be ignored
-    // Segments enclosing a space follow a counter-clockwise convention
-    let mut segments = HashSet::<[i32; 4]>::new();
-    // Reserve space for 1000 tiles
-    segments.reserve(4000);
-    // Currently assuming only 1x1 tiles
-    for solid_tran in collider_query.iter() {
-        let base = solid_tran.translation.round().as_ivec3();
-        // Bottom segment
-        segments.insert([base.x, base.y, base.x + 1, base.y]);
-        // Right segment
-        segments.insert([base.x + 1, base.y, base.x + 1, base.y + 1]);
-        // Top segment
-        segments.insert([base.x + 1, base.y + 1, base.x, base.y + 1]);
-        // Left segment
-        segments.insert([base.x, base.y + 1, base.x, base.y]);
-    }
-    let (mut player_vel, mut player_tran, mut jump) = player_query.single_mut();
-    jump.on_ground = false;
-    // Second pass: handle collisions with external segments
-    // A segment is internal if there is another segment which is its inversion
-    for solid_tran in collider_query.iter() {
-        let base = solid_tran.translation.round().as_ivec3();
-
-        let collision = collide(
-            player_tran.translation + 0.5 * player_tran.scale,
-            player_tran.scale.truncate(),
-            solid_tran.translation + 0.5 * solid_tran.scale,
-            solid_tran.scale.truncate(),
-        );
-        if let Some(collision) = collision {
-            match collision {
-                Collision::Left => {
-                    if !segments.contains(&[base.x, base.y, base.x, base.y + 1]) {
-                        if player_vel.0.x > 0.0 {
-                            player_vel.0.x = 0.0;
+                // Only the last cell is remembered, so dragging back shrinks
+                // the selection
+                TileEditTool::Select => {
+                    let start = match tile_edit.selection {
+                        Some(selection) if !tile_edit.interacted.is_empty() => selection.start,
+                        _ => cursor,
+                    };
+                    tile_edit.selection = Some(CellRect::spanning(start, cursor));
+                    tile_edit.interacted.clear();
+                    tile_edit.interacted.insert(cursor.to_array());
+                }
+                // A level has one spawn, so it is moved rather than added
+                TileEditTool::Spawn => {
+                    tile_edit.interacted.insert(cursor.to_array());
+                    match spawn_query.iter_mut().next() {
+                        Some(mut spawn) => {
+                            spawn.translation = cursor.as_vec2().extend(spawn.translation.z);
+                        }
+                        None => {
+                            spawn_player_spawn(&mut commands, cursor);
                         }
-                        player_tran.translation.x = solid_tran.translation.x - player_tran.scale.x;
                     }
+                    current_level.unsaved = true;
                 }
-                Collision::Right => {
-                    if !segments.contains(&[base.x + 1, base.y + 1, base.x + 1, base.y]) {
-                        if player_vel.0.x < 0.0 {
-                            player_vel.0.x = 0.0;
-                        }
-                        player_tran.translation.x = solid_tran.translation.x + solid_tran.scale.x;
+                // Once per click, so a drag doesn't leave a trail of them
+                TileEditTool::Enemy => {
+                    if tile_edit.interacted.is_empty() {
+                        tile_edit.interacted.insert(cursor.to_array());
+                        spawn_enemy(&mut commands, &EnemyData::new(cursor));
+                        current_level.unsaved = true;
                     }
                 }
-                Collision::Top => {
-                    if !segments.contains(&[base.x, base.y + 1, base.x + 1, base.y + 1]) {
-                        if player_vel.0.y < 0.0 {
-                            player_vel.0.y = 0.0;
-                        }
-                        player_tran.translation.y = solid_tran.translation.y + solid_tran.scale.y;
-                        jump.on_ground = true;
+                TileEditTool::Platform => {
+                    if tile_edit.interacted.is_empty() {
+                        tile_edit.interacted.insert(cursor.to_array());
+                        spawn_platform(&mut commands, &PlatformData { pos: cursor });
+                        current_level.unsaved = true;
                     }
                 }
-                Collision::Bottom => {
-                    if !segments.contains(&[base.x + 1, base.y, base.x, base.y]) {
-                        if player_vel.0.y > 0.0 {
-                            player_vel.0.y = 0.0;
+                // Once per click, rather than at every cell the cursor is dragged over
+                TileEditTool::Stamp => {
+                    if tile_edit.interacted.is_empty() {
+                        tile_edit.interacted.insert(cursor.to_array());
+                        let prefab = tile_edit.prefab.and_then(|index| prefabs.iter().nth(index));
+                        if let Some((_, prefab)) = prefab {
+                            let stamped = stamp_prefab(
+                                &mut commands,
+                                &mut tile_index,
+                                &asset_server,
+                                cursor,
+                                prefab,
+                                tile_edit.stamp_overwrite,
+                            );
+                            for cell in stamped {
+                                placed_events.send(TilePlaced(cell));
+                            }
+                            current_level.unsaved = true;
                         }
-                        player_tran.translation.y = solid_tran.translation.y - player_tran.scale.y;
                     }
                 }
-                _ => {}
             }
         }
     }
 }
 
-fn update_camera_system(
-    mut camera_query: Query<(&mut Transform, &WorldCamera), Without<Player>>,
-    player_query: Query<&Transform, With<Player>>,
+// Make every tile in the selection solid, or if some already are, make
+// them all decorative. The tiles keep their entities; physics gathers the
+// solid tiles afresh each step, so the change applies from the next one.
+fn solidify_selection_system(
+    mut commands: Commands,
+    action_state: Res<ActionState>,
+    tile_edit: Res<TileEdit>,
+    tile_index: Res<TileIndex>,
+    mut current_level: ResMut<CurrentLevel>,
+    solid_query: Query<(), With<SolidCollider>>,
 ) {
-    let (mut camera_transform, _camera) = camera_query.single_mut();
-    let player_transform = player_query.single();
-    camera_transform.translation = player_transform.translation;
+    if !action_state.just_pressed(Action::ToggleSolid) {
+        return;
+    }
+    let selection = match tile_edit.selection {
+        Some(selection) => selection,
+        None => return,
+    };
+    let tiles: Vec<Entity> = selection
+        .cells()
+        .filter_map(|cell| tile_index.tile_at(cell))
+        .collect();
+    if tiles.is_empty() {
+        return;
+    }
+    current_level.unsaved = true;
+    let any_solid = tiles.iter().any(|&tile| solid_query.contains(tile));
+    for &tile in &tiles {
+        if any_solid {
+            commands.entity(tile).remove::<SolidCollider>();
+        } else {
+            commands.entity(tile).insert(SolidCollider);
+        }
+    }
+    info!(
+        "Made {} tiles {}",
+        tiles.len(),
+        if any_solid { "decorative" } else { "solid" }
+    );
 }
 
-enum TileEditTool {
-    Paintbrush,
-    Eraser,
+// The mouse buttons aren't read while paused, so a stroke would never see its release
+fn end_tile_edit_system(mut tile_edit: ResMut<TileEdit>) {
+    tile_edit.deactivate();
 }
 
-struct TileEdit {
-    interacted: HashSet<[i32; 2]>,
-    tool: TileEditTool,
-    active: bool,
+// Place a mirror axis on the cell boundary nearest the cursor, or remove it
+// if it is already there
+fn mirror_axis_system(
+    action_state: Res<ActionState>,
+    cursor_world_pos: Res<CursorWorldPos>,
+    mut tile_edit: ResMut<TileEdit>,
+) {
+    let cursor = match cursor_world_pos.0 {
+        Some(cursor) => cursor.round().as_ivec2(),
+        None => return,
+    };
+    if tile_edit.active {
+        return;
+    }
+    if action_state.just_pressed(Action::ToggleMirrorX) {
+        let x = &mut tile_edit.mirror.x;
+        *x = if *x == Some(cursor.x) {
+            None
+        } else {
+            Some(cursor.x)
+        };
+        info!("Mirror axis x: {:?}", x);
+    }
+    if action_state.just_pressed(Action::ToggleMirrorY) {
+        let y = &mut tile_edit.mirror.y;
+        *y = if *y == Some(cursor.y) {
+            None
+        } else {
+            Some(cursor.y)
+        };
+        info!("Mirror axis y: {:?}", y);
+    }
 }
 
-struct ScreenToWorld {
-    world_offset: Vec2,
-    screen_dimensions: Vec2,
+// Highlights the cell under the cursor in the color of the tool in use, or
+// would be in use on left click. The paintbrush shows its tile instead, and
+// a wide eraser every cell it reaches.
+#[derive(Component)]
+struct CursorGhost;
+
+// A cell of the eraser's reach around the cursor, as a child of the ghost
+#[derive(Component)]
+struct EraserReachCell;
+
+// The offsets of the cells within `radius` of a cell, itself included
+fn disk(radius: i32) -> impl Iterator<Item = IVec2> {
+    (-radius..=radius)
+        .flat_map(move |y| (-radius..=radius).map(move |x| IVec2::new(x, y)))
+        .filter(move |offset| offset.x * offset.x + offset.y * offset.y <= radius * radius)
 }
 
-impl ScreenToWorld {
-    pub fn new() -> Self {
-        ScreenToWorld {
-            screen_dimensions: Vec2::ONE,
-            world_offset: Vec2::ZERO,
+// The cursor's ghost, with what it's drawn as
+type CursorGhostQuery<'w, 's> = Query<
+    'w,
+    's,
+    (
+        Entity,
+        &'static mut Transform,
+        &'static mut Sprite,
+        &'static mut Handle<Image>,
+        &'static mut Visibility,
+    ),
+    With<CursorGhost>,
+>;
+
+// The cells of the eraser's reach, kept apart from the ghost they're
+// children of
+type EraserReachQuery<'w, 's> =
+    Query<'w, 's, (Entity, &'static mut Visibility), (With<EraserReachCell>, Without<CursorGhost>)>;
+
+#[allow(clippy::too_many_arguments)]
+fn cursor_ghost_system(
+    mut commands: Commands,
+    tile_edit: Res<TileEdit>,
+    cursor_world_pos: Res<CursorWorldPos>,
+    settings: Res<Settings>,
+    asset_server: Res<AssetServer>,
+    palettes: Res<TilePalettes>,
+    mut query: CursorGhostQuery,
+    mut reach_query: EraserReachQuery,
+) {
+    for (ghost, mut transform, mut sprite, mut texture, mut visibility) in query.iter_mut() {
+        visibility.is_visible = cursor_world_pos.0.is_some();
+        // Visibility isn't inherited
+        for (_, mut reach_visibility) in reach_query.iter_mut() {
+            reach_visibility.is_visible = visibility.is_visible;
+        }
+        if let Some(cell) = cursor_world_pos.cell(settings.cell_rounding) {
+            transform.translation = cell.as_vec2().extend(SELECTION_Z);
         }
+        if !tile_edit.is_changed() {
+            continue;
+        }
+        let tool = if tile_edit.active {
+            tile_edit.tool
+        } else {
+            tile_edit.left_click_tool()
+        };
+        let brush = palettes.entry(tile_edit.palette, tile_edit.brush);
+        *texture = match (tool, &brush.appearance) {
+            (TileEditTool::Paintbrush, TileAppearanceData::Texture(path)) => {
+                asset_server.load(path.as_str())
+            }
+            _ => default(),
+        };
+        sprite.color = match tool {
+            TileEditTool::Paintbrush => match &brush.appearance {
+                TileAppearanceData::Color(color) => *color.as_rgba().set_a(0.5),
+                TileAppearanceData::Texture(_) => Color::rgba(1., 1., 1., 0.5),
+                TileAppearanceData::None => tile::HIDDEN_TILE_DEBUG_COLOR,
+            },
+            TileEditTool::Eraser => ERASER_COLOR,
+            TileEditTool::Stamp => Color::rgba(0.3, 1., 0.3, 0.4),
+            TileEditTool::Select => Color::rgba(0.3, 0.6, 1., 0.4),
+            TileEditTool::Spawn => PLAYER_SPAWN_DEBUG_COLOR,
+            TileEditTool::Enemy => *ENEMY_COLOR.as_rgba().set_a(0.5),
+            TileEditTool::Platform => *PLATFORM_COLOR.as_rgba().set_a(0.5),
+        };
+
+        for (cell, _) in reach_query.iter() {
+            commands.entity(cell).despawn_recursive();
+        }
+        let radius = match tool {
+            TileEditTool::Eraser => tile_edit.eraser_radius,
+            _ => 0,
+        };
+        commands.entity(ghost).with_children(|parent| {
+            for offset in disk(radius).filter(|&offset| offset != IVec2::ZERO) {
+                parent
+                    .spawn_bundle(SpriteBundle {
+                        transform: Transform::from_translation(offset.as_vec2().extend(0.)),
+                        sprite: Sprite {
+                            color: ERASER_COLOR,
+                            custom_size: Some(Vec2::ONE),
+                            anchor: Anchor::BottomLeft,
+                            ..default()
+                        },
+                        visibility: Visibility {
+                            is_visible: visibility.is_visible,
+                        },
+                        ..default()
+                    })
+                    .insert(EraserReachCell);
+            }
+        });
     }
+}
 
-    // Update the width and height of the screen in logical pixels
-    pub fn set_screen_dimensions(&mut self, dimensions: Vec2) {
-        self.screen_dimensions = dimensions;
+#[derive(Component)]
+struct ResetIndicator {
+    // The part which fills up, in front of the background
+    fill: bool,
+    // The player whose reset it shows
+    player: Entity,
+}
+
+// A bar above each player filling up while their reset is held
+fn reset_indicator_system(
+    input_map: Res<InputMap>,
+    player_query: Query<(&Transform, &ResetHold), Without<ResetIndicator>>,
+    mut query: Query<(&ResetIndicator, &mut Transform, &mut Visibility)>,
+) {
+    for (indicator, mut transform, mut visibility) in query.iter_mut() {
+        let (player, reset_hold) = match player_query.get(indicator.player) {
+            Ok(player) => player,
+            Err(_) => continue,
+        };
+        let progress = reset_hold.progress(input_map.reset_hold_time);
+        visibility.is_visible = progress > 0.;
+        transform.translation = player.translation + Vec3::new(0., player.scale.y + 0.25, 0.);
+        transform.translation.z = MIRROR_AXIS_Z + indicator.fill as u8 as f32 * 0.1;
+        transform.scale.x = if indicator.fill { progress } else { 1. };
     }
+}
+
+#[derive(Component)]
+struct SelectionBox;
 
-    // Update the center of screen in world coordinates
-    pub fn set_world_offset(&mut self, offset: Vec2) {
-        self.world_offset = offset;
+fn selection_box_system(
+    tile_edit: Res<TileEdit>,
+    mut query: Query<(&mut Transform, &mut Sprite, &mut Visibility), With<SelectionBox>>,
+) {
+    for (mut transform, mut sprite, mut visibility) in query.iter_mut() {
+        visibility.is_visible = tile_edit.selection.is_some();
+        if let Some(selection) = tile_edit.selection {
+            transform.translation = selection.min().as_vec2().extend(SELECTION_Z);
+            sprite.custom_size = Some(selection.size().as_vec2());
+        }
     }
+}
 
-    pub fn transform(&self, point: Vec2) -> Vec2 {
-        let dim = &self.screen_dimensions;
-        let cropped_width = dim.y * WIDTH_PIXELS as f32 / HEIGHT_PIXELS as f32;
-        let cropped_x = point.x - (dim.x - cropped_width) / 2.;
-        Vec2::new(
-            ((2. * cropped_x / cropped_width) - 1.) * WIDTH_PIXELS as f32
-                / (2. * PIXELS_PER_TILE as f32)
-                + self.world_offset.x,
-            ((2. * point.y / dim.y) - 1.) * HEIGHT_PIXELS as f32 / (2. * PIXELS_PER_TILE as f32)
-                + self.world_offset.y,
-        )
+#[derive(Component)]
+struct MirrorAxisLine {
+    vertical: bool,
+}
+
+// Keep the axis lines spanning the view, hidden while their axis is unset
+fn mirror_axis_line_system(
+    tile_edit: Res<TileEdit>,
+    camera_query: Query<&Transform, (With<WorldCamera>, Without<MirrorAxisLine>)>,
+    mut line_query: Query<(&MirrorAxisLine, &mut Transform, &mut Visibility)>,
+) {
+    let camera = match camera_query.get_single() {
+        Ok(transform) => transform.translation,
+        Err(_) => return,
+    };
+    for (line, mut transform, mut visibility) in line_query.iter_mut() {
+        let axis = if line.vertical {
+            tile_edit.mirror.x
+        } else {
+            tile_edit.mirror.y
+        };
+        visibility.is_visible = axis.is_some();
+        if let Some(axis) = axis {
+            transform.translation = if line.vertical {
+                Vec3::new(axis as f32, camera.y, MIRROR_AXIS_Z)
+            } else {
+                Vec3::new(camera.x, axis as f32, MIRROR_AXIS_Z)
+            };
+        }
+    }
+}
+
+// Lines the paintbrush and eraser are mirrored across. Axes lie on cell
+// boundaries, so x: Some(0) swaps cell -1 with cell 0.
+#[derive(Default)]
+struct MirrorAxis {
+    x: Option<i32>,
+    y: Option<i32>,
+}
+
+impl MirrorAxis {
+    // `cell` and its distinct reflections
+    fn reflections(&self, cell: IVec2) -> Vec<IVec2> {
+        let mirror_x = |cell: IVec2| self.x.map(|x| IVec2::new(2 * x - 1 - cell.x, cell.y));
+        let mirror_y = |cell: IVec2| self.y.map(|y| IVec2::new(cell.x, 2 * y - 1 - cell.y));
+        let mut cells = vec![cell];
+        cells.extend(mirror_x(cell));
+        cells.extend(mirror_y(cell));
+        cells.extend(mirror_x(cell).and_then(mirror_y));
+        cells.dedup();
+        cells
     }
 }
 
+// A rectangle of cells between two corners, inclusive
+#[derive(Clone, Copy)]
+struct CellRect {
+    // Where the selection drag started
+    start: IVec2,
+    end: IVec2,
+}
+
+impl CellRect {
+    fn spanning(start: IVec2, end: IVec2) -> Self {
+        CellRect { start, end }
+    }
+
+    fn min(&self) -> IVec2 {
+        self.start.min(self.end)
+    }
+
+    fn size(&self) -> IVec2 {
+        (self.start - self.end).abs() + IVec2::ONE
+    }
+
+    fn cells(&self) -> impl Iterator<Item = IVec2> {
+        let min = self.min();
+        let max = self.start.max(self.end);
+        (min.y..=max.y).flat_map(move |y| (min.x..=max.x).map(move |x| IVec2::new(x, y)))
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum TileEditTool {
+    Paintbrush,
+    Eraser,
+    Stamp,
+    Select,
+    // Moves the level's player spawn
+    Spawn,
+    // Places an enemy, with the default patrol
+    Enemy,
+    // Places a falling platform
+    Platform,
+}
+
+impl TileEditTool {
+    // The tools left click can be switched between. Stamping is chosen by
+    // selecting a prefab instead.
+    const SELECTABLE: [TileEditTool; 6] = [
+        TileEditTool::Paintbrush,
+        TileEditTool::Eraser,
+        TileEditTool::Select,
+        TileEditTool::Spawn,
+        TileEditTool::Enemy,
+        TileEditTool::Platform,
+    ];
+}
+
+struct TileEdit {
+    interacted: HashSet<[i32; 2]>,
+    tool: TileEditTool,
+    active: bool,
+    // The mouse button holding the current stroke
+    button: Option<MouseButton>,
+    // Or the editor action holding it, and the cell it has reached
+    key: Option<(Action, IVec2)>,
+    // The tool left click uses
+    selected_tool: TileEditTool,
+    // Index in the `TilePalettes` of the palette the paintbrush paints from
+    palette: usize,
+    // Index in that palette of the tile it paints
+    brush: usize,
+    // How many cells around the cursor the eraser also clears, in a circle
+    eraser_radius: i32,
+    // Index in the `PrefabLibrary` of the prefab left click stamps, if any
+    prefab: Option<usize>,
+    // Whether stamps replace tiles already in their cells
+    stamp_overwrite: bool,
+    mirror: MirrorAxis,
+    // Cells chosen by dragging with the middle mouse button
+    selection: Option<CellRect>,
+}
+
 impl TileEdit {
     fn new() -> Self {
         TileEdit {
             interacted: HashSet::new(),
             tool: TileEditTool::Paintbrush,
             active: false,
+            button: None,
+            key: None,
+            selected_tool: TileEditTool::Paintbrush,
+            palette: 0,
+            brush: 0,
+            eraser_radius: 0,
+            prefab: None,
+            stamp_overwrite: false,
+            mirror: MirrorAxis::default(),
+            selection: None,
         }
     }
 
     fn deactivate(&mut self) {
         self.interacted.clear();
         self.active = false;
+        self.button = None;
+        self.key = None;
     }
 
-    fn activate_paintbrush(&mut self) {
+    fn activate(&mut self, tool: TileEditTool, button: MouseButton) {
         self.active = true;
-        self.tool = TileEditTool::Paintbrush;
+        self.tool = tool;
+        self.button = Some(button);
     }
 
-    fn activate_eraser(&mut self) {
-        self.active = true;
-        self.tool = TileEditTool::Eraser;
+    fn left_click_tool(&self) -> TileEditTool {
+        match self.selected_tool {
+            TileEditTool::Paintbrush if self.prefab.is_some() => TileEditTool::Stamp,
+            tool => tool,
+        }
+    }
+}
+
+// Spawn a player at the start, along with the bar showing their reset
+fn spawn_player(commands: &mut Commands, spec: &PlayerSpec) {
+    let id = spec.id;
+    let player = player::spawn_player(commands, spec);
+    commands
+        .entity(player)
+        .insert(Name::new(format!("Player {}", id.0 + 1)));
+
+    for fill in [false, true] {
+        commands
+            .spawn_bundle(SpriteBundle {
+                sprite: Sprite {
+                    color: if fill {
+                        Color::WHITE
+                    } else {
+                        Color::rgba(0., 0., 0., 0.5)
+                    },
+                    custom_size: Some(Vec2::new(1., 2. / PIXELS_PER_TILE as f32)),
+                    anchor: Anchor::BottomLeft,
+                    ..default()
+                },
+                visibility: Visibility { is_visible: false },
+                ..default()
+            })
+            .insert(ResetIndicator { fill, player });
     }
 }
 
 fn startup_system(
     mut commands: Commands,
     asset_server: Res<AssetServer>,
-    mut screen_to_world: ResMut<ScreenToWorld>,
-    windows: Res<Windows>,
+    mut tile_index: ResMut<TileIndex>,
+    mut current_level: ResMut<CurrentLevel>,
+    prefabs: Res<PrefabLibrary>,
+    second_player: Option<Res<SecondPlayerInput>>,
 ) {
-    let window = windows.primary();
-    screen_to_world.set_screen_dimensions(Vec2::new(window.width(), window.height()));
+    spawn_player(&mut commands, &PlayerSpec::new(PlayerId(0)));
+    if second_player.is_some() {
+        spawn_player(
+            &mut commands,
+            &PlayerSpec {
+                color: Color::rgb(0.3, 0.6, 1.),
+                ..PlayerSpec::new(PlayerId(1))
+            },
+        );
+    }
+
+    for vertical in [true, false] {
+        let size = if vertical {
+            Vec2::new(1. / PIXELS_PER_TILE as f32, HEIGHT_PIXELS as f32)
+        } else {
+            Vec2::new(WIDTH_PIXELS as f32, 1. / PIXELS_PER_TILE as f32)
+        };
+        commands
+            .spawn_bundle(SpriteBundle {
+                sprite: Sprite {
+                    color: Color::rgba(1., 0.3, 0.3, 0.8),
+                    custom_size: Some(size),
+                    ..default()
+                },
+                visibility: Visibility { is_visible: false },
+                ..default()
+            })
+            .insert(MirrorAxisLine { vertical });
+    }
+
     commands
-        .spawn()
-        .insert(Label("Player".to_string()))
-        .insert_bundle(SpriteBundle {
-            transform: Transform {
-                translation: Vec3::new(0., 1., 0.),
-                scale: Vec3::new(1., 2., 1.),
+        .spawn_bundle(SpriteBundle {
+            sprite: Sprite {
+                color: Color::rgba(0.3, 0.6, 1., 0.3),
+                anchor: Anchor::BottomLeft,
                 ..default()
             },
+            visibility: Visibility { is_visible: false },
+            ..default()
+        })
+        .insert(SelectionBox);
+
+    commands
+        .spawn_bundle(SpriteBundle {
             sprite: Sprite {
-                color: Color::rgb(0., 1., 0.),
+                custom_size: Some(Vec2::ONE),
                 anchor: Anchor::BottomLeft,
                 ..default()
             },
+            visibility: Visibility { is_visible: false },
             ..default()
         })
-        .insert(Velocity(Vec3::ZERO))
-        .insert(Player)
-        .insert(Gravity(GRAVITY))
-        .insert(Mobility {
-            walk_speed: 10.,
-            // Last factor is peak jump height under normal gravity
-            jump_speed: (2. * GRAVITY * 5.8).sqrt(),
-            on_ground: false,
-            walk_direction: Direction::Neutral,
-        });
+        .insert(CursorGhost);
 
-    let appearance = tile::TileAppearance::Texture(asset_server.load("tile.png"));
-    //let appearance = tile::TileAppearance::Color(Color::rgb(0., 1., 1.));
-    for (x, y) in [
-        (-5, 0),
-        (-4, 0),
-        (-3, 0),
-        (-2, 0),
-        (-1, 0),
-        (0, 0),
-        (1, 0),
-        (2, 0),
-        (3, 0),
-        (4, 0),
-        (5, 0),
-        (5, 1),
-        (5, 2),
-        (5, 3),
-        (5, 4),
-        (5, 5),
-        (5, 6),
-        (5, 7),
-        (5, 8),
-        (5, 9),
-        (5, 10),
-        (5, 11),
-        (-5, 1),
-        (-5, 2),
-        (-5, 3),
-        (-5, 4),
-        (-5, 5),
-        (-5, 6),
-        (-5, 7),
-        (-5, 8),
-        (-5, 9),
-        (-5, 10),
-        (2, 5),
-        (3, 5),
-        (-4, 3),
-        (-3, 3),
-    ] {
-        commands.spawn_bundle(tile::SolidTile::from_spec(tile::TileSpec {
-            pos: IVec2::new(x, y),
-            appearance: appearance.clone(),
-        }));
-    }
+    let level = current_level.load_or_embedded(STARTUP_LEVEL_PATH, EMBEDDED_STARTUP_LEVEL);
+    level.shifted(current_level.offset).spawn(
+        &mut commands,
+        &asset_server,
+        &mut tile_index,
+        &prefabs,
+    );
+}
+
+// A cell written as `<x>,<y>`
+fn parse_cell(text: &str) -> Option<IVec2> {
+    let (x, y) = text.split_once(',')?;
+    Some(IVec2::new(x.trim().parse().ok()?, y.trim().parse().ok()?))
 }
 
 fn main() {
-    App::new()
-        .insert_resource(TileEdit::new())
-        .insert_resource(ScreenToWorld::new())
+    // `--write-default-level <path>` saves the built-in level as a starting
+    // point for a new level file
+    let mut args = std::env::args().skip_while(|arg| arg != "--write-default-level");
+    if args.next().is_some() {
+        let path = args.next().unwrap_or_else(|| "default.ron".to_string());
+        match default_level().save(&path, &PrefabLibrary::load(PREFAB_LIBRARY_PATH)) {
+            Ok(()) => println!("Wrote {}", path),
+            Err(err) => eprintln!("{}: {}", path, err),
+        }
+        return;
+    }
+
+    let settings = Settings::load();
+    let display_settings = DisplaySettings {
+        render_scale: settings.render_scale,
+        ..default()
+    };
+    // `--record <file>` or `--replay <file>`
+    let replay_mode = ReplayMode::from_args(std::env::args());
+    let mut app = App::new();
+    // `--coop` adds a second player
+    if std::env::args().any(|arg| arg == "--coop") {
+        app.init_resource::<SecondPlayerInput>();
+    }
+    // `--level-offset <x>,<y>` places the startup level's cell (0, 0) at that
+    // cell, as one room of a bigger world, and saves it shifted back
+    if let Some(offset) = std::env::args()
+        .skip_while(|arg| arg != "--level-offset")
+        .nth(1)
+    {
+        match parse_cell(&offset) {
+            Some(offset) => {
+                app.insert_resource(CurrentLevel {
+                    offset,
+                    ..default()
+                });
+            }
+            None => eprintln!("--level-offset: expected <x>,<y>, not {}", offset),
+        }
+    }
+    app.insert_resource(TileEdit::new())
+        .insert_resource(TilePalettes::load(TILE_PALETTES_PATH))
         .insert_resource(WindowDescriptor {
             //resizable: true,
             resizable: false,
-            mode: if cfg!(target_arch = "wasm32") {
+            present_mode: display_settings.present_mode,
+            width: (WIDTH_PIXELS * display_settings.render_scale) as f32,
+            height: (HEIGHT_PIXELS * display_settings.render_scale) as f32,
+            mode: if cfg!(target_arch = "wasm32") || !settings.fullscreen {
                 WindowMode::Windowed
             } else {
                 WindowMode::BorderlessFullscreen
-                //WindowMode::Windowed
             },
             ..default()
         })
+        .insert_resource(display_settings)
+        .insert_resource(CollisionOutline {
+            color: settings.collision_outline_color,
+            ..default()
+        })
+        .insert_resource(AudioSettings {
+            sfx_volume: settings.sfx_volume,
+            music_volume: settings.music_volume,
+            ..default()
+        })
+        .insert_resource(settings)
+        // Letterbox around the upscaled world
+        .insert_resource(ClearColor(Color::BLACK))
         .add_plugins(DefaultPlugins)
-        .add_plugin(PixelPerfectPlugin)
+        .add_plugin(InputMapPlugin)
+        .add_plugin(DisplayPlugin)
+        .add_plugin(PixelPerfectPlugin::default())
+        .add_plugin(CursorPlugin)
+        .add_plugin(ParallaxPlugin)
+        .add_plugin(AnimationPlugin)
+        .add_plugin(WrapGhostPlugin)
+        .add_plugin(ParticlePlugin)
+        .add_plugin(ScorePopupPlugin)
+        .add_plugin(SfxPlugin)
+        .add_plugin(MusicPlugin)
+        .add_plugin(TilePlugin)
+        .add_plugin(TileFeedbackPlugin)
+        .add_plugin(LevelPlugin)
+        .add_plugin(DebugModePlugin)
+        .add_plugin(CollisionOutlinePlugin)
+        .add_plugin(CursorGrabPlugin)
+        .add_plugin(DiagnosticsOverlayPlugin)
+        .add_plugin(InputOverlayPlugin)
+        .add_plugin(HudPlugin)
+        .add_plugin(VirtualButtonsPlugin)
+        .add_plugin(ReplayPlugin {
+            mode: replay_mode.clone(),
+        })
         .add_startup_system(startup_system)
+        .add_system(background_color_tuning_system)
+        .add_plugin(GameStatePlugin {
+            // A replay's inputs start from play, so it skips the menu
+            initial_state: match replay_mode {
+                ReplayMode::Off => GameState::MainMenu,
+                _ => GameState::Playing,
+            },
+        })
+        .add_system_set(
+            SystemSet::on_update(GameState::Playing)
+                .with_system(prefab_select_system)
+                .with_system(tool_select_system)
+                .with_system(mirror_axis_system)
+                .with_system(solidify_selection_system),
+        )
+        .add_system_set(SystemSet::on_enter(GameState::Paused).with_system(end_tile_edit_system))
+        .add_system_set(
+            SystemSet::on_enter(GameState::LevelSelect).with_system(end_tile_edit_system),
+        )
+        .add_system_set(
+            SystemSet::on_enter(GameState::SystemMenu).with_system(end_tile_edit_system),
+        )
+        .add_system_set(SystemSet::on_enter(GameState::GameOver).with_system(end_tile_edit_system))
+        .add_system_set(SystemSet::on_enter(GameState::MainMenu).with_system(end_tile_edit_system))
+        .add_system_set(SystemSet::on_enter(GameState::Dialog).with_system(end_tile_edit_system))
+        .add_system_set(SystemSet::on_enter(GameState::Fanfare).with_system(end_tile_edit_system))
         .add_system_set(
-            SystemSet::new()
-                .with_run_criteria(FixedTimestep::step(INPUT_TIME_STEP as f64))
-                .with_system(keyboard_input_system)
-                .with_system(mouse_input_system)
-                .with_system(update_screen_to_world_system)
-                .with_system(tile_edit_system),
+            SystemSet::on_enter(GameState::Respawning).with_system(end_tile_edit_system),
         )
+        .add_plugin(LevelSelectPlugin)
+        .add_plugin(MainMenuPlugin)
+        .add_plugin(PauseMenuPlugin)
+        .add_plugin(TileInspectorPlugin)
+        .add_plugin(SystemMenuPlugin)
+        .add_plugin(QuickSavePlugin)
+        .add_plugin(GameOverPlugin)
+        .add_plugin(NpcPlugin)
+        .add_plugin(SignPlugin)
+        .add_plugin(AbilityPlugin)
+        .add_plugin(ScreenFadePlugin)
+        .add_plugin(SpeedrunPlugin)
+        .add_system(mirror_axis_line_system.after(PhysicsSystem::Camera))
+        .add_system(selection_box_system)
+        .add_system(cursor_ghost_system)
+        .add_system(reset_indicator_system.after(PhysicsSystem::Camera))
+        .add_plugin(GamePlugin)
         .add_system_set(
-            SystemSet::new()
-                .with_run_criteria(FixedTimestep::step(PHYSICS_TIME_STEP as f64))
-                .with_system(gravity_system.label(PhysicsSystem::Gravity))
+            live_edit_system_set()
                 .with_system(
-                    physics_system
-                        .label(PhysicsSystem::Velocity)
-                        .after(PhysicsSystem::Gravity),
+                    mouse_input_system
+                        .exclusive_system()
+                        .at_start()
+                        .label(MouseInput),
                 )
                 .with_system(
-                    player_tile_collision_system
-                        .label(PhysicsSystem::Collision)
-                        .after(PhysicsSystem::Velocity),
+                    keyboard_edit_system
+                        .exclusive_system()
+                        .at_start()
+                        .label(KeyboardInput)
+                        .after(MouseInput),
                 )
                 .with_system(
-                    update_camera_system
-                        .label(PhysicsSystem::Camera)
-                        .after(PhysicsSystem::Collision),
+                    tile_edit_system
+                        .exclusive_system()
+                        .at_start()
+                        .after(KeyboardInput),
                 ),
         )
         .run();
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::asset::FileAssetIo;
+    use bevy::ecs::event::Events;
+    use bevy::tasks::TaskPoolBuilder;
+    use last_question::level::TileData;
+
+    // Editing with the cursor over cell (0, 0)
+    fn editor_app(tile_edit: TileEdit) -> App {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .insert_resource(AssetServer::new(
+                FileAssetIo::new("assets", false),
+                TaskPoolBuilder::new().build(),
+            ))
+            .insert_resource(CursorWorldPos(Some(Vec2::splat(0.5))))
+            .init_resource::<Settings>()
+            .insert_resource(tile_edit)
+            .init_resource::<TileIndex>()
+            .init_resource::<CurrentLevel>()
+            .insert_resource(PrefabLibrary::load(PREFAB_LIBRARY_PATH))
+            .init_resource::<TilePalettes>()
+            .add_event::<TilePlaced>()
+            .add_event::<TileRemoved>()
+            .add_system(tile_edit_system);
+        app
+    }
+
+    fn cells<E: Send + Sync + 'static>(app: &App, cell: impl Fn(&E) -> IVec2) -> Vec<IVec2> {
+        let events = app.world.resource::<Events<E>>();
+        let mut cells: Vec<IVec2> = events.get_reader().iter(events).map(cell).collect();
+        cells.sort_by_key(|cell| (cell.x, cell.y));
+        cells
+    }
+
+    fn placed(app: &App) -> Vec<IVec2> {
+        cells(app, |TilePlaced(cell)| *cell)
+    }
+
+    fn removed(app: &App) -> Vec<IVec2> {
+        cells(app, |TileRemoved(cell)| *cell)
+    }
+
+    fn stroke(tool: TileEditTool) -> TileEdit {
+        TileEdit {
+            tool,
+            active: true,
+            ..TileEdit::new()
+        }
+    }
+
+    #[test]
+    fn mirrored_paint_and_erase_send_each_cell() {
+        let mut tile_edit = stroke(TileEditTool::Paintbrush);
+        tile_edit.mirror.x = Some(2);
+        let mut app = editor_app(tile_edit);
+        app.update();
+        assert_eq!(placed(&app), vec![IVec2::new(0, 0), IVec2::new(3, 0)]);
+
+        let mut tile_edit = app.world.resource_mut::<TileEdit>();
+        *tile_edit = TileEdit {
+            mirror: std::mem::take(&mut tile_edit.mirror),
+            ..stroke(TileEditTool::Eraser)
+        };
+        app.update();
+        assert_eq!(removed(&app), vec![IVec2::new(0, 0), IVec2::new(3, 0)]);
+    }
+
+    #[test]
+    fn stamp_sends_the_cells_it_filled() {
+        let prefabs = PrefabLibrary::load(PREFAB_LIBRARY_PATH);
+        let (_, prefab) = prefabs.iter().next().unwrap();
+        let mut expected: Vec<IVec2> = prefab.tiles.iter().map(|tile| tile.pos).collect();
+        expected.sort_by_key(|cell| (cell.x, cell.y));
+
+        let mut app = editor_app(TileEdit {
+            prefab: Some(0),
+            ..stroke(TileEditTool::Stamp)
+        });
+        // Without overwriting, a cell already filled is left out
+        let filled = TileData::solid(expected[1]);
+        app.add_startup_system(
+            move |mut commands: Commands,
+                  asset_server: Res<AssetServer>,
+                  mut tile_index: ResMut<TileIndex>| {
+                filled.spawn(&mut commands, &asset_server, &mut tile_index, IVec2::ZERO);
+            },
+        );
+        app.update();
+        let mut left = expected.clone();
+        left.remove(1);
+        assert_eq!(placed(&app), left);
+    }
+}
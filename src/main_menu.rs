@@ -0,0 +1,287 @@
+// The menu the game starts in, with entries to play, choose a level or quit.
+//
+// Play loads the level last played, or the startup level if none has been,
+// through `LevelCommand` like any other level change. A level with unsaved
+// edits is gone back to as it was left instead, so they aren't lost. Level Select opens the
+// menu from `LevelSelectPlugin`, which comes back here when closed. There is
+// nothing to quit to on wasm, so Quit is left out there. The Escape menu's
+// Main Menu entry comes back here from play.
+
+use bevy::{app::AppExit, prelude::*};
+
+use crate::game_state::GameState;
+use crate::input::{MenuAction, MenuActionState};
+use crate::level::{CurrentLevel, LevelCommand};
+use crate::level_select::LevelSelectReturn;
+use crate::pixel_perfect::UI_FONT;
+
+const SELECTED_COLOR: Color = Color::rgb(1., 0.85, 0.3);
+const UNSELECTED_COLOR: Color = Color::rgb(0.7, 0.7, 0.7);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum MenuEntry {
+    Play,
+    LevelSelect,
+    Quit,
+}
+
+impl MenuEntry {
+    fn label(self) -> &'static str {
+        match self {
+            MenuEntry::Play => "Play",
+            MenuEntry::LevelSelect => "Level Select",
+            MenuEntry::Quit => "Quit",
+        }
+    }
+
+    // The entries shown, top to bottom
+    fn all() -> &'static [MenuEntry] {
+        if cfg!(target_arch = "wasm32") {
+            &[MenuEntry::Play, MenuEntry::LevelSelect]
+        } else {
+            &[MenuEntry::Play, MenuEntry::LevelSelect, MenuEntry::Quit]
+        }
+    }
+}
+
+#[derive(Default)]
+struct MainMenu {
+    selected: usize,
+}
+
+#[derive(Component)]
+struct MainMenuRoot;
+
+// The entry at this index in `MenuEntry::all()`
+#[derive(Component)]
+struct MenuEntryText(usize);
+
+// Needs the `MenuActionState` from `InputMapPlugin`, the `CurrentLevel` and
+// `LevelCommand` from `LevelPlugin`, the `GameState` from `GameStatePlugin`
+// and the `LevelSelectReturn` from `LevelSelectPlugin`. The game only starts
+// in the menu if `GameStatePlugin` is given `GameState::MainMenu` to start in.
+#[derive(Default)]
+pub struct MainMenuPlugin;
+
+impl Plugin for MainMenuPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<MainMenu>()
+            .add_system_set(SystemSet::on_enter(GameState::MainMenu).with_system(spawn_menu_system))
+            .add_system_set(
+                SystemSet::on_update(GameState::MainMenu)
+                    .with_system(menu_input_system)
+                    .with_system(highlight_system.after(menu_input_system)),
+            )
+            .add_system_set(
+                SystemSet::on_exit(GameState::MainMenu).with_system(despawn_menu_system),
+            );
+    }
+}
+
+fn spawn_menu_system(mut commands: Commands, asset_server: Res<AssetServer>, menu: Res<MainMenu>) {
+    let font = asset_server.load(UI_FONT);
+    let text_style = |font_size, color| TextStyle {
+        font: font.clone(),
+        font_size,
+        color,
+    };
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.), Val::Percent(100.)),
+                position_type: PositionType::Absolute,
+                // The UI is y-up, so this lists the children top to bottom
+                flex_direction: FlexDirection::ColumnReverse,
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            color: Color::BLACK.into(),
+            ..default()
+        })
+        .insert(MainMenuRoot)
+        .with_children(|parent| {
+            parent.spawn_bundle(TextBundle {
+                text: Text::with_section("Last Question", text_style(48., Color::WHITE), default()),
+                style: Style {
+                    margin: Rect {
+                        bottom: Val::Px(24.),
+                        ..default()
+                    },
+                    ..default()
+                },
+                ..default()
+            });
+            for (index, entry) in MenuEntry::all().iter().enumerate() {
+                let color = if index == menu.selected {
+                    SELECTED_COLOR
+                } else {
+                    UNSELECTED_COLOR
+                };
+                parent
+                    .spawn_bundle(TextBundle {
+                        text: Text::with_section(entry.label(), text_style(24., color), default()),
+                        style: Style {
+                            margin: Rect::all(Val::Px(4.)),
+                            ..default()
+                        },
+                        ..default()
+                    })
+                    .insert(MenuEntryText(index));
+            }
+        });
+}
+
+fn menu_input_system(
+    menu_actions: Res<MenuActionState>,
+    current_level: Res<CurrentLevel>,
+    mut menu: ResMut<MainMenu>,
+    mut level_select_return: ResMut<LevelSelectReturn>,
+    mut state: ResMut<State<GameState>>,
+    mut level_commands: EventWriter<LevelCommand>,
+    mut app_exit_events: EventWriter<AppExit>,
+) {
+    let entries = MenuEntry::all();
+    if menu_actions.just_pressed(MenuAction::Down) {
+        menu.selected = (menu.selected + 1) % entries.len();
+    }
+    if menu_actions.just_pressed(MenuAction::Up) {
+        menu.selected = (menu.selected + entries.len() - 1) % entries.len();
+    }
+    if !menu_actions.just_pressed(MenuAction::Confirm) {
+        return;
+    }
+    match entries[menu.selected] {
+        MenuEntry::Play => {
+            // Fails only if a transition is already queued this frame
+            if state.set(GameState::Playing).is_ok() && !current_level.unsaved {
+                // The startup level has a path unless it failed to load,
+                // leaving the default level. Reloading keeps its offset.
+                level_commands.send(match &current_level.path {
+                    Some(_) => LevelCommand::Reload,
+                    None => LevelCommand::ResetToDefault,
+                });
+            }
+        }
+        MenuEntry::LevelSelect => {
+            if state.set(GameState::LevelSelect).is_ok() {
+                level_select_return.0 = GameState::MainMenu;
+            }
+        }
+        MenuEntry::Quit => app_exit_events.send(AppExit),
+    }
+}
+
+fn highlight_system(menu: Res<MainMenu>, mut query: Query<(&MenuEntryText, &mut Text)>) {
+    for (entry, mut text) in query.iter_mut() {
+        let color = if entry.0 == menu.selected {
+            SELECTED_COLOR
+        } else {
+            UNSELECTED_COLOR
+        };
+        for section in text.sections.iter_mut() {
+            section.style.color = color;
+        }
+    }
+}
+
+fn despawn_menu_system(mut commands: Commands, query: Query<Entity, With<MainMenuRoot>>) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game_state::GameStatePlugin;
+    use crate::input::ActionState;
+    use bevy::asset::FileAssetIo;
+    use bevy::ecs::event::Events;
+    use bevy::tasks::TaskPoolBuilder;
+
+    // In the main menu, with `current_level` last played
+    fn menu_app(current_level: CurrentLevel) -> App {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .insert_resource(AssetServer::new(
+                FileAssetIo::new("assets", false),
+                TaskPoolBuilder::new().build(),
+            ))
+            .init_resource::<ActionState>()
+            .init_resource::<MenuActionState>()
+            .insert_resource(current_level)
+            .init_resource::<LevelSelectReturn>()
+            .add_event::<LevelCommand>()
+            .add_event::<AppExit>()
+            .add_plugin(GameStatePlugin {
+                initial_state: GameState::MainMenu,
+            })
+            .add_plugin(MainMenuPlugin);
+        app.update();
+        app
+    }
+
+    // Pressed for a frame, then released for one
+    fn press(app: &mut App, action: MenuAction) {
+        let mut menu_actions = app.world.resource_mut::<MenuActionState>();
+        menu_actions.update([action].into_iter().collect(), 0.);
+        app.update();
+        let mut menu_actions = app.world.resource_mut::<MenuActionState>();
+        menu_actions.update(default(), 0.);
+        app.update();
+    }
+
+    fn state(app: &App) -> GameState {
+        *app.world.resource::<State<GameState>>().current()
+    }
+
+    fn reloads(app: &App) -> usize {
+        let events = app.world.resource::<Events<LevelCommand>>();
+        events
+            .get_reader()
+            .iter(events)
+            .filter(|command| matches!(command, LevelCommand::Reload))
+            .count()
+    }
+
+    #[test]
+    fn up_and_down_wrap_around_the_entries() {
+        let mut app = menu_app(default());
+        let selected = |app: &App| MenuEntry::all()[app.world.resource::<MainMenu>().selected];
+        assert_eq!(selected(&app), MenuEntry::Play);
+        press(&mut app, MenuAction::Up);
+        assert_eq!(selected(&app), *MenuEntry::all().last().unwrap());
+        press(&mut app, MenuAction::Down);
+        assert_eq!(selected(&app), MenuEntry::Play);
+        press(&mut app, MenuAction::Down);
+        assert_eq!(selected(&app), MenuEntry::LevelSelect);
+
+        press(&mut app, MenuAction::Confirm);
+        assert_eq!(state(&app), GameState::LevelSelect);
+        assert_eq!(
+            app.world.resource::<LevelSelectReturn>().0,
+            GameState::MainMenu
+        );
+        let mut roots = app.world.query_filtered::<(), With<MainMenuRoot>>();
+        assert_eq!(roots.iter(&app.world).count(), 0);
+    }
+
+    #[test]
+    fn play_reloads_the_level_unless_it_has_unsaved_edits() {
+        let level = |unsaved| CurrentLevel {
+            path: Some("levels/test.ron".to_string()),
+            unsaved,
+            ..default()
+        };
+        let mut app = menu_app(level(false));
+        press(&mut app, MenuAction::Confirm);
+        assert_eq!(state(&app), GameState::Playing);
+        assert_eq!(reloads(&app), 1);
+
+        let mut app = menu_app(level(true));
+        press(&mut app, MenuAction::Confirm);
+        assert_eq!(state(&app), GameState::Playing);
+        assert_eq!(reloads(&app), 0);
+    }
+}
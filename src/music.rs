@@ -0,0 +1,218 @@
+// Background music: the current level's track, looped. Switching to a level
+// with a different track crossfades from one to the other, while a level with
+// the same track carries on without restarting it. The music is ducked while
+// the game is paused, and follows the `AudioSettings` volume and mute.
+//
+// Like sound effects, nothing starts on the web until the first interaction,
+// and the level's track then starts from its beginning.
+
+use bevy::asset::LoadState;
+use bevy::audio::{Audio, AudioSink, AudioSource, PlaybackSettings};
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::game_state::GameState;
+use crate::sfx::{AudioSettings, AudioUnlocked};
+
+// Seconds to fade from one track to the next
+pub const CROSSFADE_TIME: f32 = 1.5;
+// Music volume while paused, relative to while playing
+const PAUSED_MUSIC_VOLUME: f32 = 0.5;
+
+// The path of a music file, relative to the assets folder
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct MusicTrack(pub String);
+
+// The track to play, set when a level is spawned
+#[derive(Default)]
+pub struct LevelMusic(pub Option<MusicTrack>);
+
+struct PlayingTrack {
+    track: MusicTrack,
+    source: Handle<AudioSource>,
+    // Weak until the sink is created, once the source has loaded. Dropping
+    // the strong handle stops the track.
+    sink: Handle<AudioSink>,
+    // From 0 to 1, the track's volume relative to the music volume
+    fade: f32,
+}
+
+impl PlayingTrack {
+    // Fade `fade` towards `target` at the crossfade rate, returning whether
+    // it has got there
+    fn fade_towards(&mut self, target: f32, dt: f32) -> bool {
+        let step = dt / CROSSFADE_TIME;
+        self.fade = if self.fade < target {
+            (self.fade + step).min(target)
+        } else {
+            (self.fade - step).max(target)
+        };
+        self.fade == target
+    }
+}
+
+#[derive(Default)]
+struct MusicPlayer {
+    current: Option<PlayingTrack>,
+    fading_out: Vec<PlayingTrack>,
+    // Tracks which failed to load, and have been warned about
+    missing: Vec<MusicTrack>,
+}
+
+impl MusicPlayer {
+    // Make `track` the current one, fading out the rest. Returns it if it
+    // needs starting, not already playing or fading out.
+    fn switch_to(&mut self, track: Option<&MusicTrack>) -> Option<MusicTrack> {
+        if self.current.as_ref().map(|playing| &playing.track) == track {
+            return None;
+        }
+        self.fading_out.extend(self.current.take());
+        let track = track?;
+        match self
+            .fading_out
+            .iter()
+            .position(|playing| &playing.track == track)
+        {
+            // Coming back to a track before it has faded out
+            Some(index) => {
+                self.current = Some(self.fading_out.swap_remove(index));
+                None
+            }
+            None => Some(track.clone()),
+        }
+    }
+}
+
+// Needs the `AudioSettings` and `AudioUnlocked` from `SfxPlugin`, and the
+// `Audio` from `DefaultPlugins`
+#[derive(Default)]
+pub struct MusicPlugin;
+
+impl Plugin for MusicPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<LevelMusic>()
+            .init_resource::<MusicPlayer>()
+            .add_system(music_system);
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn music_system(
+    time: Res<Time>,
+    audio: Res<Audio>,
+    asset_server: Res<AssetServer>,
+    audio_sinks: Res<Assets<AudioSink>>,
+    settings: Res<AudioSettings>,
+    unlocked: Res<AudioUnlocked>,
+    level_music: Res<LevelMusic>,
+    game_state: Option<Res<State<GameState>>>,
+    mut player: ResMut<MusicPlayer>,
+) {
+    if !unlocked.0 {
+        return;
+    }
+    if let Some(track) = player.switch_to(level_music.0.as_ref()) {
+        let source = asset_server.load(track.0.as_str());
+        let sink = audio.play_with_settings(source.clone(), PlaybackSettings::LOOP.with_volume(0.));
+        player.current = Some(PlayingTrack {
+            track,
+            source,
+            sink,
+            fade: 0.,
+        });
+    }
+
+    let dt = time.delta_seconds();
+    if let Some(current) = &mut player.current {
+        current.fade_towards(1., dt);
+    }
+    // Dropped once silent, which stops them
+    player.fading_out.retain_mut(|playing| {
+        let silent = playing.fade_towards(0., dt);
+        if silent {
+            if let Some(sink) = audio_sinks.get(&playing.sink) {
+                sink.pause();
+            }
+        }
+        !silent
+    });
+
+    let paused = game_state.is_some_and(|state| *state.current() == GameState::Paused);
+    let volume = if settings.muted {
+        0.
+    } else if paused {
+        settings.music_volume * PAUSED_MUSIC_VOLUME
+    } else {
+        settings.music_volume
+    };
+    let MusicPlayer {
+        current,
+        fading_out,
+        missing,
+    } = &mut *player;
+    for playing in current.iter_mut().chain(fading_out.iter_mut()) {
+        if asset_server.get_load_state(&playing.source) == LoadState::Failed {
+            if !missing.contains(&playing.track) {
+                warn!("Couldn't load {}, so it won't play", playing.track.0);
+                missing.push(playing.track.clone());
+            }
+            continue;
+        }
+        if let Some(sink) = audio_sinks.get(&playing.sink) {
+            if playing.sink.is_weak() {
+                playing.sink = audio_sinks.get_handle(&playing.sink);
+            }
+            sink.set_volume(volume * playing.fade);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn playing(track: &MusicTrack) -> PlayingTrack {
+        PlayingTrack {
+            track: track.clone(),
+            source: Handle::default(),
+            sink: Handle::default(),
+            fade: 1.,
+        }
+    }
+
+    #[test]
+    fn switching_tracks_crossfades_and_keeps_the_same_track() {
+        let (a, b) = (
+            MusicTrack("music/a.ogg".to_string()),
+            MusicTrack("music/b.ogg".to_string()),
+        );
+        let mut player = MusicPlayer::default();
+        assert_eq!(player.switch_to(Some(&a)), Some(a.clone()));
+        player.current = Some(playing(&a));
+        assert_eq!(player.switch_to(Some(&a)), None);
+        assert!(player.fading_out.is_empty());
+
+        assert_eq!(player.switch_to(Some(&b)), Some(b.clone()));
+        assert_eq!(player.fading_out.len(), 1);
+        player.current = Some(playing(&b));
+        player.current.as_mut().unwrap().fade = 0.;
+        // Halfway through the crossfade
+        player.fading_out[0].fade_towards(0., CROSSFADE_TIME / 2.);
+        player
+            .current
+            .as_mut()
+            .unwrap()
+            .fade_towards(1., CROSSFADE_TIME / 2.);
+        assert!((player.fading_out[0].fade - 0.5).abs() < 1e-5);
+
+        // Going back picks up the fading track where it is
+        assert_eq!(player.switch_to(Some(&a)), None);
+        let current = player.current.as_ref().unwrap();
+        assert_eq!(current.track, a);
+        assert!((current.fade - 0.5).abs() < 1e-5);
+        assert_eq!(player.fading_out.len(), 1);
+        assert_eq!(player.switch_to(None), None);
+        assert_eq!(player.fading_out.len(), 2);
+    }
+}
@@ -0,0 +1,390 @@
+// People placed in a level, who say their lines to a player who talks to
+// them.
+//
+// A player within reach of one is prompted above them to press Interact,
+// which opens a dialog box and moves the game to `GameState::Dialog`. That
+// stops the fixed steps like pausing does, so nothing moves while it's open.
+// Each line is typed out a few characters at a time; Confirm shows the rest
+// of it at once, or moves on to the next once it's all shown, and closes the
+// box after the last. Entering `Playing` again swallows the press which closed
+// it, so it doesn't also jump.
+
+use bevy::prelude::*;
+use bevy::sprite::Anchor;
+use serde::{Deserialize, Serialize};
+
+use crate::death::Dying;
+use crate::game_state::GameState;
+use crate::input::{
+    Action, ActionState, InputMap, KeyLabels, MenuAction, MenuActionState, PlayerId,
+    SecondPlayerInput,
+};
+use crate::level::LevelEntity;
+use crate::physics::{hitbox, overlaps};
+use crate::pixel_perfect::{spawn_world_ui_text, WorldAnchor, UI_FONT};
+use crate::player::Player;
+
+pub const NPC_COLOR: Color = Color::rgb(0.55, 0.45, 0.85);
+// Size of an NPC, in tiles, from the bottom-left corner of its cell
+const NPC_SIZE: Vec2 = bevy::math::const_vec2!([1., 2.]);
+// How far past its sides a player can talk to it from, in tiles
+const NPC_REACH: f32 = 0.5;
+// Characters typed out per second
+const DIALOG_CHARS_PER_SECOND: f32 = 40.;
+const PROMPT_PIXELS: f32 = 8.;
+const DIALOG_PIXELS: f32 = 20.;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct NpcData {
+    // The cell it stands in, with its bottom-left corner at its own
+    pub pos: IVec2,
+    // What it says, a box at a time
+    pub dialog: Vec<String>,
+}
+
+#[derive(Component)]
+pub struct Npc {
+    pub dialog: Vec<String>,
+}
+
+// The prompt shown above `npc` while a player can talk to it
+#[derive(Component)]
+struct NpcPrompt {
+    npc: Entity,
+}
+
+pub fn spawn_npc(commands: &mut Commands, data: &NpcData) -> Entity {
+    commands
+        .spawn_bundle(SpriteBundle {
+            transform: Transform::from_translation(data.pos.as_vec2().extend(0.)),
+            sprite: Sprite {
+                color: NPC_COLOR,
+                custom_size: Some(NPC_SIZE),
+                anchor: Anchor::BottomLeft,
+                ..default()
+            },
+            ..default()
+        })
+        .insert(Npc {
+            dialog: data.dialog.clone(),
+        })
+        .insert(LevelEntity)
+        .id()
+}
+
+// How an NPC was placed, read back from it
+pub fn npc_data(npc: &Npc, transform: &Transform) -> NpcData {
+    NpcData {
+        pos: transform.translation.truncate().round().as_ivec2(),
+        dialog: npc.dialog.clone(),
+    }
+}
+
+// Whether a player at `transform` is close enough to talk to an NPC at
+// `npc_transform`
+fn in_reach(transform: &Transform, npc_transform: &Transform) -> bool {
+    let reach = Vec2::new(NPC_REACH, 0.);
+    let npc_box = (
+        npc_transform.translation.truncate() - reach,
+        NPC_SIZE + 2. * reach,
+    );
+    overlaps(hitbox(transform), npc_box)
+}
+
+// The lines being read and how far through them the box is
+pub struct Dialog {
+    lines: Vec<String>,
+    line: usize,
+    // Characters of the line typed out so far, in part
+    typed: f32,
+}
+
+impl Dialog {
+    pub fn new(lines: Vec<String>) -> Self {
+        Dialog {
+            lines,
+            line: 0,
+            typed: 0.,
+        }
+    }
+
+    fn line_chars(&self) -> usize {
+        self.lines
+            .get(self.line)
+            .map_or(0, |line| line.chars().count())
+    }
+
+    // The part of the line typed out so far
+    pub fn shown(&self) -> &str {
+        let line = match self.lines.get(self.line) {
+            Some(line) => line,
+            None => return "",
+        };
+        match line.char_indices().nth(self.typed as usize) {
+            Some((end, _)) => &line[..end],
+            None => line,
+        }
+    }
+
+    pub fn is_typed_out(&self) -> bool {
+        self.typed as usize >= self.line_chars()
+    }
+
+    pub fn advance(&mut self, dt: f32) {
+        if !self.is_typed_out() {
+            self.typed += dt * DIALOG_CHARS_PER_SECOND;
+        }
+    }
+
+    // Show the rest of the line, or move on to the next. False once there
+    // are no lines left.
+    pub fn confirm(&mut self) -> bool {
+        if self.is_typed_out() {
+            self.line += 1;
+            self.typed = 0.;
+        } else {
+            self.typed = self.line_chars() as f32;
+        }
+        self.line < self.lines.len()
+    }
+}
+
+// The dialog being read, while in `GameState::Dialog`
+#[derive(Default)]
+pub struct ActiveDialog(pub Option<Dialog>);
+
+#[derive(Component)]
+struct DialogBox;
+
+#[derive(Component)]
+struct DialogText;
+
+// Needs the `ActionState`, `InputMap`, `KeyLabels` and `MenuActionState` from
+// `InputMapPlugin`, the `GameState` from `GameStatePlugin` and the world UI
+// layer from `PixelPerfectPlugin`
+#[derive(Default)]
+pub struct NpcPlugin;
+
+impl Plugin for NpcPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ActiveDialog>()
+            .add_system(spawn_prompt_system)
+            .add_system(prompt_system.after(spawn_prompt_system))
+            .add_system_set(SystemSet::on_update(GameState::Playing).with_system(talk_system))
+            .add_system_set(SystemSet::on_enter(GameState::Dialog).with_system(spawn_dialog_box))
+            .add_system_set(SystemSet::on_update(GameState::Dialog).with_system(dialog_system))
+            .add_system_set(SystemSet::on_exit(GameState::Dialog).with_system(close_dialog_box));
+    }
+}
+
+// A prompt for every NPC, hidden until a player is in reach
+fn spawn_prompt_system(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    npc_query: Query<Entity, With<Npc>>,
+    prompt_query: Query<(Entity, &NpcPrompt)>,
+) {
+    for (prompt, &NpcPrompt { npc }) in prompt_query.iter() {
+        if !npc_query.contains(npc) {
+            commands.entity(prompt).despawn();
+        }
+    }
+    for npc in npc_query.iter() {
+        if prompt_query.iter().all(|(_, prompt)| prompt.npc != npc) {
+            let text = Text::with_section(
+                "",
+                TextStyle {
+                    font: asset_server.load(UI_FONT),
+                    font_size: PROMPT_PIXELS,
+                    color: Color::WHITE,
+                },
+                TextAlignment {
+                    vertical: VerticalAlign::Bottom,
+                    horizontal: HorizontalAlign::Center,
+                },
+            );
+            let prompt = spawn_world_ui_text(&mut commands, Vec2::ZERO, text);
+            commands
+                .entity(prompt)
+                .insert(NpcPrompt { npc })
+                .insert(Visibility { is_visible: false });
+        }
+    }
+}
+
+// Above the NPC, naming the first player's key while playing
+fn prompt_system(
+    state: Res<State<GameState>>,
+    input_map: Res<InputMap>,
+    key_labels: Res<KeyLabels>,
+    player_query: Query<&Transform, (With<Player>, Without<Dying>)>,
+    npc_query: Query<&Transform, With<Npc>>,
+    mut prompt_query: Query<(&NpcPrompt, &mut WorldAnchor, &mut Visibility, &mut Text)>,
+) {
+    let playing = *state.current() == GameState::Playing;
+    for (prompt, mut anchor, mut visibility, mut text) in prompt_query.iter_mut() {
+        let npc_transform = match npc_query.get(prompt.npc) {
+            Ok(transform) => transform,
+            Err(_) => continue,
+        };
+        let shown = playing
+            && player_query
+                .iter()
+                .any(|transform| in_reach(transform, npc_transform));
+        if visibility.is_visible != shown {
+            visibility.is_visible = shown;
+        }
+        if !shown {
+            continue;
+        }
+        anchor.0 = npc_transform.translation.truncate() + Vec2::new(NPC_SIZE.x / 2., NPC_SIZE.y);
+        let key = match input_map.keys(Action::Interact).first() {
+            Some(&binding) => key_labels.label(binding),
+            None => "Interact".to_string(),
+        };
+        let value = format!("{} to talk", key);
+        if text.sections[0].value != value {
+            text.sections[0].value = value;
+        }
+    }
+}
+
+// The players still in play, who can talk to an NPC in reach
+type TalkerQuery<'w, 's> =
+    Query<'w, 's, (&'static PlayerId, &'static Transform), (With<Player>, Without<Dying>)>;
+
+// A player pressing Interact in reach of an NPC opens its dialog
+fn talk_system(
+    action_state: Res<ActionState>,
+    second_player: Option<Res<SecondPlayerInput>>,
+    mut active_dialog: ResMut<ActiveDialog>,
+    mut state: ResMut<State<GameState>>,
+    player_query: TalkerQuery,
+    npc_query: Query<(&Npc, &Transform)>,
+) {
+    for (&id, transform) in player_query.iter() {
+        let talking = SecondPlayerInput::actions(id, &action_state, second_player.as_deref())
+            .is_some_and(|actions| actions.just_pressed(Action::Interact));
+        if !talking {
+            continue;
+        }
+        let npc = npc_query.iter().find(|(npc, npc_transform)| {
+            !npc.dialog.is_empty() && in_reach(transform, npc_transform)
+        });
+        if let Some((npc, _)) = npc {
+            // Fails only if a transition is already queued this frame
+            if state.set(GameState::Dialog).is_ok() {
+                active_dialog.0 = Some(Dialog::new(npc.dialog.clone()));
+            }
+            return;
+        }
+    }
+}
+
+fn spawn_dialog_box(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.), Val::Percent(100.)),
+                position_type: PositionType::Absolute,
+                justify_content: JustifyContent::Center,
+                // The UI is y-up, so this puts the box at the bottom
+                align_items: AlignItems::FlexStart,
+                ..default()
+            },
+            color: Color::NONE.into(),
+            ..default()
+        })
+        .insert(DialogBox)
+        .with_children(|parent| {
+            parent
+                .spawn_bundle(NodeBundle {
+                    style: Style {
+                        size: Size::new(Val::Percent(80.), Val::Percent(25.)),
+                        margin: Rect::all(Val::Percent(4.)),
+                        padding: Rect::all(Val::Px(16.)),
+                        flex_direction: FlexDirection::ColumnReverse,
+                        ..default()
+                    },
+                    color: Color::rgba(0., 0., 0., 0.85).into(),
+                    ..default()
+                })
+                .with_children(|parent| {
+                    parent
+                        .spawn_bundle(TextBundle {
+                            text: Text::with_section(
+                                "",
+                                TextStyle {
+                                    font: asset_server.load(UI_FONT),
+                                    font_size: DIALOG_PIXELS,
+                                    color: Color::WHITE,
+                                },
+                                default(),
+                            ),
+                            ..default()
+                        })
+                        .insert(DialogText);
+                });
+        });
+}
+
+// Typing out the line, and Confirm to skip ahead or read on. Back closes it
+// whatever's left.
+fn dialog_system(
+    time: Res<Time>,
+    menu_actions: Res<MenuActionState>,
+    mut active_dialog: ResMut<ActiveDialog>,
+    mut state: ResMut<State<GameState>>,
+    mut text_query: Query<&mut Text, With<DialogText>>,
+) {
+    let dialog = match &mut active_dialog.0 {
+        Some(dialog) => dialog,
+        None => {
+            let _ = state.set(GameState::Playing);
+            return;
+        }
+    };
+    if menu_actions.just_pressed(MenuAction::Back)
+        || (menu_actions.just_pressed(MenuAction::Confirm) && !dialog.confirm())
+    {
+        let _ = state.set(GameState::Playing);
+        return;
+    }
+    dialog.advance(time.delta_seconds());
+    for mut text in text_query.iter_mut() {
+        if text.sections[0].value != dialog.shown() {
+            text.sections[0].value = dialog.shown().to_string();
+        }
+    }
+}
+
+fn close_dialog_box(
+    mut commands: Commands,
+    mut active_dialog: ResMut<ActiveDialog>,
+    query: Query<Entity, With<DialogBox>>,
+) {
+    active_dialog.0 = None;
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn confirm_skips_the_typing_then_pages_through() {
+        let mut dialog = Dialog::new(vec!["Hello there".to_string(), "Bye".to_string()]);
+        assert_eq!(dialog.shown(), "");
+        dialog.advance(3. / DIALOG_CHARS_PER_SECOND);
+        assert_eq!(dialog.shown(), "Hel");
+        assert!(dialog.confirm());
+        assert_eq!(dialog.shown(), "Hello there");
+        assert!(dialog.confirm());
+        assert_eq!(dialog.shown(), "");
+        dialog.advance(1.);
+        assert_eq!(dialog.shown(), "Bye");
+        assert!(!dialog.confirm());
+    }
+}
@@ -0,0 +1,183 @@
+// Named groups of the tiles the editor's paintbrush paints, so a level's
+// forest tiles and cave tiles each get a short list of their own.
+//
+// Palettes are read from `assets/config/palettes.ron`, a list of palettes
+// each with a name and its entries. The editor switches between them in the
+// order they are listed, and cycles through the entries of the active one.
+// Without the file, there is a single palette of the plain tile's shapes.
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::level::TileAppearanceData;
+use crate::tile::{ColliderShape, TileSpec};
+
+pub const TILE_PALETTES_PATH: &str = "assets/config/palettes.ron";
+
+// A tile to paint, wherever it's painted
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PaletteEntry {
+    #[serde(default)]
+    pub shape: ColliderShape,
+    #[serde(default)]
+    pub appearance: TileAppearanceData,
+}
+
+impl PaletteEntry {
+    pub fn spec(&self, pos: IVec2, asset_server: &AssetServer) -> TileSpec {
+        TileSpec {
+            pos,
+            appearance: self.appearance.load(asset_server),
+            shape: self.shape,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TilePalette {
+    pub name: String,
+    pub entries: Vec<PaletteEntry>,
+}
+
+impl Default for TilePalette {
+    // Each shape of the plain tile, then an invisible collider
+    fn default() -> Self {
+        let textured = |shape| PaletteEntry {
+            shape,
+            appearance: default(),
+        };
+        TilePalette {
+            name: "Basic".to_string(),
+            entries: vec![
+                textured(ColliderShape::Aabb),
+                textured(ColliderShape::SlopeNE),
+                textured(ColliderShape::SlopeNW),
+                textured(ColliderShape::SlopeSE),
+                textured(ColliderShape::SlopeSW),
+                PaletteEntry {
+                    shape: ColliderShape::Aabb,
+                    appearance: TileAppearanceData::None,
+                },
+            ],
+        }
+    }
+}
+
+// Never empty, and none of its palettes are either
+pub struct TilePalettes {
+    palettes: Vec<TilePalette>,
+}
+
+impl Default for TilePalettes {
+    fn default() -> Self {
+        TilePalettes {
+            palettes: vec![TilePalette::default()],
+        }
+    }
+}
+
+impl TilePalettes {
+    pub fn load(path: &str) -> Self {
+        let text = match std::fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(err) => {
+                // There is no filesystem on wasm
+                if !cfg!(target_arch = "wasm32") {
+                    warn!("{}: {}, using the basic palette", path, err);
+                }
+                return TilePalettes::default();
+            }
+        };
+        match ron::from_str(&text) {
+            Ok(palettes) => TilePalettes::new(palettes),
+            Err(err) => {
+                warn!("{}: {}, using the basic palette", path, err);
+                TilePalettes::default()
+            }
+        }
+    }
+
+    // Palettes without entries are left out, and without any others the
+    // basic palette is used
+    pub fn new(palettes: Vec<TilePalette>) -> Self {
+        let palettes: Vec<_> = palettes
+            .into_iter()
+            .filter(|palette| {
+                if palette.entries.is_empty() {
+                    warn!("Palette {:?} has no tiles, leaving it out", palette.name);
+                }
+                !palette.entries.is_empty()
+            })
+            .collect();
+        if palettes.is_empty() {
+            return TilePalettes::default();
+        }
+        TilePalettes { palettes }
+    }
+
+    // How many palettes there are, at least one
+    pub fn count(&self) -> usize {
+        self.palettes.len()
+    }
+
+    // The palette at `index`, wrapping around
+    pub fn palette(&self, index: usize) -> &TilePalette {
+        &self.palettes[index % self.palettes.len()]
+    }
+
+    // The entry at `entry` in the palette at `palette`, both wrapping around
+    pub fn entry(&self, palette: usize, entry: usize) -> &PaletteEntry {
+        let entries = &self.palette(palette).entries;
+        &entries[entry % entries.len()]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn palettes_without_tiles_are_left_out() {
+        let forest = TilePalette {
+            name: "Forest".to_string(),
+            entries: vec![PaletteEntry {
+                shape: ColliderShape::SlopeNE,
+                appearance: TileAppearanceData::Color(Color::GREEN),
+            }],
+        };
+        let empty = TilePalette {
+            name: "Empty".to_string(),
+            entries: Vec::new(),
+        };
+        let palettes = TilePalettes::new(vec![empty.clone(), forest.clone()]);
+        assert_eq!(palettes.count(), 1);
+        assert_eq!(palettes.palette(0), &forest);
+        // Indices wrap around
+        assert_eq!(palettes.entry(1, 3), &forest.entries[0]);
+
+        let palettes = TilePalettes::new(vec![empty]);
+        assert_eq!(palettes.palette(0), &TilePalette::default());
+    }
+
+    #[test]
+    fn entries_read_with_default_shape_and_appearance() {
+        let palettes: Vec<TilePalette> =
+            ron::from_str(r#"[(name: "Cave", entries: [(shape: SlopeNE), (appearance: None)])]"#)
+                .unwrap();
+        let entries = &palettes[0].entries;
+        assert_eq!(entries[0].shape, ColliderShape::SlopeNE);
+        assert_eq!(entries[0].appearance, TileAppearanceData::default());
+        assert_eq!(entries[1].shape, ColliderShape::Aabb);
+        assert_eq!(entries[1].appearance, TileAppearanceData::None);
+    }
+
+    #[test]
+    fn palettes_file_parses() {
+        let text = std::fs::read_to_string(TILE_PALETTES_PATH).unwrap();
+        let palettes: Vec<TilePalette> = ron::from_str(&text).unwrap();
+        assert!(!palettes.is_empty());
+        for palette in &palettes {
+            assert!(!palette.entries.is_empty(), "{} is empty", palette.name);
+        }
+    }
+}
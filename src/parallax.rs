@@ -0,0 +1,226 @@
+// Background layers which scroll at a fraction of the camera's speed.
+//
+// Each layer is an entity holding a `ParallaxLayer` which is repositioned
+// every frame from the `WorldCamera` translation. Once the layer's texture
+// has loaded, sprites are spawned as children: one for a plain layer, or
+// enough copies to span the view plus one for a repeating layer, spawned
+// again whenever the zoom changes how many that takes. Repeating layers are
+// shifted by whole texture widths so the copies always cover the view, which
+// makes the wrap invisible.
+
+use bevy::prelude::*;
+use bevy::sprite::Anchor;
+
+use crate::pixel_perfect::{CameraZoom, WorldCamera, PIXELS_PER_TILE};
+
+// Depth of the farthest layer. Tiles sit at z = 0, so all layers stay behind them.
+pub const PARALLAX_BASE_Z: f32 = -100.;
+
+#[derive(Component)]
+pub struct ParallaxLayer {
+    pub factor: Vec2,
+    pub offset: Vec2,
+    pub texture: Handle<Image>,
+    pub repeat: bool,
+}
+
+#[derive(Bundle)]
+pub struct ParallaxLayerBundle {
+    pub layer: ParallaxLayer,
+    pub transform: Transform,
+    pub global_transform: GlobalTransform,
+}
+
+impl ParallaxLayerBundle {
+    // `depth` orders the layers: 0 is the farthest
+    pub fn new(
+        factor: Vec2,
+        offset: Vec2,
+        texture: Handle<Image>,
+        repeat: bool,
+        depth: usize,
+    ) -> Self {
+        ParallaxLayerBundle {
+            layer: ParallaxLayer {
+                factor,
+                offset,
+                texture,
+                repeat,
+            },
+            transform: Transform::from_xyz(offset.x, offset.y, PARALLAX_BASE_Z + depth as f32),
+            global_transform: GlobalTransform::default(),
+        }
+    }
+}
+
+// Needs the `CameraZoom` from `PixelPerfectPlugin`
+#[derive(Default)]
+pub struct ParallaxPlugin;
+
+impl Plugin for ParallaxPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system_to_stage(CoreStage::PostUpdate, spawn_layer_sprites_system)
+            .add_system_to_stage(
+                CoreStage::PostUpdate,
+                parallax_system.before(bevy::transform::TransformSystem::TransformPropagate),
+            );
+    }
+}
+
+// Size of an image in world units
+fn texture_size(image: &Image) -> Vec2 {
+    let size = image.texture_descriptor.size;
+    Vec2::new(size.width as f32, size.height as f32) / PIXELS_PER_TILE as f32
+}
+
+fn snap_to_pixel(value: f32) -> f32 {
+    (value * PIXELS_PER_TILE as f32).round() / PIXELS_PER_TILE as f32
+}
+
+fn spawn_layer_sprites_system(
+    mut commands: Commands,
+    images: Res<Assets<Image>>,
+    zoom: Res<CameraZoom>,
+    layer_query: Query<(Entity, &ParallaxLayer, Option<&Children>)>,
+) {
+    let view_width = 2. * zoom.view_half_extent().x;
+    for (entity, layer, children) in layer_query.iter() {
+        let image = match images.get(&layer.texture) {
+            Some(image) => image,
+            None => continue,
+        };
+        let size = texture_size(image);
+        let copies = if layer.repeat {
+            (view_width / size.x).ceil() as usize + 1
+        } else {
+            1
+        };
+        let mut layer_commands = commands.entity(entity);
+        if let Some(children) = children {
+            if children.len() == copies {
+                continue;
+            }
+            layer_commands.despawn_descendants();
+        }
+        layer_commands.with_children(|parent| {
+            for i in 0..copies {
+                parent.spawn_bundle(SpriteBundle {
+                    transform: Transform::from_xyz(i as f32 * size.x, 0., 0.),
+                    sprite: Sprite {
+                        custom_size: Some(size),
+                        anchor: Anchor::BottomLeft,
+                        ..default()
+                    },
+                    texture: layer.texture.clone(),
+                    ..default()
+                });
+            }
+        });
+    }
+}
+
+fn parallax_system(
+    images: Res<Assets<Image>>,
+    zoom: Res<CameraZoom>,
+    camera_query: Query<&Transform, With<WorldCamera>>,
+    mut layer_query: Query<(&ParallaxLayer, &mut Transform), Without<WorldCamera>>,
+) {
+    let camera = match camera_query.get_single() {
+        Ok(transform) => transform.translation.truncate(),
+        Err(_) => return,
+    };
+    let view_left = camera.x - zoom.view_half_extent().x;
+
+    for (layer, mut transform) in layer_query.iter_mut() {
+        let mut position = layer.offset + camera * layer.factor;
+        if layer.repeat {
+            if let Some(image) = images.get(&layer.texture) {
+                // Shift by whole copies so the leftmost copy starts at or
+                // before the view's edge
+                let width = texture_size(image).x;
+                position.x += ((view_left - position.x) / width).floor() * width;
+            }
+        }
+        transform.translation.x = snap_to_pixel(position.x);
+        transform.translation.y = snap_to_pixel(position.y);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::asset::AssetPlugin;
+    use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat};
+    use bevy::render::texture::BevyDefault;
+
+    // 10 tiles wide, so the default 32 tile view needs 5 copies
+    const WIDTH: f32 = 10.;
+
+    fn app() -> App {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .add_plugin(AssetPlugin)
+            .add_asset::<Image>()
+            .init_resource::<CameraZoom>()
+            .add_plugin(ParallaxPlugin);
+        let texture = app
+            .world
+            .resource_mut::<Assets<Image>>()
+            .add(Image::new_fill(
+                Extent3d {
+                    width: WIDTH as u32 * PIXELS_PER_TILE,
+                    height: PIXELS_PER_TILE,
+                    depth_or_array_layers: 1,
+                },
+                TextureDimension::D2,
+                &[0, 0, 0, 255],
+                TextureFormat::bevy_default(),
+            ));
+        app.world
+            .spawn()
+            .insert(WorldCamera)
+            .insert(Transform::from_xyz(3.3, 0., 0.));
+        app.world.spawn().insert_bundle(ParallaxLayerBundle::new(
+            Vec2::new(0.5, 0.),
+            Vec2::ZERO,
+            texture,
+            true,
+            0,
+        ));
+        app
+    }
+
+    // How many copies the layer has, and the span from the left edge of the
+    // first to the right edge of the last
+    fn coverage(app: &mut App) -> (usize, f32, f32) {
+        let (transform, children) = app
+            .world
+            .query_filtered::<(&Transform, &Children), With<ParallaxLayer>>()
+            .iter(&app.world)
+            .next()
+            .unwrap();
+        let left = transform.translation.x;
+        (children.len(), left, left + children.len() as f32 * WIDTH)
+    }
+
+    #[test]
+    fn zooming_out_spawns_enough_copies_to_cover_the_wider_view() {
+        let mut app = app();
+        app.update();
+        app.update();
+        assert_eq!(coverage(&mut app).0, 5);
+
+        let mut zoom = CameraZoom::default();
+        zoom.target = 0.5;
+        zoom.snap();
+        let half_extent = zoom.view_half_extent();
+        app.insert_resource(zoom);
+        app.update();
+        app.update();
+
+        let (copies, left, right) = coverage(&mut app);
+        assert_eq!(copies, 8);
+        assert!(left <= 3.3 - half_extent.x);
+        assert!(right >= 3.3 + half_extent.x);
+    }
+}
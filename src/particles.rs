@@ -0,0 +1,322 @@
+// Dust kicked up by the players: a puff on landing, sized by how hard they
+// land, a little on jumping, and a trail behind them while running.
+//
+// Particles are plain sprites in the world, drawn with the tiles so they
+// stay pixel-styled. They drift under their own gravity and fade out,
+// passing through tiles. Like the editor's tile flashes, they come from a
+// fixed pool reused oldest first, so even a large burst spawns nothing.
+
+use bevy::math::Mat2;
+use bevy::prelude::*;
+
+use crate::physics::{Landed, Mobility, Velocity, GRAVITY};
+use crate::pixel_perfect::PIXELS_PER_TILE;
+use crate::player::{Jumped, Player};
+
+// How many particles can show at once
+pub const PARTICLE_POOL_SIZE: usize = 256;
+// In front of the tiles and players, behind the editor's overlays
+const PARTICLE_Z: f32 = 5.;
+const DUST_COLOR: Color = Color::rgb(0.85, 0.8, 0.7);
+// Landing speed per particle in a landing puff, in tiles per second
+const LANDING_SPEED_PER_PARTICLE: f32 = 3.;
+// Seconds between puffs behind a running player, and the speed on the ground
+// above which they are running, in tiles per second
+const RUN_DUST_INTERVAL: f32 = 0.1;
+const RUN_DUST_MIN_SPEED: f32 = 11.;
+
+#[derive(Component, Default)]
+pub struct Particle {
+    // In tiles per second
+    velocity: Vec2,
+    gravity_scale: f32,
+    // Seconds it lasts, fading out all the while
+    lifetime: f32,
+    // Seconds since it was emitted, or None while unused
+    elapsed: Option<f32>,
+    alpha: f32,
+}
+
+impl Particle {
+    // Move it on by `dt` seconds, returning its alpha, or None once it's gone
+    fn advance(&mut self, translation: &mut Vec3, dt: f32) -> Option<f32> {
+        let elapsed = self.elapsed.as_mut()?;
+        *elapsed += dt;
+        if *elapsed >= self.lifetime {
+            self.elapsed = None;
+            return None;
+        }
+        let progress = *elapsed / self.lifetime;
+        self.velocity.y -= GRAVITY * self.gravity_scale * dt;
+        *translation += (self.velocity * dt).extend(0.);
+        Some(self.alpha * (1. - progress))
+    }
+}
+
+// How the particles of a burst start out
+#[derive(Clone, Copy, Debug)]
+pub struct ParticleEmitter {
+    pub color: Color,
+    // Side of the square particles, in tiles
+    pub size: f32,
+    // Particles head within `spread` radians either side of `direction`, at
+    // speeds between `min_speed` and `max_speed`, in tiles per second
+    pub direction: Vec2,
+    pub spread: f32,
+    pub min_speed: f32,
+    pub max_speed: f32,
+    // Times `GRAVITY`
+    pub gravity_scale: f32,
+    // Seconds, varied by up to a half either way
+    pub lifetime: f32,
+}
+
+impl ParticleEmitter {
+    // Dust for a burst of `speed` tiles per second heading `direction`
+    pub fn dust(direction: Vec2, speed: f32) -> Self {
+        ParticleEmitter {
+            color: DUST_COLOR,
+            size: 2. / PIXELS_PER_TILE as f32,
+            direction,
+            spread: 0.4,
+            min_speed: speed / 2.,
+            max_speed: speed,
+            gravity_scale: 0.1,
+            lifetime: 0.35,
+        }
+    }
+
+    // Emit `count` particles from `at`
+    pub fn burst(
+        &self,
+        pool: &mut ParticlePool,
+        particles: &mut ParticleQuery,
+        at: Vec2,
+        count: usize,
+    ) {
+        for _ in 0..count {
+            let entity = match pool.take() {
+                Some(entity) => entity,
+                None => return,
+            };
+            let angle = self.spread * (2. * pool.random() - 1.);
+            let speed = self.min_speed + (self.max_speed - self.min_speed) * pool.random();
+            let lifetime = self.lifetime * (0.5 + pool.random());
+            if let Ok((mut particle, mut transform, mut sprite, _)) = particles.get_mut(entity) {
+                *particle = Particle {
+                    velocity: Mat2::from_angle(angle) * self.direction.normalize_or_zero() * speed,
+                    gravity_scale: self.gravity_scale,
+                    lifetime,
+                    elapsed: Some(0.),
+                    alpha: self.color.a(),
+                };
+                transform.translation = at.extend(PARTICLE_Z);
+                transform.scale = Vec3::new(self.size, self.size, 1.);
+                sprite.color = self.color;
+            }
+        }
+    }
+}
+
+pub type ParticleQuery<'w, 's> = Query<
+    'w,
+    's,
+    (
+        &'static mut Particle,
+        &'static mut Transform,
+        &'static mut Sprite,
+        &'static mut Visibility,
+    ),
+>;
+
+#[derive(Default)]
+pub struct ParticlePool {
+    particles: Vec<Entity>,
+    // Index of the particle to reuse next
+    next: usize,
+    // For spreading the particles of a burst, which needn't be repeatable
+    seed: u32,
+}
+
+impl ParticlePool {
+    fn take(&mut self) -> Option<Entity> {
+        let entity = *self.particles.get(self.next)?;
+        self.next = (self.next + 1) % self.particles.len();
+        Some(entity)
+    }
+
+    // From 0 to 1, by xorshift
+    fn random(&mut self) -> f32 {
+        let mut x = self.seed.max(1);
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.seed = x;
+        (x >> 8) as f32 / (1 << 24) as f32
+    }
+}
+
+// Needs the `Landed` and `Jumped` events from `GamePlugin`
+#[derive(Default)]
+pub struct ParticlePlugin;
+
+impl Plugin for ParticlePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ParticlePool>()
+            .add_startup_system(spawn_pool_system)
+            .add_system(landing_dust_system)
+            .add_system(jump_dust_system)
+            .add_system(run_dust_system)
+            .add_system(
+                particle_system
+                    .after(landing_dust_system)
+                    .after(jump_dust_system)
+                    .after(run_dust_system),
+            );
+    }
+}
+
+fn spawn_pool_system(mut commands: Commands, mut pool: ResMut<ParticlePool>) {
+    pool.particles = (0..PARTICLE_POOL_SIZE)
+        .map(|_| {
+            commands
+                .spawn_bundle(SpriteBundle {
+                    sprite: Sprite {
+                        custom_size: Some(Vec2::ONE),
+                        ..default()
+                    },
+                    visibility: Visibility { is_visible: false },
+                    ..default()
+                })
+                .insert(Particle::default())
+                .id()
+        })
+        .collect();
+}
+
+// Out to both sides, more and faster the harder the landing
+fn landing_dust_system(
+    mut landed_events: EventReader<Landed>,
+    player_query: Query<(), With<Player>>,
+    mut pool: ResMut<ParticlePool>,
+    mut particles: ParticleQuery,
+) {
+    for landed in landed_events.iter() {
+        if !player_query.contains(landed.entity) {
+            continue;
+        }
+        let count = (landed.speed / LANDING_SPEED_PER_PARTICLE).clamp(2., 16.) as usize;
+        let speed = (landed.speed / 4.).clamp(2., 8.);
+        for side in [-1., 1.] {
+            ParticleEmitter::dust(Vec2::new(side, 0.2), speed).burst(
+                &mut pool,
+                &mut particles,
+                landed.at,
+                count / 2,
+            );
+        }
+    }
+}
+
+fn jump_dust_system(
+    mut jumped_events: EventReader<Jumped>,
+    player_query: Query<&Transform, (With<Player>, Without<Particle>)>,
+    mut pool: ResMut<ParticlePool>,
+    mut particles: ParticleQuery,
+) {
+    for jumped in jumped_events.iter() {
+        if let Ok(transform) = player_query.get(jumped.player) {
+            let at = transform.translation.truncate() + Vec2::new(transform.scale.x / 2., 0.);
+            for side in [-1., 1.] {
+                ParticleEmitter::dust(Vec2::new(side, 0.5), 3.).burst(
+                    &mut pool,
+                    &mut particles,
+                    at,
+                    2,
+                );
+            }
+        }
+    }
+}
+
+// The players, by how they're moving
+type RunnerQuery<'w, 's> = Query<
+    'w,
+    's,
+    (&'static Transform, &'static Velocity, &'static Mobility),
+    (With<Player>, Without<Particle>),
+>;
+
+// A puff from the back foot of each player running on the ground
+fn run_dust_system(
+    time: Res<Time>,
+    mut since_puff: Local<f32>,
+    player_query: RunnerQuery,
+    mut pool: ResMut<ParticlePool>,
+    mut particles: ParticleQuery,
+) {
+    *since_puff += time.delta_seconds();
+    if *since_puff < RUN_DUST_INTERVAL {
+        return;
+    }
+    *since_puff = 0.;
+    for (transform, velocity, mobility) in player_query.iter() {
+        let speed = velocity.0.x;
+        if !mobility.on_ground || speed.abs() < RUN_DUST_MIN_SPEED {
+            continue;
+        }
+        let back = if speed > 0. { 0. } else { transform.scale.x };
+        ParticleEmitter::dust(Vec2::new(-speed.signum(), 0.6), 2.).burst(
+            &mut pool,
+            &mut particles,
+            transform.translation.truncate() + Vec2::new(back, 0.),
+            1,
+        );
+    }
+}
+
+fn particle_system(time: Res<Time>, mut particles: ParticleQuery) {
+    for (mut particle, mut transform, mut sprite, mut visibility) in particles.iter_mut() {
+        if particle.elapsed.is_none() {
+            continue;
+        }
+        match particle.advance(&mut transform.translation, time.delta_seconds()) {
+            Some(alpha) => {
+                sprite.color.set_a(alpha);
+                visibility.is_visible = true;
+            }
+            None => visibility.is_visible = false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::ecs::system::SystemState;
+
+    #[test]
+    fn large_bursts_reuse_the_pool_and_fade_away() {
+        let mut world = World::new();
+        world.init_resource::<ParticlePool>();
+        let mut stage = SystemStage::single_threaded().with_system(spawn_pool_system);
+        stage.run(&mut world);
+
+        let mut state: SystemState<(ResMut<ParticlePool>, ParticleQuery)> =
+            SystemState::new(&mut world);
+        let (mut pool, mut particles) = state.get_mut(&mut world);
+        ParticleEmitter::dust(Vec2::X, 4.).burst(&mut pool, &mut particles, Vec2::ZERO, 300);
+        state.apply(&mut world);
+        let mut query = world.query::<&mut Particle>();
+        assert_eq!(query.iter(&world).count(), PARTICLE_POOL_SIZE);
+
+        // Gone once the longest lived has run its course
+        let mut translation = Vec3::ZERO;
+        for mut particle in query.iter_mut(&mut world) {
+            let alpha = particle.advance(&mut translation, 0.01);
+            assert!(alpha.is_some_and(|alpha| alpha < 1.));
+            assert!(particle.advance(&mut translation, 1.).is_none());
+            assert!(particle.elapsed.is_none());
+        }
+    }
+}
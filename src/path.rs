@@ -0,0 +1,150 @@
+// Fixed routes through a list of waypoints, for things which travel the same
+// way whatever happens around them, such as saw blades or moving platforms.
+//
+// Whatever follows a path is moved straight along it each physics step,
+// without colliding with tiles on the way, so paths should be laid out clear
+// of them.
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::physics::PHYSICS_TIME_STEP;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PathMode {
+    // Back and forth, turning around at either end
+    #[default]
+    PingPong,
+    // From the last waypoint straight back to the first, and round again
+    Loop,
+}
+
+// Moves the entity's translation through `waypoints` at `speed` tiles per
+// second, starting at the first
+#[derive(Component, Clone, Debug)]
+pub struct WaypointPath {
+    pub waypoints: Vec<Vec2>,
+    pub mode: PathMode,
+    pub speed: f32,
+    // Index of the waypoint it's headed for
+    target: usize,
+    // Whether it's going back through the waypoints, while ping-ponging
+    reversed: bool,
+}
+
+impl WaypointPath {
+    pub fn new(waypoints: Vec<Vec2>, mode: PathMode, speed: f32) -> Self {
+        WaypointPath {
+            target: waypoints.len().min(1),
+            waypoints,
+            mode,
+            speed,
+            reversed: false,
+        }
+    }
+
+    // Move `distance` on from `position`, turning at as many waypoints as
+    // that reaches
+    pub fn advance(&mut self, mut position: Vec2, mut distance: f32) -> Vec2 {
+        if self.waypoints.len() < 2 {
+            return position;
+        }
+        // Once round is as far as a step could sensibly go, and stops a path
+        // whose waypoints all coincide from spinning forever
+        for _ in 0..self.waypoints.len() * 2 {
+            let to_target = self.waypoints[self.target] - position;
+            let remaining = to_target.length();
+            if distance < remaining {
+                return position + to_target * (distance / remaining);
+            }
+            distance -= remaining;
+            position = self.waypoints[self.target];
+            self.next_target();
+        }
+        position
+    }
+
+    fn next_target(&mut self) {
+        let last = self.waypoints.len() - 1;
+        match self.mode {
+            PathMode::Loop => self.target = (self.target + 1) % self.waypoints.len(),
+            PathMode::PingPong => {
+                if (self.target == last && !self.reversed) || (self.target == 0 && self.reversed) {
+                    self.reversed = !self.reversed;
+                }
+                self.target = if self.reversed {
+                    self.target - 1
+                } else {
+                    self.target + 1
+                };
+            }
+        }
+    }
+}
+
+// In the physics step
+pub fn follow_path_system(mut query: Query<(&mut WaypointPath, &mut Transform)>) {
+    for (mut path, mut transform) in query.iter_mut() {
+        let distance = path.speed * PHYSICS_TIME_STEP;
+        let position = path.advance(transform.translation.truncate(), distance);
+        transform.translation = position.extend(transform.translation.z);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn square(mode: PathMode) -> WaypointPath {
+        WaypointPath::new(
+            vec![
+                Vec2::new(0., 0.),
+                Vec2::new(2., 0.),
+                Vec2::new(2., 2.),
+                Vec2::new(0., 2.),
+            ],
+            mode,
+            1.,
+        )
+    }
+
+    #[test]
+    fn ping_pong_turns_back_at_the_last_waypoint() {
+        let mut path = square(PathMode::PingPong);
+        let position = path.advance(Vec2::ZERO, 7.);
+        // Along all three sides, then one back
+        assert_eq!(position, Vec2::new(1., 2.));
+        let position = path.advance(position, 4.);
+        assert_eq!(position, Vec2::new(1., 0.));
+    }
+
+    #[test]
+    fn loop_goes_straight_back_to_the_first_waypoint() {
+        let mut path = square(PathMode::Loop);
+        let position = path.advance(Vec2::ZERO, 7.);
+        assert_eq!(position, Vec2::new(0., 1.));
+        let position = path.advance(position, 2.);
+        assert_eq!(position, Vec2::new(1., 0.));
+    }
+
+    #[test]
+    fn one_long_step_turns_at_both_ends() {
+        let mut path = WaypointPath::new(
+            vec![Vec2::new(0., 0.), Vec2::new(1., 0.), Vec2::new(2., 0.)],
+            PathMode::PingPong,
+            1.,
+        );
+        // Out to the end, all the way back and out again past the middle
+        let position = path.advance(Vec2::ZERO, 5.5);
+        assert_eq!(position, Vec2::new(1.5, 0.));
+        // Still headed out, so it turns at the far end next
+        let position = path.advance(position, 1.);
+        assert_eq!(position, Vec2::new(1.5, 0.));
+    }
+
+    #[test]
+    fn waypoints_in_one_place_hold_it_there() {
+        let mut path = WaypointPath::new(vec![Vec2::ONE; 3], PathMode::Loop, 1.);
+        assert_eq!(path.advance(Vec2::ONE, 10.), Vec2::ONE);
+    }
+}
@@ -0,0 +1,420 @@
+// The menu shown while the game is paused, with entries to resume, restart
+// the level, change settings or go back to the main menu.
+//
+// It is driven by `MenuActionState`: Up and Down move the highlight, Confirm
+// picks the entry and Back resumes, or leaves the settings page. Settings
+// entries cycle through their values on Confirm, and take effect at once.
+// The volumes and fullscreen are kept in the `Settings`.
+// Entering play swallows the press which resumed, so it doesn't also jump.
+
+use bevy::prelude::*;
+use bevy::window::{PresentMode, WindowMode};
+
+use crate::display::DisplaySettings;
+use crate::game_state::GameState;
+use crate::health::Health;
+use crate::input::{MenuAction, MenuActionState};
+use crate::level::{CurrentLevel, LevelCommand};
+use crate::pixel_perfect::UI_FONT;
+use crate::player::Player;
+use crate::settings::Settings;
+use crate::sfx::AudioSettings;
+
+const SELECTED_COLOR: Color = Color::rgb(1., 0.85, 0.3);
+const UNSELECTED_COLOR: Color = Color::rgb(0.7, 0.7, 0.7);
+// How much a volume entry changes the volume by on each press, wrapping
+// around past full
+const VOLUME_STEP: f32 = 0.1;
+// Text slots for entries, enough for the longest page
+const ENTRY_SLOTS: usize = 5;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum MenuEntry {
+    Resume,
+    RestartLevel,
+    Settings,
+    QuitToMenu,
+    SfxVolume,
+    MusicVolume,
+    // Not on wasm, where the game fills the canvas
+    Fullscreen,
+    Vsync,
+    Back,
+}
+
+impl MenuEntry {
+    const MAIN: &'static [MenuEntry] = &[
+        MenuEntry::Resume,
+        MenuEntry::RestartLevel,
+        MenuEntry::Settings,
+        MenuEntry::QuitToMenu,
+    ];
+
+    fn settings() -> &'static [MenuEntry] {
+        if cfg!(target_arch = "wasm32") {
+            &[
+                MenuEntry::SfxVolume,
+                MenuEntry::MusicVolume,
+                MenuEntry::Vsync,
+                MenuEntry::Back,
+            ]
+        } else {
+            &[
+                MenuEntry::SfxVolume,
+                MenuEntry::MusicVolume,
+                MenuEntry::Fullscreen,
+                MenuEntry::Vsync,
+                MenuEntry::Back,
+            ]
+        }
+    }
+
+    fn label(
+        self,
+        audio: &AudioSettings,
+        settings: &Settings,
+        display: &DisplaySettings,
+    ) -> String {
+        let on_off = |on| if on { "On" } else { "Off" };
+        match self {
+            MenuEntry::Resume => "Resume".to_string(),
+            MenuEntry::RestartLevel => "Restart Level".to_string(),
+            MenuEntry::Settings => "Settings".to_string(),
+            MenuEntry::QuitToMenu => "Quit to Menu".to_string(),
+            MenuEntry::SfxVolume => format!("Sound Volume: {:.0}%", audio.sfx_volume * 100.),
+            MenuEntry::MusicVolume => format!("Music Volume: {:.0}%", audio.music_volume * 100.),
+            MenuEntry::Fullscreen => format!("Fullscreen: {}", on_off(settings.fullscreen)),
+            MenuEntry::Vsync => format!(
+                "Vsync: {}",
+                on_off(display.present_mode == PresentMode::Fifo)
+            ),
+            MenuEntry::Back => "Back".to_string(),
+        }
+    }
+}
+
+#[derive(Default)]
+struct PauseMenu {
+    settings_page: bool,
+    selected: usize,
+}
+
+impl PauseMenu {
+    fn entries(&self) -> &'static [MenuEntry] {
+        if self.settings_page {
+            MenuEntry::settings()
+        } else {
+            MenuEntry::MAIN
+        }
+    }
+
+    // Switch page, highlighting its first entry
+    fn show_settings(&mut self, settings_page: bool) {
+        self.settings_page = settings_page;
+        self.selected = 0;
+    }
+}
+
+#[derive(Component)]
+struct PauseMenuRoot;
+
+#[derive(Component)]
+struct PauseTitleText;
+
+// Shows the current page's entry at this index, if it has one
+#[derive(Component)]
+struct EntrySlot(usize);
+
+// Needs the `MenuActionState` from `InputMapPlugin`, the `CurrentLevel` and
+// `LevelCommand` from `LevelPlugin`, the `DisplaySettings` and `Settings`
+// from `DisplayPlugin`, the `AudioSettings` from `SfxPlugin` and the
+// `GameState` from `GameStatePlugin`
+#[derive(Default)]
+pub struct PauseMenuPlugin;
+
+impl Plugin for PauseMenuPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<PauseMenu>()
+            .add_system_set(SystemSet::on_enter(GameState::Paused).with_system(spawn_menu_system))
+            .add_system_set(
+                SystemSet::on_update(GameState::Paused)
+                    .with_system(menu_input_system)
+                    .with_system(update_entries_system.after(menu_input_system)),
+            )
+            .add_system_set(SystemSet::on_exit(GameState::Paused).with_system(despawn_menu_system));
+    }
+}
+
+fn spawn_menu_system(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut menu: ResMut<PauseMenu>,
+) {
+    menu.show_settings(false);
+    let font = asset_server.load(UI_FONT);
+    let text_style = |font_size, color| TextStyle {
+        font: font.clone(),
+        font_size,
+        color,
+    };
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.), Val::Percent(100.)),
+                position_type: PositionType::Absolute,
+                // The UI is y-up, so this lists the children top to bottom
+                flex_direction: FlexDirection::ColumnReverse,
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            color: Color::rgba(0., 0., 0., 0.5).into(),
+            ..default()
+        })
+        .insert(PauseMenuRoot)
+        .with_children(|parent| {
+            parent
+                .spawn_bundle(TextBundle {
+                    text: Text::with_section("Paused", text_style(48., Color::WHITE), default()),
+                    style: Style {
+                        margin: Rect {
+                            bottom: Val::Px(16.),
+                            ..default()
+                        },
+                        ..default()
+                    },
+                    ..default()
+                })
+                .insert(PauseTitleText);
+            // Filled in by `update_entries_system`
+            for index in 0..ENTRY_SLOTS {
+                parent
+                    .spawn_bundle(TextBundle {
+                        text: Text::with_section("", text_style(24., UNSELECTED_COLOR), default()),
+                        style: Style {
+                            margin: Rect::all(Val::Px(4.)),
+                            ..default()
+                        },
+                        ..default()
+                    })
+                    .insert(EntrySlot(index));
+            }
+        });
+}
+
+#[allow(clippy::too_many_arguments)]
+fn menu_input_system(
+    menu_actions: Res<MenuActionState>,
+    current_level: Res<CurrentLevel>,
+    mut menu: ResMut<PauseMenu>,
+    mut state: ResMut<State<GameState>>,
+    mut level_commands: EventWriter<LevelCommand>,
+    mut audio: ResMut<AudioSettings>,
+    mut settings: ResMut<Settings>,
+    mut display: ResMut<DisplaySettings>,
+    mut windows: ResMut<Windows>,
+    mut health_query: Query<&mut Health, With<Player>>,
+) {
+    let entries = menu.entries();
+    if menu_actions.just_pressed(MenuAction::Down) {
+        menu.selected = (menu.selected + 1) % entries.len();
+    }
+    if menu_actions.just_pressed(MenuAction::Up) {
+        menu.selected = (menu.selected + entries.len() - 1) % entries.len();
+    }
+    if menu_actions.just_pressed(MenuAction::Back) {
+        if menu.settings_page {
+            menu.show_settings(false);
+        } else {
+            // Fails only if a transition is already queued this frame
+            let _ = state.set(GameState::Playing);
+        }
+        return;
+    }
+    if !menu_actions.just_pressed(MenuAction::Confirm) {
+        return;
+    }
+    let cycle_volume = |volume: f32| {
+        let steps = (1. / VOLUME_STEP).round() as i32;
+        (((volume / VOLUME_STEP).round() as i32 + 1) % (steps + 1)) as f32 * VOLUME_STEP
+    };
+    match entries[menu.selected] {
+        MenuEntry::Resume => {
+            let _ = state.set(GameState::Playing);
+        }
+        MenuEntry::RestartLevel => {
+            if state.set(GameState::Playing).is_ok() {
+                // Reloading needs a file, and a level without one is the
+                // default level
+                level_commands.send(match current_level.path {
+                    Some(_) => LevelCommand::Reload,
+                    None => LevelCommand::ResetToDefault,
+                });
+                for mut health in health_query.iter_mut() {
+                    health.restore();
+                }
+            }
+        }
+        MenuEntry::Settings => menu.show_settings(true),
+        MenuEntry::QuitToMenu => {
+            let _ = state.set(GameState::MainMenu);
+        }
+        MenuEntry::SfxVolume => {
+            audio.sfx_volume = cycle_volume(audio.sfx_volume);
+            settings.sfx_volume = audio.sfx_volume;
+            settings.save();
+        }
+        MenuEntry::MusicVolume => {
+            audio.music_volume = cycle_volume(audio.music_volume);
+            settings.music_volume = audio.music_volume;
+            settings.save();
+        }
+        MenuEntry::Fullscreen => {
+            settings.fullscreen = !settings.fullscreen;
+            settings.save();
+            if let Some(window) = windows.get_primary_mut() {
+                window.set_mode(if settings.fullscreen {
+                    WindowMode::BorderlessFullscreen
+                } else {
+                    WindowMode::Windowed
+                });
+            }
+        }
+        MenuEntry::Vsync => {
+            display.present_mode = match display.present_mode {
+                PresentMode::Fifo => PresentMode::Immediate,
+                _ => PresentMode::Fifo,
+            };
+        }
+        MenuEntry::Back => menu.show_settings(false),
+    }
+}
+
+fn update_entries_system(
+    menu: Res<PauseMenu>,
+    audio: Res<AudioSettings>,
+    settings: Res<Settings>,
+    display: Res<DisplaySettings>,
+    mut title_query: Query<&mut Text, (With<PauseTitleText>, Without<EntrySlot>)>,
+    mut slot_query: Query<(&EntrySlot, &mut Text, &mut Style)>,
+    added_query: Query<(), Added<EntrySlot>>,
+) {
+    let changed = menu.is_changed()
+        || audio.is_changed()
+        || settings.is_changed()
+        || display.is_changed()
+        || !added_query.is_empty();
+    if !changed {
+        return;
+    }
+    for mut text in title_query.iter_mut() {
+        text.sections[0].value = if menu.settings_page {
+            "Settings"
+        } else {
+            "Paused"
+        }
+        .to_string();
+    }
+    let entries = menu.entries();
+    for (slot, mut text, mut style) in slot_query.iter_mut() {
+        let entry = entries.get(slot.0);
+        // Hidden slots would still take up room
+        style.display = if entry.is_some() {
+            Display::Flex
+        } else {
+            Display::None
+        };
+        if let Some(entry) = entry {
+            let section = &mut text.sections[0];
+            section.value = entry.label(&audio, &settings, &display);
+            section.style.color = if slot.0 == menu.selected {
+                SELECTED_COLOR
+            } else {
+                UNSELECTED_COLOR
+            };
+        }
+    }
+}
+
+fn despawn_menu_system(mut commands: Commands, query: Query<Entity, With<PauseMenuRoot>>) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game_state::GameStatePlugin;
+    use crate::input::ActionState;
+    use bevy::asset::FileAssetIo;
+    use bevy::tasks::TaskPoolBuilder;
+
+    fn paused_app() -> App {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .insert_resource(AssetServer::new(
+                FileAssetIo::new("assets", false),
+                TaskPoolBuilder::new().build(),
+            ))
+            .init_resource::<ActionState>()
+            .init_resource::<MenuActionState>()
+            .init_resource::<CurrentLevel>()
+            .init_resource::<AudioSettings>()
+            .init_resource::<Settings>()
+            .init_resource::<DisplaySettings>()
+            .init_resource::<Windows>()
+            .add_event::<LevelCommand>()
+            .add_plugin(GameStatePlugin {
+                initial_state: GameState::Paused,
+            })
+            .add_plugin(PauseMenuPlugin);
+        app.update();
+        app
+    }
+
+    // Pressed for a frame, then released for one
+    fn press(app: &mut App, action: MenuAction) {
+        let mut menu_actions = app.world.resource_mut::<MenuActionState>();
+        menu_actions.update([action].into_iter().collect(), 0.);
+        app.update();
+        let mut menu_actions = app.world.resource_mut::<MenuActionState>();
+        menu_actions.update(default(), 0.);
+        app.update();
+    }
+
+    fn selected(app: &App) -> MenuEntry {
+        let menu = app.world.resource::<PauseMenu>();
+        menu.entries()[menu.selected]
+    }
+
+    #[test]
+    fn back_leaves_the_settings_page_then_resumes() {
+        let mut app = paused_app();
+        press(&mut app, MenuAction::Up);
+        assert_eq!(selected(&app), MenuEntry::QuitToMenu);
+        press(&mut app, MenuAction::Up);
+        assert_eq!(selected(&app), MenuEntry::Settings);
+
+        press(&mut app, MenuAction::Confirm);
+        assert_eq!(selected(&app), MenuEntry::SfxVolume);
+        press(&mut app, MenuAction::Up);
+        assert_eq!(selected(&app), MenuEntry::Back);
+        press(&mut app, MenuAction::Confirm);
+        assert_eq!(selected(&app), MenuEntry::Resume);
+
+        press(&mut app, MenuAction::Down);
+        press(&mut app, MenuAction::Down);
+        press(&mut app, MenuAction::Confirm);
+        assert_eq!(selected(&app), MenuEntry::SfxVolume);
+        press(&mut app, MenuAction::Back);
+        assert_eq!(selected(&app), MenuEntry::Resume);
+        let state = *app.world.resource::<State<GameState>>().current();
+        assert_eq!(state, GameState::Paused);
+        press(&mut app, MenuAction::Back);
+        let state = *app.world.resource::<State<GameState>>().current();
+        assert_eq!(state, GameState::Playing);
+        let mut roots = app.world.query_filtered::<(), With<PauseMenuRoot>>();
+        assert_eq!(roots.iter(&app.world).count(), 0);
+    }
+}
@@ -0,0 +1,1478 @@
+use bevy::{
+    ecs::event::Events,
+    prelude::*,
+    sprite::collide_aabb::{collide, Collision},
+};
+
+use bevy::utils::{Duration, HashMap, Instant};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+use crate::tile::{ColliderShape, SolidCollider};
+
+pub const PHYSICS_TIME_STEP: f32 = 1.0 / 240.0;
+pub const GRAVITY: f32 = 30.;
+// How quickly a running `Mobility` speeds up past its walk speed, and eases
+// back down once it stops running on the ground, in tiles per second squared
+pub const RUN_ACCELERATION: f32 = 20.;
+// Gravity scale while a `Mobility` is fast-falling
+pub const FAST_FALL_GRAVITY_SCALE: f32 = 3.;
+// How quickly a `Mobility` changes speed on a surface with a friction of 1,
+// were it to slip at all, in tiles per second squared
+pub const SLIP_ACCELERATION: f32 = 100.;
+
+#[derive(Component)]
+pub struct Velocity(pub Vec3);
+
+#[derive(Component)]
+pub struct Gravity(pub f32);
+
+// Multiplies an entity's `Gravity` for temporary effects such as fast-fall,
+// without losing its base value. Entities without one fall at a scale of 1.
+#[derive(Component, Clone, Copy, Debug, PartialEq)]
+pub struct GravityScale(pub f32);
+
+impl Default for GravityScale {
+    fn default() -> Self {
+        GravityScale(1.)
+    }
+}
+
+// The fastest an entity falls under gravity, however strong
+#[derive(Component, Clone, Copy, Debug, PartialEq)]
+pub struct TerminalVelocity(pub f32);
+
+// How a solid tile's surface affects the bodies touching it. Tiles without
+// one behave as `SurfaceMaterial::default()`.
+#[derive(Component, Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SurfaceMaterial {
+    // Grip when walking on the surface. At 1 or more a `Mobility` changes
+    // speed at once; below that it slips, like on ice.
+    pub friction: f32,
+    // Fraction of the speed into the surface which is bounced back
+    pub restitution: f32,
+}
+
+impl SurfaceMaterial {
+    pub const ICE: SurfaceMaterial = SurfaceMaterial {
+        friction: 0.1,
+        restitution: 0.,
+    };
+    pub const TRAMPOLINE: SurfaceMaterial = SurfaceMaterial {
+        friction: 1.,
+        restitution: 0.9,
+    };
+
+    // The velocity `dt` seconds after moving at `current` while trying to move
+    // at `target`
+    pub fn approach(&self, current: f32, target: f32, dt: f32) -> f32 {
+        if self.friction >= 1. {
+            return target;
+        }
+        let max_change = self.friction * SLIP_ACCELERATION * dt;
+        current + (target - current).clamp(-max_change, max_change)
+    }
+
+    // The velocity along a surface's normal after hitting it at `into`
+    fn bounce(&self, into: f32) -> f32 {
+        -into * self.restitution
+    }
+}
+
+impl Default for SurfaceMaterial {
+    fn default() -> Self {
+        SurfaceMaterial {
+            friction: 1.,
+            restitution: 0.,
+        }
+    }
+}
+
+// Marks a moving entity which is pushed out of solid tiles.
+// The hitbox is the entity's scale, anchored at its bottom-left corner.
+#[derive(Component, Default)]
+pub struct TileCollider;
+
+// A body's bottom-left corner and size
+pub fn hitbox(transform: &Transform) -> (Vec2, Vec2) {
+    (transform.translation.truncate(), transform.scale.truncate())
+}
+
+// Whether two boxes overlap, each its bottom-left corner and size like a
+// `hitbox`. Boxes which only touch along an edge don't.
+pub fn overlaps(a: (Vec2, Vec2), b: (Vec2, Vec2)) -> bool {
+    a.0.cmplt(b.0 + b.1).all() && b.0.cmplt(a.0 + a.1).all()
+}
+
+// Which things collide with which. Each bit is a kind of thing: `layer` is
+// the kinds this is, and `mask` the kinds it collides with. Two things only
+// collide when each is in the other's mask, so either can opt out.
+//
+// The bits are the associated constants below, and the rest are free for
+// new kinds. A body without this is on every layer and collides with
+// everything, and a solid tile without it is on `TILES` and collides with
+// everything. So far only tile collisions are filtered.
+#[derive(Component, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct CollisionLayers {
+    pub layer: u32,
+    pub mask: u32,
+}
+
+impl CollisionLayers {
+    pub const TILES: u32 = 1 << 0;
+    pub const PLAYERS: u32 = 1 << 1;
+    pub const ENEMIES: u32 = 1 << 2;
+    pub const PROJECTILES: u32 = 1 << 3;
+    pub const BOXES: u32 = 1 << 4;
+    pub const ALL: u32 = u32::MAX;
+
+    pub fn new(layer: u32, mask: u32) -> Self {
+        CollisionLayers { layer, mask }
+    }
+
+    // On `layer`, colliding with everything
+    pub fn on(layer: u32) -> Self {
+        CollisionLayers::new(layer, CollisionLayers::ALL)
+    }
+
+    pub fn collides_with(&self, other: &CollisionLayers) -> bool {
+        self.layer & other.mask != 0 && other.layer & self.mask != 0
+    }
+}
+
+impl Default for CollisionLayers {
+    fn default() -> Self {
+        CollisionLayers::new(CollisionLayers::ALL, CollisionLayers::ALL)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Stance {
+    #[default]
+    Standing,
+    Crouching,
+}
+
+// A body whose hitbox depends on its stance. The stance only changes once
+// the new hitbox fits, so standing up under a low ceiling waits until the
+// body has moved out from under it.
+#[derive(Component, Default)]
+pub struct Pose {
+    pub stance: Stance,
+    // The stance to change to as soon as possible
+    pub requested: Stance,
+}
+
+// Hitbox size for each `Stance`. The hitbox keeps its bottom-left corner when
+// it changes, so it grows and shrinks from the feet.
+#[derive(Component, Clone, Copy, Debug)]
+pub struct StanceHitboxes {
+    pub standing: Vec2,
+    pub crouching: Vec2,
+}
+
+impl StanceHitboxes {
+    pub fn size(&self, stance: Stance) -> Vec2 {
+        match stance {
+            Stance::Standing => self.standing,
+            Stance::Crouching => self.crouching,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Direction {
+    Left,
+    Right,
+    #[default]
+    Neutral,
+}
+
+#[derive(Component)]
+pub struct Mobility {
+    pub on_ground: bool,
+    pub jump_speed: f32,
+    pub walk_speed: f32,
+    pub walk_direction: Direction,
+    // Top speed while running, as a multiple of `walk_speed`
+    pub run_multiplier: f32,
+    // Falling under `FAST_FALL_GRAVITY_SCALE`; cleared on landing
+    pub fast_falling: bool,
+    // Pressed against a wall, as of the last collision step
+    pub on_wall: bool,
+    // Bumped into a tile overhead, as of the last collision step
+    pub on_ceiling: bool,
+    // Seconds after leaving a wall during which a wall jump is still allowed,
+    // tuned separately from ground jumps
+    pub wall_coyote_time: f32,
+    // Counts down from `wall_coyote_time` once the wall is left
+    pub wall_coyote_timer: f32,
+    // Which side of the body the wall last pressed against was on, which a
+    // wall jump pushes away from
+    pub wall_side: Direction,
+    // Counts down after a wall jump, while walking can't steer against the
+    // push away from the wall
+    pub wall_push_timer: f32,
+    // Surface of the ground stood on, as of the last collision step
+    pub ground_material: SurfaceMaterial,
+    // In the crouching `Stance`, as of the last collision step. Crouching is
+    // asked for through the body's `Pose`.
+    pub crouching: bool,
+    // Speed while crouching, which replaces walking and running
+    pub crouch_speed: f32,
+    // The steepest ground, in radians from flat, which can be stood on.
+    // Steeper ground can't be walked up or jumped from; it slides the body
+    // down instead.
+    pub max_walkable_slope: f32,
+    // Sliding down ground steeper than `max_walkable_slope`, until landing on
+    // walkable ground or meeting a wall. Walking doesn't steer a slide.
+    pub sliding: bool,
+    // Gravity scale in the air while the vertical speed is within
+    // `apex_threshold` of 0, giving a little hang time at the top of a jump.
+    // At 1 there is none. Fast-falling overrides it.
+    pub apex_gravity_scale: f32,
+    pub apex_threshold: f32,
+    // Gravity scale in the air while moving down, past the apex, for a
+    // quicker fall than rise. At 1 the arc is symmetric. Fast-falling
+    // overrides it.
+    pub fall_gravity_scale: f32,
+    // Jumps which can be made in the air before landing again, and how many
+    // have been since leaving the ground
+    pub max_air_jumps: u32,
+    pub air_jumps: u32,
+    // Movement which has to be unlocked, by an ability pickup for players
+    pub dash_unlocked: bool,
+    pub wall_jump_unlocked: bool,
+}
+
+// Standing still in the air, with no speed to walk or jump at and nothing
+// unlocked
+impl Default for Mobility {
+    fn default() -> Self {
+        Mobility {
+            on_ground: false,
+            jump_speed: 0.,
+            walk_speed: 0.,
+            walk_direction: Direction::Neutral,
+            run_multiplier: 1.,
+            fast_falling: false,
+            on_wall: false,
+            on_ceiling: false,
+            wall_coyote_time: 0.,
+            wall_coyote_timer: 0.,
+            wall_side: Direction::Neutral,
+            wall_push_timer: 0.,
+            ground_material: default(),
+            crouching: false,
+            crouch_speed: 0.,
+            max_walkable_slope: 50f32.to_radians(),
+            sliding: false,
+            // Off
+            apex_gravity_scale: 1.,
+            apex_threshold: 0.,
+            fall_gravity_scale: 1.,
+            max_air_jumps: 0,
+            air_jumps: 0,
+            dash_unlocked: false,
+            wall_jump_unlocked: false,
+        }
+    }
+}
+
+impl Mobility {
+    // The same body standing still in the air, keeping how it's tuned and
+    // what it has unlocked
+    pub fn at_rest(&self) -> Self {
+        Mobility {
+            jump_speed: self.jump_speed,
+            walk_speed: self.walk_speed,
+            run_multiplier: self.run_multiplier,
+            wall_coyote_time: self.wall_coyote_time,
+            crouch_speed: self.crouch_speed,
+            max_walkable_slope: self.max_walkable_slope,
+            apex_gravity_scale: self.apex_gravity_scale,
+            apex_threshold: self.apex_threshold,
+            fall_gravity_scale: self.fall_gravity_scale,
+            max_air_jumps: self.max_air_jumps,
+            dash_unlocked: self.dash_unlocked,
+            wall_jump_unlocked: self.wall_jump_unlocked,
+            ..default()
+        }
+    }
+
+    // The speed to move at in the walk direction, `dt` seconds after moving
+    // at `current`. Walking is immediate, but running builds up to its top
+    // speed on the ground. In the air the speed is kept, so a running jump
+    // carries further and letting go of run doesn't brake.
+    pub fn walk_speed_after(&self, current: f32, running: bool, dt: f32) -> f32 {
+        if self.crouching {
+            return self.crouch_speed;
+        }
+        let run_speed = self.walk_speed * self.run_multiplier;
+        let current = current.clamp(self.walk_speed, run_speed.max(self.walk_speed));
+        if !self.on_ground {
+            current
+        } else if running {
+            (current + RUN_ACCELERATION * dt).min(run_speed)
+        } else {
+            (current - RUN_ACCELERATION * dt).max(self.walk_speed)
+        }
+    }
+
+    // The horizontal velocity `dt` seconds after moving at `current`, walking
+    // in `direction` (-1, 0 or 1). Slippery ground eases towards it.
+    pub fn walk_velocity_after(&self, current: f32, direction: f32, running: bool, dt: f32) -> f32 {
+        if self.sliding {
+            return current;
+        }
+        let target = if direction == 0. {
+            0.
+        } else {
+            direction * self.walk_speed_after(current * direction, running, dt)
+        };
+        if self.on_ground {
+            self.ground_material.approach(current, target, dt)
+        } else {
+            target
+        }
+    }
+
+    // Whether ground with this surface normal is shallow enough to stand on
+    pub fn can_stand_on(&self, normal: Vec2) -> bool {
+        normal.y >= self.max_walkable_slope.cos()
+    }
+
+    pub fn can_wall_jump(&self) -> bool {
+        self.wall_jump_unlocked && !self.on_ground && (self.on_wall || self.wall_coyote_timer > 0.)
+    }
+}
+
+// A `Mobility` has come down on the ground from the air. Only sent while the
+// `Events<Landed>` resource exists.
+pub struct Landed {
+    pub entity: Entity,
+    // Where it landed, the middle of its bottom edge
+    pub at: Vec2,
+    // How fast it was falling, in tiles per second
+    pub speed: f32,
+}
+
+#[derive(Clone, Hash, Debug, PartialEq, Eq, SystemLabel)]
+pub enum PhysicsSystem {
+    Gravity,
+    Velocity,
+    Collision,
+    Camera,
+}
+
+// Gravity, velocity integration and tile collision, in that order. The caller
+// decides how often the set runs; each run advances one PHYSICS_TIME_STEP.
+pub fn physics_system_set() -> SystemSet {
+    SystemSet::new()
+        .with_system(gravity_system.label(PhysicsSystem::Gravity))
+        .with_system(
+            physics_system
+                .label(PhysicsSystem::Velocity)
+                .after(PhysicsSystem::Gravity),
+        )
+        .with_system(
+            tile_collision_system
+                .label(PhysicsSystem::Collision)
+                .after(PhysicsSystem::Velocity),
+        )
+}
+
+pub fn physics_system(mut query: Query<(&mut Transform, &Velocity)>) {
+    for (mut transform, velocity) in query.iter_mut() {
+        transform.translation += velocity.0 * PHYSICS_TIME_STEP;
+    }
+}
+
+// The bodies gravity pulls on, with what changes how hard
+type FallingBodyQuery<'w, 's> = Query<
+    'w,
+    's,
+    (
+        &'static mut Velocity,
+        &'static Gravity,
+        Option<&'static GravityScale>,
+        Option<&'static Mobility>,
+        Option<&'static TerminalVelocity>,
+    ),
+>;
+
+pub fn gravity_system(mut query: FallingBodyQuery) {
+    for (mut velocity, gravity, scale, mobility, terminal_velocity) in query.iter_mut() {
+        let mut scale = scale.copied().unwrap_or_default().0;
+        if let Some(mobility) = mobility.filter(|mobility| !mobility.on_ground) {
+            if mobility.fast_falling {
+                scale *= FAST_FALL_GRAVITY_SCALE;
+            } else if velocity.0.y.abs() < mobility.apex_threshold {
+                scale *= mobility.apex_gravity_scale;
+            } else if velocity.0.y < 0. {
+                scale *= mobility.fall_gravity_scale;
+            }
+        }
+        velocity.0.y -= gravity.0 * scale * PHYSICS_TIME_STEP;
+        if let Some(terminal_velocity) = terminal_velocity {
+            velocity.0.y = velocity.0.y.max(-terminal_velocity.0);
+        }
+    }
+}
+
+// Counts of the physics work done each frame. Only collected while the
+// resource exists, so insert it to enable measuring.
+#[derive(Default)]
+pub struct PhysicsStats {
+    pub steps_last_frame: u32,
+    pub collision_time_last_frame: Duration,
+    steps: u32,
+    collision_time: Duration,
+}
+
+impl PhysicsStats {
+    // Call once per frame, before the physics steps run
+    pub fn start_frame(&mut self) {
+        self.steps_last_frame = std::mem::take(&mut self.steps);
+        self.collision_time_last_frame = std::mem::take(&mut self.collision_time);
+    }
+}
+
+#[derive(Default)]
+pub struct Contacts {
+    pub on_ground: bool,
+    // Pushed out through a tile's left or right side
+    pub on_wall: bool,
+    // Which side of the body that tile was on
+    pub wall_side: Direction,
+    // Pushed out down through a tile's underside
+    pub on_ceiling: bool,
+    // Surface of the last ground tile touched
+    pub ground_material: SurfaceMaterial,
+    // Normal of the flattest ground touched, if any was
+    pub ground_normal: Vec2,
+}
+
+impl Contacts {
+    fn touch_ground(&mut self, normal: Vec2, material: SurfaceMaterial) {
+        if !self.on_ground || normal.y > self.ground_normal.y {
+            self.ground_normal = normal;
+        }
+        self.on_ground = true;
+        self.ground_material = material;
+    }
+}
+
+// The solid tiles of a level, prepared for resolving collisions against them
+pub struct SolidTiles {
+    // Segments enclosing a space follow a counter-clockwise convention
+    segments: HashSet<[i32; 4]>,
+    // Sorted so resolution does not depend on the order tiles were found in
+    tiles: Vec<(IVec2, ColliderShape)>,
+    // Only for tiles with a material other than the default
+    materials: HashMap<IVec2, SurfaceMaterial>,
+}
+
+impl SolidTiles {
+    // Currently assuming only 1x1 tiles
+    pub fn new(tiles: impl IntoIterator<Item = (IVec2, ColliderShape)>) -> Self {
+        let mut tiles: Vec<(IVec2, ColliderShape)> = tiles.into_iter().collect();
+        tiles.sort_unstable_by_key(|(cell, _)| (cell.x, cell.y));
+        tiles.dedup_by_key(|(cell, _)| *cell);
+
+        // Detect internal segments to be ignored
+        let mut segments = HashSet::<[i32; 4]>::with_capacity(4 * tiles.len());
+        for (base, shape) in tiles.iter() {
+            let (x, y) = (base.x, base.y);
+            let bottom = [x, y, x + 1, y];
+            let right = [x + 1, y, x + 1, y + 1];
+            let top = [x + 1, y + 1, x, y + 1];
+            let left = [x, y + 1, x, y];
+            let shape_segments = match shape {
+                ColliderShape::Aabb => vec![bottom, right, top, left],
+                ColliderShape::SlopeNE => vec![bottom, [x + 1, y, x, y + 1], left],
+                ColliderShape::SlopeNW => vec![bottom, right, [x + 1, y + 1, x, y]],
+                ColliderShape::SlopeSE => vec![[x, y, x + 1, y + 1], top, left],
+                ColliderShape::SlopeSW => vec![right, top, [x, y + 1, x + 1, y]],
+            };
+            segments.extend(shape_segments);
+        }
+
+        SolidTiles {
+            segments,
+            tiles,
+            materials: HashMap::default(),
+        }
+    }
+
+    // Give the tiles in these cells a material other than the default
+    pub fn with_materials(
+        mut self,
+        materials: impl IntoIterator<Item = (IVec2, SurfaceMaterial)>,
+    ) -> Self {
+        self.materials.extend(
+            materials
+                .into_iter()
+                .filter(|(_, material)| *material != SurfaceMaterial::default()),
+        );
+        self
+    }
+
+    // The segments not shared with a neighbouring tile, which bound the solid
+    // space and are all that collide, in a stable order
+    pub fn external_segments(&self) -> Vec<[i32; 4]> {
+        let mut external: Vec<[i32; 4]> = self
+            .segments
+            .iter()
+            .filter(|&&[x0, y0, x1, y1]| !self.segments.contains(&[x1, y1, x0, y0]))
+            .copied()
+            .collect();
+        external.sort_unstable();
+        external
+    }
+
+    // The external segments joined end to end into closed loops, each given
+    // by its corners, counter-clockwise around solid space. Where loops touch
+    // at a corner they may be joined into one, crossing there.
+    pub fn boundary_loops(&self) -> Vec<Vec<IVec2>> {
+        let mut next_points = HashMap::<IVec2, Vec<IVec2>>::default();
+        for [x0, y0, x1, y1] in self.external_segments() {
+            next_points
+                .entry(IVec2::new(x0, y0))
+                .or_default()
+                .push(IVec2::new(x1, y1));
+        }
+        let mut starts: Vec<IVec2> = next_points.keys().copied().collect();
+        starts.sort_unstable_by_key(|point| (point.x, point.y));
+
+        let mut loops = Vec::new();
+        for start in starts {
+            while let Some(mut point) = next_points.get_mut(&start).and_then(Vec::pop) {
+                let mut points = vec![start];
+                while point != start {
+                    points.push(point);
+                    point = match next_points.get_mut(&point).and_then(Vec::pop) {
+                        Some(next) => next,
+                        None => break,
+                    };
+                }
+                // Leave out the points partway along straight runs
+                let n = points.len();
+                loops.push(
+                    (0..n)
+                        .filter(|&i| {
+                            points[i] - points[(i + n - 1) % n] != points[(i + 1) % n] - points[i]
+                        })
+                        .map(|i| points[i])
+                        .collect(),
+                );
+            }
+        }
+        loops
+    }
+
+    // Push a box with its bottom-left corner at `translation` out of the tiles,
+    // cancelling the velocity into any surface it hits, or bouncing it back
+    // off a surface with restitution.
+    pub fn resolve(&self, translation: &mut Vec3, size: Vec2, velocity: &mut Vec3) -> Contacts {
+        let mut contacts = Contacts::default();
+        for (base, shape) in self.tiles.iter() {
+            let collision = collide(
+                *translation + 0.5 * size.extend(0.),
+                size,
+                base.as_vec2().extend(0.) + Vec3::new(0.5, 0.5, 0.),
+                Vec2::ONE,
+            );
+            let collision = match collision {
+                Some(collision) => collision,
+                None => continue,
+            };
+            let material = self.materials.get(base).copied().unwrap_or_default();
+            match shape {
+                ColliderShape::Aabb => self.resolve_side(
+                    collision,
+                    *base,
+                    material,
+                    translation,
+                    size,
+                    velocity,
+                    &mut contacts,
+                ),
+                _ => self.resolve_slope(
+                    collision,
+                    *base,
+                    *shape,
+                    material,
+                    translation,
+                    size,
+                    velocity,
+                    &mut contacts,
+                ),
+            }
+        }
+        contacts
+    }
+
+    // Whether growing a box at `translation` from `size` to `new_size` would
+    // push it into a tile. Only the added part is checked, so a box already
+    // touching a slope or wall can still grow away from it. Tiles are treated
+    // as full squares.
+    pub fn blocks_growth(&self, translation: Vec3, size: Vec2, new_size: Vec2) -> bool {
+        let min = translation.truncate();
+        let max = min + new_size;
+        // The part of the new box outside the old one, taking growth upwards
+        // or rightwards as the only cases, as the bottom-left corner is kept
+        let added = [
+            (Vec2::new(min.x, min.y + size.y), max),
+            (Vec2::new(min.x + size.x, min.y), max),
+        ];
+        self.tiles.iter().any(|(base, _)| {
+            let tile_min = base.as_vec2();
+            let tile_max = tile_min + Vec2::ONE;
+            added.iter().any(|(lo, hi)| {
+                lo.x < hi.x
+                    && lo.y < hi.y
+                    && lo.x < tile_max.x
+                    && tile_min.x < hi.x
+                    && lo.y < tile_max.y
+                    && tile_min.y < hi.y
+            })
+        })
+    }
+
+    // Push the box out through the side of the tile it hit, as if the tile were
+    // a full square. A segment is internal if there is another segment which is
+    // its inversion, and internal segments are ignored.
+    #[allow(clippy::too_many_arguments)]
+    fn resolve_side(
+        &self,
+        collision: Collision,
+        base: IVec2,
+        material: SurfaceMaterial,
+        translation: &mut Vec3,
+        size: Vec2,
+        velocity: &mut Vec3,
+        contacts: &mut Contacts,
+    ) {
+        let tile_pos = base.as_vec2();
+        match collision {
+            Collision::Left
+                if !self
+                    .segments
+                    .contains(&[base.x, base.y, base.x, base.y + 1]) =>
+            {
+                if velocity.x > 0.0 {
+                    velocity.x = material.bounce(velocity.x);
+                }
+                translation.x = tile_pos.x - size.x;
+                contacts.on_wall = true;
+                contacts.wall_side = Direction::Right;
+            }
+            Collision::Right
+                if !self
+                    .segments
+                    .contains(&[base.x + 1, base.y + 1, base.x + 1, base.y]) =>
+            {
+                if velocity.x < 0.0 {
+                    velocity.x = material.bounce(velocity.x);
+                }
+                translation.x = tile_pos.x + 1.;
+                contacts.on_wall = true;
+                contacts.wall_side = Direction::Left;
+            }
+            Collision::Top
+                if !self
+                    .segments
+                    .contains(&[base.x, base.y + 1, base.x + 1, base.y + 1]) =>
+            {
+                if velocity.y < 0.0 {
+                    velocity.y = material.bounce(velocity.y);
+                }
+                translation.y = tile_pos.y + 1.;
+                contacts.touch_ground(Vec2::Y, material);
+            }
+            Collision::Bottom
+                if !self
+                    .segments
+                    .contains(&[base.x + 1, base.y, base.x, base.y]) =>
+            {
+                if velocity.y > 0.0 {
+                    velocity.y = material.bounce(velocity.y);
+                }
+                translation.y = tile_pos.y - size.y;
+                contacts.on_ceiling = true;
+            }
+            _ => {}
+        }
+    }
+
+    // Hits on a slope's two straight sides resolve like a square tile. Anything
+    // else is a hit on the diagonal, which pushes the box vertically so it can
+    // walk up floor slopes and slide along ceiling slopes.
+    #[allow(clippy::too_many_arguments)]
+    fn resolve_slope(
+        &self,
+        collision: Collision,
+        base: IVec2,
+        shape: ColliderShape,
+        material: SurfaceMaterial,
+        translation: &mut Vec3,
+        size: Vec2,
+        velocity: &mut Vec3,
+        contacts: &mut Contacts,
+    ) {
+        let floor = matches!(shape, ColliderShape::SlopeNE | ColliderShape::SlopeNW);
+        // The straight sides are the flat bottom or top, and the full-height side
+        let flat_side = match collision {
+            Collision::Bottom => floor,
+            Collision::Top => !floor,
+            Collision::Left => matches!(shape, ColliderShape::SlopeNE | ColliderShape::SlopeSE),
+            Collision::Right => matches!(shape, ColliderShape::SlopeNW | ColliderShape::SlopeSW),
+            Collision::Inside => false,
+        };
+        if flat_side {
+            self.resolve_side(
+                collision,
+                base,
+                material,
+                translation,
+                size,
+                velocity,
+                contacts,
+            );
+            return;
+        }
+
+        // Height of the diagonal under (or over) the box's corner closest to
+        // the slope's high point
+        let tile_pos = base.as_vec2();
+        let corner_x = match shape {
+            ColliderShape::SlopeNE | ColliderShape::SlopeSE => translation.x,
+            _ => translation.x + size.x,
+        };
+        let x = (corner_x - tile_pos.x).clamp(0., 1.);
+        let surface = tile_pos.y
+            + match shape {
+                ColliderShape::SlopeNE | ColliderShape::SlopeSW => 1. - x,
+                _ => x,
+            };
+
+        if floor {
+            if translation.y < surface {
+                if velocity.y < 0.0 {
+                    velocity.y = material.bounce(velocity.y);
+                }
+                translation.y = surface;
+                let normal = match shape {
+                    ColliderShape::SlopeNE => Vec2::new(1., 1.),
+                    _ => Vec2::new(-1., 1.),
+                };
+                contacts.touch_ground(normal.normalize(), material);
+            }
+        } else if translation.y + size.y > surface {
+            if velocity.y > 0.0 {
+                velocity.y = material.bounce(velocity.y);
+            }
+            translation.y = surface - size.y;
+            contacts.on_ceiling = true;
+        }
+    }
+}
+
+// The bodies which collide with tiles, with their contacts and stance
+type CollidingBodyQuery<'w, 's> = Query<
+    'w,
+    's,
+    (
+        Entity,
+        &'static mut Transform,
+        &'static mut Velocity,
+        Option<&'static mut Mobility>,
+        Option<(&'static mut Pose, &'static StanceHitboxes)>,
+        Option<&'static CollisionLayers>,
+    ),
+    With<TileCollider>,
+>;
+
+// The solids bodies collide with, and what they're made of
+type SolidQuery<'w, 's> = Query<
+    'w,
+    's,
+    (
+        &'static Transform,
+        Option<&'static ColliderShape>,
+        Option<&'static SurfaceMaterial>,
+        Option<&'static CollisionLayers>,
+    ),
+    (With<SolidCollider>, Without<TileCollider>),
+>;
+
+pub fn tile_collision_system(
+    mut body_query: CollidingBodyQuery,
+    solid_query: SolidQuery,
+    stats: Option<ResMut<PhysicsStats>>,
+    mut landed_events: Option<ResMut<Events<Landed>>>,
+) {
+    let start = stats.as_ref().map(|_| Instant::now());
+    let cell = |transform: &Transform| transform.translation.truncate().round().as_ivec2();
+    let tile_layers = |layers: Option<&CollisionLayers>| {
+        layers
+            .copied()
+            .unwrap_or_else(|| CollisionLayers::on(CollisionLayers::TILES))
+    };
+    // The tiles each body's layers collide with. Most bodies share the same
+    // layers, so there are only ever a few of these to build.
+    let solids_for =
+        |layers: CollisionLayers| {
+            let colliding: Vec<_> = solid_query
+                .iter()
+                .filter(|(.., tile)| tile_layers(*tile).collides_with(&layers))
+                .collect();
+            SolidTiles::new(colliding.iter().map(|(transform, shape, ..)| {
+                (cell(transform), shape.copied().unwrap_or_default())
+            }))
+            .with_materials(colliding.iter().filter_map(
+                |(transform, _, material, _)| material.map(|material| (cell(transform), *material)),
+            ))
+        };
+    let mut solids_by_layers: HashMap<CollisionLayers, SolidTiles> = HashMap::default();
+
+    // Resolve bodies in a stable order so runs are reproducible
+    let mut bodies: Vec<Entity> = body_query.iter().map(|(entity, ..)| entity).collect();
+    bodies.sort_unstable_by_key(|entity| entity.id());
+
+    for entity in bodies {
+        let (_, mut transform, mut velocity, mut mobility, pose, layers) =
+            body_query.get_mut(entity).unwrap();
+        let layers = layers.copied().unwrap_or_default();
+        let solids = solids_by_layers
+            .entry(layers)
+            .or_insert_with(|| solids_for(layers));
+        let size = transform.scale.truncate();
+        let incoming = velocity.0;
+        let contacts = solids.resolve(&mut transform.translation, size, &mut velocity.0);
+        if let Some((mut pose, hitboxes)) = pose {
+            let new_size = hitboxes.size(pose.requested);
+            if pose.stance != pose.requested
+                && !solids.blocks_growth(transform.translation, size, new_size)
+            {
+                pose.stance = pose.requested;
+                transform.scale = new_size.extend(transform.scale.z);
+            }
+            if let Some(mobility) = &mut mobility {
+                mobility.crouching = pose.stance == Stance::Crouching;
+            }
+        }
+        if let Some(mobility) = &mut mobility {
+            let steep = contacts.on_ground && !mobility.can_stand_on(contacts.ground_normal);
+            if steep {
+                // Keep the part of the velocity along the slope, rather than
+                // only stopping the fall into it, so gravity slides the body
+                // down and walking into it is slowed
+                let normal = contacts.ground_normal;
+                let incoming = incoming.truncate();
+                velocity.0 = (incoming - incoming.dot(normal) * normal).extend(velocity.0.z);
+                mobility.sliding = true;
+            } else if contacts.on_ground || contacts.on_wall {
+                mobility.sliding = false;
+            }
+            let was_on_ground = mobility.on_ground;
+            mobility.on_ground = contacts.on_ground && !steep;
+            if let Some(landed_events) = &mut landed_events {
+                if mobility.on_ground && !was_on_ground {
+                    let size = transform.scale.truncate();
+                    landed_events.send(Landed {
+                        entity,
+                        at: transform.translation.truncate() + Vec2::new(size.x / 2., 0.),
+                        speed: -incoming.y,
+                    });
+                }
+            }
+            mobility.ground_material = contacts.ground_material;
+            if contacts.on_ground {
+                mobility.fast_falling = false;
+            }
+            if contacts.on_wall {
+                mobility.wall_coyote_timer = mobility.wall_coyote_time;
+                mobility.wall_side = contacts.wall_side;
+            } else {
+                mobility.wall_coyote_timer =
+                    (mobility.wall_coyote_timer - PHYSICS_TIME_STEP).max(0.);
+            }
+            mobility.on_wall = contacts.on_wall;
+            mobility.on_ceiling = contacts.on_ceiling;
+        }
+    }
+
+    if let (Some(mut stats), Some(start)) = (stats, start) {
+        stats.steps += 1;
+        stats.collision_time += start.elapsed();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn simulate() -> Vec<Vec3> {
+        let mut world = World::new();
+        // A floor with walls at both ends
+        for x in -6..=6 {
+            world
+                .spawn()
+                .insert_bundle((Transform::from_xyz(x as f32, 0., 0.), SolidCollider));
+        }
+        for y in 1..10 {
+            for x in [-6, 6] {
+                world
+                    .spawn()
+                    .insert_bundle((Transform::from_xyz(x as f32, y as f32, 0.), SolidCollider));
+            }
+        }
+        let bodies: Vec<Entity> = (0..5)
+            .map(|i| {
+                world
+                    .spawn()
+                    .insert_bundle((
+                        Transform {
+                            translation: Vec3::new(-4. + 2. * i as f32, 2. + i as f32, 0.),
+                            scale: Vec3::new(1., 1. + 0.5 * i as f32, 1.),
+                            ..default()
+                        },
+                        Velocity(Vec3::new(3. * (i as f32 - 2.), 0., 0.)),
+                        Gravity(GRAVITY),
+                        TileCollider,
+                    ))
+                    .id()
+            })
+            .collect();
+
+        let mut stage = SystemStage::single_threaded().with_system_set(physics_system_set());
+        for _ in 0..480 {
+            stage.run(&mut world);
+        }
+
+        bodies
+            .into_iter()
+            .map(|entity| world.get::<Transform>(entity).unwrap().translation)
+            .collect()
+    }
+
+    #[test]
+    fn multiple_bodies_resolve_deterministically() {
+        let first = simulate();
+        for _ in 0..3 {
+            assert_eq!(simulate(), first);
+        }
+        // Everything came to rest on the floor, inside the walls
+        for translation in first {
+            assert_eq!(translation.y, 1.);
+            assert!(translation.x >= -5. && translation.x <= 5.);
+        }
+    }
+
+    #[test]
+    fn bodies_pass_through_tiles_outside_their_masks() {
+        let mut world = World::new();
+        // A barrier only enemies are stopped by, over a floor for everything
+        for (y, layers) in [
+            (0, CollisionLayers::on(CollisionLayers::TILES)),
+            (
+                3,
+                CollisionLayers::new(CollisionLayers::TILES, CollisionLayers::ENEMIES),
+            ),
+        ] {
+            for x in -2..=2 {
+                world.spawn().insert_bundle((
+                    Transform::from_xyz(x as f32, y as f32, 0.),
+                    SolidCollider,
+                    layers,
+                ));
+            }
+        }
+        let mut spawn_body = |layers| {
+            world
+                .spawn()
+                .insert_bundle((
+                    Transform::from_xyz(0., 6., 0.),
+                    Velocity(Vec3::ZERO),
+                    Gravity(GRAVITY),
+                    TileCollider,
+                    layers,
+                ))
+                .id()
+        };
+        let player = spawn_body(CollisionLayers::on(CollisionLayers::PLAYERS));
+        let enemy = spawn_body(CollisionLayers::on(CollisionLayers::ENEMIES));
+        // Ignores the floor as well
+        let ghost = spawn_body(CollisionLayers::new(CollisionLayers::PLAYERS, 0));
+
+        let mut stage = SystemStage::single_threaded().with_system_set(physics_system_set());
+        for _ in 0..240 {
+            stage.run(&mut world);
+        }
+        let height = |entity| world.get::<Transform>(entity).unwrap().translation.y;
+        assert_eq!(height(enemy), 4.);
+        assert_eq!(height(player), 1.);
+        assert!(height(ghost) < 0.);
+    }
+
+    // Fall in open space for `steps` steps, returning the y velocity
+    fn fall(world: &mut World, entity: Entity, steps: usize) -> f32 {
+        let mut stage = SystemStage::single_threaded().with_system_set(physics_system_set());
+        for _ in 0..steps {
+            stage.run(world);
+        }
+        world.get::<Velocity>(entity).unwrap().0.y
+    }
+
+    fn spawn_faller(world: &mut World, gravity: f32) -> Entity {
+        world
+            .spawn()
+            .insert_bundle((Transform::default(), Velocity(Vec3::ZERO), Gravity(gravity)))
+            .id()
+    }
+
+    #[test]
+    fn entities_fall_at_their_own_gravity() {
+        let mut world = World::new();
+        let floaty = spawn_faller(&mut world, 5.);
+        let player = spawn_faller(&mut world, GRAVITY);
+        let heavy = spawn_faller(&mut world, 3. * GRAVITY);
+        let unaffected = world
+            .spawn()
+            .insert_bundle((Transform::default(), Velocity(Vec3::ZERO)))
+            .id();
+        fall(&mut world, player, 240);
+
+        let velocity = |entity| world.get::<Velocity>(entity).unwrap().0.y;
+        assert!((velocity(floaty) + 5.).abs() < 1e-3);
+        assert!((velocity(player) + GRAVITY).abs() < 1e-3);
+        assert!((velocity(heavy) + 3. * GRAVITY).abs() < 1e-3);
+        assert_eq!(velocity(unaffected), 0.);
+    }
+
+    #[test]
+    fn gravity_scale_applies_only_while_present() {
+        let mut world = World::new();
+        let body = spawn_faller(&mut world, GRAVITY);
+        let reference = spawn_faller(&mut world, GRAVITY);
+
+        world.entity_mut(body).insert(GravityScale(2.));
+        fall(&mut world, body, 120);
+        world.entity_mut(body).remove::<GravityScale>();
+        fall(&mut world, body, 120);
+
+        let velocity = |entity| world.get::<Velocity>(entity).unwrap().0.y;
+        // Half a second at double gravity, then half a second at normal gravity
+        assert!((velocity(body) + 1.5 * GRAVITY).abs() < 1e-3);
+        assert!((velocity(reference) + GRAVITY).abs() < 1e-3);
+    }
+
+    #[test]
+    fn fast_fall_is_capped_by_terminal_velocity() {
+        let mut world = World::new();
+        let body = spawn_faller(&mut world, GRAVITY);
+        world.entity_mut(body).insert_bundle((
+            Mobility {
+                fast_falling: true,
+                ..default()
+            },
+            TerminalVelocity(2. * GRAVITY),
+        ));
+        let speed = -fall(&mut world, body, 60);
+        assert!((speed - FAST_FALL_GRAVITY_SCALE * GRAVITY / 4.).abs() < 1e-3);
+        assert_eq!(fall(&mut world, body, 240), -2. * GRAVITY);
+    }
+
+    #[test]
+    fn apex_gravity_applies_near_the_top_unless_fast_falling() {
+        let mut world = World::new();
+        let mobility = |fast_falling| Mobility {
+            fast_falling,
+            apex_gravity_scale: 0.5,
+            apex_threshold: 5.,
+            ..default()
+        };
+        let floating = spawn_faller(&mut world, GRAVITY);
+        world.entity_mut(floating).insert(mobility(false));
+        let fast_falling = spawn_faller(&mut world, GRAVITY);
+        world.entity_mut(fast_falling).insert(mobility(true));
+
+        fall(&mut world, floating, 240);
+
+        let velocity = |entity| world.get::<Velocity>(entity).unwrap().0.y;
+        // A third of a second at half gravity to leave the apex, then the
+        // rest at full gravity
+        assert!((velocity(floating) + 25.).abs() < 0.1);
+        assert!((velocity(fast_falling) + FAST_FALL_GRAVITY_SCALE * GRAVITY).abs() < 1e-3);
+    }
+
+    #[test]
+    fn fall_gravity_makes_the_descent_quicker_than_the_rise() {
+        let mut world = World::new();
+        let body = spawn_faller(&mut world, GRAVITY);
+        world.entity_mut(body).insert(Mobility {
+            fall_gravity_scale: 2.,
+            ..default()
+        });
+        world.get_mut::<Velocity>(body).unwrap().0.y = GRAVITY / 2.;
+        let mut stage = SystemStage::single_threaded().with_system_set(physics_system_set());
+        let height = |world: &World| world.get::<Transform>(body).unwrap().translation.y;
+        let (mut peak, mut peaked_at, mut steps) = (0f32, 0, 0);
+        loop {
+            stage.run(&mut world);
+            steps += 1;
+            if height(&world) > peak {
+                peak = height(&world);
+                peaked_at = steps;
+            }
+            if height(&world) < 0. {
+                break;
+            }
+        }
+        // The rise is as high and as long as under normal gravity, half a
+        // second, and the fall at double gravity takes 1/sqrt(2) as long
+        assert!((peak - GRAVITY / 8.).abs() < 0.1, "peaked at {}", peak);
+        assert!((peaked_at - 120i32).abs() <= 1);
+        let fall_steps = (steps - peaked_at) as f32;
+        assert!(
+            (fall_steps - 120. / 2f32.sqrt()).abs() < 2.,
+            "fell in {}",
+            fall_steps
+        );
+    }
+
+    // Step a walking body through `solids` without the ECS
+    fn walk(solids: &SolidTiles, translation: &mut Vec3, walk_velocity: f32, steps: usize) {
+        let mut velocity = Vec3::ZERO;
+        for _ in 0..steps {
+            velocity.x = walk_velocity;
+            velocity.y -= GRAVITY * PHYSICS_TIME_STEP;
+            *translation += velocity * PHYSICS_TIME_STEP;
+            solids.resolve(translation, Vec2::new(1., 2.), &mut velocity);
+        }
+    }
+
+    #[test]
+    fn walks_up_floor_slope() {
+        // A floor with a slope up to a one tile step
+        let mut tiles: Vec<(IVec2, ColliderShape)> = (-3..=4)
+            .map(|x| (IVec2::new(x, 0), ColliderShape::Aabb))
+            .collect();
+        tiles.push((IVec2::new(1, 1), ColliderShape::SlopeNW));
+        tiles.push((IVec2::new(2, 1), ColliderShape::Aabb));
+        tiles.push((IVec2::new(3, 1), ColliderShape::Aabb));
+        let solids = SolidTiles::new(tiles);
+
+        let mut translation = Vec3::new(-2., 1., 0.);
+        walk(&solids, &mut translation, 5., 240);
+        assert!(translation.x > 2.5, "stopped at {:?}", translation);
+        assert!((translation.y - 2.).abs() < 1e-3, "{:?}", translation);
+
+        // Without the slope the step is a wall
+        let solids = SolidTiles::new(
+            (-3..=4)
+                .map(|x| (IVec2::new(x, 0), ColliderShape::Aabb))
+                .chain([(IVec2::new(2, 1), ColliderShape::Aabb)]),
+        );
+        let mut translation = Vec3::new(-2., 1., 0.);
+        walk(&solids, &mut translation, 5., 240);
+        assert_eq!(translation, Vec3::new(1., 1., 0.));
+    }
+
+    #[test]
+    fn running_builds_up_and_is_kept_in_the_air() {
+        let mut mobility = Mobility {
+            on_ground: true,
+            walk_speed: 10.,
+            walk_direction: Direction::Right,
+            run_multiplier: 1.6,
+            ..default()
+        };
+        let dt = PHYSICS_TIME_STEP;
+        let mut speed = 0.;
+        speed = mobility.walk_speed_after(speed, true, dt);
+        assert!(speed > 10. && speed < 10.5);
+        for _ in 0..240 {
+            speed = mobility.walk_speed_after(speed, true, dt);
+        }
+        assert_eq!(speed, 16.);
+
+        mobility.on_ground = false;
+        assert_eq!(mobility.walk_speed_after(speed, false, dt), 16.);
+
+        mobility.on_ground = true;
+        assert!(mobility.walk_speed_after(speed, false, dt) < 16.);
+        assert_eq!(mobility.walk_speed_after(0., false, dt), 10.);
+    }
+
+    #[test]
+    fn crouching_replaces_walking_and_running() {
+        let mut mobility = Mobility {
+            on_ground: true,
+            walk_speed: 10.,
+            walk_direction: Direction::Right,
+            run_multiplier: 1.6,
+            crouching: true,
+            crouch_speed: 4.,
+            ..default()
+        };
+        let dt = PHYSICS_TIME_STEP;
+        assert_eq!(mobility.walk_velocity_after(16., 1., true, dt), 4.);
+        assert_eq!(mobility.walk_velocity_after(0., -1., false, dt), -4.);
+        mobility.crouching = false;
+        assert_eq!(mobility.walk_velocity_after(4., 1., false, dt), 10.);
+    }
+
+    #[test]
+    fn low_friction_ground_slips() {
+        let mut mobility = Mobility {
+            on_ground: true,
+            walk_speed: 10.,
+            walk_direction: Direction::Right,
+            ..default()
+        };
+        let dt = PHYSICS_TIME_STEP;
+        // Normal ground grips at once, both ways
+        assert_eq!(mobility.walk_velocity_after(0., 1., false, dt), 10.);
+        assert_eq!(mobility.walk_velocity_after(10., 0., false, dt), 0.);
+
+        mobility.ground_material = SurfaceMaterial::ICE;
+        let mut speed = mobility.walk_velocity_after(0., 1., false, dt);
+        assert!(speed > 0. && speed < 0.1, "{}", speed);
+        for _ in 0..240 {
+            speed = mobility.walk_velocity_after(speed, 1., false, dt);
+        }
+        assert_eq!(speed, 10.);
+        // Letting go slides on rather than stopping
+        let sliding = mobility.walk_velocity_after(speed, 0., false, dt);
+        assert!(sliding > 9.9 && sliding < 10., "{}", sliding);
+
+        // Ice only matters underfoot
+        mobility.on_ground = false;
+        assert_eq!(mobility.walk_velocity_after(0., 1., false, dt), 10.);
+    }
+
+    #[test]
+    fn bouncy_surface_sends_bodies_back_up() {
+        let mut world = World::new();
+        world.spawn().insert_bundle((
+            Transform::from_xyz(0., 0., 0.),
+            SolidCollider,
+            SurfaceMaterial::TRAMPOLINE,
+        ));
+        world
+            .spawn()
+            .insert_bundle((Transform::from_xyz(3., 0., 0.), SolidCollider));
+        let drop = |world: &mut World, x: f32| {
+            world
+                .spawn()
+                .insert_bundle((
+                    Transform::from_xyz(x, 4., 0.),
+                    Velocity(Vec3::ZERO),
+                    Gravity(GRAVITY),
+                    TileCollider,
+                ))
+                .id()
+        };
+        let bouncy = drop(&mut world, 0.);
+        let plain = drop(&mut world, 3.);
+
+        // Falling 3 tiles takes under half a second
+        let mut stage = SystemStage::single_threaded().with_system_set(physics_system_set());
+        let mut bounced = false;
+        for _ in 0..120 {
+            stage.run(&mut world);
+            bounced |= world.get::<Velocity>(bouncy).unwrap().0.y > 5.;
+            assert!(world.get::<Velocity>(plain).unwrap().0.y <= 0.);
+        }
+        assert!(bounced);
+        assert_eq!(world.get::<Transform>(plain).unwrap().translation.y, 1.);
+    }
+
+    #[test]
+    fn standing_up_is_blocked_by_a_low_ceiling() {
+        let mut world = World::new();
+        // A one tile high tunnel, open on the right
+        for x in -2..=4 {
+            world
+                .spawn()
+                .insert_bundle((Transform::from_xyz(x as f32, 0., 0.), SolidCollider));
+        }
+        for x in -2..=1 {
+            world
+                .spawn()
+                .insert_bundle((Transform::from_xyz(x as f32, 2., 0.), SolidCollider));
+        }
+        let body = world
+            .spawn()
+            .insert_bundle((
+                Transform {
+                    translation: Vec3::new(0., 1., 0.),
+                    scale: Vec3::new(1., 1., 1.),
+                    ..default()
+                },
+                Velocity(Vec3::ZERO),
+                Gravity(GRAVITY),
+                TileCollider,
+                Pose {
+                    stance: Stance::Crouching,
+                    requested: Stance::Standing,
+                },
+                StanceHitboxes {
+                    standing: Vec2::new(1., 2.),
+                    crouching: Vec2::new(1., 1.),
+                },
+            ))
+            .id();
+
+        let mut stage = SystemStage::single_threaded().with_system_set(physics_system_set());
+        for _ in 0..10 {
+            stage.run(&mut world);
+        }
+        assert_eq!(world.get::<Pose>(body).unwrap().stance, Stance::Crouching);
+        assert_eq!(world.get::<Transform>(body).unwrap().scale.y, 1.);
+
+        // Out from under the ceiling
+        world.get_mut::<Transform>(body).unwrap().translation.x = 3.;
+        stage.run(&mut world);
+        assert_eq!(world.get::<Pose>(body).unwrap().stance, Stance::Standing);
+        let transform = world.get::<Transform>(body).unwrap();
+        assert_eq!(transform.scale.y, 2.);
+        assert_eq!(transform.translation.y, 1.);
+    }
+
+    #[test]
+    fn pushing_into_a_wall_is_a_wall_contact() {
+        let solids = SolidTiles::new((0..3).map(|y| (IVec2::new(1, y), ColliderShape::Aabb)));
+        let mut translation = Vec3::new(0.1, 0.5, 0.);
+        let mut velocity = Vec3::new(5., -1., 0.);
+        let contacts = solids.resolve(&mut translation, Vec2::new(1., 2.), &mut velocity);
+        assert!(contacts.on_wall);
+        assert!(!contacts.on_ground);
+        assert_eq!(translation.x, 0.);
+
+        // Walking away, the wall is no longer touched
+        let mut velocity = Vec3::new(-5., -1., 0.);
+        translation.x -= 0.1;
+        let contacts = solids.resolve(&mut translation, Vec2::new(1., 2.), &mut velocity);
+        assert!(!contacts.on_wall);
+    }
+
+    #[test]
+    fn boundary_loops_follow_the_outside_of_merged_tiles() {
+        // A 3x2 block with a slope falling away to its right, and a lone tile
+        // apart
+        let mut tiles: Vec<(IVec2, ColliderShape)> = (0..3)
+            .flat_map(|x| (0..2).map(move |y| (IVec2::new(x, y), ColliderShape::Aabb)))
+            .collect();
+        tiles.push((IVec2::new(3, 0), ColliderShape::SlopeNE));
+        tiles.push((IVec2::new(6, 5), ColliderShape::Aabb));
+        let solids = SolidTiles::new(tiles);
+        assert_eq!(solids.external_segments().len(), 3 + 2 + 1 + 3 + 2 + 4);
+
+        let mut loops = solids.boundary_loops();
+        loops.sort_unstable_by_key(|points| points.len());
+        let corners = |points: &[(i32, i32)]| {
+            points
+                .iter()
+                .map(|&(x, y)| IVec2::new(x, y))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(loops[0], corners(&[(6, 5), (7, 5), (7, 6), (6, 6)]));
+        assert_eq!(loops[1], corners(&[(0, 0), (4, 0), (3, 1), (3, 2), (0, 2)]));
+    }
+
+    #[test]
+    fn ceiling_slope_pushes_down() {
+        let solids = SolidTiles::new([(IVec2::new(0, 3), ColliderShape::SlopeSE)]);
+        // Rising into the low end of the slope's underside
+        let mut translation = Vec3::new(0.5, 1.7, 0.);
+        let mut velocity = Vec3::new(0., 5., 0.);
+        let contacts = solids.resolve(&mut translation, Vec2::new(1., 2.), &mut velocity);
+        assert!(!contacts.on_ground);
+        assert_eq!(velocity.y, 0.);
+        assert!((translation.y - 1.5).abs() < 1e-5, "{:?}", translation);
+    }
+
+    // A body standing on a slope rising to the right, from a floor at y = 1
+    // to a ledge at y = 2, returning it after `steps` steps
+    fn stand_on_slope(max_walkable_slope: f32, velocity: Vec3, steps: usize) -> (Vec3, Mobility) {
+        let mut world = World::new();
+        for x in -20..=8 {
+            world
+                .spawn()
+                .insert_bundle((Transform::from_xyz(x as f32, 0., 0.), SolidCollider));
+        }
+        world.spawn().insert_bundle((
+            Transform::from_xyz(3., 1., 0.),
+            SolidCollider,
+            ColliderShape::SlopeNW,
+        ));
+        for x in 4..=8 {
+            world
+                .spawn()
+                .insert_bundle((Transform::from_xyz(x as f32, 1., 0.), SolidCollider));
+        }
+        let body = world
+            .spawn()
+            .insert_bundle((
+                Transform {
+                    // Halfway up the slope
+                    translation: Vec3::new(2.5, 1.5, 0.),
+                    scale: Vec3::new(1., 2., 1.),
+                    ..default()
+                },
+                Velocity(velocity),
+                Gravity(GRAVITY),
+                TileCollider,
+                Mobility {
+                    max_walkable_slope,
+                    ..default()
+                },
+            ))
+            .id();
+        let mut stage = SystemStage::single_threaded().with_system_set(physics_system_set());
+        for _ in 0..steps {
+            stage.run(&mut world);
+        }
+        let mut body = world.entity_mut(body);
+        let translation = body.get::<Transform>().unwrap().translation;
+        (translation, body.remove::<Mobility>().unwrap())
+    }
+
+    #[test]
+    fn walkable_slope_holds_still_and_can_be_climbed() {
+        let (translation, mobility) = stand_on_slope(50f32.to_radians(), Vec3::ZERO, 60);
+        assert_eq!(translation, Vec3::new(2.5, 1.5, 0.));
+        assert!(mobility.on_ground);
+        assert!(!mobility.sliding);
+
+        // Walking right carries on up onto the ledge
+        let (translation, mobility) = stand_on_slope(50f32.to_radians(), Vec3::X * 5., 120);
+        assert!(translation.x > 4., "{:?}", translation);
+        assert_eq!(translation.y, 2.);
+        assert!(mobility.on_ground);
+    }
+
+    #[test]
+    fn too_steep_slope_slides_down() {
+        // Sliding, so not standing
+        let (translation, mobility) = stand_on_slope(30f32.to_radians(), Vec3::ZERO, 10);
+        assert!(translation.x < 2.5, "{:?}", translation);
+        assert!(mobility.sliding);
+        assert!(!mobility.on_ground);
+
+        // Down at the bottom, where the floor is walkable again
+        let (translation, mobility) = stand_on_slope(30f32.to_radians(), Vec3::ZERO, 120);
+        assert!(translation.x < 2., "{:?}", translation);
+        assert_eq!(translation.y, 1.);
+        assert!(mobility.on_ground);
+        assert!(!mobility.sliding);
+
+        // Heading up, the slide turns the body back before the ledge
+        let (translation, _) = stand_on_slope(30f32.to_radians(), Vec3::X * 5., 120);
+        assert!(translation.x < 2.5, "{:?}", translation);
+    }
+}
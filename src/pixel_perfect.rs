@@ -3,6 +3,10 @@
 // 1. First the 2d renderer renders all sprites to a texture with the
 // desired pixel dimensions.
 // 2. Then the 3d renderer renders a quad covering the screen with the texture
+// The world is drawn by `WorldCamera`, followed by `WorldUiCamera` which draws
+// world-anchored UI (damage numbers, prompts, editor overlays) on top of it at
+// one unit per pixel, so it stays crisp however the world camera is scaled.
+// bevy_ui is drawn straight to the window by its own camera.
 // This is just a hacky stopgap until bevy adds better support for this
 // use case. Hopefully 0.7's render target improvements will let most
 // of this code go away.
@@ -16,34 +20,225 @@ use bevy::{
         render_graph::{Node, NodeRunError, RenderGraph, RenderGraphContext, SlotValue},
         render_phase::RenderPhase,
         render_resource::{
-            Extent3d, TextureDescriptor, TextureDimension, TextureFormat, TextureUsages,
+            Extent3d, PipelineCache, TextureDescriptor, TextureDimension, TextureFormat,
+            TextureUsages,
         },
         renderer::RenderContext,
+        texture::BevyDefault,
+        view::RenderLayers,
         RenderApp, RenderStage,
     },
+    transform::TransformSystem,
 };
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+// Font for text drawn by bevy_ui
+pub const UI_FONT: &str = "fonts/FiraMono-Medium.ttf";
 
 pub const PIXELS_PER_TILE: u32 = 16;
 pub const WIDTH_PIXELS: u32 = PIXELS_PER_TILE * 2 * 16;
 pub const HEIGHT_PIXELS: u32 = PIXELS_PER_TILE * 2 * 9;
 
+// Within this of its target, an easing zoom snaps the rest of the way
+const ZOOM_SNAP_DISTANCE: f32 = 0.001;
+
+// Render layers of the world render target. Entities without a `RenderLayers`
+// component are on layer 0 and drawn by the world camera.
+pub const WORLD_LAYER: u8 = 0;
+pub const WORLD_UI_LAYER: u8 = 1;
+
 #[derive(Component, Default)]
 pub struct WorldCamera;
 
+// How far the world camera is zoomed in, 1 drawing `PIXELS_PER_TILE` pixels
+// per tile. Setting `target` eases the camera to it rather than snapping,
+// unless `snap` is called too. Zooms other than whole numbers and their
+// reciprocals draw tiles at uneven sizes, so are best kept to transitions.
+pub struct CameraZoom {
+    current: f32,
+    pub target: f32,
+    // How quickly the zoom closes in on the target: the fraction of the
+    // remaining difference closed in 1/speed seconds is 1 - 1/e
+    pub speed: f32,
+}
+
+impl Default for CameraZoom {
+    fn default() -> Self {
+        CameraZoom {
+            current: 1.,
+            target: 1.,
+            speed: 8.,
+        }
+    }
+}
+
+impl CameraZoom {
+    // The zoom the camera is drawn at, partway to the target while easing
+    pub fn current(&self) -> f32 {
+        self.current
+    }
+
+    // Half the size of the view in tiles, at the zoom it's drawn at
+    pub fn view_half_extent(&self) -> Vec2 {
+        Vec2::new(WIDTH_PIXELS as f32, HEIGHT_PIXELS as f32)
+            / (2. * PIXELS_PER_TILE as f32 * self.current)
+    }
+
+    // Jump straight to the target
+    pub fn snap(&mut self) {
+        self.current = self.target;
+    }
+
+    // Ease towards the target for `dt` seconds
+    pub fn advance(&mut self, dt: f32) {
+        let remaining = self.target - self.current;
+        if remaining.abs() <= ZOOM_SNAP_DISTANCE {
+            self.current = self.target;
+        } else {
+            // Framerate independent, unlike a fixed fraction per frame
+            self.current += remaining * (1. - (-self.speed * dt).exp());
+        }
+    }
+}
+
+// Draws `WORLD_UI_LAYER` over the world. It stays at the origin with one unit
+// per pixel; `WorldAnchor` entities are placed relative to the world camera.
+#[derive(Component, Default)]
+pub struct WorldUiCamera;
+
+// World point an entity on `WORLD_UI_LAYER` is drawn at. Its translation is
+// overwritten every frame with the matching pixel position, and its size is
+// in pixels regardless of the world camera's scale.
+#[derive(Component, Clone, Copy, Default)]
+pub struct WorldAnchor(pub Vec2);
+
+// Add to a sprite or text entity to put it on the world UI layer
+#[derive(Bundle)]
+pub struct WorldUiBundle {
+    pub anchor: WorldAnchor,
+    pub render_layers: RenderLayers,
+}
+
+impl WorldUiBundle {
+    pub fn new(anchor: Vec2) -> Self {
+        WorldUiBundle {
+            anchor: WorldAnchor(anchor),
+            render_layers: RenderLayers::layer(WORLD_UI_LAYER),
+        }
+    }
+}
+
+// Spawn a sprite drawn at `anchor`. The sprite's size is in pixels.
+pub fn spawn_world_ui_sprite(
+    commands: &mut Commands,
+    anchor: Vec2,
+    sprite: SpriteBundle,
+) -> Entity {
+    commands
+        .spawn_bundle(sprite)
+        .insert_bundle(WorldUiBundle::new(anchor))
+        .id()
+}
+
+// Spawn text drawn at `anchor`. Font sizes are in pixels.
+pub fn spawn_world_ui_text(commands: &mut Commands, anchor: Vec2, text: Text) -> Entity {
+    commands
+        .spawn_bundle(Text2dBundle { text, ..default() })
+        .insert_bundle(WorldUiBundle::new(anchor))
+        .id()
+}
+
+// Background of the world render target. The letterbox area around the
+// upscaled quad is cleared with bevy's `ClearColor` instead.
+#[derive(Clone, Copy)]
+pub struct WorldClearColor(pub Color);
+
+impl Default for WorldClearColor {
+    fn default() -> Self {
+        WorldClearColor(Color::BLACK)
+    }
+}
+
+// The image the world camera renders to, inserted by the plugin's startup
+// system. The handle is strong and the resource is never removed, so the image
+// lives as long as the app. It is always WIDTH_PIXELS x HEIGHT_PIXELS: window
+// resizes and scale changes only affect how it is displayed, never the image
+// itself.
+pub struct WorldRenderTarget {
+    pub image: Handle<Image>,
+}
+
+// Sent once, on the first frame after the world camera has drawn to the world
+// render target with all of its pipelines compiled. Until then the target only
+// holds its clear color, so loading screens and fade-ins can wait for this.
+pub struct RenderPipelineReady;
+
+// Set by the render app's first pass node, read by the main app
+#[derive(Clone, Default)]
+struct FirstPassDrawn(Arc<AtomicBool>);
+
+impl FirstPassDrawn {
+    fn get(&self) -> bool {
+        self.0.load(Ordering::Acquire)
+    }
+}
+
 // The name of the final node of the first pass.
 pub const FIRST_PASS_DRIVER: &str = "first_pass_driver";
 
-#[derive(Default)]
-pub struct PixelPerfectPlugin;
+// The world render target is always in `TextureFormat::bevy_default()`,
+// Rgba8UnormSrgb on wasm and android and Bgra8UnormSrgb elsewhere. bevy 0.7
+// builds its 2d pipelines for that format only, so there's no choosing
+// another until they follow their target's format.
+pub struct PixelPerfectPlugin {
+    // Whether to spawn the 3d camera and quad which display the world render
+    // target on the window. Without them the host app is responsible for
+    // displaying `WorldRenderTarget::image` however it likes.
+    pub manage_upscale: bool,
+}
+
+impl Default for PixelPerfectPlugin {
+    fn default() -> Self {
+        PixelPerfectPlugin {
+            manage_upscale: true,
+        }
+    }
+}
+
+// The plugin's settings
+struct PixelPerfectConfig {
+    manage_upscale: bool,
+}
 
 impl Plugin for PixelPerfectPlugin {
     fn build(&self, app: &mut App) {
-        app.insert_resource(Msaa { samples: 1 }) // Use 4x MSAA
-            .add_plugin(CameraTypePlugin::<WorldCamera>::default())
-            .add_startup_system(setup);
+        let first_pass_drawn = FirstPassDrawn::default();
+        app.insert_resource(PixelPerfectConfig {
+            manage_upscale: self.manage_upscale,
+        })
+        .insert_resource(Msaa { samples: 1 }) // Use 4x MSAA
+        .insert_resource(first_pass_drawn.clone())
+        .add_event::<RenderPipelineReady>()
+        .init_resource::<WorldClearColor>()
+        .init_resource::<CameraZoom>()
+        .add_plugin(CameraTypePlugin::<WorldCamera>::default())
+        .add_plugin(CameraTypePlugin::<WorldUiCamera>::default())
+        .add_startup_system(setup)
+        .add_system_to_stage(CoreStage::PostUpdate, update_world_clear_color_system)
+        .add_system_to_stage(CoreStage::PostUpdate, camera_zoom_system)
+        .add_system_to_stage(
+            CoreStage::PostUpdate,
+            world_anchor_system
+                .after(camera_zoom_system)
+                .before(TransformSystem::TransformPropagate),
+        )
+        .add_system_to_stage(CoreStage::First, render_pipeline_ready_system);
 
         let render_app = app.sub_app_mut(RenderApp);
-        let driver = WorldCameraDriver::new(&mut render_app.world);
+        let driver = WorldCameraDriver::new(&mut render_app.world, first_pass_drawn);
         render_app.add_system_to_stage(RenderStage::Extract, extract_first_pass_camera_phases);
 
         let mut graph = render_app.world.resource_mut::<RenderGraph>();
@@ -69,8 +264,9 @@ impl Plugin for PixelPerfectPlugin {
 fn extract_first_pass_camera_phases(
     mut commands: Commands,
     active: Res<ActiveCamera<WorldCamera>>,
+    active_ui: Res<ActiveCamera<WorldUiCamera>>,
 ) {
-    if let Some(entity) = active.get() {
+    for entity in [active.get(), active_ui.get()].into_iter().flatten() {
         commands
             .get_or_spawn(entity)
             .insert_bundle((RenderPhase::<Transparent2d>::default(),));
@@ -79,18 +275,23 @@ fn extract_first_pass_camera_phases(
 
 struct WorldCameraDriver {
     query: QueryState<Entity, With<WorldCamera>>,
+    ui_query: QueryState<Entity, With<WorldUiCamera>>,
+    drawn: FirstPassDrawn,
 }
 
 impl WorldCameraDriver {
-    pub fn new(render_world: &mut World) -> Self {
+    fn new(render_world: &mut World, drawn: FirstPassDrawn) -> Self {
         Self {
             query: QueryState::new(render_world),
+            ui_query: QueryState::new(render_world),
+            drawn,
         }
     }
 }
 impl Node for WorldCameraDriver {
     fn update(&mut self, world: &mut World) {
         self.query.update_archetypes(world);
+        self.ui_query.update_archetypes(world);
     }
 
     fn run(
@@ -99,9 +300,33 @@ impl Node for WorldCameraDriver {
         _render_context: &mut RenderContext,
         world: &World,
     ) -> Result<(), NodeRunError> {
-        for camera in self.query.iter_manual(world) {
+        // The 2d main pass loads rather than clears its target, so the UI
+        // camera draws over what the world camera drew
+        for camera in self
+            .query
+            .iter_manual(world)
+            .chain(self.ui_query.iter_manual(world))
+        {
             graph.run_sub_graph(draw_2d_graph::NAME, vec![SlotValue::Entity(camera)])?;
         }
+
+        // Items whose pipeline is still compiling are skipped when drawing
+        if !self.drawn.get() {
+            let pipeline_cache = world.resource::<PipelineCache>();
+            for camera in self.query.iter_manual(world) {
+                let ready = world
+                    .get::<RenderPhase<Transparent2d>>(camera)
+                    .is_some_and(|phase| {
+                        phase
+                            .items
+                            .iter()
+                            .all(|item| pipeline_cache.get_render_pipeline(item.pipeline).is_some())
+                    });
+                if ready {
+                    self.drawn.0.store(true, Ordering::Release);
+                }
+            }
+        }
         Ok(())
     }
 }
@@ -112,6 +337,8 @@ fn setup(
     mut materials: ResMut<Assets<StandardMaterial>>,
     mut images: ResMut<Assets<Image>>,
     mut clear_colors: ResMut<RenderTargetClearColors>,
+    clear_color: Res<WorldClearColor>,
+    config: Res<PixelPerfectConfig>,
 ) {
     let size = Extent3d {
         width: WIDTH_PIXELS,
@@ -125,11 +352,7 @@ fn setup(
             label: None,
             size,
             dimension: TextureDimension::D2,
-            format: if cfg!(target_arch = "wasm32") {
-                TextureFormat::Rgba8UnormSrgb
-            } else {
-                TextureFormat::Bgra8UnormSrgb
-            },
+            format: TextureFormat::bevy_default(),
             mip_level_count: 1,
             sample_count: 1,
             usage: TextureUsages::TEXTURE_BINDING
@@ -139,28 +362,53 @@ fn setup(
         ..default()
     };
 
-    // fill image.data with zeroes
+    // Start out with the clear color so nothing else is ever displayed
     image.resize(size);
+    fill_with_color(&mut image, clear_color.0);
 
     let image_handle = images.add(image);
 
     // First pass camera
     let render_target = RenderTarget::Image(image_handle.clone());
-    clear_colors.insert(render_target.clone(), Color::BLACK);
+    clear_colors.insert(render_target.clone(), clear_color.0);
+    commands.insert_resource(WorldRenderTarget {
+        image: image_handle.clone(),
+    });
 
     let mut cam_2d = OrthographicCameraBundle::new_2d();
-    cam_2d.camera.target = render_target;
+    cam_2d.camera.target = render_target.clone();
     cam_2d.transform.scale =
         Vec3::new(1. / PIXELS_PER_TILE as f32, 1. / PIXELS_PER_TILE as f32, 1.);
-    commands.spawn_bundle(OrthographicCameraBundle::<WorldCamera> {
-        camera: cam_2d.camera,
-        orthographic_projection: cam_2d.orthographic_projection,
-        visible_entities: cam_2d.visible_entities,
-        frustum: cam_2d.frustum,
-        transform: cam_2d.transform,
-        global_transform: cam_2d.global_transform,
-        marker: WorldCamera,
-    });
+    commands
+        .spawn_bundle(OrthographicCameraBundle::<WorldCamera> {
+            camera: cam_2d.camera,
+            orthographic_projection: cam_2d.orthographic_projection,
+            visible_entities: cam_2d.visible_entities,
+            frustum: cam_2d.frustum,
+            transform: cam_2d.transform,
+            global_transform: cam_2d.global_transform,
+            marker: WorldCamera,
+        })
+        .insert(RenderLayers::layer(WORLD_LAYER));
+
+    // World UI camera, drawn after the world camera to the same target
+    let mut cam_2d = OrthographicCameraBundle::new_2d();
+    cam_2d.camera.target = render_target;
+    commands
+        .spawn_bundle(OrthographicCameraBundle::<WorldUiCamera> {
+            camera: cam_2d.camera,
+            orthographic_projection: cam_2d.orthographic_projection,
+            visible_entities: cam_2d.visible_entities,
+            frustum: cam_2d.frustum,
+            transform: cam_2d.transform,
+            global_transform: cam_2d.global_transform,
+            marker: WorldUiCamera,
+        })
+        .insert(RenderLayers::layer(WORLD_UI_LAYER));
+
+    if !config.manage_upscale {
+        return;
+    }
 
     // Scaling the quad and texture coordinates so we are only using a quadrant
     // of the quad that is contained in a single triangle. This is to avoid
@@ -204,4 +452,88 @@ fn setup(
         global_transform: cam_2d.global_transform,
         marker: Camera3d,
     });
+
+    // bevy_ui draws over the upscaled world at the window's native resolution
+    commands.spawn_bundle(UiCameraBundle::default());
+}
+
+// Runs after the frame's level changes so a new color is used from its first frame
+fn update_world_clear_color_system(
+    clear_color: Res<WorldClearColor>,
+    target: Option<Res<WorldRenderTarget>>,
+    first_pass_drawn: Res<FirstPassDrawn>,
+    mut clear_colors: ResMut<RenderTargetClearColors>,
+    mut images: ResMut<Assets<Image>>,
+) {
+    if !clear_color.is_changed() {
+        return;
+    }
+    if let Some(target) = target {
+        clear_colors.insert(RenderTarget::Image(target.image.clone()), clear_color.0);
+        // Keep the placeholder contents in step until the first pass replaces them
+        if !first_pass_drawn.get() {
+            if let Some(image) = images.get_mut(&target.image) {
+                fill_with_color(image, clear_color.0);
+            }
+        }
+    }
+}
+
+// Overwrite every pixel of `image` with `color`. Only the formats the world
+// render target can have are supported; others are left as they are.
+fn fill_with_color(image: &mut Image, color: Color) {
+    let [r, g, b, a] = color
+        .as_rgba_f32()
+        .map(|c| (c.clamp(0., 1.) * 255.).round() as u8);
+    let pixel = match image.texture_descriptor.format {
+        TextureFormat::Rgba8UnormSrgb => [r, g, b, a],
+        TextureFormat::Bgra8UnormSrgb => [b, g, r, a],
+        _ => return,
+    };
+    for chunk in image.data.chunks_exact_mut(4) {
+        chunk.copy_from_slice(&pixel);
+    }
+}
+
+fn render_pipeline_ready_system(
+    first_pass_drawn: Res<FirstPassDrawn>,
+    mut sent: Local<bool>,
+    mut events: EventWriter<RenderPipelineReady>,
+) {
+    if !*sent && first_pass_drawn.get() {
+        *sent = true;
+        events.send(RenderPipelineReady);
+    }
+}
+
+fn camera_zoom_system(
+    time: Res<Time>,
+    mut zoom: ResMut<CameraZoom>,
+    mut camera_query: Query<&mut Transform, With<WorldCamera>>,
+) {
+    if zoom.current != zoom.target {
+        zoom.advance(time.delta_seconds());
+    }
+    let scale = 1. / (PIXELS_PER_TILE as f32 * zoom.current);
+    for mut transform in camera_query.iter_mut() {
+        if transform.scale.x != scale {
+            transform.scale = Vec3::new(scale, scale, 1.);
+        }
+    }
+}
+
+// Place world UI entities at the pixel their anchor is drawn at by the world camera
+fn world_anchor_system(
+    camera_query: Query<&Transform, With<WorldCamera>>,
+    mut anchor_query: Query<(&mut Transform, &WorldAnchor), Without<WorldCamera>>,
+) {
+    let camera = match camera_query.get_single() {
+        Ok(camera) => camera,
+        Err(_) => return,
+    };
+    for (mut transform, anchor) in anchor_query.iter_mut() {
+        let pixel = (anchor.0 - camera.translation.truncate()) / camera.scale.truncate();
+        transform.translation.x = pixel.x.round();
+        transform.translation.y = pixel.y.round();
+    }
 }
@@ -0,0 +1,918 @@
+// The player character: spawning it, steering it from the action layer and
+// keeping the camera on it, as tuned by `CameraController`.
+
+use bevy::{prelude::*, sprite::Anchor};
+
+use crate::camera::CameraController;
+use crate::death::Dying;
+use crate::game_state::PhysicsSubsteps;
+use crate::health::{Health, Invincible};
+use crate::input::{Action, ActionState, DoubleTap, InputMap, PlayerId, SecondPlayerInput};
+use crate::ledge_grab::{Hanging, LedgeGrab};
+use crate::level::{LevelBounds, LevelEdges, PlayerSpawn, PLAYER_START};
+use crate::physics::{
+    CollisionLayers, Direction, Gravity, Mobility, Pose, Stance, StanceHitboxes, TerminalVelocity,
+    TileCollider, Velocity, GRAVITY, PHYSICS_TIME_STEP,
+};
+use crate::pixel_perfect::{CameraZoom, WorldCamera};
+use crate::projectile::AttackCooldown;
+use crate::replay::{ReplayChecked, ReplayDelta};
+use crate::respawn::RespawnState;
+use crate::tile::TileIndex;
+
+// In co-op, how far inside the edges of the view the players are kept from
+// each other, in tiles
+const CO_OP_SEPARATION_MARGIN: f32 = 2.;
+pub const PLAYER_MAX_HEALTH: i32 = 3;
+// How fast a wall jump pushes the player away from the wall, in tiles per
+// second, and for how long walking can't steer against it, in seconds
+const WALL_JUMP_PUSH_SPEED: f32 = 12.;
+const WALL_JUMP_PUSH_TIME: f32 = 0.15;
+
+#[derive(Component)]
+pub struct Player;
+
+// A player has jumped, from the ground or off a wall
+pub struct Jumped {
+    pub player: Entity,
+}
+
+// Which way an entity is looking: the way it last walked. Never
+// `Direction::Neutral`, since standing still keeps the last facing.
+#[derive(Component, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Facing(pub Direction);
+
+impl Default for Facing {
+    fn default() -> Self {
+        Facing(Direction::Right)
+    }
+}
+
+impl Facing {
+    // -1 facing left, 1 facing right
+    pub fn sign(self) -> f32 {
+        match self.0 {
+            Direction::Left => -1.,
+            _ => 1.,
+        }
+    }
+}
+
+// How long the player's reset has been held, so a stray tap doesn't send
+// them back to the start
+#[derive(Component, Default)]
+pub struct ResetHold {
+    // None once the reset has happened, until it is released
+    held: Option<f32>,
+}
+
+impl ResetHold {
+    // Advance one input step of `dt` seconds, returning whether the reset
+    // happens now
+    fn update(&mut self, action_state: &ActionState, hold_time: f32, dt: f32) -> bool {
+        if action_state.step_just_pressed(Action::Reset) {
+            self.held = Some(0.);
+        } else if !action_state.pressed(Action::Reset) {
+            self.held = None;
+        }
+        let held = match &mut self.held {
+            Some(held) => held,
+            None => return false,
+        };
+        *held += dt;
+        if *held >= hold_time {
+            self.held = None;
+            return true;
+        }
+        false
+    }
+
+    pub fn progress(&self, hold_time: f32) -> f32 {
+        match self.held {
+            Some(held) if hold_time > 0. => (held / hold_time).min(1.),
+            _ => 0.,
+        }
+    }
+}
+
+// A short burst of horizontal speed which ignores gravity, started with the
+// dash action or by tapping a direction twice
+#[derive(Component)]
+pub struct Dash {
+    pub speed: f32,
+    // Seconds a dash lasts
+    pub duration: f32,
+    // Seconds from the start of one dash until another can start
+    pub cooldown: f32,
+    // Seconds left of the current dash, and its direction, -1 or 1
+    active: Option<(f32, f32)>,
+    cooldown_timer: f32,
+}
+
+impl Dash {
+    pub fn new(speed: f32, duration: f32, cooldown: f32) -> Self {
+        Dash {
+            speed,
+            duration,
+            cooldown,
+            active: None,
+            cooldown_timer: 0.,
+        }
+    }
+
+    pub fn dashing(&self) -> bool {
+        self.active.is_some()
+    }
+
+    // Start dashing in `direction` unless the last dash is still cooling
+    // down, returning whether it started
+    pub fn start(&mut self, direction: f32) -> bool {
+        if self.cooldown_timer > 0. {
+            return false;
+        }
+        self.active = Some((self.duration, direction.signum()));
+        self.cooldown_timer = self.cooldown;
+        true
+    }
+
+    // Advance by `dt` seconds, returning the horizontal velocity to dash at
+    // if still dashing
+    pub fn advance(&mut self, dt: f32) -> Option<f32> {
+        self.cooldown_timer = (self.cooldown_timer - dt).max(0.);
+        let (remaining, direction) = self.active?;
+        if remaining <= 0. {
+            self.active = None;
+            return None;
+        }
+        self.active = Some((remaining - dt, direction));
+        Some(direction * self.speed)
+    }
+}
+
+// How a player looks and how big they are
+pub struct PlayerSpec {
+    pub id: PlayerId,
+    // Tints the texture, or fills the box without one
+    pub color: Color,
+    // Standing hitbox, in tiles. Crouching halves its height.
+    pub size: Vec2,
+    // Stretched over the hitbox, until an animation takes over
+    pub texture: Option<Handle<Image>>,
+}
+
+impl PlayerSpec {
+    // A plain green box a tile wide and two tall
+    pub fn new(id: PlayerId) -> Self {
+        PlayerSpec {
+            id,
+            color: Color::GREEN,
+            size: Vec2::new(1., 2.),
+            texture: None,
+        }
+    }
+}
+
+// Spawn a player at the start, until the level's spawn has been placed
+pub fn spawn_player(commands: &mut Commands, spec: &PlayerSpec) -> Entity {
+    let mut sprite = SpriteBundle {
+        transform: Transform {
+            translation: PLAYER_START.extend(0.),
+            scale: spec.size.extend(1.),
+            ..default()
+        },
+        sprite: Sprite {
+            color: spec.color,
+            // A texture is otherwise drawn at its size in pixels, times
+            // the hitbox
+            custom_size: Some(Vec2::ONE),
+            anchor: Anchor::BottomLeft,
+            ..default()
+        },
+        ..default()
+    };
+    if let Some(texture) = &spec.texture {
+        sprite.texture = texture.clone();
+    }
+    commands
+        .spawn_bundle(sprite)
+        .insert(Velocity(Vec3::ZERO))
+        .insert(Player)
+        .insert(spec.id)
+        .insert(ResetHold::default())
+        .insert(Dash::new(30., 0.15, 0.6))
+        .insert(LedgeGrab::default())
+        .insert(DoubleTap::default())
+        .insert(Facing::default())
+        .insert(AttackCooldown::default())
+        .insert(Health::new(PLAYER_MAX_HEALTH))
+        .insert(ReplayChecked)
+        .insert(TileCollider)
+        .insert(CollisionLayers::on(CollisionLayers::PLAYERS))
+        .insert(Gravity(GRAVITY))
+        .insert(Mobility {
+            walk_speed: 10.,
+            // Last factor is peak jump height under normal gravity. The
+            // rise is never under `fall_gravity_scale`, so it doesn't change
+            // the height.
+            jump_speed: (2. * GRAVITY * 5.8).sqrt(),
+            run_multiplier: 1.6,
+            wall_coyote_time: 0.1,
+            crouch_speed: 4.,
+            // The apex gravity is off, with a threshold ready for tuning the
+            // scale
+            apex_threshold: 2.,
+            // Air jumps, dashes and wall jumps wait for ability pickups
+            ..default()
+        })
+        .insert(TerminalVelocity(40.))
+        .insert(Pose::default())
+        .insert(StanceHitboxes {
+            standing: spec.size,
+            crouching: Vec2::new(spec.size.x, spec.size.y / 2.),
+        })
+        .id()
+}
+
+// Only players have a `PlayerId`. Those dying can't be controlled, nor
+// can those being knocked back. Those hanging from a ledge are steered by
+// `hang_control_system` instead.
+type ControlledPlayerQuery<'w, 's> = Query<
+    'w,
+    's,
+    (
+        Entity,
+        &'static PlayerId,
+        &'static mut ResetHold,
+        &'static mut Velocity,
+        &'static mut Mobility,
+        &'static mut Pose,
+        Option<&'static Invincible>,
+    ),
+    (Without<Dying>, Without<Hanging>),
+>;
+
+pub fn player_control_system(
+    action_state: Res<ActionState>,
+    second_player: Option<Res<SecondPlayerInput>>,
+    input_map: Res<InputMap>,
+    substeps: Res<PhysicsSubsteps>,
+    mut respawn: ResMut<RespawnState>,
+    mut query: ControlledPlayerQuery,
+    mut jumped_events: EventWriter<Jumped>,
+) {
+    let dt = substeps.input_time_step();
+    for (player, &id, mut reset_hold, mut velocity, mut mobility, mut pose, invincible) in
+        query.iter_mut()
+    {
+        let actions = SecondPlayerInput::actions(id, &action_state, second_player.as_deref());
+        if let Some(actions) = actions {
+            if reset_hold.update(actions, input_map.reset_hold_time, dt) {
+                respawn.request(player);
+            }
+            if !invincible.is_some_and(Invincible::knocked_back)
+                && control_player(actions, &mut velocity, &mut mobility, &mut pose, dt)
+            {
+                jumped_events.send(Jumped { player });
+            }
+        }
+    }
+}
+
+// After `player_control_system`, so it sees the way they are walking now,
+// in the air as well as on the ground
+pub fn facing_system(mut query: Query<(&Mobility, &mut Facing)>) {
+    for (mobility, mut facing) in query.iter_mut() {
+        let walking = mobility.walk_direction;
+        if walking != Direction::Neutral && facing.0 != walking {
+            facing.0 = walking;
+        }
+    }
+}
+
+// Whatever has turned around, with the sprite to mirror
+type FacingSpriteQuery<'w, 's> = Query<
+    'w,
+    's,
+    (
+        &'static Facing,
+        Option<&'static mut Sprite>,
+        Option<&'static mut TextureAtlasSprite>,
+    ),
+    Changed<Facing>,
+>;
+
+// Sprites are drawn facing right, and mirrored for those facing left
+pub fn facing_sprite_system(mut query: FacingSpriteQuery) {
+    for (facing, sprite, sheet_sprite) in query.iter_mut() {
+        let flip_x = facing.0 == Direction::Left;
+        if let Some(mut sprite) = sprite {
+            sprite.flip_x = flip_x;
+        }
+        if let Some(mut sprite) = sheet_sprite {
+            sprite.flip_x = flip_x;
+        }
+    }
+}
+
+// The players who can dash right now
+type DasherQuery<'w, 's> = Query<
+    'w,
+    's,
+    (
+        &'static PlayerId,
+        &'static mut Dash,
+        &'static mut DoubleTap,
+        &'static mut Velocity,
+        &'static Mobility,
+    ),
+    (Without<Dying>, Without<Hanging>),
+>;
+
+// After `player_control_system`, so a dash overrides walking
+pub fn player_dash_system(
+    action_state: Res<ActionState>,
+    second_player: Option<Res<SecondPlayerInput>>,
+    input_map: Res<InputMap>,
+    substeps: Res<PhysicsSubsteps>,
+    mut query: DasherQuery,
+) {
+    let dt = substeps.input_time_step();
+    for (&id, mut dash, mut double_tap, mut velocity, mobility) in query.iter_mut() {
+        let actions = match SecondPlayerInput::actions(id, &action_state, second_player.as_deref())
+        {
+            Some(actions) => actions,
+            None => continue,
+        };
+        let tapped_twice = double_tap.step(actions, dt, input_map.double_tap_window);
+        let direction = if actions.step_just_pressed(Action::Dash) {
+            // Standing still, the way the player last moved
+            Some(match mobility.walk_direction {
+                Direction::Left => -1.,
+                Direction::Right => 1.,
+                Direction::Neutral if velocity.0.x < 0. => -1.,
+                Direction::Neutral => 1.,
+            })
+        } else if input_map.double_tap_dash {
+            match tapped_twice {
+                Some(Action::MoveLeft) => Some(-1.),
+                Some(Action::MoveRight) => Some(1.),
+                _ => None,
+            }
+        } else {
+            None
+        };
+        if let Some(direction) = direction.filter(|_| mobility.dash_unlocked) {
+            dash.start(direction);
+        }
+        if let Some(speed) = dash.advance(dt) {
+            velocity.0.x = speed;
+            velocity.0.y = 0.;
+        }
+    }
+}
+
+// Where the players' bottom-left corners start, given the level's spawn
+// marker if it has one yet
+pub fn spawn_point(spawn: Option<&Transform>) -> Vec2 {
+    spawn.map_or(PLAYER_START, |spawn| spawn.translation.truncate())
+}
+
+// The players, kept apart from the spawns whose transforms are read
+type MovedPlayerQuery<'w, 's> = Query<
+    'w,
+    's,
+    (&'static mut Transform, &'static mut Velocity),
+    (With<Player>, Without<PlayerSpawn>),
+>;
+
+// Put the players at a newly loaded level's spawn, so they aren't left inside
+// its tiles. Moving an existing spawn in the editor leaves them be.
+pub fn move_to_new_spawn_system(
+    mut controller: ResMut<CameraController>,
+    spawn_query: Query<&Transform, Added<PlayerSpawn>>,
+    mut player_query: MovedPlayerQuery,
+) {
+    if let Some(spawn) = spawn_query.iter().next() {
+        controller.snap();
+        for (mut transform, mut velocity) in player_query.iter_mut() {
+            transform.translation = spawn_point(Some(spawn)).extend(0.);
+            velocity.0 = Vec3::ZERO;
+        }
+    }
+}
+
+// Advances one input step of `dt` seconds, returning whether the player jumped
+fn control_player(
+    action_state: &ActionState,
+    velocity: &mut Velocity,
+    mobility: &mut Mobility,
+    pose: &mut Pose,
+    dt: f32,
+) -> bool {
+    if action_state.step_just_pressed(Action::MoveLeft) {
+        mobility.walk_direction = Direction::Left;
+    }
+    if action_state.step_just_released(Action::MoveLeft)
+        && matches!(mobility.walk_direction, Direction::Left)
+    {
+        mobility.walk_direction = if action_state.pressed(Action::MoveRight) {
+            Direction::Right
+        } else {
+            Direction::Neutral
+        };
+    }
+
+    if action_state.step_just_pressed(Action::MoveRight) {
+        mobility.walk_direction = Direction::Right;
+    }
+    if action_state.step_just_released(Action::MoveRight)
+        && matches!(mobility.walk_direction, Direction::Right)
+    {
+        mobility.walk_direction = if action_state.pressed(Action::MoveLeft) {
+            Direction::Left
+        } else {
+            Direction::Neutral
+        };
+    }
+
+    let direction = match mobility.walk_direction {
+        Direction::Left => -1.0,
+        Direction::Right => 1.0,
+        Direction::Neutral => 0.0,
+    };
+    let running = action_state.pressed(Action::Run);
+    if mobility.wall_push_timer > 0. {
+        mobility.wall_push_timer = (mobility.wall_push_timer - dt).max(0.);
+    } else {
+        velocity.0.x = mobility.walk_velocity_after(velocity.0.x, direction, running, dt);
+    }
+
+    if mobility.on_ground {
+        mobility.air_jumps = 0;
+    }
+    let mut jumped = false;
+    if action_state.step_just_pressed(Action::Jump) {
+        if mobility.on_ground {
+            mobility.on_ground = false;
+            velocity.0.y = mobility.jump_speed;
+            jumped = true;
+        } else if mobility.can_wall_jump() {
+            mobility.on_wall = false;
+            mobility.wall_coyote_timer = 0.;
+            velocity.0.y = mobility.jump_speed;
+            velocity.0.x = match mobility.wall_side {
+                Direction::Left => WALL_JUMP_PUSH_SPEED,
+                Direction::Right => -WALL_JUMP_PUSH_SPEED,
+                Direction::Neutral => velocity.0.x,
+            };
+            mobility.wall_push_timer = WALL_JUMP_PUSH_TIME;
+            jumped = true;
+        } else if mobility.air_jumps < mobility.max_air_jumps {
+            mobility.air_jumps += 1;
+            mobility.fast_falling = false;
+            velocity.0.y = mobility.jump_speed;
+            jumped = true;
+        }
+    }
+    if action_state.step_just_released(Action::Jump) && velocity.0.y > 0.0 {
+        velocity.0.y = 0.0;
+    }
+
+    if action_state.step_just_pressed(Action::FastFall) && !mobility.on_ground {
+        mobility.fast_falling = true;
+    }
+    if action_state.step_just_released(Action::FastFall) {
+        mobility.fast_falling = false;
+    }
+    // Down crouches on the ground, and the crouch is kept through a jump
+    // while it's held. Standing up waits for room above.
+    pose.requested =
+        if action_state.pressed(Action::FastFall) && (mobility.on_ground || mobility.crouching) {
+            Stance::Crouching
+        } else {
+            Stance::Standing
+        };
+    jumped
+}
+
+// In co-op, bring a player who has got too far from the first player back
+// to them, so the camera can always frame both
+pub fn player_separation_system(
+    zoom: Res<CameraZoom>,
+    mut query: Query<(&PlayerId, &mut Transform, &mut Velocity), With<Player>>,
+) {
+    let first = match query.iter().find(|(id, ..)| id.0 == 0) {
+        Some((_, transform, _)) => transform.translation,
+        None => return,
+    };
+    let max_separation = 2. * zoom.view_half_extent() - Vec2::splat(CO_OP_SEPARATION_MARGIN);
+    for (id, mut transform, mut velocity) in query.iter_mut() {
+        let separation = (transform.translation - first).truncate().abs();
+        if id.0 != 0 && (separation.x > max_separation.x || separation.y > max_separation.y) {
+            transform.translation = first;
+            velocity.0 = Vec3::ZERO;
+        }
+    }
+}
+
+// After the collision step, hold the players inside the level's edges, if it
+// has them. Walls only stop the outward side of a player's velocity, so one
+// pushed against a wall can still fall or jump along it. Wrapping moves a
+// player to the other side once their middle crosses an edge, and the camera
+// along with them, so the view doesn't pan across the level after them.
+pub fn level_bounds_system(
+    bounds: Res<LevelBounds>,
+    tile_index: Res<TileIndex>,
+    mut extent: Local<Option<(f32, f32)>>,
+    mut controller: ResMut<CameraController>,
+    mut camera_query: Query<&mut Transform, (With<WorldCamera>, Without<Player>)>,
+    mut query: Query<(&mut Transform, &mut Velocity), With<Player>>,
+) {
+    // Finding the tiles' bounds visits every tile, so only when they change
+    if bounds.is_changed() || tile_index.is_changed() {
+        *extent = bounds.extent(&tile_index);
+    }
+    let (left, right) = match *extent {
+        Some(extent) => extent,
+        None => return,
+    };
+    let mut wrapped = 0.;
+    let mut count = 0;
+    for (mut transform, mut velocity) in query.iter_mut() {
+        count += 1;
+        let x = transform.translation.x;
+        let width = transform.scale.x;
+        match bounds.edges {
+            LevelEdges::Open => {}
+            LevelEdges::Walls => {
+                if x < left {
+                    transform.translation.x = left;
+                    velocity.0.x = velocity.0.x.max(0.);
+                } else if x > right - width {
+                    transform.translation.x = right - width;
+                    velocity.0.x = velocity.0.x.min(0.);
+                }
+            }
+            LevelEdges::Wrap => {
+                let middle = x + width / 2.;
+                let offset = if middle < left {
+                    right - left
+                } else if middle >= right {
+                    left - right
+                } else {
+                    continue;
+                };
+                transform.translation.x += offset;
+                wrapped += offset;
+            }
+        }
+    }
+    // The camera follows the players' midpoint, which moved by this much
+    if wrapped != 0. {
+        let shift = Vec2::new(wrapped / count as f32, 0.);
+        controller.shift(shift);
+        for mut camera_transform in camera_query.iter_mut() {
+            camera_transform.translation += shift.extend(0.);
+        }
+    }
+}
+
+// The world camera, kept apart from the players it follows
+type FollowingCameraQuery<'w, 's> =
+    Query<'w, 's, &'static mut Transform, (With<WorldCamera>, Without<Player>)>;
+
+// The players, by where they are headed, and whether they're fading out
+type FollowedPlayerQuery<'w, 's> = Query<
+    'w,
+    's,
+    (
+        &'static Transform,
+        &'static Velocity,
+        Option<&'static Dying>,
+    ),
+    With<Player>,
+>;
+
+// Once per physics step
+pub fn update_camera_system(
+    mut controller: ResMut<CameraController>,
+    tile_index: Res<TileIndex>,
+    zoom: Res<CameraZoom>,
+    mut camera_query: FollowingCameraQuery,
+    player_query: FollowedPlayerQuery,
+) {
+    follow_players(
+        &mut controller,
+        &tile_index,
+        &zoom,
+        &mut camera_query,
+        &player_query,
+        PHYSICS_TIME_STEP,
+    );
+}
+
+// Every frame while respawning, which has no physics steps
+pub fn respawn_camera_system(
+    time: Res<Time>,
+    replay_delta: Option<Res<ReplayDelta>>,
+    mut controller: ResMut<CameraController>,
+    tile_index: Res<TileIndex>,
+    zoom: Res<CameraZoom>,
+    mut camera_query: FollowingCameraQuery,
+    player_query: FollowedPlayerQuery,
+) {
+    let dt = match replay_delta {
+        Some(delta) => delta.0 as f32,
+        None => time.delta_seconds(),
+    };
+    follow_players(
+        &mut controller,
+        &tile_index,
+        &zoom,
+        &mut camera_query,
+        &player_query,
+        dt,
+    );
+}
+
+// Follow the midpoint of the players through the `CameraController` for
+// `dt` seconds. Players fading out after dying are left out, so with none
+// left the camera holds still.
+fn follow_players(
+    controller: &mut CameraController,
+    tile_index: &TileIndex,
+    zoom: &CameraZoom,
+    camera_query: &mut FollowingCameraQuery,
+    player_query: &FollowedPlayerQuery,
+    dt: f32,
+) {
+    // There is no camera without a window
+    let mut camera_transform = match camera_query.get_single_mut() {
+        Ok(camera_transform) => camera_transform,
+        Err(_) => return,
+    };
+    let (position_sum, velocity_sum, count) = player_query
+        .iter()
+        .filter(|(.., dying)| dying.is_none_or(Dying::respawned))
+        .fold(
+            (Vec2::ZERO, Vec2::ZERO, 0),
+            |(position_sum, velocity_sum, count), (transform, velocity, _)| {
+                (
+                    position_sum + transform.translation.truncate(),
+                    velocity_sum + velocity.0.truncate(),
+                    count + 1,
+                )
+            },
+        );
+    if count == 0 {
+        return;
+    }
+    // The outer edges of the outermost tiles, which span their cells up and
+    // to the right
+    let level = tile_index
+        .bounds()
+        .map(|(min, max)| (min.as_vec2(), (max + IVec2::ONE).as_vec2()));
+    let camera = controller.follow(
+        camera_transform.translation.truncate(),
+        position_sum / count as f32,
+        velocity_sum / count as f32,
+        level,
+        zoom.view_half_extent(),
+        dt,
+    );
+    // Keep the camera's own depth so sprites in front of the player stay in view
+    camera_transform.translation = camera.extend(camera_transform.translation.z);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::headless::{floor, HeadlessGame};
+    use crate::level::{LevelData, TileData};
+    use crate::pixel_perfect::PIXELS_PER_TILE;
+
+    #[test]
+    fn turning_in_the_air_flips_at_once_and_stays_flipped() {
+        let mut game = HeadlessGame::new();
+        // No level, so the player is falling throughout
+        game.step(&[Action::MoveRight]);
+        game.step(&[Action::MoveLeft]);
+        let flipped = |game: &HeadlessGame| {
+            let player = game.player();
+            let facing = *game.app.world.get::<Facing>(player).unwrap();
+            (facing, game.app.world.get::<Sprite>(player).unwrap().flip_x)
+        };
+        assert_eq!(flipped(&game), (Facing(Direction::Left), true));
+        game.run(10, &[]);
+        assert_eq!(flipped(&game), (Facing(Direction::Left), true));
+        game.step(&[Action::MoveRight]);
+        assert_eq!(flipped(&game), (Facing(Direction::Right), false));
+    }
+
+    // A floor from x = -3 to 4, standing on it at the middle
+    fn floor_with_edges(edges: LevelEdges) -> HeadlessGame {
+        let mut game = HeadlessGame::new();
+        game.spawn_level(&LevelData {
+            edges,
+            ..floor(-3..=3)
+        });
+        game.place_player(Vec2::new(0., 1.));
+        game
+    }
+
+    #[test]
+    fn side_walls_stop_the_player_at_the_edge_of_the_tiles() {
+        let mut game = floor_with_edges(LevelEdges::Walls);
+        game.run(240, &[Action::MoveRight]);
+        let player = game.player();
+        assert_eq!(
+            game.player_transform().translation.truncate(),
+            Vec2::new(3., 1.)
+        );
+        assert_eq!(game.app.world.get::<Velocity>(player).unwrap().0.x, 0.);
+        game.run(240, &[Action::MoveLeft]);
+        assert_eq!(
+            game.player_transform().translation.truncate(),
+            Vec2::new(-3., 1.)
+        );
+    }
+
+    #[test]
+    fn jumping_into_a_tile_overhead_bumps_the_ceiling() {
+        let mut game = floor_with_edges(LevelEdges::Open);
+        // Just over the player's head, which is at y = 3
+        game.spawn_level(&LevelData {
+            tiles: vec![TileData::solid(IVec2::new(0, 4))],
+            ..default()
+        });
+        game.run(10, &[]);
+        let on_ceiling = |game: &HeadlessGame| {
+            game.app
+                .world
+                .get::<Mobility>(game.player())
+                .unwrap()
+                .on_ceiling
+        };
+        assert!(!on_ceiling(&game));
+        let mut bumped = false;
+        for _ in 0..120 {
+            game.step(&[Action::Jump]);
+            bumped |= on_ceiling(&game);
+        }
+        assert!(bumped);
+        assert!(game.player_transform().translation.y + 2. <= 4.);
+        // Cleared again once back down
+        game.run(240, &[]);
+        assert!(!on_ceiling(&game));
+    }
+
+    #[test]
+    fn wrapping_carries_the_player_round_at_full_speed() {
+        let mut game = floor_with_edges(LevelEdges::Wrap);
+        let mut wrapped_at = None;
+        for _ in 0..240 {
+            let before = game.player_transform().translation.x;
+            game.step(&[Action::MoveRight]);
+            let after = game.player_transform().translation.x;
+            if after < before {
+                wrapped_at = Some(after);
+                break;
+            }
+        }
+        let player = game.player();
+        // Just over the left edge, by as much as its middle was over the right
+        let x = wrapped_at.expect("never wrapped");
+        assert!((-3.5..-3.).contains(&x), "wrapped to {}", x);
+        assert!(game.app.world.get::<Velocity>(player).unwrap().0.x > 0.);
+    }
+
+    // A wall from far below to far above, so there's no ledge to grab, with
+    // the player falling beside it, wall jumps unlocked
+    fn beside_a_wall() -> HeadlessGame {
+        let mut game = HeadlessGame::new();
+        game.spawn_level(&LevelData {
+            tiles: (-40..40)
+                .map(|y| TileData::solid(IVec2::new(2, y)))
+                .collect(),
+            ..default()
+        });
+        game.place_player(Vec2::new(1., 0.));
+        let player = game.player();
+        let mut mobility = game.app.world.get_mut::<Mobility>(player).unwrap();
+        mobility.wall_jump_unlocked = true;
+        game
+    }
+
+    #[test]
+    fn wall_jump_pushes_away_from_the_wall() {
+        let mut game = beside_a_wall();
+        game.run(10, &[Action::MoveRight]);
+        let player = game.player();
+        assert!(game.app.world.get::<Mobility>(player).unwrap().on_wall);
+        let before = game.player_transform().translation;
+
+        // Still pressing into the wall, so only the push moves it off
+        game.step(&[Action::MoveRight, Action::Jump]);
+        let velocity = game.app.world.get::<Velocity>(player).unwrap().0;
+        assert!(velocity.x < 0., "velocity {}", velocity);
+        assert!(velocity.y > 0., "velocity {}", velocity);
+        game.run(20, &[Action::MoveRight, Action::Jump]);
+        let after = game.player_transform().translation;
+        assert!(after.x < before.x - 0.5, "from {} to {}", before, after);
+        assert!(after.y > before.y, "from {} to {}", before, after);
+    }
+
+    #[test]
+    fn wall_jump_waits_out_the_wall_coyote_time() {
+        let jumped_after = |steps: u32| {
+            let mut game = beside_a_wall();
+            game.run(10, &[Action::MoveRight]);
+            // Off the wall, falling alongside it
+            game.run(steps, &[]);
+            let player = game.player();
+            assert!(!game.app.world.get::<Mobility>(player).unwrap().on_wall);
+            game.step(&[Action::Jump]);
+            game.app.world.get::<Velocity>(player).unwrap().0.y > 0.
+        };
+        let coyote_time = 0.1;
+        let steps = |seconds: f32| (seconds / PHYSICS_TIME_STEP) as u32;
+        assert!(jumped_after(steps(coyote_time / 2.)));
+        assert!(!jumped_after(steps(coyote_time * 2.)));
+    }
+
+    #[test]
+    fn dash_waits_out_its_cooldown() {
+        let mut game = HeadlessGame::new();
+        game.spawn_level(&floor(-30..=30));
+        game.place_player(Vec2::new(0., 1.));
+        let player = game.player();
+        game.app
+            .world
+            .get_mut::<Mobility>(player)
+            .unwrap()
+            .dash_unlocked = true;
+        let dash = game.app.world.get::<Dash>(player).unwrap();
+        let (duration, cooldown) = (dash.duration, dash.cooldown);
+        let input_time_step = PhysicsSubsteps::default().input_time_step();
+        let steps = |seconds: f32| (seconds / input_time_step).round() as u32;
+        let dashing = |game: &HeadlessGame| game.app.world.get::<Dash>(player).unwrap().dashing();
+
+        game.step(&[Action::Dash]);
+        assert!(dashing(&game));
+        game.run(steps(duration), &[]);
+        assert!(!dashing(&game));
+
+        // Pressed again before the cooldown is up, which does nothing
+        game.step(&[Action::Dash]);
+        assert!(!dashing(&game));
+        assert_eq!(game.app.world.get::<Velocity>(player).unwrap().0.x, 0.);
+
+        game.run(steps(cooldown - duration), &[]);
+        game.step(&[Action::Dash]);
+        assert!(dashing(&game));
+    }
+
+    #[test]
+    fn jump_height_and_run_speed_hold_at_any_substep_count() {
+        // The height of a held jump and the top running speed from when
+        // input was read at 300 Hz. The height comes from the physics alone
+        // once the jump speed is set, and running eases toward its top speed
+        // per second, so neither should depend on how often input is read.
+        const JUMP_HEIGHT: f32 = 5.761;
+        const RUN_SPEED: f32 = 16.;
+        // Within a pixel
+        let tolerance = 1. / PIXELS_PER_TILE as f32;
+        for substeps in [1, 2, 4] {
+            let mut game = HeadlessGame::new();
+            game.app.insert_resource(PhysicsSubsteps(substeps));
+            game.spawn_level(&floor(-30..=300));
+            game.place_player(Vec2::new(0., 1.));
+            game.run(8, &[]);
+
+            let start = game.player_transform().translation.y;
+            let mut top = start;
+            for _ in 0..240 {
+                game.step(&[Action::Jump]);
+                top = top.max(game.player_transform().translation.y);
+            }
+            let height = top - start;
+            assert!(
+                (height - JUMP_HEIGHT).abs() < tolerance,
+                "jumped {} with {} substeps",
+                height,
+                substeps
+            );
+
+            // Landed by now, so run from a standstill on the ground
+            game.run(240, &[]);
+            game.run(120, &[Action::MoveRight, Action::Run]);
+            let speed = game.app.world.get::<Velocity>(game.player()).unwrap().0.x;
+            assert!(
+                (speed - RUN_SPEED).abs() < tolerance,
+                "ran at {} with {} substeps",
+                speed,
+                substeps
+            );
+        }
+    }
+}
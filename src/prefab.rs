@@ -0,0 +1,107 @@
+// Reusable multi-tile structures which can be stamped into a level.
+//
+// The library is read from `assets/prefabs.ron`, a list of `(name, prefab)`
+// pairs. Levels place prefabs by name, and the editor cycles through them in
+// the order they are listed.
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::level::TileData;
+use crate::tile::TileIndex;
+
+pub const PREFAB_LIBRARY_PATH: &str = "assets/prefabs.ron";
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Prefab {
+    // Positions are relative to the cell the prefab is stamped at
+    pub tiles: Vec<TileData>,
+}
+
+impl Prefab {
+    // The prefab mirrored left to right about the cell it is stamped at
+    pub fn flipped_x(&self) -> Prefab {
+        Prefab {
+            tiles: self
+                .tiles
+                .iter()
+                .map(|tile| TileData {
+                    pos: IVec2::new(-tile.pos.x, tile.pos.y),
+                    shape: tile.shape.flipped_x(),
+                    ..tile.clone()
+                })
+                .collect(),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct PrefabLibrary {
+    prefabs: Vec<(String, Prefab)>,
+}
+
+impl PrefabLibrary {
+    pub fn load(path: &str) -> Self {
+        let text = match std::fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(err) => {
+                // There is no filesystem on wasm
+                if !cfg!(target_arch = "wasm32") {
+                    warn!("{}: {}, no prefabs available", path, err);
+                }
+                return PrefabLibrary::default();
+            }
+        };
+        match ron::from_str(&text) {
+            Ok(prefabs) => PrefabLibrary { prefabs },
+            Err(err) => {
+                warn!("{}: {}, no prefabs available", path, err);
+                PrefabLibrary::default()
+            }
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Prefab> {
+        self.prefabs
+            .iter()
+            .find(|(prefab_name, _)| prefab_name == name)
+            .map(|(_, prefab)| prefab)
+    }
+
+    // Prefabs in the order they are listed in the file
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Prefab)> {
+        self.prefabs
+            .iter()
+            .map(|(name, prefab)| (name.as_str(), prefab))
+    }
+
+    pub fn len(&self) -> usize {
+        self.prefabs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.prefabs.is_empty()
+    }
+}
+
+// Spawn the prefab's tiles offset by `origin`. Cells which already hold a
+// tile are replaced if `overwrite` is set and skipped otherwise.
+// Returns the cells it spawned tiles in.
+pub fn stamp_prefab(
+    commands: &mut Commands,
+    tile_index: &mut TileIndex,
+    asset_server: &AssetServer,
+    origin: IVec2,
+    prefab: &Prefab,
+    overwrite: bool,
+) -> Vec<IVec2> {
+    let mut stamped = Vec::new();
+    for tile in &prefab.tiles {
+        let cell = origin + tile.pos;
+        if overwrite || tile_index.tile_at(cell).is_none() {
+            tile.spawn(commands, asset_server, tile_index, origin);
+            stamped.push(cell);
+        }
+    }
+    stamped
+}
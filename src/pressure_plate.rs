@@ -0,0 +1,207 @@
+// Plates on the floor which are held down by anything resting on them, a
+// player, an enemy or a box, and spring back up once everything has left.
+// Each has an id shared with whatever it works, such as a door, which
+// listens for the `PlateActivated` and `PlateDeactivated` events with it.
+//
+// A plate isn't solid. Whatever stands on the floor beneath it sinks into
+// its thin strip along the bottom of its cells, which is what presses it.
+
+use bevy::prelude::*;
+use bevy::sprite::Anchor;
+use serde::{Deserialize, Serialize};
+
+use crate::level::LevelEntity;
+use crate::physics::{hitbox, overlaps, TileCollider};
+
+const PLATE_COLOR: Color = Color::rgb(0.8, 0.2, 0.2);
+const PRESSED_PLATE_COLOR: Color = Color::rgb(0.4, 0.1, 0.1);
+// How far a plate sticks up from the bottom of its cells, in tiles, and how
+// far once pressed
+const PLATE_HEIGHT: f32 = 0.25;
+const PRESSED_PLATE_HEIGHT: f32 = 0.1;
+
+// A pressure plate in a level file
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PlateData {
+    // Its leftmost cell
+    pub pos: IVec2,
+    // In cells
+    #[serde(default = "default_plate_width")]
+    pub width: i32,
+    pub id: u32,
+}
+
+fn default_plate_width() -> i32 {
+    1
+}
+
+#[derive(Component)]
+pub struct PressurePlate {
+    pub id: u32,
+    // In cells
+    pub width: i32,
+    // Whether anything is resting on it, as of the last physics step
+    pub pressed: bool,
+}
+
+// Something came to rest on a plate with nothing else on it
+pub struct PlateActivated {
+    pub id: u32,
+    pub plate: Entity,
+}
+
+// The last thing resting on a plate left it
+pub struct PlateDeactivated {
+    pub id: u32,
+    pub plate: Entity,
+}
+
+pub fn spawn_plate(commands: &mut Commands, data: &PlateData) -> Entity {
+    commands
+        .spawn_bundle(SpriteBundle {
+            transform: Transform::from_translation(data.pos.as_vec2().extend(0.)),
+            sprite: Sprite {
+                color: PLATE_COLOR,
+                custom_size: Some(Vec2::new(data.width as f32, PLATE_HEIGHT)),
+                anchor: Anchor::BottomLeft,
+                ..default()
+            },
+            ..default()
+        })
+        .insert(PressurePlate {
+            id: data.id,
+            width: data.width,
+            pressed: false,
+        })
+        .insert(LevelEntity)
+        .id()
+}
+
+// How a plate was placed, read back from it
+pub fn plate_data(plate: &PressurePlate, transform: &Transform) -> PlateData {
+    PlateData {
+        pos: transform.translation.truncate().round().as_ivec2(),
+        width: plate.width,
+        id: plate.id,
+    }
+}
+
+// After boxes have settled, so the bodies are where they end the step
+pub fn pressure_plate_system(
+    body_query: Query<&Transform, With<TileCollider>>,
+    mut plate_query: Query<
+        (Entity, &mut PressurePlate, &Transform, &mut Sprite),
+        Without<TileCollider>,
+    >,
+    mut activated_events: EventWriter<PlateActivated>,
+    mut deactivated_events: EventWriter<PlateDeactivated>,
+) {
+    for (entity, mut plate, transform, mut sprite) in plate_query.iter_mut() {
+        let strip = (
+            transform.translation.truncate(),
+            Vec2::new(plate.width as f32, PLATE_HEIGHT),
+        );
+        let pressed = body_query
+            .iter()
+            .any(|transform| overlaps(hitbox(transform), strip));
+        if pressed == plate.pressed {
+            continue;
+        }
+        plate.pressed = pressed;
+        let id = plate.id;
+        let height = if pressed {
+            activated_events.send(PlateActivated { id, plate: entity });
+            sprite.color = PRESSED_PLATE_COLOR;
+            PRESSED_PLATE_HEIGHT
+        } else {
+            deactivated_events.send(PlateDeactivated { id, plate: entity });
+            sprite.color = PLATE_COLOR;
+            PLATE_HEIGHT
+        };
+        sprite.custom_size = Some(Vec2::new(plate.width as f32, height));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::headless::{floor, HeadlessGame};
+    use crate::level::LevelData;
+    use crate::push_box::{BoxData, PushBox};
+    use bevy::ecs::event::{Events, ManualEventReader};
+
+    #[derive(Default)]
+    struct Counts {
+        activated: ManualEventReader<PlateActivated>,
+        deactivated: ManualEventReader<PlateDeactivated>,
+        // Read so far
+        on: usize,
+        off: usize,
+    }
+
+    impl Counts {
+        fn run(&mut self, game: &mut HeadlessGame, steps: u32) {
+            for _ in 0..steps {
+                game.step(&[]);
+                let world = &game.app.world;
+                self.on += self
+                    .activated
+                    .iter(world.resource::<Events<PlateActivated>>())
+                    .count();
+                self.off += self
+                    .deactivated
+                    .iter(world.resource::<Events<PlateDeactivated>>())
+                    .count();
+            }
+        }
+    }
+
+    fn pressed(game: &mut HeadlessGame) -> bool {
+        game.app
+            .world
+            .query::<&PressurePlate>()
+            .iter(&game.app.world)
+            .next()
+            .unwrap()
+            .pressed
+    }
+
+    #[test]
+    fn plate_stays_down_until_everything_has_left() {
+        let mut game = HeadlessGame::new();
+        game.spawn_level(&LevelData {
+            plates: vec![PlateData {
+                pos: IVec2::new(0, 1),
+                width: 3,
+                id: 7,
+            }],
+            boxes: vec![BoxData {
+                pos: IVec2::new(0, 1),
+            }],
+            ..floor(-6..=6)
+        });
+        // Beside the box, on the same plate
+        game.place_player(Vec2::new(2., 1.));
+        let mut counts = Counts::default();
+        counts.run(&mut game, 10);
+        assert!(pressed(&mut game));
+        assert_eq!((counts.on, counts.off), (1, 0));
+
+        let pushed = game
+            .app
+            .world
+            .query_filtered::<Entity, With<PushBox>>()
+            .iter(&game.app.world)
+            .next()
+            .unwrap();
+        game.app.world.despawn(pushed);
+        counts.run(&mut game, 10);
+        assert!(pressed(&mut game));
+        assert_eq!((counts.on, counts.off), (1, 0));
+
+        game.place_player(Vec2::new(-4., 1.));
+        counts.run(&mut game, 10);
+        assert!(!pressed(&mut game));
+        assert_eq!((counts.on, counts.off), (1, 1));
+    }
+}
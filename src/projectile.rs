@@ -0,0 +1,182 @@
+// The players' attack: a small shot fired the way they are facing. It flies
+// straight, ignoring gravity, until it hits a solid tile or something with
+// `Health` other than a player, or runs out of time.
+//
+// Each player can only fire once per `ATTACK_COOLDOWN`, so however fast the
+// button is pressed only a handful of shots are ever in flight.
+
+use bevy::{prelude::*, sprite::Anchor};
+
+use crate::death::Dying;
+use crate::game_state::PhysicsSubsteps;
+use crate::health::{Damage, Health};
+use crate::input::{Action, ActionState, PlayerId, SecondPlayerInput};
+use crate::level::LevelEntity;
+use crate::physics::{hitbox, overlaps, Velocity, PHYSICS_TIME_STEP};
+use crate::player::{Facing, Player};
+use crate::tile::{SolidCollider, TileIndex};
+
+// Seconds between shots
+pub const ATTACK_COOLDOWN: f32 = 0.3;
+// Seconds a shot flies before vanishing
+pub const PROJECTILE_LIFETIME: f32 = 1.5;
+// In tiles per second
+pub const PROJECTILE_SPEED: f32 = 20.;
+pub const PROJECTILE_DAMAGE: i32 = 1;
+const PROJECTILE_SIZE: f32 = 0.25;
+const PROJECTILE_COLOR: Color = Color::rgb(1., 0.9, 0.3);
+
+#[derive(Component)]
+pub struct Projectile {
+    // Seconds since it was fired
+    elapsed: f32,
+}
+
+// Seconds until a player can fire again
+#[derive(Component, Default)]
+pub struct AttackCooldown(pub f32);
+
+// Once per input step, after the players' facing is updated
+pub fn player_attack_system(
+    mut commands: Commands,
+    action_state: Res<ActionState>,
+    second_player: Option<Res<SecondPlayerInput>>,
+    substeps: Res<PhysicsSubsteps>,
+    mut query: Query<(&PlayerId, &Transform, &Facing, &mut AttackCooldown), Without<Dying>>,
+) {
+    for (&id, transform, facing, mut cooldown) in query.iter_mut() {
+        cooldown.0 = (cooldown.0 - substeps.input_time_step()).max(0.);
+        let actions = SecondPlayerInput::actions(id, &action_state, second_player.as_deref());
+        if cooldown.0 > 0.
+            || !actions.is_some_and(|actions| actions.step_just_pressed(Action::Attack))
+        {
+            continue;
+        }
+        cooldown.0 = ATTACK_COOLDOWN;
+        // From the player's middle, at their edge in the direction they face
+        let size = transform.scale.truncate();
+        let center = transform.translation.truncate()
+            + size / 2.
+            + Vec2::new(facing.sign() * size.x / 2., 0.);
+        spawn_projectile(&mut commands, center, facing.sign());
+    }
+}
+
+fn spawn_projectile(commands: &mut Commands, center: Vec2, direction: f32) -> Entity {
+    commands
+        .spawn_bundle(SpriteBundle {
+            transform: Transform {
+                translation: (center - PROJECTILE_SIZE / 2.).extend(0.),
+                scale: Vec3::new(PROJECTILE_SIZE, PROJECTILE_SIZE, 1.),
+                ..default()
+            },
+            sprite: Sprite {
+                color: PROJECTILE_COLOR,
+                anchor: Anchor::BottomLeft,
+                ..default()
+            },
+            ..default()
+        })
+        .insert(Projectile { elapsed: 0. })
+        .insert(Velocity(Vec3::new(direction * PROJECTILE_SPEED, 0., 0.)))
+        .insert(LevelEntity)
+        .id()
+}
+
+// What a projectile can hurt
+type TargetQuery<'w, 's> = Query<
+    'w,
+    's,
+    (Entity, &'static Transform),
+    (With<Health>, Without<Player>, Without<Projectile>),
+>;
+
+// After the velocity step, before damage is taken. A shot is checked along
+// the whole way it moved in the step, so it can't pass through a thin wall.
+pub fn projectile_system(
+    mut commands: Commands,
+    tile_index: Res<TileIndex>,
+    solid_query: Query<(), With<SolidCollider>>,
+    mut projectile_query: Query<(Entity, &mut Projectile, &Transform, &Velocity)>,
+    target_query: TargetQuery,
+    mut damage_events: EventWriter<Damage>,
+) {
+    for (projectile, mut state, transform, velocity) in projectile_query.iter_mut() {
+        state.elapsed += PHYSICS_TIME_STEP;
+        let min = transform.translation.truncate();
+        let max = min + transform.scale.truncate();
+        let center = (min + max) / 2.;
+        let previous = center - velocity.0.truncate() * PHYSICS_TIME_STEP;
+        let hit_tile = tile_index
+            .raycast(previous, center, |tile| solid_query.contains(tile))
+            .is_some();
+        let hit = target_query
+            .iter()
+            .find(|(_, target)| overlaps(hitbox(transform), hitbox(target)));
+        if let Some((target, _)) = hit {
+            damage_events.send(Damage {
+                target,
+                amount: PROJECTILE_DAMAGE,
+                source: Some(center),
+            });
+        }
+        if hit_tile || hit.is_some() || state.elapsed >= PROJECTILE_LIFETIME {
+            commands.entity(projectile).despawn_recursive();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::enemy::{Enemy, EnemyData};
+    use crate::headless::HeadlessGame;
+    use crate::level::{LevelData, TileData};
+
+    fn level(tiles: impl Iterator<Item = (i32, i32)>, enemies: Vec<EnemyData>) -> LevelData {
+        LevelData {
+            tiles: tiles
+                .map(|(x, y)| TileData::solid(IVec2::new(x, y)))
+                .collect(),
+            enemies,
+            ..default()
+        }
+    }
+
+    fn count<T: Component>(game: &mut HeadlessGame) -> usize {
+        game.app
+            .world
+            .query_filtered::<(), With<T>>()
+            .iter(&game.app.world)
+            .count()
+    }
+
+    #[test]
+    fn shot_kills_an_enemy_ahead() {
+        let mut game = HeadlessGame::new();
+        let mut enemy = EnemyData::new(IVec2::new(5, 1));
+        enemy.speed = 0.;
+        game.spawn_level(&level((-2..=8).map(|x| (x, 0)), vec![enemy]));
+        game.place_player(Vec2::new(0., 1.));
+        game.run(10, &[]);
+        game.step(&[Action::Attack]);
+        game.run(60, &[]);
+        assert_eq!(count::<Enemy>(&mut game), 0);
+        assert_eq!(count::<Projectile>(&mut game), 0);
+    }
+
+    #[test]
+    fn shot_stops_at_a_wall() {
+        let mut game = HeadlessGame::new();
+        // A floor, then a wall three tiles to the right
+        let tiles = (-2..=8).map(|x| (x, 0)).chain((1..=3).map(|y| (3, y)));
+        game.spawn_level(&level(tiles, Vec::new()));
+        game.place_player(Vec2::new(0., 1.));
+        game.run(10, &[]);
+        game.step(&[Action::Attack]);
+        assert_eq!(count::<Projectile>(&mut game), 1);
+        // Well short of its lifetime
+        game.run(30, &[]);
+        assert_eq!(count::<Projectile>(&mut game), 0);
+    }
+}
@@ -0,0 +1,305 @@
+// Boxes which fall, rest on tiles and each other, and can be pushed along by
+// walking into them, for puzzles such as weighing down a switch. Named so as
+// not to hide the standard `Box`.
+//
+// They're bodies like the players, colliding with tiles in the collision
+// step. Boxes against boxes and players against boxes are resolved after
+// it, by moving each pair apart along whichever axis they overlap least.
+// A player walking into a box's side is held at it while the box takes on
+// their speed, and it's the tiles which stop the box, so one pushed into a
+// wall holds the player there too. A box sliding into a player whose back
+// is to a wall stops at them rather than pushing them into it. A player
+// standing on a box moves along with it.
+
+use bevy::prelude::*;
+use bevy::sprite::Anchor;
+use serde::{Deserialize, Serialize};
+
+use crate::level::LevelEntity;
+use crate::physics::{
+    hitbox, CollisionLayers, Gravity, Mobility, TerminalVelocity, TileCollider, Velocity, GRAVITY,
+    PHYSICS_TIME_STEP,
+};
+use crate::player::Player;
+use crate::tile::{ColliderShape, SolidCollider, TileIndex};
+
+pub const BOX_COLOR: Color = Color::rgb(0.55, 0.4, 0.25);
+// How quickly a box slides to a stop once nothing pushes it, in tiles per
+// second squared
+const BOX_FRICTION: f32 = 30.;
+
+// A box in a level file
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct BoxData {
+    // The cell it starts in
+    pub pos: IVec2,
+}
+
+#[derive(Component)]
+pub struct PushBox {
+    // Where it was placed, which it's put back at when the level is flipped
+    pub data: BoxData,
+}
+
+// A box fills its cell
+pub fn spawn_box(commands: &mut Commands, data: &BoxData) -> Entity {
+    commands
+        .spawn_bundle(SpriteBundle {
+            transform: Transform::from_translation(data.pos.as_vec2().extend(0.)),
+            sprite: Sprite {
+                color: BOX_COLOR,
+                custom_size: Some(Vec2::ONE),
+                anchor: Anchor::BottomLeft,
+                ..default()
+            },
+            ..default()
+        })
+        .insert(PushBox { data: data.clone() })
+        .insert(Velocity(Vec3::ZERO))
+        .insert(Gravity(GRAVITY))
+        .insert(TerminalVelocity(40.))
+        .insert(TileCollider)
+        .insert(CollisionLayers::on(CollisionLayers::BOXES))
+        .insert(LevelEntity)
+        .id()
+}
+
+// How far the box `a` has to move to stop overlapping the box `b`, along the
+// axis they overlap least, if they overlap. Each is its bottom-left corner
+// and size, like a body's translation and scale.
+pub fn separation(a: (Vec2, Vec2), b: (Vec2, Vec2)) -> Option<Vec2> {
+    let overlap = (a.0 + a.1).min(b.0 + b.1) - a.0.max(b.0);
+    if overlap.x <= 0. || overlap.y <= 0. {
+        return None;
+    }
+    let away = (a.0 + a.1 / 2.) - (b.0 + b.1 / 2.);
+    Some(if overlap.x < overlap.y {
+        Vec2::new(overlap.x.copysign(away.x), 0.)
+    } else {
+        Vec2::new(0., overlap.y.copysign(away.y))
+    })
+}
+
+// How much of the sideways move `dx` the body with its bottom-left corner
+// at `min` can make before a square tile stops it. Slopes are left to the
+// collision step.
+fn room_beside(
+    tile_index: &TileIndex,
+    shape_query: &Query<&ColliderShape, With<SolidCollider>>,
+    (min, size): (Vec2, Vec2),
+    dx: f32,
+) -> f32 {
+    // Touching a cell's edge, give or take rounding, isn't overlapping it
+    const TOLERANCE: f32 = 0.001;
+    let bottom = (min.y + TOLERANCE).floor() as i32;
+    let top = (min.y + size.y - TOLERANCE).ceil() as i32;
+    let blocked = |x: i32| {
+        (bottom..top).map(|y| IVec2::new(x, y)).any(|cell| {
+            tile_index
+                .tile_at(cell)
+                .and_then(|tile| shape_query.get(tile).ok())
+                .is_some_and(|shape| matches!(shape, ColliderShape::Aabb))
+        })
+    };
+    if dx > 0. {
+        let edge = min.x + size.x;
+        let first = (edge - TOLERANCE).ceil() as i32;
+        (first..(edge + dx).ceil() as i32)
+            .find(|&x| blocked(x))
+            .map_or(dx, |x| (x as f32 - edge).clamp(0., dx))
+    } else {
+        let edge = min.x;
+        let first = (edge + TOLERANCE).floor() as i32 - 1;
+        ((edge + dx).floor() as i32..=first)
+            .rev()
+            .find(|&x| blocked(x))
+            .map_or(dx, |x| ((x + 1) as f32 - edge).clamp(dx, 0.))
+    }
+}
+
+// The players, who push boxes and ride on them
+type PusherQuery<'w, 's> = Query<
+    'w,
+    's,
+    (
+        &'static mut Transform,
+        &'static mut Velocity,
+        &'static mut Mobility,
+    ),
+    (With<Player>, Without<PushBox>),
+>;
+
+// After the collision step
+pub fn push_box_system(
+    mut player_query: PusherQuery,
+    mut box_query: Query<(Entity, &mut Transform, &mut Velocity), With<PushBox>>,
+    tile_index: Res<TileIndex>,
+    shape_query: Query<&ColliderShape, With<SolidCollider>>,
+) {
+    for (_, _, mut velocity) in box_query.iter_mut() {
+        let slowed = velocity.0.x.abs() - BOX_FRICTION * PHYSICS_TIME_STEP;
+        velocity.0.x = slowed.max(0.).copysign(velocity.0.x);
+    }
+
+    // From the bottom up, so each box settles on those already settled
+    // beneath it
+    let mut boxes: Vec<(Entity, f32)> = box_query
+        .iter()
+        .map(|(entity, transform, _)| (entity, transform.translation.y))
+        .collect();
+    boxes.sort_by(|a, b| a.1.total_cmp(&b.1).then(a.0.id().cmp(&b.0.id())));
+    for (i, &(upper, _)) in boxes.iter().enumerate() {
+        for &(lower, _) in &boxes[..i] {
+            let (_, lower_transform, lower_velocity) = box_query.get(lower).unwrap();
+            let (lower_box, lower_velocity) = (hitbox(lower_transform), lower_velocity.0);
+            let (_, mut transform, mut velocity) = box_query.get_mut(upper).unwrap();
+            let push = match separation(hitbox(&transform), lower_box) {
+                Some(push) => push,
+                None => continue,
+            };
+            transform.translation += push.extend(0.);
+            if push.y > 0. {
+                velocity.0.y = velocity.0.y.max(lower_velocity.y);
+            } else if push.x != 0. {
+                // Side by side, a pushed box shoves the other along with it
+                let shared = (velocity.0.x + lower_velocity.x) / 2.;
+                velocity.0.x = shared;
+                let (_, _, mut lower_velocity) = box_query.get_mut(lower).unwrap();
+                lower_velocity.0.x = shared;
+            }
+        }
+    }
+
+    for (mut transform, mut velocity, mut mobility) in player_query.iter_mut() {
+        for (_, mut box_transform, mut box_velocity) in box_query.iter_mut() {
+            let push = match separation(hitbox(&transform), hitbox(&box_transform)) {
+                Some(push) => push,
+                None => continue,
+            };
+            if push.y > 0. {
+                // Standing on it, and carried along as it slides
+                transform.translation.y += push.y;
+                transform.translation.x += box_velocity.0.x * PHYSICS_TIME_STEP;
+                velocity.0.y = velocity.0.y.max(box_velocity.0.y);
+                mobility.on_ground = true;
+                mobility.fast_falling = false;
+            } else if push.y < 0. {
+                // Landed on their head
+                box_transform.translation.y -= push.y;
+                box_velocity.0.y = box_velocity.0.y.max(velocity.0.y);
+            } else {
+                // Against a wall the player can't give way, so the box does
+                let moved = room_beside(&tile_index, &shape_query, hitbox(&transform), push.x);
+                transform.translation.x += moved;
+                if moved != push.x {
+                    box_transform.translation.x -= push.x - moved;
+                    if box_velocity.0.x * push.x > 0. {
+                        box_velocity.0.x = 0.;
+                    }
+                }
+                if velocity.0.x * push.x < 0. {
+                    box_velocity.0.x = velocity.0.x;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::headless::HeadlessGame;
+    use crate::input::Action;
+    use crate::level::{LevelData, TileData};
+
+    fn level(tiles: impl Iterator<Item = (i32, i32)>, boxes: &[(i32, i32)]) -> LevelData {
+        LevelData {
+            tiles: tiles
+                .map(|(x, y)| TileData::solid(IVec2::new(x, y)))
+                .collect(),
+            boxes: boxes
+                .iter()
+                .map(|&(x, y)| BoxData {
+                    pos: IVec2::new(x, y),
+                })
+                .collect(),
+            ..default()
+        }
+    }
+
+    fn box_positions(game: &mut HeadlessGame) -> Vec<Vec3> {
+        let mut positions: Vec<Vec3> = game
+            .app
+            .world
+            .query_filtered::<&Transform, With<PushBox>>()
+            .iter(&game.app.world)
+            .map(|transform| transform.translation)
+            .collect();
+        positions.sort_by(|a, b| a.y.total_cmp(&b.y));
+        positions
+    }
+
+    #[test]
+    fn box_pushed_into_a_wall_holds_the_player_there() {
+        let mut game = HeadlessGame::new();
+        let floor = (-6..=6).map(|x| (x, 0));
+        let wall = (1..4).map(|y| (5, y));
+        game.spawn_level(&level(floor.chain(wall), &[(1, 1)]));
+        game.place_player(Vec2::new(-2., 1.));
+
+        // Pushed along the floor, then up against the wall
+        game.run(240, &[Action::MoveRight]);
+        let pushed = box_positions(&mut game)[0];
+        assert!((pushed.x - 4.).abs() < 1e-3, "box at {}", pushed);
+        assert_eq!(pushed.y, 1.);
+        let player = game.player_transform().translation;
+        assert!((player.x - 3.).abs() < 1e-2, "player at {}", player);
+
+        // And nothing gives however long it's pushed
+        game.run(120, &[Action::MoveRight]);
+        assert!((box_positions(&mut game)[0].x - 4.).abs() < 1e-3);
+        assert!((game.player_transform().translation.x - 3.).abs() < 1e-2);
+    }
+
+    #[test]
+    fn boxes_stack_and_hold_a_player_up() {
+        let mut game = HeadlessGame::new();
+        game.spawn_level(&level((-4..=4).map(|x| (x, 0)), &[(0, 1), (0, 3)]));
+        game.place_player(Vec2::new(0., 6.));
+        game.run(240, &[]);
+
+        let positions = box_positions(&mut game);
+        assert_eq!(positions[0].y, 1.);
+        assert!((positions[1].y - 2.).abs() < 1e-3);
+        assert!((game.player_transform().translation.y - 3.).abs() < 1e-3);
+        let mobility = game.app.world.get::<Mobility>(game.player()).unwrap();
+        assert!(mobility.on_ground);
+    }
+
+    #[test]
+    fn box_sliding_into_a_player_against_a_wall_stops_at_them() {
+        let mut game = HeadlessGame::new();
+        let floor = (-6..=6).map(|x| (x, 0));
+        let wall = (1..4).map(|y| (-3, y));
+        game.spawn_level(&level(floor.chain(wall), &[(2, 1)]));
+        // Back to the wall, with the box sliding at them
+        game.place_player(Vec2::new(-2., 1.));
+        let pushed = game
+            .app
+            .world
+            .query_filtered::<Entity, With<PushBox>>()
+            .iter(&game.app.world)
+            .next()
+            .unwrap();
+        game.app.world.get_mut::<Velocity>(pushed).unwrap().0.x = -20.;
+
+        for _ in 0..120 {
+            game.step(&[]);
+            let player = game.player_transform().translation;
+            assert!(player.x > -2. - 1e-3, "player at {}", player);
+            let pushed = box_positions(&mut game)[0];
+            assert!(pushed.x > -1. - 1e-3, "box at {}", pushed);
+        }
+        assert_eq!(game.player_transform().translation.y, 1.);
+    }
+}
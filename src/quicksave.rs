@@ -0,0 +1,444 @@
+// Quick save and quick load, for retrying part of a level without replaying the
+// way there. QuickSave snapshots the players, the tiles, edits included, the
+// score and the coins collected into a single slot, also written to
+// `QUICKSAVE_PATH` off the web. QuickLoad puts them back, reading the file if
+// nothing has been saved since starting.
+//
+// Anything which restores a `Snapshot`, such as a save system, can send
+// `RestoreSnapshot` to have it put back the same way.
+
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::Path;
+
+use crate::camera::CameraController;
+use crate::coin::{coin_cell, spawn_coin, Coin, CoinCount, COIN_TEXTURE};
+use crate::game_state::FixedStepReset;
+use crate::health::Health;
+use crate::input::{Action, ActionState, PlayerId};
+use crate::level::{current_tiles, CurrentLevel, TileData, TileDataQuery, Unspawned};
+use crate::physics::{Direction, Mobility, PhysicsSystem, Pose, Stance, StanceHitboxes, Velocity};
+use crate::score::Score;
+use crate::tile::TileIndex;
+
+pub const QUICKSAVE_PATH: &str = "quicksave.ron";
+
+// Everything about the game which changes while playing
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Snapshot {
+    // The level file the tiles started out from, if any
+    pub level_path: Option<String>,
+    // Whether the tiles differ from that file
+    pub unsaved: bool,
+    pub tiles: Vec<TileData>,
+    pub players: Vec<PlayerSnapshot>,
+    // Snapshots from before there was a score load with none
+    #[serde(default)]
+    pub score: u64,
+    // Snapshots from before coins were saved load with none collected
+    #[serde(default)]
+    pub coins: u32,
+    // Cells of the coins collected so far
+    #[serde(default)]
+    pub collected_coins: Vec<IVec2>,
+}
+
+// A player's motion, leaving out what only tuning changes, such as speeds
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PlayerSnapshot {
+    pub id: usize,
+    pub translation: Vec3,
+    pub velocity: Vec3,
+    pub stance: Stance,
+    pub walk_direction: Direction,
+    pub on_ground: bool,
+    pub fast_falling: bool,
+    pub on_wall: bool,
+    pub wall_coyote_timer: f32,
+    pub sliding: bool,
+    // Jumps made in the air since leaving the ground
+    #[serde(default)]
+    pub air_jumps: u32,
+    // Snapshots from before health was saved leave it as it is
+    #[serde(default)]
+    pub health: Option<i32>,
+}
+
+#[derive(Debug)]
+pub enum SnapshotError {
+    Io(std::io::Error),
+    Parse(ron::Error),
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SnapshotError::Io(err) => write!(f, "failed to read snapshot: {}", err),
+            SnapshotError::Parse(err) => write!(f, "invalid snapshot: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for SnapshotError {}
+
+impl Snapshot {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, SnapshotError> {
+        let text = std::fs::read_to_string(path).map_err(SnapshotError::Io)?;
+        ron::from_str(&text).map_err(SnapshotError::Parse)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), SnapshotError> {
+        let text = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+            .map_err(SnapshotError::Parse)?;
+        std::fs::write(path, text).map_err(SnapshotError::Io)
+    }
+}
+
+impl PlayerSnapshot {
+    fn capture(
+        id: PlayerId,
+        transform: &Transform,
+        velocity: &Velocity,
+        mobility: &Mobility,
+        pose: &Pose,
+        health: Option<&Health>,
+    ) -> Self {
+        PlayerSnapshot {
+            id: id.0,
+            translation: transform.translation,
+            velocity: velocity.0,
+            stance: pose.stance,
+            walk_direction: mobility.walk_direction,
+            on_ground: mobility.on_ground,
+            fast_falling: mobility.fast_falling,
+            on_wall: mobility.on_wall,
+            wall_coyote_timer: mobility.wall_coyote_timer,
+            sliding: mobility.sliding,
+            air_jumps: mobility.air_jumps,
+            health: health.map(|health| health.current),
+        }
+    }
+
+    fn restore(
+        &self,
+        transform: &mut Transform,
+        velocity: &mut Velocity,
+        mobility: &mut Mobility,
+        pose: &mut Pose,
+        hitboxes: Option<&StanceHitboxes>,
+        health: Option<&mut Health>,
+    ) {
+        transform.translation = self.translation;
+        if let Some(hitboxes) = hitboxes {
+            transform.scale = hitboxes.size(self.stance).extend(transform.scale.z);
+        }
+        velocity.0 = self.velocity;
+        pose.stance = self.stance;
+        pose.requested = self.stance;
+        mobility.walk_direction = self.walk_direction;
+        mobility.on_ground = self.on_ground;
+        mobility.fast_falling = self.fast_falling;
+        mobility.on_wall = self.on_wall;
+        mobility.wall_coyote_timer = self.wall_coyote_timer;
+        mobility.crouching = self.stance == Stance::Crouching;
+        mobility.sliding = self.sliding;
+        mobility.air_jumps = self.air_jumps;
+        if let (Some(health), Some(current)) = (health, self.health) {
+            health.current = current;
+        }
+    }
+}
+
+// Put the game back as it was in the snapshot
+pub struct RestoreSnapshot(pub Snapshot);
+
+#[derive(Default)]
+struct QuickSaveSlot(Option<Snapshot>);
+
+// Needs the `ActionState` from `InputMapPlugin`, the `CurrentLevel` from
+// `LevelPlugin`, the `TileIndex` from `TilePlugin`, the `FixedStepReset` event
+// from `GameStatePlugin` and the `Score`, `CoinCount` and `Unspawned` from
+// `GamePlugin`
+#[derive(Default)]
+pub struct QuickSavePlugin;
+
+impl Plugin for QuickSavePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<QuickSaveSlot>()
+            .add_event::<RestoreSnapshot>()
+            // After the physics, so nothing moves between loading and drawing
+            .add_system(quick_save_system.after(PhysicsSystem::Camera))
+            .add_system(quick_load_system.after(quick_save_system))
+            .add_system(restore_tiles_system.after(quick_load_system))
+            .add_system(restore_players_system.after(quick_load_system))
+            .add_system(restore_score_system.after(quick_load_system))
+            .add_system(restore_coins_system.after(quick_load_system));
+    }
+}
+
+// Everything a snapshot captures of each player
+type SavedPlayerQuery<'w, 's> = Query<
+    'w,
+    's,
+    (
+        &'static PlayerId,
+        &'static Transform,
+        &'static Velocity,
+        &'static Mobility,
+        &'static Pose,
+        Option<&'static Health>,
+    ),
+>;
+
+#[allow(clippy::too_many_arguments)]
+fn quick_save_system(
+    action_state: Res<ActionState>,
+    current_level: Res<CurrentLevel>,
+    tile_index: Res<TileIndex>,
+    asset_server: Res<AssetServer>,
+    tile_query: TileDataQuery,
+    player_query: SavedPlayerQuery,
+    score: Res<Score>,
+    coin_count: Res<CoinCount>,
+    unspawned: Res<Unspawned>,
+    mut slot: ResMut<QuickSaveSlot>,
+) {
+    if !action_state.just_pressed(Action::QuickSave) {
+        return;
+    }
+    let mut players: Vec<PlayerSnapshot> = player_query
+        .iter()
+        .map(|(&id, transform, velocity, mobility, pose, health)| {
+            PlayerSnapshot::capture(id, transform, velocity, mobility, pose, health)
+        })
+        .collect();
+    players.sort_by_key(|player| player.id);
+    let snapshot = Snapshot {
+        level_path: current_level.path.clone(),
+        unsaved: current_level.unsaved,
+        tiles: current_tiles(&tile_index, &asset_server, &tile_query),
+        players,
+        score: score.0,
+        coins: coin_count.0,
+        collected_coins: unspawned.0.coins.clone(),
+    };
+    if !cfg!(target_arch = "wasm32") {
+        if let Err(err) = snapshot.save(QUICKSAVE_PATH) {
+            warn!("Failed to save {}: {}", QUICKSAVE_PATH, err);
+        }
+    }
+    info!("Quick saved");
+    slot.0 = Some(snapshot);
+}
+
+fn quick_load_system(
+    action_state: Res<ActionState>,
+    slot: Res<QuickSaveSlot>,
+    mut restore_events: EventWriter<RestoreSnapshot>,
+) {
+    if !action_state.just_pressed(Action::QuickLoad) {
+        return;
+    }
+    let snapshot = match &slot.0 {
+        Some(snapshot) => snapshot.clone(),
+        None if cfg!(target_arch = "wasm32") => {
+            info!("Nothing has been quick saved");
+            return;
+        }
+        None => match Snapshot::load(QUICKSAVE_PATH) {
+            Ok(snapshot) => snapshot,
+            Err(err) => {
+                info!("Nothing to quick load: {}: {}", QUICKSAVE_PATH, err);
+                return;
+            }
+        },
+    };
+    info!("Quick loaded");
+    restore_events.send(RestoreSnapshot(snapshot));
+}
+
+// Only cells whose tile differs from the snapshot are respawned
+fn restore_tiles_system(
+    mut commands: Commands,
+    mut restore_events: EventReader<RestoreSnapshot>,
+    mut current_level: ResMut<CurrentLevel>,
+    mut tile_index: ResMut<TileIndex>,
+    asset_server: Res<AssetServer>,
+    tile_query: TileDataQuery,
+) {
+    for RestoreSnapshot(snapshot) in restore_events.iter() {
+        let mut current: HashMap<IVec2, TileData> =
+            current_tiles(&tile_index, &asset_server, &tile_query)
+                .into_iter()
+                .map(|tile| (tile.pos, tile))
+                .collect();
+        for tile in &snapshot.tiles {
+            if current.remove(&tile.pos).as_ref() != Some(tile) {
+                tile_index.despawn(&mut commands, tile.pos);
+                tile.spawn(&mut commands, &asset_server, &mut tile_index, IVec2::ZERO);
+            }
+        }
+        // Tiles placed since the snapshot
+        for &pos in current.keys() {
+            tile_index.despawn(&mut commands, pos);
+        }
+        current_level.path = snapshot.level_path.clone();
+        current_level.unsaved = snapshot.unsaved;
+    }
+}
+
+fn restore_score_system(
+    mut restore_events: EventReader<RestoreSnapshot>,
+    mut score: ResMut<Score>,
+) {
+    for RestoreSnapshot(snapshot) in restore_events.iter() {
+        score.0 = snapshot.score;
+    }
+}
+
+// Coins collected since the snapshot are spawned again, and any collected
+// before it which are back, such as after reloading the level, are taken away
+fn restore_coins_system(
+    mut commands: Commands,
+    mut restore_events: EventReader<RestoreSnapshot>,
+    asset_server: Res<AssetServer>,
+    mut coin_count: ResMut<CoinCount>,
+    mut unspawned: ResMut<Unspawned>,
+    coin_query: Query<(Entity, &Transform), With<Coin>>,
+) {
+    for RestoreSnapshot(snapshot) in restore_events.iter() {
+        for &cell in &unspawned.0.coins {
+            if !snapshot.collected_coins.contains(&cell) {
+                spawn_coin(&mut commands, asset_server.load(COIN_TEXTURE), cell);
+            }
+        }
+        for (coin, transform) in coin_query.iter() {
+            if snapshot.collected_coins.contains(&coin_cell(transform)) {
+                commands.entity(coin).despawn_recursive();
+            }
+        }
+        unspawned.0.coins = snapshot.collected_coins.clone();
+        coin_count.0 = snapshot.coins;
+    }
+}
+
+// Everything a snapshot restores of each player
+type RestoredPlayerQuery<'w, 's> = Query<
+    'w,
+    's,
+    (
+        &'static PlayerId,
+        &'static mut Transform,
+        &'static mut Velocity,
+        &'static mut Mobility,
+        &'static mut Pose,
+        Option<&'static StanceHitboxes>,
+        Option<&'static mut Health>,
+    ),
+>;
+
+// Also drops the time the fixed steps were behind by, so the players don't
+// jump ahead of where they were saved, and has the camera snap to them on its
+// next update rather than pan
+fn restore_players_system(
+    mut restore_events: EventReader<RestoreSnapshot>,
+    mut controller: ResMut<CameraController>,
+    mut player_query: RestoredPlayerQuery,
+    mut fixed_step_resets: EventWriter<FixedStepReset>,
+) {
+    for RestoreSnapshot(snapshot) in restore_events.iter() {
+        for (id, mut transform, mut velocity, mut mobility, mut pose, hitboxes, mut health) in
+            player_query.iter_mut()
+        {
+            if let Some(saved) = snapshot.players.iter().find(|player| player.id == id.0) {
+                saved.restore(
+                    &mut transform,
+                    &mut velocity,
+                    &mut mobility,
+                    &mut pose,
+                    hitboxes,
+                    health.as_deref_mut(),
+                );
+            }
+        }
+        controller.snap();
+        fixed_step_resets.send(FixedStepReset);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::headless::{floor, HeadlessGame};
+    use crate::input::Action;
+    use crate::level::LevelData;
+    use crate::player::PLAYER_MAX_HEALTH;
+    use bevy::asset::FileAssetIo;
+    use bevy::ecs::event::Events;
+    use bevy::tasks::TaskPoolBuilder;
+
+    #[test]
+    fn loading_gives_back_coins_and_health_lost_since_saving() {
+        let mut game = HeadlessGame::new();
+        game.app
+            .insert_resource(AssetServer::new(
+                FileAssetIo::new("assets", false),
+                TaskPoolBuilder::new().build(),
+            ))
+            .init_resource::<CurrentLevel>()
+            .add_plugin(QuickSavePlugin);
+        let level = LevelData {
+            coins: vec![IVec2::new(2, 1), IVec2::new(4, 1)],
+            ..floor(-2..=8)
+        };
+        game.spawn_level(&level);
+        game.place_player(Vec2::new(0., 1.));
+        game.step(&[]);
+
+        // As if saved with the first coin already collected
+        let player = game.app.world.entity(game.player());
+        let saved = PlayerSnapshot::capture(
+            PlayerId(0),
+            player.get().unwrap(),
+            player.get().unwrap(),
+            player.get().unwrap(),
+            player.get().unwrap(),
+            player.get(),
+        );
+        let snapshot = Snapshot {
+            level_path: None,
+            unsaved: false,
+            tiles: level.tiles.clone(),
+            players: vec![saved],
+            score: 0,
+            coins: 1,
+            collected_coins: vec![IVec2::new(2, 1)],
+        };
+
+        game.run(120, &[Action::MoveRight]);
+        assert_eq!(game.app.world.resource::<CoinCount>().0, 2);
+        game.app
+            .world
+            .get_mut::<Health>(game.player())
+            .unwrap()
+            .current = 1;
+
+        game.app
+            .world
+            .resource_mut::<Events<RestoreSnapshot>>()
+            .send(RestoreSnapshot(snapshot));
+        game.step(&[]);
+        assert_eq!(game.app.world.resource::<CoinCount>().0, 1);
+        let mut coins = game.app.world.query_filtered::<&Transform, With<Coin>>();
+        let left: Vec<IVec2> = coins.iter(&game.app.world).map(coin_cell).collect();
+        assert_eq!(left, vec![IVec2::new(4, 1)]);
+        assert_eq!(
+            game.app.world.resource::<Unspawned>().0.coins,
+            vec![IVec2::new(2, 1)]
+        );
+        let health = game.app.world.get::<Health>(game.player()).unwrap();
+        assert_eq!(health.current, PLAYER_MAX_HEALTH);
+    }
+}
@@ -0,0 +1,244 @@
+// Recording and playback of the action layer, for reproducing physics bugs.
+//
+// `--record <file>` writes one RON `ReplayFrame` per line: the frame's delta
+// time and the actions held during it. Every `CHECKSUM_INTERVAL` frames it
+// also stores a hash of the transforms of the `ReplayChecked` entities.
+// `--replay <file>` ignores the real devices and drives `ActionState` and the
+// fixed timesteps from the log instead, and complains loudly whenever a
+// stored hash doesn't match, which means the simulation has diverged.
+//
+// Only the first player's action layer is recorded, so mouse editing and a
+// co-op second player aren't replayed.
+
+use bevy::prelude::*;
+use bevy::utils::HashSet;
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::DefaultHasher;
+use std::fs::File;
+use std::hash::{Hash, Hasher};
+use std::io::{LineWriter, Write};
+
+use crate::input::{Action, ActionState, ActionStateUpdate, IgnoreDevices};
+
+pub const CHECKSUM_INTERVAL: u64 = 60;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ReplayFrame {
+    pub delta: f64,
+    pub pressed: Vec<Action>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub checksum: Option<u64>,
+}
+
+// Entities whose transforms are hashed to detect a diverging replay
+#[derive(Component)]
+pub struct ReplayChecked;
+
+// While present, the fixed timesteps advance by this many seconds each frame
+// in place of the real frame time
+pub struct ReplayDelta(pub f64);
+
+#[derive(Clone, Debug, Default)]
+pub enum ReplayMode {
+    #[default]
+    Off,
+    Record(String),
+    Replay(String),
+}
+
+impl ReplayMode {
+    // From `--record <file>` or `--replay <file>` among the launch arguments
+    pub fn from_args(args: impl IntoIterator<Item = String>) -> Self {
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let mode: fn(String) -> Self = match arg.as_str() {
+                "--record" => ReplayMode::Record,
+                "--replay" => ReplayMode::Replay,
+                _ => continue,
+            };
+            return args.next().map_or(ReplayMode::Off, mode);
+        }
+        ReplayMode::Off
+    }
+}
+
+struct Recorder {
+    file: LineWriter<File>,
+    frame: u64,
+}
+
+struct Playback {
+    frames: Vec<ReplayFrame>,
+    frame: usize,
+    desyncs: usize,
+}
+
+#[derive(Default)]
+pub struct ReplayPlugin {
+    pub mode: ReplayMode,
+}
+
+impl Plugin for ReplayPlugin {
+    fn build(&self, app: &mut App) {
+        match &self.mode {
+            ReplayMode::Off => {}
+            ReplayMode::Record(path) => match File::create(path) {
+                Ok(file) => {
+                    info!("Recording input to {}", path);
+                    app.insert_resource(Recorder {
+                        file: LineWriter::new(file),
+                        frame: 0,
+                    })
+                    .add_system_to_stage(CoreStage::Last, record_system);
+                }
+                Err(err) => error!("{}: {}", path, err),
+            },
+            ReplayMode::Replay(path) => match load_frames(path) {
+                Ok(frames) => {
+                    info!("Replaying {} frames from {}", frames.len(), path);
+                    app.insert_resource(Playback {
+                        frames,
+                        frame: 0,
+                        desyncs: 0,
+                    })
+                    .insert_resource(IgnoreDevices)
+                    .add_system_to_stage(CoreStage::First, replay_delta_system)
+                    .add_system_to_stage(
+                        CoreStage::PreUpdate,
+                        replay_actions_system.label(ActionStateUpdate),
+                    )
+                    .add_system_to_stage(CoreStage::Last, replay_check_system);
+                }
+                Err(err) => error!("{}: {}", path, err),
+            },
+        }
+    }
+}
+
+fn load_frames(path: &str) -> Result<Vec<ReplayFrame>, String> {
+    let text = std::fs::read_to_string(path).map_err(|err| err.to_string())?;
+    text.lines()
+        .filter(|line| !line.trim().is_empty())
+        .enumerate()
+        .map(|(index, line)| ron::from_str(line).map_err(|err| format!("frame {}: {}", index, err)))
+        .collect()
+}
+
+// Hashes the exact bits, so any difference at all is caught
+fn checksum<'a>(transforms: impl Iterator<Item = (Entity, &'a Transform)>) -> u64 {
+    let mut transforms: Vec<_> = transforms.collect();
+    transforms.sort_unstable_by_key(|(entity, _)| entity.id());
+    let mut hasher = DefaultHasher::new();
+    for (_, transform) in transforms {
+        for value in transform.translation.to_array() {
+            value.to_bits().hash(&mut hasher);
+        }
+    }
+    hasher.finish()
+}
+
+fn pressed_actions(action_state: &ActionState) -> Vec<Action> {
+    Action::ALL
+        .iter()
+        .copied()
+        .filter(|&action| action_state.pressed(action))
+        .collect()
+}
+
+fn record_system(
+    time: Res<Time>,
+    action_state: Res<ActionState>,
+    mut recorder: ResMut<Recorder>,
+    query: Query<(Entity, &Transform), With<ReplayChecked>>,
+) {
+    recorder.frame += 1;
+    let frame = ReplayFrame {
+        delta: time.delta_seconds_f64(),
+        pressed: pressed_actions(&action_state),
+        checksum: recorder
+            .frame
+            .is_multiple_of(CHECKSUM_INTERVAL)
+            .then(|| checksum(query.iter())),
+    };
+    let result = ron::to_string(&frame)
+        .map_err(|err| err.to_string())
+        .and_then(|line| writeln!(recorder.file, "{}", line).map_err(|err| err.to_string()));
+    if let Err(err) = result {
+        error!("Failed to record frame {}: {}", recorder.frame, err);
+    }
+}
+
+fn replay_delta_system(mut commands: Commands, playback: Res<Playback>) {
+    match playback.frames.get(playback.frame) {
+        Some(frame) => commands.insert_resource(ReplayDelta(frame.delta)),
+        None => commands.remove_resource::<ReplayDelta>(),
+    }
+}
+
+fn replay_actions_system(playback: Res<Playback>, mut action_state: ResMut<ActionState>) {
+    if let Some(frame) = playback.frames.get(playback.frame) {
+        action_state.update(frame.pressed.iter().copied().collect::<HashSet<_>>());
+    }
+}
+
+fn replay_check_system(
+    mut commands: Commands,
+    mut playback: ResMut<Playback>,
+    query: Query<(Entity, &Transform), With<ReplayChecked>>,
+) {
+    let index = playback.frame;
+    let expected = match playback.frames.get(index) {
+        Some(frame) => frame.checksum,
+        None => return,
+    };
+    if let Some(expected) = expected {
+        let actual = checksum(query.iter());
+        if actual != expected {
+            playback.desyncs += 1;
+            error!(
+                "REPLAY DESYNC at frame {}: expected checksum {:016x}, got {:016x}",
+                index + 1,
+                expected,
+                actual
+            );
+        }
+    }
+    playback.frame += 1;
+    if playback.frame == playback.frames.len() {
+        if playback.desyncs == 0 {
+            info!("Replay finished in sync");
+        } else {
+            error!("Replay finished with {} desyncs", playback.desyncs);
+        }
+        // Hand control back to the real devices
+        commands.remove_resource::<IgnoreDevices>();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_mode_from_args() {
+        let args = |args: &[&str]| ReplayMode::from_args(args.iter().map(|arg| arg.to_string()));
+        assert!(matches!(args(&["game"]), ReplayMode::Off));
+        assert!(matches!(
+            args(&["game", "--replay", "jump.ron"]),
+            ReplayMode::Replay(path) if path == "jump.ron"
+        ));
+        assert!(matches!(args(&["game", "--record"]), ReplayMode::Off));
+    }
+
+    #[test]
+    fn frame_round_trips_through_one_line() {
+        let frame = ReplayFrame {
+            delta: 1. / 60.,
+            pressed: vec![Action::MoveLeft, Action::Jump],
+            checksum: Some(u64::MAX),
+        };
+        let line = ron::to_string(&frame).unwrap();
+        assert!(!line.contains('\n'));
+        assert_eq!(ron::from_str::<ReplayFrame>(&line).unwrap(), frame);
+    }
+}
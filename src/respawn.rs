@@ -0,0 +1,257 @@
+// Resetting a player to the spawn by holding the reset action. The screen
+// fades out, the player is moved while it's dark, and it fades back in. The
+// game is held in `GameState::Respawning` throughout, so nothing moves, and
+// for a moment after it the players' presses are ignored so nobody sets off
+// before they've seen where they are.
+//
+// Deaths don't come through here. They fade out just the player who died,
+// in `death`, while anyone else plays on, which holding the whole game still
+// behind a fade of the screen would stop, and the lock here would swallow
+// the others' presses too. A dying player's controls are off until they've
+// faded back in at the spawn, so they need no lock of their own.
+
+use bevy::prelude::*;
+
+use crate::camera::CameraController;
+use crate::game_state::{GameState, PhysicsSubsteps};
+use crate::level::PlayerSpawn;
+use crate::physics::{Direction, Mobility, Velocity};
+use crate::player::{spawn_point, Player};
+use crate::replay::ReplayDelta;
+
+// How a reset looks. Zero for both fades moves the player at once, with no
+// fade and no lock.
+pub struct RespawnFade {
+    // Seconds spent fading to black, then back from it at the spawn
+    pub fade_out_time: f32,
+    pub fade_in_time: f32,
+    // Seconds after the fade in during which the players can't act
+    pub input_lock_time: f32,
+}
+
+impl Default for RespawnFade {
+    fn default() -> Self {
+        RespawnFade {
+            fade_out_time: 0.25,
+            fade_in_time: 0.25,
+            input_lock_time: 0.2,
+        }
+    }
+}
+
+impl RespawnFade {
+    fn enabled(&self) -> bool {
+        self.fade_out_time + self.fade_in_time > 0.
+    }
+}
+
+// The reset in progress, if any
+#[derive(Default)]
+pub struct RespawnState {
+    // Players waiting to be moved to the spawn
+    resetting: Vec<Entity>,
+    // Seconds since the fade out began
+    elapsed: f32,
+    // Seconds left of the input lock
+    lock: f32,
+}
+
+impl RespawnState {
+    // Send `player` back to the spawn at the end of this input step
+    pub fn request(&mut self, player: Entity) {
+        if !self.resetting.contains(&player) {
+            self.resetting.push(player);
+        }
+    }
+
+    pub fn input_locked(&self) -> bool {
+        self.lock > 0.
+    }
+
+    // How dark the screen is, from 0 up to 1 and back down
+    pub fn darkness(&self, fade: &RespawnFade) -> f32 {
+        let fade_in = self.elapsed - fade.fade_out_time;
+        if self.elapsed <= 0. {
+            0.
+        } else if fade_in < 0. {
+            self.elapsed / fade.fade_out_time
+        } else if fade.fade_in_time > 0. {
+            (1. - fade_in / fade.fade_in_time).max(0.)
+        } else {
+            0.
+        }
+    }
+}
+
+fn move_to_spawn(
+    spawn: Vec2,
+    transform: &mut Transform,
+    velocity: &mut Velocity,
+    mobility: &mut Mobility,
+) {
+    transform.translation = spawn.extend(0.);
+    velocity.0 = Vec3::ZERO;
+    // Directions held through the fade have to be pressed again
+    mobility.walk_direction = Direction::Neutral;
+    mobility.fast_falling = false;
+}
+
+// After `player_control_system`, which requests the resets. Without a fade
+// they happen now.
+pub fn start_respawn_system(
+    fade: Res<RespawnFade>,
+    mut respawn: ResMut<RespawnState>,
+    mut state: ResMut<State<GameState>>,
+    spawn_query: Query<&Transform, (With<PlayerSpawn>, Without<Player>)>,
+    mut player_query: Query<(&mut Transform, &mut Velocity, &mut Mobility), With<Player>>,
+) {
+    if respawn.resetting.is_empty() || respawn.elapsed > 0. {
+        return;
+    }
+    if fade.enabled() {
+        // Fails only if a transition is already queued this frame, in which
+        // case it's tried again next step
+        let _ = state.set(GameState::Respawning);
+        return;
+    }
+    let spawn = spawn_point(spawn_query.iter().next());
+    for player in std::mem::take(&mut respawn.resetting) {
+        if let Ok((mut transform, mut velocity, mut mobility)) = player_query.get_mut(player) {
+            move_to_spawn(spawn, &mut transform, &mut velocity, &mut mobility);
+        }
+    }
+}
+
+// While `GameState::Respawning`. The players are moved once the screen is
+// dark, and the game carries on once it has faded back in.
+#[allow(clippy::too_many_arguments)]
+pub fn respawn_fade_system(
+    time: Res<Time>,
+    replay_delta: Option<Res<ReplayDelta>>,
+    fade: Res<RespawnFade>,
+    mut respawn: ResMut<RespawnState>,
+    mut state: ResMut<State<GameState>>,
+    mut controller: ResMut<CameraController>,
+    spawn_query: Query<&Transform, (With<PlayerSpawn>, Without<Player>)>,
+    mut player_query: Query<(&mut Transform, &mut Velocity, &mut Mobility), With<Player>>,
+) {
+    respawn.elapsed += match replay_delta {
+        Some(delta) => delta.0 as f32,
+        None => time.delta_seconds(),
+    };
+    if respawn.elapsed >= fade.fade_out_time && !respawn.resetting.is_empty() {
+        let spawn = spawn_point(spawn_query.iter().next());
+        for player in std::mem::take(&mut respawn.resetting) {
+            if let Ok((mut transform, mut velocity, mut mobility)) = player_query.get_mut(player) {
+                move_to_spawn(spawn, &mut transform, &mut velocity, &mut mobility);
+            }
+        }
+        controller.snap();
+    }
+    if respawn.elapsed >= fade.fade_out_time + fade.fade_in_time
+        && state.set(GameState::Playing).is_ok()
+    {
+        respawn.elapsed = 0.;
+        respawn.lock = fade.input_lock_time;
+    }
+}
+
+// Once per input step, after the step's presses have been discarded if the
+// input is locked
+pub fn respawn_lock_system(substeps: Res<PhysicsSubsteps>, mut respawn: ResMut<RespawnState>) {
+    respawn.lock = (respawn.lock - substeps.input_time_step()).max(0.);
+}
+
+// A black cover over the whole screen
+#[derive(Component)]
+struct ScreenFade;
+
+// Draws the fade. Needs the `RespawnFade` and `RespawnState` from
+// `GamePlugin`.
+#[derive(Default)]
+pub struct ScreenFadePlugin;
+
+impl Plugin for ScreenFadePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_startup_system(spawn_screen_fade)
+            .add_system(screen_fade_system);
+    }
+}
+
+fn spawn_screen_fade(mut commands: Commands) {
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.), Val::Percent(100.)),
+                position_type: PositionType::Absolute,
+                ..default()
+            },
+            color: Color::NONE.into(),
+            ..default()
+        })
+        .insert(ScreenFade);
+}
+
+fn screen_fade_system(
+    fade: Res<RespawnFade>,
+    respawn: Res<RespawnState>,
+    mut query: Query<&mut UiColor, With<ScreenFade>>,
+) {
+    if !respawn.is_changed() {
+        return;
+    }
+    for mut color in query.iter_mut() {
+        color.0 = Color::rgba(0., 0., 0., respawn.darkness(&fade));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::headless::{floor, HeadlessGame};
+    use crate::input::{Action, InputMap};
+    use crate::physics::PHYSICS_TIME_STEP;
+
+    #[test]
+    fn reset_fades_with_the_game_held_then_locks_input() {
+        let mut game = HeadlessGame::new();
+        game.spawn_level(&floor(-10..=10));
+        game.place_player(Vec2::new(3., 1.));
+        game.run(10, &[]);
+        let state = |game: &HeadlessGame| *game.app.world.resource::<State<GameState>>().current();
+
+        // Walking right while the reset is held
+        let hold_time = game.app.world.resource::<InputMap>().reset_hold_time;
+        let mut steps = 0;
+        while state(&game) == GameState::Playing {
+            game.step(&[Action::Reset, Action::MoveRight]);
+            steps += 1;
+            assert!(steps < 1000, "never reset");
+        }
+        assert!(steps as f32 * PHYSICS_TIME_STEP >= hold_time);
+        let held_at = game.player_transform().translation;
+        assert!(held_at.x > 3.);
+
+        // Nothing moves while fading out
+        let fade = RespawnFade::default();
+        let fade_out_steps = (fade.fade_out_time / PHYSICS_TIME_STEP) as u32;
+        game.run(fade_out_steps - 2, &[Action::MoveRight]);
+        assert_eq!(game.player_transform().translation, held_at);
+
+        // At the spawn once it's dark, and playing again after the fade in
+        let fade_in_steps = (fade.fade_in_time / PHYSICS_TIME_STEP) as u32;
+        game.run(fade_in_steps + 4, &[]);
+        assert_eq!(state(&game), GameState::Playing);
+        let spawn = spawn_point(None).extend(0.);
+        assert_eq!(game.player_transform().translation, spawn);
+
+        // A press during the lock is ignored
+        assert!(game.app.world.resource::<RespawnState>().input_locked());
+        game.step(&[Action::MoveRight]);
+        game.run(60, &[Action::MoveRight]);
+        assert_eq!(game.player_transform().translation, spawn);
+        game.step(&[]);
+        game.run(60, &[Action::MoveRight]);
+        assert!(game.player_transform().translation.x > spawn.x);
+    }
+}
@@ -0,0 +1,148 @@
+// Saw blades, which spin along a path and hurt any player they touch. Unlike
+// enemies they can't be stomped or shot, and they pass straight through
+// tiles, so a level's paths are checked for waypoints inside solid ones as
+// it's loaded.
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::death::LivePlayerQuery;
+use crate::health::Damage;
+use crate::level::LevelEntity;
+use crate::path::{PathMode, WaypointPath};
+use crate::physics::PHYSICS_TIME_STEP;
+use crate::player::Player;
+
+pub const SAW_COLOR: Color = Color::rgb(0.75, 0.75, 0.8);
+// Across the blade, in tiles
+const SAW_SIZE: f32 = 0.8;
+// Radians per second
+const SAW_SPIN_SPEED: f32 = 12.;
+// Health taken by touching a blade
+pub const SAW_DAMAGE: i32 = 1;
+
+// A saw blade in a level file
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SawData {
+    // The cells whose middles its center passes through, in order, starting
+    // at the first
+    pub path: Vec<IVec2>,
+    #[serde(default)]
+    pub mode: PathMode,
+    // In tiles per second
+    #[serde(default = "default_saw_speed")]
+    pub speed: f32,
+    // Only spawned in builds with the `test-saws` feature, for trying blades
+    // out in levels which aren't meant to have them
+    #[serde(default)]
+    pub test_only: bool,
+}
+
+fn default_saw_speed() -> f32 {
+    2.
+}
+
+#[derive(Component)]
+pub struct SawBlade {
+    // How it was placed, which the level is saved with rather than wherever
+    // it has got to along its path
+    pub data: SawData,
+}
+
+// Drawn as a square of `SAW_COLOR`, turning about its center. Blades without
+// a path, or only for testing in a build without it, aren't spawned.
+pub fn spawn_saw(commands: &mut Commands, data: &SawData) -> Option<Entity> {
+    if data.test_only && !cfg!(feature = "test-saws") {
+        return None;
+    }
+    let waypoints: Vec<Vec2> = data
+        .path
+        .iter()
+        .map(|cell| cell.as_vec2() + Vec2::splat(0.5))
+        .collect();
+    let start = *waypoints.first()?;
+    let saw = commands
+        .spawn_bundle(SpriteBundle {
+            transform: Transform::from_translation(start.extend(0.)),
+            sprite: Sprite {
+                color: SAW_COLOR,
+                custom_size: Some(Vec2::splat(SAW_SIZE)),
+                ..default()
+            },
+            ..default()
+        })
+        .insert(SawBlade { data: data.clone() })
+        .insert(WaypointPath::new(waypoints, data.mode, data.speed))
+        .insert(LevelEntity)
+        .id();
+    Some(saw)
+}
+
+// After the blades have moved along their paths. Anything touching the
+// circle of a blade is hurt, from its center.
+pub fn saw_system(
+    player_query: LivePlayerQuery,
+    mut saw_query: Query<&mut Transform, (With<SawBlade>, Without<Player>)>,
+    mut damage_events: EventWriter<Damage>,
+) {
+    for mut saw_transform in saw_query.iter_mut() {
+        saw_transform.rotate(Quat::from_rotation_z(-SAW_SPIN_SPEED * PHYSICS_TIME_STEP));
+        let center = saw_transform.translation.truncate();
+        for (player, transform) in player_query.iter() {
+            let min = transform.translation.truncate();
+            let max = min + transform.scale.truncate();
+            let closest = center.clamp(min, max);
+            if closest.distance_squared(center) < (SAW_SIZE / 2.).powi(2) {
+                damage_events.send(Damage {
+                    target: player,
+                    amount: SAW_DAMAGE,
+                    source: Some(center),
+                });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::headless::{floor, HeadlessGame};
+    use crate::health::Health;
+    use crate::level::{LevelData, TileData};
+
+    #[test]
+    fn blade_passes_through_tiles_and_hurts_the_player_it_reaches() {
+        let mut game = HeadlessGame::new();
+        // Through a wall on its way to the player
+        let mut level = LevelData {
+            saws: vec![SawData {
+                path: vec![IVec2::new(-4, 1), IVec2::new(4, 1)],
+                mode: PathMode::PingPong,
+                speed: 4.,
+                test_only: false,
+            }],
+            ..floor(-6..=6)
+        };
+        level.tiles.push(TileData::solid(IVec2::new(-2, 1)));
+        game.spawn_level(&level);
+        game.place_player(Vec2::new(0., 1.));
+        let health =
+            |game: &HeadlessGame| game.app.world.get::<Health>(game.player()).unwrap().current;
+        let max = health(&game);
+        // Into the wall, and on through it
+        game.run(90, &[]);
+        let center = game
+            .app
+            .world
+            .query_filtered::<&Transform, With<SawBlade>>()
+            .iter(&game.app.world)
+            .next()
+            .unwrap()
+            .translation;
+        assert!((center.x + 2.).abs() < 0.01, "at {}", center);
+        assert_eq!(health(&game), max);
+        // Out the other side and up to the player, 3.5 tiles on
+        game.run(120, &[]);
+        assert_eq!(health(&game), max - 1);
+    }
+}
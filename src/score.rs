@@ -0,0 +1,243 @@
+// Points for collecting coins, stomping enemies and finishing levels quickly.
+//
+// Coins, stomps and pickups for abilities the players already have are
+// multiplied by the `Combo`, which grows with each one
+// scored within `COMBO_WINDOW` of the last and starts over once that runs out
+// or a player is hurt. Finishing a level adds a bonus for each second under
+// `PAR_TIME`, which the combo doesn't multiply. `ScorePopupPlugin` shows each
+// score as a number drifting up from where it was made.
+
+use bevy::prelude::*;
+
+use crate::ability::AbilityCollected;
+use crate::coin::CoinCollected;
+use crate::death::Dying;
+use crate::enemy::EnemyStomped;
+use crate::health::Invincible;
+use crate::physics::PHYSICS_TIME_STEP;
+use crate::pixel_perfect::{spawn_world_ui_text, WorldAnchor, UI_FONT};
+use crate::player::Player;
+use crate::speedrun::LevelFinished;
+
+pub const COIN_POINTS: u64 = 10;
+pub const STOMP_POINTS: u64 = 50;
+// For collecting an ability which is already unlocked
+pub const OWNED_ABILITY_POINTS: u64 = 100;
+// Seconds after scoring in which scoring again grows the combo
+pub const COMBO_WINDOW: f32 = 2.;
+pub const MAX_COMBO: u64 = 8;
+// Finishing a level in less than this many seconds earns a bonus for each
+// second under
+pub const PAR_TIME: f32 = 120.;
+pub const TIME_BONUS_PER_SECOND: f32 = 10.;
+
+// Seconds a popup takes to drift up and fade out, and how far it drifts, in
+// tiles
+const POPUP_TIME: f32 = 0.8;
+const POPUP_RISE: f32 = 1.5;
+const POPUP_PIXELS: f32 = 8.;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Score(pub u64);
+
+pub struct Combo {
+    pub multiplier: u64,
+    // Seconds left in which scoring grows the multiplier
+    timer: f32,
+}
+
+impl Default for Combo {
+    fn default() -> Self {
+        Combo {
+            multiplier: 1,
+            timer: 0.,
+        }
+    }
+}
+
+impl Combo {
+    // `points` multiplied by the combo, which this scoring grows if it came
+    // soon enough after the last
+    pub fn score(&mut self, points: u64) -> u64 {
+        self.multiplier = if self.timer > 0. {
+            (self.multiplier + 1).min(MAX_COMBO)
+        } else {
+            1
+        };
+        self.timer = COMBO_WINDOW;
+        points * self.multiplier
+    }
+
+    pub fn advance(&mut self, dt: f32) {
+        self.timer = (self.timer - dt).max(0.);
+        if self.timer == 0. && self.multiplier != 1 {
+            self.multiplier = 1;
+        }
+    }
+
+    pub fn reset(&mut self) {
+        *self = Combo::default();
+    }
+}
+
+// Points added to the `Score`, centred `at`
+pub struct Scored {
+    pub points: u64,
+    pub at: Vec2,
+}
+
+// The bonus for finishing a level in `seconds`
+pub fn time_bonus(seconds: f32) -> u64 {
+    ((PAR_TIME - seconds).max(0.) * TIME_BONUS_PER_SECOND).round() as u64
+}
+
+// The players just hurt or killed
+type HurtPlayerQuery<'w, 's> =
+    Query<'w, 's, (), (With<Player>, Or<(Added<Invincible>, Added<Dying>)>)>;
+
+// In the physics step, after anything which sends what's scored for and
+// `damage_system`
+#[allow(clippy::too_many_arguments)]
+pub fn score_system(
+    mut score: ResMut<Score>,
+    mut combo: ResMut<Combo>,
+    mut coin_events: EventReader<CoinCollected>,
+    mut stomped_events: EventReader<EnemyStomped>,
+    mut ability_events: EventReader<AbilityCollected>,
+    mut finished_events: EventReader<LevelFinished>,
+    mut scored_events: EventWriter<Scored>,
+    player_query: Query<&Transform, With<Player>>,
+    hurt_query: HurtPlayerQuery,
+) {
+    if !hurt_query.is_empty() {
+        combo.reset();
+    }
+    // Without marking it changed while it stands still
+    if combo.timer > 0. {
+        combo.advance(PHYSICS_TIME_STEP);
+    }
+    let mut add = |points: u64, at: Vec2| {
+        score.0 += points;
+        scored_events.send(Scored { points, at });
+    };
+    for collected in coin_events.iter() {
+        add(combo.score(COIN_POINTS), collected.at);
+    }
+    for stomped in stomped_events.iter() {
+        add(combo.score(STOMP_POINTS), stomped.at);
+    }
+    for collected in ability_events.iter().filter(|collected| collected.owned) {
+        add(combo.score(OWNED_ABILITY_POINTS), collected.at);
+    }
+    for finished in finished_events.iter() {
+        let bonus = time_bonus(finished.seconds);
+        let at = match player_query.get(finished.player) {
+            Ok(transform) => transform.translation.truncate() + transform.scale.truncate() / 2.,
+            Err(_) => continue,
+        };
+        if bonus > 0 {
+            add(bonus, at);
+        }
+    }
+}
+
+#[derive(Component)]
+struct ScorePopup {
+    from: Vec2,
+    // Seconds since it was spawned
+    elapsed: f32,
+}
+
+// Needs the `Scored` events from `GamePlugin` and the world UI layer from
+// `PixelPerfectPlugin`
+#[derive(Default)]
+pub struct ScorePopupPlugin;
+
+impl Plugin for ScorePopupPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(spawn_popup_system)
+            .add_system(popup_system.after(spawn_popup_system));
+    }
+}
+
+fn spawn_popup_system(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut scored_events: EventReader<Scored>,
+) {
+    for scored in scored_events.iter() {
+        let text = Text::with_section(
+            format!("+{}", scored.points),
+            TextStyle {
+                font: asset_server.load(UI_FONT),
+                font_size: POPUP_PIXELS,
+                color: Color::WHITE,
+            },
+            TextAlignment {
+                vertical: VerticalAlign::Center,
+                horizontal: HorizontalAlign::Center,
+            },
+        );
+        let popup = spawn_world_ui_text(&mut commands, scored.at, text);
+        commands.entity(popup).insert(ScorePopup {
+            from: scored.at,
+            elapsed: 0.,
+        });
+    }
+}
+
+// Drift up and fade out, then despawn
+fn popup_system(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut query: Query<(Entity, &mut ScorePopup, &mut WorldAnchor, &mut Text)>,
+) {
+    for (entity, mut popup, mut anchor, mut text) in query.iter_mut() {
+        popup.elapsed += time.delta_seconds();
+        let progress = popup.elapsed / POPUP_TIME;
+        if progress >= 1. {
+            commands.entity(entity).despawn();
+            continue;
+        }
+        anchor.0 = popup.from + Vec2::new(0., POPUP_RISE * progress);
+        text.sections[0].style.color.set_a(1. - progress * progress);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::coin::CoinCount;
+    use crate::headless::{floor, HeadlessGame};
+    use crate::input::Action;
+    use crate::level::LevelData;
+
+    #[test]
+    fn combo_grows_within_the_window_and_lapses_after() {
+        let mut combo = Combo::default();
+        assert_eq!(combo.score(10), 10);
+        combo.advance(COMBO_WINDOW / 2.);
+        assert_eq!(combo.score(10), 20);
+        assert_eq!(combo.score(10), 30);
+        combo.advance(COMBO_WINDOW);
+        assert_eq!(combo.multiplier, 1);
+        assert_eq!(combo.score(10), 10);
+    }
+
+    #[test]
+    fn coins_in_a_row_score_with_a_growing_combo() {
+        let mut game = HeadlessGame::new();
+        let level = LevelData {
+            coins: vec![IVec2::new(2, 1), IVec2::new(3, 1), IVec2::new(4, 1)],
+            ..floor(-2..=8)
+        };
+        game.spawn_level(&level);
+        game.place_player(Vec2::new(0., 1.));
+        game.run(120, &[Action::MoveRight]);
+        assert_eq!(game.app.world.resource::<CoinCount>().0, 3);
+        assert_eq!(
+            game.app.world.resource::<Score>().0,
+            COIN_POINTS * (1 + 2 + 3)
+        );
+    }
+}
@@ -0,0 +1,91 @@
+// Player preferences which persist between runs.
+//
+// They are stored as RON next to the executable's working directory.
+// On wasm there is no filesystem, so the defaults are always used and
+// nothing is saved.
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::ability::Ability;
+use crate::collision_outline::CollisionOutline;
+use crate::cursor::CellRounding;
+use crate::sfx::AudioSettings;
+
+pub const SETTINGS_PATH: &str = "settings.ron";
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    // Ignored on wasm, where the game fills the canvas
+    pub fullscreen: bool,
+    // Window pixels per world pixel while windowed
+    pub render_scale: u32,
+    // Quit on Escape straight away rather than opening the system menu
+    pub escape_quits: bool,
+    // Keep the cursor inside the window while editing in debug mode
+    pub confine_cursor: bool,
+    // On-screen buttons to move and jump with the mouse or touches
+    pub virtual_buttons: bool,
+    // How the editor picks the cell under the cursor
+    pub cell_rounding: CellRounding,
+    // Of the outline showing what of the solid tiles collides
+    pub collision_outline_color: Color,
+    // The fastest finish of each level, in seconds, by its file's path
+    pub best_times: BTreeMap<String, f32>,
+    // Movement abilities the players have unlocked
+    pub abilities: BTreeSet<Ability>,
+    // As set in the pause menu, from 0 to 1
+    pub sfx_volume: f32,
+    pub music_volume: f32,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            fullscreen: true,
+            render_scale: 2,
+            escape_quits: false,
+            confine_cursor: false,
+            virtual_buttons: false,
+            cell_rounding: CellRounding::Floor,
+            collision_outline_color: CollisionOutline::default().color,
+            best_times: BTreeMap::new(),
+            abilities: BTreeSet::new(),
+            sfx_volume: AudioSettings::default().sfx_volume,
+            music_volume: AudioSettings::default().music_volume,
+        }
+    }
+}
+
+impl Settings {
+    pub fn load() -> Self {
+        if cfg!(target_arch = "wasm32") {
+            return Settings::default();
+        }
+        match std::fs::read_to_string(SETTINGS_PATH) {
+            Ok(text) => ron::from_str(&text).unwrap_or_else(|err| {
+                warn!("{}: {}, using default settings", SETTINGS_PATH, err);
+                Settings::default()
+            }),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Settings::default(),
+            Err(err) => {
+                warn!("{}: {}, using default settings", SETTINGS_PATH, err);
+                Settings::default()
+            }
+        }
+    }
+
+    pub fn save(&self) {
+        if cfg!(target_arch = "wasm32") {
+            return;
+        }
+        let result = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+            .map_err(|err| err.to_string())
+            .and_then(|text| std::fs::write(SETTINGS_PATH, text).map_err(|err| err.to_string()));
+        if let Err(err) = result {
+            warn!("Failed to save {}: {}", SETTINGS_PATH, err);
+        }
+    }
+}
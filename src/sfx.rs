@@ -0,0 +1,273 @@
+// Sound effects: jumping, landing, and placing and erasing tiles in the
+// editor. Anything else can send `PlaySfx` for a sound to be played the same
+// way, at the `AudioSettings` volume unless muted.
+//
+// Each sound plays at most once per `SFX_MIN_INTERVAL`, so a quick run of
+// events, such as a drag across many cells, doesn't pile up into noise. On
+// the web, browsers only allow sound once the page has been interacted with,
+// so nothing plays before the first key, button or touch.
+
+use bevy::asset::LoadState;
+use bevy::audio::{Audio, AudioSource, PlaybackSettings};
+use bevy::prelude::*;
+use bevy::utils::{HashMap, HashSet};
+
+use crate::input::{Action, ActionState};
+use crate::physics::Landed;
+use crate::player::{Jumped, Player};
+use crate::tile::{TilePlaced, TileRemoved};
+
+// Seconds within which a sound isn't played again
+pub const SFX_MIN_INTERVAL: f64 = 0.05;
+// Landing at this speed or faster, in tiles per second, thuds the loudest
+const LOUDEST_LANDING_SPEED: f32 = 30.;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Sfx {
+    Jump,
+    Land,
+    Place,
+    Erase,
+}
+
+impl Sfx {
+    pub const ALL: [Sfx; 4] = [Sfx::Jump, Sfx::Land, Sfx::Place, Sfx::Erase];
+
+    pub fn path(self) -> &'static str {
+        match self {
+            Sfx::Jump => "sfx/jump.wav",
+            Sfx::Land => "sfx/land.wav",
+            Sfx::Place => "sfx/place.wav",
+            Sfx::Erase => "sfx/erase.wav",
+        }
+    }
+
+    // Volume relative to the other sounds, so the editor's clicks stay subtle
+    fn volume(self) -> f32 {
+        match self {
+            Sfx::Jump => 0.6,
+            Sfx::Land => 1.,
+            Sfx::Place | Sfx::Erase => 0.3,
+        }
+    }
+}
+
+// Play a sound, at `volume` times its usual volume and `speed` times its
+// usual speed, which also raises or lowers its pitch
+pub struct PlaySfx {
+    pub sfx: Sfx,
+    pub volume: f32,
+    pub speed: f32,
+}
+
+impl PlaySfx {
+    pub fn new(sfx: Sfx) -> Self {
+        PlaySfx {
+            sfx,
+            volume: 1.,
+            speed: 1.,
+        }
+    }
+}
+
+pub struct AudioSettings {
+    // From 0 to 1
+    pub sfx_volume: f32,
+    pub music_volume: f32,
+    // Silences music as well as sound effects
+    pub muted: bool,
+}
+
+impl Default for AudioSettings {
+    fn default() -> Self {
+        AudioSettings {
+            sfx_volume: 0.7,
+            music_volume: 0.5,
+            muted: false,
+        }
+    }
+}
+
+struct SfxPlayer {
+    sounds: HashMap<Sfx, Handle<AudioSource>>,
+    // When each sound last played, in seconds since startup
+    last_played: HashMap<Sfx, f64>,
+    // Sounds which failed to load, and have been warned about
+    missing: HashSet<Sfx>,
+}
+
+// Whether sound may play yet. Only waits for an interaction on the web.
+pub struct AudioUnlocked(pub bool);
+
+impl Default for AudioUnlocked {
+    fn default() -> Self {
+        AudioUnlocked(!cfg!(target_arch = "wasm32"))
+    }
+}
+
+impl SfxPlayer {
+    // Whether `sfx` may play at `now`, in seconds since startup, noting that
+    // it has if so
+    fn try_play(&mut self, sfx: Sfx, now: f64) -> bool {
+        let recent = self
+            .last_played
+            .get(&sfx)
+            .is_some_and(|&last| now - last < SFX_MIN_INTERVAL);
+        if !recent {
+            self.last_played.insert(sfx, now);
+        }
+        !recent
+    }
+}
+
+impl FromWorld for SfxPlayer {
+    fn from_world(world: &mut World) -> Self {
+        let asset_server = world.resource::<AssetServer>();
+        SfxPlayer {
+            sounds: Sfx::ALL
+                .iter()
+                .map(|&sfx| (sfx, asset_server.load(sfx.path())))
+                .collect(),
+            last_played: HashMap::default(),
+            missing: HashSet::default(),
+        }
+    }
+}
+
+// Needs the `ActionState` from `InputMapPlugin`, the tile events from
+// `TilePlugin`, the `Landed` and `Jumped` events from `GamePlugin`, and the
+// `Audio` from `DefaultPlugins`
+#[derive(Default)]
+pub struct SfxPlugin;
+
+impl Plugin for SfxPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<AudioSettings>()
+            .init_resource::<AudioUnlocked>()
+            .init_resource::<SfxPlayer>()
+            .add_event::<PlaySfx>()
+            .add_system(mute_system)
+            .add_system(unlock_audio_system)
+            .add_system(game_sfx_system)
+            .add_system(editor_sfx_system)
+            .add_system(
+                play_sfx_system
+                    .after(unlock_audio_system)
+                    .after(game_sfx_system)
+                    .after(editor_sfx_system),
+            );
+    }
+}
+
+fn mute_system(action_state: Res<ActionState>, mut settings: ResMut<AudioSettings>) {
+    if action_state.just_pressed(Action::ToggleMute) {
+        settings.muted = !settings.muted;
+        info!("Sound {}", if settings.muted { "muted" } else { "on" });
+    }
+}
+
+fn unlock_audio_system(
+    keys: Res<Input<KeyCode>>,
+    mouse_buttons: Res<Input<MouseButton>>,
+    touches: Res<Touches>,
+    mut unlocked: ResMut<AudioUnlocked>,
+) {
+    if !unlocked.0
+        && (keys.get_just_pressed().next().is_some()
+            || mouse_buttons.get_just_pressed().next().is_some()
+            || touches.iter_just_pressed().next().is_some())
+    {
+        unlocked.0 = true;
+    }
+}
+
+// Harder landings thud louder and lower
+fn game_sfx_system(
+    mut jumped_events: EventReader<Jumped>,
+    mut landed_events: EventReader<Landed>,
+    player_query: Query<(), With<Player>>,
+    mut sfx_events: EventWriter<PlaySfx>,
+) {
+    for _ in jumped_events.iter() {
+        sfx_events.send(PlaySfx::new(Sfx::Jump));
+    }
+    for landed in landed_events.iter() {
+        if player_query.contains(landed.entity) {
+            let impact = (landed.speed / LOUDEST_LANDING_SPEED).clamp(0.2, 1.);
+            sfx_events.send(PlaySfx {
+                sfx: Sfx::Land,
+                volume: impact,
+                speed: 1.2 - 0.4 * impact,
+            });
+        }
+    }
+}
+
+fn editor_sfx_system(
+    mut placed_events: EventReader<TilePlaced>,
+    mut removed_events: EventReader<TileRemoved>,
+    mut sfx_events: EventWriter<PlaySfx>,
+) {
+    if placed_events.iter().count() > 0 {
+        sfx_events.send(PlaySfx::new(Sfx::Place));
+    }
+    if removed_events.iter().count() > 0 {
+        sfx_events.send(PlaySfx::new(Sfx::Erase));
+    }
+}
+
+fn play_sfx_system(
+    time: Res<Time>,
+    audio: Res<Audio>,
+    asset_server: Res<AssetServer>,
+    settings: Res<AudioSettings>,
+    unlocked: Res<AudioUnlocked>,
+    mut player: ResMut<SfxPlayer>,
+    mut sfx_events: EventReader<PlaySfx>,
+) {
+    let now = time.seconds_since_startup();
+    for request in sfx_events.iter() {
+        if settings.muted || !unlocked.0 {
+            continue;
+        }
+        let sound = match player.sounds.get(&request.sfx) {
+            Some(sound) => sound.clone(),
+            None => continue,
+        };
+        if asset_server.get_load_state(&sound) == LoadState::Failed {
+            if player.missing.insert(request.sfx) {
+                warn!("Couldn't load {}, so it won't play", request.sfx.path());
+            }
+            continue;
+        }
+        if !player.try_play(request.sfx, now) {
+            continue;
+        }
+        audio.play_with_settings(
+            sound,
+            PlaybackSettings::ONCE
+                .with_volume(settings.sfx_volume * request.sfx.volume() * request.volume)
+                .with_speed(request.speed),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn identical_sounds_are_rate_limited() {
+        let mut player = SfxPlayer {
+            sounds: HashMap::default(),
+            last_played: HashMap::default(),
+            missing: HashSet::default(),
+        };
+        assert!(player.try_play(Sfx::Place, 1.));
+        assert!(!player.try_play(Sfx::Place, 1.02));
+        // Other sounds aren't held back
+        assert!(player.try_play(Sfx::Erase, 1.02));
+        // Dropped sounds don't push back the next
+        assert!(player.try_play(Sfx::Place, 1.06));
+    }
+}
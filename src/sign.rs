@@ -0,0 +1,189 @@
+// Sign tiles, whose text shows in a bubble above them while a player stands
+// in front of one. Unlike an NPC's dialog there's nothing to press, and the
+// bubble goes as soon as they walk away.
+//
+// A tile is a sign if its `TileData` has text for it. Long text wraps at
+// `BUBBLE_MAX_WIDTH` pixels.
+
+use bevy::prelude::*;
+use bevy::render::view::RenderLayers;
+use bevy::sprite::Anchor;
+use bevy::text::{Text2dBounds, Text2dSize};
+
+use crate::death::Dying;
+use crate::physics::{hitbox, overlaps};
+use crate::pixel_perfect::{spawn_world_ui_sprite, UI_FONT, WORLD_UI_LAYER};
+use crate::player::Player;
+
+// In pixels, like everything on the world UI layer
+const BUBBLE_MAX_WIDTH: f32 = 96.;
+const BUBBLE_PADDING: f32 = 3.;
+const BUBBLE_TEXT_PIXELS: f32 = 8.;
+const BUBBLE_COLOR: Color = Color::rgba(0., 0., 0., 0.75);
+
+#[derive(Component, Clone, Debug, PartialEq, Eq)]
+pub struct Sign {
+    pub text: String,
+}
+
+// The bubble over `sign`, with its text as a child
+#[derive(Component)]
+struct SignBubble {
+    sign: Entity,
+}
+
+// Needs the world UI layer from `PixelPerfectPlugin`
+#[derive(Default)]
+pub struct SignPlugin;
+
+impl Plugin for SignPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(sign_bubble_system)
+            .add_system(bubble_size_system.after(sign_bubble_system));
+    }
+}
+
+// Whether a player's hitbox overlaps the sign's cell
+fn in_front(transform: &Transform, sign_transform: &Transform) -> bool {
+    let sign_cell = (sign_transform.translation.truncate(), Vec2::ONE);
+    overlaps(hitbox(transform), sign_cell)
+}
+
+// A bubble for every sign with a player in front of it, and none for the rest
+fn sign_bubble_system(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    player_query: Query<&Transform, (With<Player>, Without<Dying>)>,
+    sign_query: Query<(Entity, &Sign, &Transform)>,
+    bubble_query: Query<(Entity, &SignBubble)>,
+) {
+    let read = |sign_transform: &Transform| {
+        player_query
+            .iter()
+            .any(|transform| in_front(transform, sign_transform))
+    };
+    for (bubble, &SignBubble { sign }) in bubble_query.iter() {
+        if !sign_query
+            .get(sign)
+            .is_ok_and(|(_, _, sign_transform)| read(sign_transform))
+        {
+            commands.entity(bubble).despawn_recursive();
+        }
+    }
+    for (entity, sign, sign_transform) in sign_query.iter() {
+        if !read(sign_transform) || bubble_query.iter().any(|(_, bubble)| bubble.sign == entity) {
+            continue;
+        }
+        let anchor = sign_transform.translation.truncate() + Vec2::new(0.5, 1.);
+        // Sized to the text once it's laid out
+        let bubble = spawn_world_ui_sprite(
+            &mut commands,
+            anchor,
+            SpriteBundle {
+                sprite: Sprite {
+                    color: BUBBLE_COLOR,
+                    custom_size: Some(Vec2::ZERO),
+                    anchor: Anchor::BottomCenter,
+                    ..default()
+                },
+                ..default()
+            },
+        );
+        commands
+            .entity(bubble)
+            .insert(SignBubble { sign: entity })
+            .with_children(|parent| {
+                parent
+                    .spawn_bundle(Text2dBundle {
+                        text: Text::with_section(
+                            sign.text.clone(),
+                            TextStyle {
+                                font: asset_server.load(UI_FONT),
+                                font_size: BUBBLE_TEXT_PIXELS,
+                                color: Color::WHITE,
+                            },
+                            TextAlignment {
+                                vertical: VerticalAlign::Bottom,
+                                horizontal: HorizontalAlign::Center,
+                            },
+                        ),
+                        text_2d_bounds: Text2dBounds {
+                            size: Size::new(BUBBLE_MAX_WIDTH, f32::MAX),
+                        },
+                        transform: Transform::from_xyz(0., BUBBLE_PADDING, 1.),
+                        ..default()
+                    })
+                    .insert(RenderLayers::layer(WORLD_UI_LAYER));
+            });
+    }
+}
+
+// Fit the bubble around its text, with a margin
+fn bubble_size_system(
+    text_query: Query<(&Parent, &Text2dSize), Changed<Text2dSize>>,
+    mut bubble_query: Query<&mut Sprite, With<SignBubble>>,
+) {
+    for (parent, text_size) in text_query.iter() {
+        if let Ok(mut sprite) = bubble_query.get_mut(parent.0) {
+            sprite.custom_size = Some(
+                Vec2::new(text_size.size.width, text_size.size.height)
+                    + Vec2::splat(BUBBLE_PADDING * 2.),
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::headless::{floor, HeadlessGame};
+    use crate::level::TileData;
+    use bevy::asset::FileAssetIo;
+    use bevy::tasks::TaskPoolBuilder;
+
+    // The text of each bubble shown
+    fn bubbles(game: &mut HeadlessGame) -> Vec<String> {
+        let world = &mut game.app.world;
+        let mut bubble_query = world.query_filtered::<&Children, With<SignBubble>>();
+        let mut text_query = world.query::<&Text>();
+        bubble_query
+            .iter(world)
+            .flat_map(|children| children.iter())
+            .filter_map(|&child| text_query.get(world, child).ok())
+            .map(|text| text.sections[0].value.clone())
+            .collect()
+    }
+
+    #[test]
+    fn bubble_shows_while_a_player_is_in_front_of_the_sign() {
+        let mut game = HeadlessGame::new();
+        game.app
+            .insert_resource(AssetServer::new(
+                FileAssetIo::new("assets", false),
+                TaskPoolBuilder::new().build(),
+            ))
+            .add_plugin(SignPlugin);
+        let sign = TileData {
+            solid: false,
+            sign: Some("Mind the gap".to_string()),
+            ..TileData::solid(IVec2::new(2, 1))
+        };
+        let mut level = floor(-3..=3);
+        level.tiles.push(sign);
+        game.spawn_level(&level);
+        game.place_player(Vec2::new(-2., 1.));
+        game.run(2, &[]);
+        assert!(bubbles(&mut game).is_empty());
+
+        game.place_player(Vec2::new(2., 1.));
+        game.run(2, &[]);
+        assert_eq!(bubbles(&mut game), ["Mind the gap"]);
+        // Only the one, however long they stay
+        game.run(10, &[]);
+        assert_eq!(bubbles(&mut game).len(), 1);
+
+        game.place_player(Vec2::new(-2., 1.));
+        game.run(2, &[]);
+        assert!(bubbles(&mut game).is_empty());
+    }
+}
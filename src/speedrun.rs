@@ -0,0 +1,246 @@
+// Timing runs through a level, from the players' first move after it's spawned
+// until one of them touches its exit.
+//
+// `LevelTimer` counts physics steps rather than frames or wall-clock time, so
+// a run takes as long at any frame rate, and it stands still while paused
+// like everything else the fixed steps drive. `SpeedrunPlugin` keeps the best
+// time for each level file in the `Settings`, and compares finished runs with
+// it.
+
+use bevy::prelude::*;
+
+use crate::death::Dying;
+use crate::input::{Action, ActionState, PlayerId, SecondPlayerInput};
+use crate::level::{CurrentLevel, PlayerSpawn};
+use crate::level_exit::ExitReached;
+use crate::physics::PHYSICS_TIME_STEP;
+use crate::player::Player;
+use crate::settings::Settings;
+
+// Actions which start the timer
+const MOVE_ACTIONS: [Action; 5] = [
+    Action::MoveLeft,
+    Action::MoveRight,
+    Action::Jump,
+    Action::FastFall,
+    Action::Dash,
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum TimerState {
+    // For the players' first move
+    Waiting,
+    Running,
+    Finished,
+}
+
+// How a finished run compares with the best run before it
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Comparison {
+    FirstFinish,
+    // The best time before, in seconds
+    Best(f32),
+}
+
+pub struct LevelTimer {
+    // Start again from zero when a player dies, rather than running on
+    // through the respawn
+    pub reset_on_death: bool,
+    // Set once the run is finished, by `SpeedrunPlugin` if there is one
+    pub comparison: Option<Comparison>,
+    steps: u32,
+    state: TimerState,
+}
+
+impl Default for LevelTimer {
+    fn default() -> Self {
+        LevelTimer {
+            reset_on_death: false,
+            comparison: None,
+            steps: 0,
+            state: TimerState::Waiting,
+        }
+    }
+}
+
+impl LevelTimer {
+    pub fn seconds(&self) -> f32 {
+        self.steps as f32 * PHYSICS_TIME_STEP
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.state == TimerState::Running
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.state == TimerState::Finished
+    }
+
+    // Back to zero, waiting for the next move
+    pub fn reset(&mut self) {
+        *self = LevelTimer {
+            reset_on_death: self.reset_on_death,
+            ..default()
+        };
+    }
+}
+
+// Minutes, seconds and centiseconds, e.g. 1:05.25. Partial centiseconds are
+// dropped, so a time shows as no better than it was.
+pub fn format_time(seconds: f32) -> String {
+    // Steps are a fraction of a centisecond, so their sum can fall just short
+    let centiseconds = (seconds * 100. + 0.001).floor() as u32;
+    format!(
+        "{}:{:02}.{:02}",
+        centiseconds / 6000,
+        centiseconds / 100 % 60,
+        centiseconds % 100
+    )
+}
+
+// Once per input step. Any player moving starts the timer.
+pub fn start_level_timer_system(
+    action_state: Res<ActionState>,
+    second_player: Option<Res<SecondPlayerInput>>,
+    mut timer: ResMut<LevelTimer>,
+    query: Query<&PlayerId, (With<Player>, Without<Dying>)>,
+) {
+    if timer.state != TimerState::Waiting {
+        return;
+    }
+    let moved = query.iter().any(|&id| {
+        SecondPlayerInput::actions(id, &action_state, second_player.as_deref()).is_some_and(
+            |actions| {
+                // A tap between two steps is only in the step's presses
+                MOVE_ACTIONS
+                    .iter()
+                    .any(|&action| actions.pressed(action) || actions.step_just_pressed(action))
+            },
+        )
+    });
+    if moved {
+        timer.state = TimerState::Running;
+    }
+}
+
+// Once per physics step, including the one the timer starts in
+pub fn level_timer_system(mut timer: ResMut<LevelTimer>) {
+    // Without marking it changed once it stops
+    if timer.is_running() {
+        timer.steps += 1;
+    }
+}
+
+// A player touched the exit and finished a timed run, in `seconds`
+pub struct LevelFinished {
+    pub player: Entity,
+    pub seconds: f32,
+}
+
+// After `level_exit_system`
+pub fn finish_level_timer_system(
+    mut reached_events: EventReader<ExitReached>,
+    mut timer: ResMut<LevelTimer>,
+    mut finished_events: EventWriter<LevelFinished>,
+) {
+    let player = match reached_events.iter().last() {
+        Some(reached) => reached.player,
+        None => return,
+    };
+    if timer.is_running() {
+        timer.state = TimerState::Finished;
+        info!("Finished the level in {}", format_time(timer.seconds()));
+        finished_events.send(LevelFinished {
+            player,
+            seconds: timer.seconds(),
+        });
+    }
+}
+
+// Every frame. Spawning a level, or rebuilding it, starts a new run, and so
+// does dying with `reset_on_death`.
+pub fn reset_level_timer_system(
+    mut timer: ResMut<LevelTimer>,
+    spawn_query: Query<(), Added<PlayerSpawn>>,
+    dying_query: Query<(), (With<Player>, Added<Dying>)>,
+) {
+    if !spawn_query.is_empty() || (timer.reset_on_death && !dying_query.is_empty()) {
+        timer.reset();
+    }
+}
+
+// Needs the `LevelTimer` from `GamePlugin`, the `CurrentLevel` from
+// `LevelPlugin` and the `Settings` from `DisplayPlugin`
+#[derive(Default)]
+pub struct SpeedrunPlugin;
+
+impl Plugin for SpeedrunPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(best_time_system);
+    }
+}
+
+// Levels are told apart by their file, so a level without one has no best time
+fn best_time_system(
+    mut timer: ResMut<LevelTimer>,
+    current_level: Res<CurrentLevel>,
+    mut settings: ResMut<Settings>,
+) {
+    if !timer.is_changed() || !timer.is_finished() || timer.comparison.is_some() {
+        return;
+    }
+    let path = match &current_level.path {
+        Some(path) => path.clone(),
+        None => return,
+    };
+    let time = timer.seconds();
+    let best = settings.best_times.get(&path).copied();
+    timer.comparison = Some(match best {
+        Some(best) => Comparison::Best(best),
+        None => Comparison::FirstFinish,
+    });
+    if best.is_none_or(|best| time < best) {
+        settings.best_times.insert(path, time);
+        settings.save();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::headless::{floor, HeadlessGame};
+    use crate::level::LevelData;
+
+    #[test]
+    fn times_are_shown_to_the_centisecond() {
+        assert_eq!(format_time(0.), "0:00.00");
+        assert_eq!(format_time(65.25), "1:05.25");
+        assert_eq!(format_time(59.999), "0:59.99");
+        assert_eq!(format_time(240. * PHYSICS_TIME_STEP), "0:01.00");
+    }
+
+    #[test]
+    fn timer_runs_from_the_first_move_to_the_exit() {
+        let mut game = HeadlessGame::new();
+        let level = LevelData {
+            exit: Some(IVec2::new(6, 1)),
+            ..floor(-2..=12)
+        };
+        game.spawn_level(&level);
+        game.place_player(Vec2::new(0., 1.));
+        game.run(60, &[]);
+        let timer = |game: &HeadlessGame| {
+            let timer = game.app.world.resource::<LevelTimer>();
+            (timer.steps, timer.state)
+        };
+        assert_eq!(timer(&game), (0, TimerState::Waiting));
+
+        game.run(600, &[Action::MoveRight]);
+        let (steps, state) = timer(&game);
+        assert_eq!(state, TimerState::Finished);
+        assert!(steps > 0 && steps < 600, "took {} steps", steps);
+        // Stopped for good, however long the player stays
+        game.run(60, &[Action::MoveLeft]);
+        assert_eq!(timer(&game), (steps, TimerState::Finished));
+    }
+}
@@ -0,0 +1,264 @@
+// The menu Escape opens, with entries to resume, save the level, go back to
+// the main menu or quit.
+//
+// Pressing Escape again within `DOUBLE_ESCAPE_TIME` of opening the menu quits
+// straight away, and any later press closes it. With the `escape_quits`
+// setting Escape quits immediately instead, without a menu. On wasm there is
+// nothing to quit to, so Escape does nothing.
+//
+// Within the menu, `MenuActionState` moves the highlight with Up and Down and
+// picks the entry with Confirm. Back resumes too, unless it's the Escape press
+// already handled as the Quit action.
+
+use bevy::{app::AppExit, prelude::*};
+
+use crate::game_state::GameState;
+use crate::input::{Action, ActionState, MenuAction, MenuActionState};
+use crate::level::{CurrentLevel, SaveLevel};
+use crate::pixel_perfect::UI_FONT;
+use crate::settings::Settings;
+
+// Seconds within which a second Escape quits
+pub const DOUBLE_ESCAPE_TIME: f64 = 1.;
+const SELECTED_COLOR: Color = Color::rgb(1., 0.85, 0.3);
+const UNSELECTED_COLOR: Color = Color::rgb(0.7, 0.7, 0.7);
+const WARNING_COLOR: Color = Color::rgb(1., 0.4, 0.3);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum MenuEntry {
+    Resume,
+    SaveLevel,
+    MainMenu,
+    Quit,
+}
+
+impl MenuEntry {
+    const ALL: [MenuEntry; 4] = [
+        MenuEntry::Resume,
+        MenuEntry::SaveLevel,
+        MenuEntry::MainMenu,
+        MenuEntry::Quit,
+    ];
+
+    fn label(self) -> &'static str {
+        match self {
+            MenuEntry::Resume => "Resume",
+            MenuEntry::SaveLevel => "Save Level",
+            MenuEntry::MainMenu => "Main Menu",
+            MenuEntry::Quit => "Quit",
+        }
+    }
+}
+
+#[derive(Default)]
+struct SystemMenu {
+    selected: usize,
+    // Time since startup when the menu was opened
+    opened_at: f64,
+}
+
+#[derive(Component)]
+struct SystemMenuRoot;
+
+// The entry at this index in `MenuEntry::ALL`
+#[derive(Component)]
+struct MenuEntryText(usize);
+
+#[derive(Component)]
+struct UnsavedChangesText;
+
+// Needs the `ActionState` and `MenuActionState` from `InputMapPlugin`, the
+// `CurrentLevel` from `LevelPlugin`, the `GameState` from `GameStatePlugin`
+// and the `Settings` resource
+#[derive(Default)]
+pub struct SystemMenuPlugin;
+
+impl Plugin for SystemMenuPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SystemMenu>()
+            .add_system(escape_system)
+            .add_system_set(
+                SystemSet::on_enter(GameState::SystemMenu).with_system(spawn_menu_system),
+            )
+            .add_system_set(
+                SystemSet::on_update(GameState::SystemMenu)
+                    .with_system(menu_input_system)
+                    .with_system(highlight_system.after(menu_input_system))
+                    .with_system(unsaved_changes_system),
+            )
+            .add_system_set(
+                SystemSet::on_exit(GameState::SystemMenu).with_system(despawn_menu_system),
+            );
+    }
+}
+
+fn escape_system(
+    action_state: Res<ActionState>,
+    settings: Res<Settings>,
+    time: Res<Time>,
+    mut menu: ResMut<SystemMenu>,
+    mut state: ResMut<State<GameState>>,
+    mut app_exit_events: EventWriter<AppExit>,
+) {
+    if cfg!(target_arch = "wasm32") || !action_state.just_pressed(Action::Quit) {
+        return;
+    }
+    if settings.escape_quits {
+        app_exit_events.send(AppExit);
+        return;
+    }
+    let now = time.seconds_since_startup();
+    match state.current() {
+        // Resuming from game over goes straight back to it
+        GameState::Playing | GameState::GameOver => {
+            menu.selected = 0;
+            menu.opened_at = now;
+            // Fails only if a transition is already queued this frame
+            let _ = state.set(GameState::SystemMenu);
+        }
+        GameState::SystemMenu if now - menu.opened_at <= DOUBLE_ESCAPE_TIME => {
+            app_exit_events.send(AppExit);
+        }
+        GameState::SystemMenu => {
+            let _ = state.set(GameState::Playing);
+        }
+        // The level select and pause menus and the dialog box close on Back
+        // themselves, the main menu has nowhere to go back to, and a fanfare
+        // or a respawn fade is over in a moment
+        GameState::LevelSelect
+        | GameState::Paused
+        | GameState::MainMenu
+        | GameState::Dialog
+        | GameState::Fanfare
+        | GameState::Respawning => {}
+    }
+}
+
+fn spawn_menu_system(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    current_level: Res<CurrentLevel>,
+) {
+    let font = asset_server.load(UI_FONT);
+    let text_style = |font_size, color| TextStyle {
+        font: font.clone(),
+        font_size,
+        color,
+    };
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.), Val::Percent(100.)),
+                position_type: PositionType::Absolute,
+                // The UI is y-up, so this lists the children top to bottom
+                flex_direction: FlexDirection::ColumnReverse,
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            color: Color::rgba(0., 0., 0., 0.75).into(),
+            ..default()
+        })
+        .insert(SystemMenuRoot)
+        .with_children(|parent| {
+            for (index, entry) in MenuEntry::ALL.iter().enumerate() {
+                parent
+                    .spawn_bundle(TextBundle {
+                        text: Text::with_section(
+                            entry.label(),
+                            text_style(24., UNSELECTED_COLOR),
+                            default(),
+                        ),
+                        style: Style {
+                            margin: Rect::all(Val::Px(4.)),
+                            ..default()
+                        },
+                        ..default()
+                    })
+                    .insert(MenuEntryText(index));
+            }
+            parent
+                .spawn_bundle(TextBundle {
+                    text: Text::with_section(
+                        "You have unsaved changes",
+                        text_style(16., WARNING_COLOR),
+                        default(),
+                    ),
+                    style: Style {
+                        margin: Rect {
+                            top: Val::Px(16.),
+                            ..default()
+                        },
+                        ..default()
+                    },
+                    visibility: Visibility {
+                        is_visible: current_level.unsaved,
+                    },
+                    ..default()
+                })
+                .insert(UnsavedChangesText);
+        });
+}
+
+fn menu_input_system(
+    action_state: Res<ActionState>,
+    menu_actions: Res<MenuActionState>,
+    mut menu: ResMut<SystemMenu>,
+    mut state: ResMut<State<GameState>>,
+    mut save_events: EventWriter<SaveLevel>,
+    mut app_exit_events: EventWriter<AppExit>,
+) {
+    let count = MenuEntry::ALL.len();
+    if menu_actions.just_pressed(MenuAction::Down) {
+        menu.selected = (menu.selected + 1) % count;
+    }
+    if menu_actions.just_pressed(MenuAction::Up) {
+        menu.selected = (menu.selected + count - 1) % count;
+    }
+    if menu_actions.just_pressed(MenuAction::Back) && !action_state.just_pressed(Action::Quit) {
+        let _ = state.set(GameState::Playing);
+        return;
+    }
+    if !menu_actions.just_pressed(MenuAction::Confirm) {
+        return;
+    }
+    match MenuEntry::ALL[menu.selected] {
+        MenuEntry::Resume => {
+            let _ = state.set(GameState::Playing);
+        }
+        // Stays open, so the unsaved changes line can be seen to clear
+        MenuEntry::SaveLevel => save_events.send(SaveLevel),
+        MenuEntry::MainMenu => {
+            let _ = state.set(GameState::MainMenu);
+        }
+        MenuEntry::Quit => app_exit_events.send(AppExit),
+    }
+}
+
+fn highlight_system(menu: Res<SystemMenu>, mut query: Query<(&MenuEntryText, &mut Text)>) {
+    for (entry, mut text) in query.iter_mut() {
+        let color = if entry.0 == menu.selected {
+            SELECTED_COLOR
+        } else {
+            UNSELECTED_COLOR
+        };
+        for section in text.sections.iter_mut() {
+            section.style.color = color;
+        }
+    }
+}
+
+fn unsaved_changes_system(
+    current_level: Res<CurrentLevel>,
+    mut query: Query<&mut Visibility, With<UnsavedChangesText>>,
+) {
+    for mut visibility in query.iter_mut() {
+        visibility.is_visible = current_level.unsaved;
+    }
+}
+
+fn despawn_menu_system(mut commands: Commands, query: Query<Entity, With<SystemMenuRoot>>) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
@@ -1,30 +1,121 @@
 use bevy::prelude::*;
 use bevy::sprite::Anchor;
+use bevy::utils::HashMap;
+use serde::{Deserialize, Serialize};
+
+use crate::debug::DebugMode;
+use crate::pixel_perfect::{WorldCamera, HEIGHT_PIXELS, WIDTH_PIXELS};
+
+// Tiles are shown once they come within CULL_SHOW_MARGIN tiles of the view,
+// and hidden only after leaving it by CULL_HIDE_MARGIN. The gap between the
+// two keeps tiles near the edge from toggling every frame.
+pub const CULL_SHOW_MARGIN: f32 = 2.;
+pub const CULL_HIDE_MARGIN: f32 = 4.;
+
+// How tiles with no appearance are drawn while `DebugMode` is on
+pub const HIDDEN_TILE_DEBUG_COLOR: Color = Color::rgba(1., 0., 1., 0.5);
+
+// The grid tiles are laid out on, as `SolidTile` draws them: a world unit
+// per cell, with each tile's position at its bottom-left corner
+pub const TILE_GRID: CellGrid = CellGrid {
+    size: 1.,
+    anchor: Vec2::ZERO,
+};
+
+// A grid of square cells, each the size of a tile
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CellGrid {
+    // Width of a cell in world units
+    pub size: f32,
+    // Where a tile's position is within it, as a fraction of its size from
+    // its bottom-left corner, so the center is (0.5, 0.5)
+    pub anchor: Vec2,
+}
 
 #[derive(Clone)]
 pub enum TileAppearance {
     Color(Color),
     Texture(Handle<Image>),
+    // Collides like any other tile but is only drawn in debug mode, for
+    // invisible walls and level boundaries
     None,
 }
 
 pub struct TileSpec {
     pub pos: IVec2,
     pub appearance: TileAppearance,
+    pub shape: ColliderShape,
 }
 
-#[derive(Component)]
+#[derive(Component, Clone, Copy)]
 pub struct SolidCollider;
 
-#[derive(Component)]
+// The solid part of a tile's cell. Slopes fill half the cell, split along a
+// diagonal, and are named after the direction their diagonal faces:
+//
+//   SlopeNE  |\      SlopeNW    /|    SlopeSE  |‾/     SlopeSW  \‾|
+//            |_\               /_|             |/                 \|
+//
+// NE and NW are floors which can be walked up; SE and SW are ceilings.
+#[derive(Component, Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ColliderShape {
+    #[default]
+    Aabb,
+    SlopeNE,
+    SlopeNW,
+    SlopeSE,
+    SlopeSW,
+}
+
+impl ColliderShape {
+    // The following shape, in declaration order, wrapping around
+    pub fn next(self) -> Self {
+        match self {
+            ColliderShape::Aabb => ColliderShape::SlopeNE,
+            ColliderShape::SlopeNE => ColliderShape::SlopeNW,
+            ColliderShape::SlopeNW => ColliderShape::SlopeSE,
+            ColliderShape::SlopeSE => ColliderShape::SlopeSW,
+            ColliderShape::SlopeSW => ColliderShape::Aabb,
+        }
+    }
+
+    // The shape mirrored left to right
+    pub fn flipped_x(self) -> Self {
+        match self {
+            ColliderShape::Aabb => ColliderShape::Aabb,
+            ColliderShape::SlopeNE => ColliderShape::SlopeNW,
+            ColliderShape::SlopeNW => ColliderShape::SlopeNE,
+            ColliderShape::SlopeSE => ColliderShape::SlopeSW,
+            ColliderShape::SlopeSW => ColliderShape::SlopeSE,
+        }
+    }
+}
+
+#[derive(Component, Clone, Copy)]
 pub struct Tile;
 
+// Sent by the editor for each tile it paints, for feedback such as sounds
+pub struct TilePlaced(pub IVec2);
+
+// Sent by the editor for each tile it erases
+pub struct TileRemoved(pub IVec2);
+
+// Kills players who touch it
+#[derive(Component, Clone, Copy, Default)]
+pub struct Hazard;
+
+// Whether the tile is only drawn in debug mode
+#[derive(Component, Clone, Copy, Default)]
+pub struct HiddenTile(pub bool);
+
 #[derive(Bundle)]
 pub struct SolidTile {
     #[bundle]
     pub sprite: SpriteBundle,
     pub collider: SolidCollider,
+    pub shape: ColliderShape,
     pub tile: Tile,
+    pub hidden: HiddenTile,
 }
 
 impl SolidTile {
@@ -43,16 +134,316 @@ impl SolidTile {
                 ..default()
             },
             collider: SolidCollider {},
+            shape: spec.shape,
             tile: Tile {},
+            hidden: HiddenTile(false),
         };
 
-        if let TileAppearance::Color(color) = spec.appearance {
-            tile.sprite.sprite.color = color;
-        }
-        if let TileAppearance::Texture(texture) = spec.appearance {
-            tile.sprite.texture = texture;
+        match spec.appearance {
+            TileAppearance::Color(color) => tile.sprite.sprite.color = color,
+            TileAppearance::Texture(texture) => tile.sprite.texture = texture,
+            TileAppearance::None => {
+                tile.sprite.sprite.color = HIDDEN_TILE_DEBUG_COLOR;
+                tile.sprite.visibility.is_visible = false;
+                tile.hidden = HiddenTile(true);
+            }
         }
 
         tile
     }
 }
+
+// The tile entity in each occupied cell.
+//
+// Tiles must be spawned and despawned through `spawn` and `despawn` so the
+// index stays in sync; a tile despawned any other way leaves a stale entry.
+#[derive(Default)]
+pub struct TileIndex {
+    tiles: HashMap<IVec2, Entity>,
+}
+
+impl TileIndex {
+    pub fn tile_at(&self, cell: IVec2) -> Option<Entity> {
+        self.tiles.get(&cell).copied()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (IVec2, Entity)> + '_ {
+        self.tiles.iter().map(|(&cell, &entity)| (cell, entity))
+    }
+
+    // Spawn a tile, replacing any tile already in its cell
+    pub fn spawn(&mut self, commands: &mut Commands, spec: TileSpec) -> Entity {
+        let cell = spec.pos;
+        let entity = commands.spawn_bundle(SolidTile::from_spec(spec)).id();
+        if let Some(old) = self.tiles.insert(cell, entity) {
+            commands.entity(old).despawn_recursive();
+        }
+        entity
+    }
+
+    // Despawn the tile in `cell`, returning it if there was one
+    pub fn despawn(&mut self, commands: &mut Commands, cell: IVec2) -> Option<Entity> {
+        let entity = self.tiles.remove(&cell)?;
+        commands.entity(entity).despawn_recursive();
+        Some(entity)
+    }
+
+    // The lowest and highest cells holding tiles, if there are any
+    pub fn bounds(&self) -> Option<(IVec2, IVec2)> {
+        self.tiles.keys().fold(None, |bounds, &cell| match bounds {
+            Some((min, max)) => Some((cell.min(min), cell.max(max))),
+            None => Some((cell, cell)),
+        })
+    }
+
+    // Move every entry by `offset`. The tiles' transforms are left for the
+    // caller to move alike.
+    pub fn shift(&mut self, offset: IVec2) {
+        self.tiles = self
+            .tiles
+            .drain()
+            .map(|(cell, entity)| (cell + offset, entity))
+            .collect();
+    }
+
+    // The first cell crossed by the segment from `from` to `to` whose tile
+    // `blocks` is true for, if any
+    pub fn raycast(&self, from: Vec2, to: Vec2, blocks: impl Fn(Entity) -> bool) -> Option<IVec2> {
+        let start = from.floor().as_ivec2();
+        let end = to.floor().as_ivec2();
+        let delta = to - from;
+        let step = (end - start).signum();
+        // How far along the segment, from 0 to 1, each cell boundary is crossed
+        let t_delta = Vec2::ONE / delta.abs();
+        let first_boundary = start.as_vec2() + step.max(IVec2::ZERO).as_vec2();
+        let mut t_max = Vec2::select(
+            step.cmpne(IVec2::ZERO),
+            (first_boundary - from) / delta,
+            Vec2::splat(f32::INFINITY),
+        );
+        let mut cell = start;
+        // One cell for each boundary crossed, then the last
+        for _ in 0..=(end - start).abs().dot(IVec2::ONE) {
+            if self.tile_at(cell).is_some_and(&blocks) {
+                return Some(cell);
+            }
+            if t_max.x < t_max.y {
+                cell.x += step.x;
+                t_max.x += t_delta.x;
+            } else {
+                cell.y += step.y;
+                t_max.y += t_delta.y;
+            }
+        }
+        None
+    }
+
+    // The nearest ground below a body spanning `left` to `right` with its
+    // bottom at `bottom`: the first row of cells under it, within `max_depth`
+    // rows, holding a tile `blocks` is true for. Tiles count as their whole
+    // cell, slopes included. Only the cells under the body are looked up, so
+    // it costs the same however big the level is.
+    pub fn ground_below(
+        &self,
+        left: f32,
+        right: f32,
+        bottom: f32,
+        max_depth: u32,
+        blocks: impl Fn(Entity) -> bool,
+    ) -> Option<Ground> {
+        // Resting on a cell's top, give or take rounding, is being above it
+        const TOLERANCE: f32 = 0.001;
+        let first_x = left.floor() as i32;
+        // A body ending on a cell edge doesn't reach into the next cell
+        let last_x = (right.ceil() as i32 - 1).max(first_x);
+        let first_y = (bottom + TOLERANCE).floor() as i32 - 1;
+        (0..max_depth as i32).find_map(|depth| {
+            let y = first_y - depth;
+            (first_x..=last_x)
+                .map(|x| IVec2::new(x, y))
+                .find(|&cell| self.tile_at(cell).is_some_and(&blocks))
+                .map(|cell| {
+                    let top = (y + 1) as f32;
+                    Ground {
+                        cell,
+                        top,
+                        gap: (bottom - top).max(0.),
+                    }
+                })
+        })
+    }
+
+    // Despawn every tile
+    pub fn clear(&mut self, commands: &mut Commands) {
+        for (_, entity) in self.tiles.drain() {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
+
+// The ground found by `TileIndex::ground_below`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Ground {
+    // The leftmost blocking cell in the row found
+    pub cell: IVec2,
+    // Height of the row's top
+    pub top: f32,
+    // How far the body's bottom is above it
+    pub gap: f32,
+}
+
+#[derive(Default)]
+pub struct TilePlugin;
+
+impl Plugin for TilePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<TileIndex>()
+            .add_event::<TilePlaced>()
+            .add_event::<TileRemoved>()
+            .add_system_to_stage(CoreStage::PostUpdate, tile_culling_system);
+    }
+}
+
+// The tiles, kept apart from the camera, and whether each is hidden
+// anyway
+type CulledTileQuery<'w, 's> = Query<
+    'w,
+    's,
+    (
+        &'static Transform,
+        &'static mut Visibility,
+        Option<&'static HiddenTile>,
+    ),
+    (With<Tile>, Without<WorldCamera>),
+>;
+
+fn tile_culling_system(
+    debug_mode: Option<Res<DebugMode>>,
+    camera_query: Query<&Transform, With<WorldCamera>>,
+    mut tile_query: CulledTileQuery,
+) {
+    let debug = debug_mode.is_some_and(|debug_mode| debug_mode.0);
+    let (camera, scale) = match camera_query.get_single() {
+        Ok(transform) => (transform.translation.truncate(), transform.scale.truncate()),
+        Err(_) => return,
+    };
+    // The camera's scale follows its zoom
+    let half_extent = Vec2::new(WIDTH_PIXELS as f32, HEIGHT_PIXELS as f32) * scale / 2.;
+    let view_min = camera - half_extent;
+    let view_max = camera + half_extent;
+
+    for (transform, mut visibility, hidden) in tile_query.iter_mut() {
+        if hidden.is_some_and(|hidden| hidden.0) && !debug {
+            if visibility.is_visible {
+                visibility.is_visible = false;
+            }
+            continue;
+        }
+        let tile_min = transform.translation.truncate();
+        let tile_max = tile_min + Vec2::ONE;
+        // How far the tile is outside the view, 0 if it overlaps
+        let outside = (view_min - tile_max)
+            .max(tile_min - view_max)
+            .max(Vec2::ZERO)
+            .max_element();
+
+        if visibility.is_visible && outside > CULL_HIDE_MARGIN {
+            visibility.is_visible = false;
+        } else if !visibility.is_visible && outside <= CULL_SHOW_MARGIN {
+            visibility.is_visible = true;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::physics::{physics_system_set, Gravity, TileCollider, Velocity, GRAVITY};
+
+    #[test]
+    fn raycast_stops_at_the_first_blocking_tile() {
+        let tile_index = TileIndex {
+            tiles: [(2, 0), (3, 0), (2, 3)]
+                .into_iter()
+                .map(|(x, y)| (IVec2::new(x, y), Entity::from_raw(0)))
+                .collect(),
+        };
+        let raycast = |from: (f32, f32), to: (f32, f32)| {
+            tile_index.raycast(Vec2::from(from), Vec2::from(to), |_| true)
+        };
+        assert_eq!(raycast((0.5, 0.5), (5.5, 0.5)), Some(IVec2::new(2, 0)));
+        assert_eq!(raycast((5.5, 0.5), (0.5, 0.5)), Some(IVec2::new(3, 0)));
+        assert_eq!(raycast((0.5, 1.5), (5.5, 1.5)), None);
+        // Diagonally, passing between (2, 0) and (2, 3)
+        assert_eq!(raycast((0.5, 0.5), (4.5, 3.5)), None);
+        assert_eq!(raycast((0.2, 0.9), (2.5, 3.5)), Some(IVec2::new(2, 3)));
+        assert_eq!(raycast((0.5, 0.5), (0.5, 0.5)), None);
+    }
+
+    #[test]
+    fn ground_below_finds_the_nearest_row_under_the_body() {
+        let tile_index = TileIndex {
+            tiles: [(0, -3), (2, 0), (3, -1)]
+                .into_iter()
+                .map(|(x, y)| (IVec2::new(x, y), Entity::from_raw(0)))
+                .collect(),
+        };
+        let ground =
+            |left, right, bottom| tile_index.ground_below(left, right, bottom, 8, |_| true);
+        // Standing on (2, 0)
+        assert_eq!(
+            ground(1.5, 2.5, 1.),
+            Some(Ground {
+                cell: IVec2::new(2, 0),
+                top: 1.,
+                gap: 0.,
+            })
+        );
+        // Ending on the edge of (2, 0), so only (0, -3) is under it
+        let below = ground(0.5, 2., 1.5).unwrap();
+        assert_eq!((below.cell, below.gap), (IVec2::new(0, -3), 3.5));
+        assert_eq!(ground(3.2, 3.8, 0.25).unwrap().cell, IVec2::new(3, -1));
+        assert_eq!(tile_index.ground_below(0.2, 0.8, 1., 3, |_| true), None);
+    }
+
+    #[test]
+    fn hidden_tile_collides_but_is_only_drawn_in_debug_mode() {
+        let mut world = World::new();
+        world.insert_resource(DebugMode(false));
+        world
+            .spawn()
+            .insert_bundle((Transform::default(), WorldCamera));
+        let tile = world
+            .spawn()
+            .insert_bundle(SolidTile::from_spec(TileSpec {
+                pos: IVec2::ZERO,
+                appearance: TileAppearance::None,
+                shape: ColliderShape::Aabb,
+            }))
+            .id();
+        let body = world
+            .spawn()
+            .insert_bundle((
+                Transform::from_xyz(0., 2., 0.),
+                Velocity(Vec3::ZERO),
+                Gravity(GRAVITY),
+                TileCollider,
+            ))
+            .id();
+
+        let mut stage = SystemStage::single_threaded()
+            .with_system_set(physics_system_set())
+            .with_system(tile_culling_system);
+        for _ in 0..240 {
+            stage.run(&mut world);
+        }
+
+        let landed = world.get::<Transform>(body).unwrap().translation;
+        assert!((landed.y - 1.).abs() < 1e-3, "body ended at {:?}", landed);
+        assert!(!world.get::<Visibility>(tile).unwrap().is_visible);
+
+        world.resource_mut::<DebugMode>().0 = true;
+        stage.run(&mut world);
+        assert!(world.get::<Visibility>(tile).unwrap().is_visible);
+    }
+}
@@ -0,0 +1,123 @@
+// Brief flashes where the editor places or removes tiles, so strokes feel
+// responsive: one shrinking onto each new tile, and one shrinking away from
+// each removed tile's cell.
+//
+// The flashes come from a fixed pool of sprites, reused oldest first, so
+// dragging quickly across many cells can't pile up entities. Leave the plugin
+// out for no feedback.
+
+use bevy::prelude::*;
+
+use crate::tile::{TilePlaced, TileRemoved};
+
+// How many flashes can show at once
+pub const TILE_FEEDBACK_POOL_SIZE: usize = 16;
+// Seconds a flash lasts
+const FLASH_TIME: f32 = 0.15;
+// In front of the tiles, behind the editor's overlays
+const FLASH_Z: f32 = 8.;
+const FLASH_ALPHA: f32 = 0.6;
+
+#[derive(Component)]
+struct TileFlash {
+    // Seconds since it started, or None while unused
+    elapsed: Option<f32>,
+    placed: bool,
+}
+
+#[derive(Default)]
+struct FlashPool {
+    flashes: Vec<Entity>,
+    // Index of the flash to reuse next
+    next: usize,
+}
+
+// Needs the events from `TilePlugin`
+#[derive(Default)]
+pub struct TileFeedbackPlugin;
+
+impl Plugin for TileFeedbackPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<FlashPool>()
+            .add_startup_system(spawn_pool_system)
+            .add_system(start_flash_system)
+            .add_system(animate_flash_system.after(start_flash_system));
+    }
+}
+
+fn spawn_pool_system(mut commands: Commands, mut pool: ResMut<FlashPool>) {
+    pool.flashes = (0..TILE_FEEDBACK_POOL_SIZE)
+        .map(|_| {
+            commands
+                .spawn_bundle(SpriteBundle {
+                    sprite: Sprite {
+                        custom_size: Some(Vec2::ONE),
+                        ..default()
+                    },
+                    visibility: Visibility { is_visible: false },
+                    ..default()
+                })
+                .insert(TileFlash {
+                    elapsed: None,
+                    placed: false,
+                })
+                .id()
+        })
+        .collect();
+}
+
+fn start_flash_system(
+    mut pool: ResMut<FlashPool>,
+    mut placed_events: EventReader<TilePlaced>,
+    mut removed_events: EventReader<TileRemoved>,
+    mut query: Query<(&mut TileFlash, &mut Transform)>,
+) {
+    let placed = placed_events.iter().map(|TilePlaced(cell)| (*cell, true));
+    let removed = removed_events
+        .iter()
+        .map(|TileRemoved(cell)| (*cell, false));
+    for (cell, placed) in placed.chain(removed) {
+        let entity = match pool.flashes.get(pool.next) {
+            Some(&entity) => entity,
+            None => return,
+        };
+        pool.next = (pool.next + 1) % pool.flashes.len();
+        if let Ok((mut flash, mut transform)) = query.get_mut(entity) {
+            *flash = TileFlash {
+                elapsed: Some(0.),
+                placed,
+            };
+            // Sprites are centered, tiles anchored at their bottom-left corner
+            transform.translation = (cell.as_vec2() + 0.5).extend(FLASH_Z);
+        }
+    }
+}
+
+fn animate_flash_system(
+    time: Res<Time>,
+    mut query: Query<(&mut TileFlash, &mut Transform, &mut Sprite, &mut Visibility)>,
+) {
+    for (mut flash, mut transform, mut sprite, mut visibility) in query.iter_mut() {
+        let elapsed = match &mut flash.elapsed {
+            Some(elapsed) => {
+                *elapsed += time.delta_seconds();
+                *elapsed
+            }
+            None => continue,
+        };
+        let progress = elapsed / FLASH_TIME;
+        if progress >= 1. {
+            flash.elapsed = None;
+            visibility.is_visible = false;
+            continue;
+        }
+        visibility.is_visible = true;
+        let scale = if flash.placed {
+            1.4 - 0.4 * progress
+        } else {
+            1. - 0.8 * progress
+        };
+        transform.scale = Vec3::new(scale, scale, 1.);
+        sprite.color = Color::rgba(1., 1., 1., FLASH_ALPHA * (1. - progress));
+    }
+}
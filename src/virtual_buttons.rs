@@ -0,0 +1,150 @@
+// On-screen buttons to move and jump, for playing with a trackpad or a touch
+// screen when the keyboard is awkward, as in a browser embed.
+//
+// Holding the mouse button or a touch on a button holds its action, through
+// `VirtualInput`, so it behaves exactly like the bound key, releases
+// included. They are off unless the `virtual_buttons` setting is on, or on
+// wasm, where there are no settings, the page's URL has `buttons` in its
+// query, e.g. `index.html?buttons`.
+
+use bevy::prelude::*;
+use bevy::utils::HashSet;
+
+use crate::input::{Action, ActionStateUpdate, VirtualInput};
+use crate::pixel_perfect::UI_FONT;
+use crate::settings::Settings;
+
+const BUTTON_SIZE: f32 = 64.;
+const BUTTON_MARGIN: f32 = 16.;
+const BUTTON_COLOR: Color = Color::rgba(1., 1., 1., 0.2);
+const PRESSED_BUTTON_COLOR: Color = Color::rgba(1., 1., 1., 0.45);
+
+#[derive(Component)]
+struct VirtualButton(Action);
+
+// Needs the `VirtualInput` from `InputMapPlugin` and the `Settings` resource
+#[derive(Default)]
+pub struct VirtualButtonsPlugin;
+
+impl Plugin for VirtualButtonsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_startup_system(spawn_buttons).add_system_to_stage(
+            CoreStage::PreUpdate,
+            virtual_buttons_system.before(ActionStateUpdate),
+        );
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+fn requested_by_page() -> bool {
+    web_sys::window()
+        .and_then(|window| window.location().search().ok())
+        .map_or(false, |query| {
+            query
+                .trim_start_matches('?')
+                .split('&')
+                .any(|param| param == "buttons" || param.starts_with("buttons="))
+        })
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn requested_by_page() -> bool {
+    false
+}
+
+fn spawn_buttons(mut commands: Commands, asset_server: Res<AssetServer>, settings: Res<Settings>) {
+    if !settings.virtual_buttons && !requested_by_page() {
+        return;
+    }
+    let font = asset_server.load(UI_FONT);
+    // Left and right in the bottom-left corner, jump in the bottom-right
+    let buttons = [
+        (
+            Action::MoveLeft,
+            "<",
+            Val::Px(BUTTON_MARGIN),
+            Val::Undefined,
+        ),
+        (
+            Action::MoveRight,
+            ">",
+            Val::Px(2. * BUTTON_MARGIN + BUTTON_SIZE),
+            Val::Undefined,
+        ),
+        (Action::Jump, "^", Val::Undefined, Val::Px(BUTTON_MARGIN)),
+    ];
+    for (action, label, left, right) in buttons {
+        commands
+            .spawn_bundle(NodeBundle {
+                style: Style {
+                    size: Size::new(Val::Px(BUTTON_SIZE), Val::Px(BUTTON_SIZE)),
+                    position_type: PositionType::Absolute,
+                    position: Rect {
+                        left,
+                        right,
+                        bottom: Val::Px(BUTTON_MARGIN),
+                        ..default()
+                    },
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    ..default()
+                },
+                color: BUTTON_COLOR.into(),
+                ..default()
+            })
+            .insert(VirtualButton(action))
+            .with_children(|parent| {
+                parent.spawn_bundle(TextBundle {
+                    text: Text::with_section(
+                        label,
+                        TextStyle {
+                            font: font.clone(),
+                            font_size: 32.,
+                            color: Color::rgba(1., 1., 1., 0.8),
+                        },
+                        default(),
+                    ),
+                    ..default()
+                });
+            });
+    }
+}
+
+// Hit tests the mouse and every touch against the buttons as laid out last
+// frame. Bevy's `Interaction` only follows the mouse cursor, which would
+// rule out holding two buttons at once.
+fn virtual_buttons_system(
+    windows: Res<Windows>,
+    mouse_buttons: Res<Input<MouseButton>>,
+    touches: Res<Touches>,
+    mut virtual_input: ResMut<VirtualInput>,
+    mut query: Query<(&VirtualButton, &Node, &GlobalTransform, &mut UiColor)>,
+) {
+    let cursor = windows
+        .get_primary()
+        .and_then(|window| window.cursor_position());
+    let mut held: Vec<Vec2> = touches.iter().map(|touch| touch.position()).collect();
+    if mouse_buttons.pressed(MouseButton::Left) {
+        held.extend(cursor);
+    }
+
+    let mut pressed = HashSet::default();
+    let mut pointer_over = false;
+    for (button, node, transform, mut color) in query.iter_mut() {
+        let center = transform.translation.truncate();
+        let contains = |point: &Vec2| (*point - center).abs().cmple(node.size / 2.).all();
+        pointer_over |= cursor.as_ref().is_some_and(contains);
+        let is_pressed = held.iter().any(contains);
+        if is_pressed {
+            pressed.insert(button.0);
+        }
+        *color = if is_pressed {
+            PRESSED_BUTTON_COLOR
+        } else {
+            BUTTON_COLOR
+        }
+        .into();
+    }
+    virtual_input.pressed = pressed;
+    virtual_input.pointer_over = pointer_over;
+}
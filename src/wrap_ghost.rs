@@ -0,0 +1,146 @@
+// Copies of the players drawn across the edges of a level which wraps, so a
+// player straddling an edge shows on both sides of it rather than popping
+// across once their middle is over it.
+//
+// Each player has a ghost, drawn as they are but a level's width away, and
+// shown only while part of them is past an edge.
+
+use bevy::prelude::*;
+
+use crate::animation::animation_system;
+use crate::level::{LevelBounds, LevelEdges};
+use crate::physics::PhysicsSystem;
+use crate::player::Player;
+use crate::tile::TileIndex;
+
+#[derive(Component)]
+struct WrapGhost {
+    player: Entity,
+}
+
+// Needs the `LevelBounds` from `GamePlugin` and the `TileIndex` from
+// `TilePlugin`
+#[derive(Default)]
+pub struct WrapGhostPlugin;
+
+impl Plugin for WrapGhostPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(spawn_ghost_system).add_system(
+            ghost_system
+                .after(spawn_ghost_system)
+                .after(animation_system)
+                .after(PhysicsSystem::Camera),
+        );
+    }
+}
+
+// A ghost for every player, drawn like a plain player until `ghost_system`
+// sees what they are drawn with
+fn spawn_ghost_system(
+    mut commands: Commands,
+    player_query: Query<Entity, With<Player>>,
+    ghost_query: Query<(Entity, &WrapGhost)>,
+) {
+    for (ghost, &WrapGhost { player }) in ghost_query.iter() {
+        if !player_query.contains(player) {
+            commands.entity(ghost).despawn();
+        }
+    }
+    for player in player_query.iter() {
+        if ghost_query.iter().all(|(_, ghost)| ghost.player != player) {
+            commands
+                .spawn_bundle(SpriteBundle {
+                    visibility: Visibility { is_visible: false },
+                    ..default()
+                })
+                .insert(WrapGhost { player });
+        }
+    }
+}
+
+// The players, by where they are and how they look
+type GhostedPlayerQuery<'w, 's> = Query<
+    'w,
+    's,
+    (
+        &'static Transform,
+        Option<&'static Sprite>,
+        Option<&'static TextureAtlasSprite>,
+        Option<&'static Handle<TextureAtlas>>,
+    ),
+    With<Player>,
+>;
+
+// The ghosts, with what of their players' looks they copy
+type GhostQuery<'w, 's> = Query<
+    'w,
+    's,
+    (
+        Entity,
+        &'static WrapGhost,
+        &'static mut Transform,
+        &'static mut Visibility,
+        Option<&'static mut Sprite>,
+        Option<&'static mut TextureAtlasSprite>,
+    ),
+    Without<Player>,
+>;
+
+fn ghost_system(
+    mut commands: Commands,
+    bounds: Res<LevelBounds>,
+    tile_index: Res<TileIndex>,
+    mut extent: Local<Option<(f32, f32)>>,
+    player_query: GhostedPlayerQuery,
+    mut ghost_query: GhostQuery,
+) {
+    // Finding the tiles' bounds visits every tile, so only when they change
+    if bounds.is_changed() || tile_index.is_changed() {
+        *extent = match bounds.edges {
+            LevelEdges::Wrap => bounds.extent(&tile_index),
+            _ => None,
+        };
+    }
+    for (ghost, wrap_ghost, mut transform, mut visibility, ghost_sprite, ghost_sheet_sprite) in
+        ghost_query.iter_mut()
+    {
+        let (player_transform, sprite, sheet_sprite, atlas) =
+            match player_query.get(wrap_ghost.player) {
+                Ok(player) => player,
+                Err(_) => continue,
+            };
+        let x = player_transform.translation.x;
+        let offset = match *extent {
+            Some((left, right)) if x < left => right - left,
+            Some((left, right)) if x + player_transform.scale.x > right => left - right,
+            _ => 0.,
+        };
+        if visibility.is_visible != (offset != 0.) {
+            visibility.is_visible = offset != 0.;
+        }
+        if offset == 0. {
+            continue;
+        }
+        *transform = *player_transform;
+        transform.translation.x += offset;
+        match (sheet_sprite, atlas, ghost_sheet_sprite) {
+            (Some(sheet_sprite), Some(_), Some(mut ghost_sheet_sprite)) => {
+                *ghost_sheet_sprite = sheet_sprite.clone();
+            }
+            // Drawn from the sheet like the player, from the next frame
+            (Some(sheet_sprite), Some(atlas), None) => {
+                commands
+                    .entity(ghost)
+                    .remove::<Sprite>()
+                    .remove::<Handle<Image>>()
+                    .insert(sheet_sprite.clone())
+                    .insert(atlas.clone());
+            }
+            _ => {
+                if let (Some(sprite), Some(mut ghost_sprite)) = (sprite, ghost_sprite) {
+                    *ghost_sprite = sprite.clone();
+                }
+            }
+        }
+    }
+}